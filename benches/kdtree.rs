@@ -0,0 +1,34 @@
+//! Times the construction of a kd-tree over a large list of triangles.
+//!
+//! Run with `cargo bench --bench kdtree`.
+
+use std::time::Instant;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rpt::*;
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut point =
+        |scale: f64| glm::vec3(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>()) * scale;
+    let triangles: Vec<_> = (0..200_000)
+        .map(|_| {
+            let v = point(100.0);
+            Triangle::from_vertices(v, v + point(1.0), v + point(1.0))
+        })
+        .collect();
+
+    let runs = 5;
+    let start = Instant::now();
+    for _ in 0..runs {
+        let tree = KdTree::new(triangles.clone());
+        assert!(tree.bounding_box().surface_area() > 0.0);
+    }
+    let elapsed = start.elapsed() / runs;
+    println!(
+        "KdTree::new over {} triangles: {:?} per build ({} threads)",
+        triangles.len(),
+        elapsed,
+        rayon::current_num_threads()
+    );
+}
@@ -0,0 +1,35 @@
+//! Times an unseeded iterative render with small batches, where per-batch overhead such
+//! as seeding random number generators is most visible.
+//!
+//! Run with `cargo bench --bench sampling`.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use rpt::*;
+
+fn main() {
+    let mut scene = Scene::new();
+    scene.add(Object::new(sphere()).material(Material::diffuse(hex_color(0xAAAAAA))));
+    scene.add(Light::Ambient(glm::vec3(0.5, 0.5, 0.5)));
+    let renderer = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+        .width(160)
+        .height(120)
+        .max_bounces(2)
+        .num_samples(64);
+
+    let runs = 5;
+    let start = Instant::now();
+    for _ in 0..runs {
+        renderer.iterative_render(1, |_, _| {});
+    }
+    let elapsed = start.elapsed() / runs;
+    println!(
+        "iterative_render at {}x{}, {} samples in batches of 1: {:?} per render ({} threads)",
+        renderer.width,
+        renderer.height,
+        renderer.num_samples,
+        elapsed,
+        rayon::current_num_threads()
+    );
+}
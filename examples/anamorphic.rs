@@ -0,0 +1,91 @@
+//! This is an example of an anamorphic lens, which squeezes a wide field of view onto
+//! the sensor and gives out-of-focus highlights an oval shape once de-squeezed.
+
+use std::sync::Arc;
+
+use common::SeedFromEnv;
+use rpt::lens::{AchromaticDoublet, AchromaticDoubletParams};
+use rpt::*;
+
+mod common;
+
+fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+
+    let mut scene = Scene::new();
+
+    let spheres = [
+        (
+            glm::vec3(0.5, 4.0, 1.0),
+            Material::light(hex_color(0xE78999), 10.0),
+        ),
+        (
+            glm::vec3(3.15, -0.7, 1.5),
+            Material::light(hex_color(0xE7A94D), 2.0),
+        ),
+        (
+            glm::vec3(0.1, -2.0, 0.6),
+            Material::light(hex_color(0xB3E7AA), 0.5),
+        ),
+        (
+            glm::vec3(-1.7, -0.2, 1.1),
+            Material::light(hex_color(0x7CA3E7), 2.0),
+        ),
+        (
+            glm::vec3(1.2, 0.4, 0.5),
+            Material::light(hex_color(0xAAAAAA), 3.0),
+        ),
+    ];
+    scene.add(
+        Object::new(plane(glm::vec3(0.0, 0.0, 1.0), 0.0))
+            .material(Material::diffuse(hex_color(0xE7E7E7))),
+    );
+    for (pos, mtl) in spheres.iter() {
+        scene.add(
+            Object::new(sphere().scale(&glm::vec3(0.1, 0.1, 0.1)).translate(pos))
+                .material(mtl.clone()),
+        )
+    }
+    scene.add(Light::Object(
+        Object::new(
+            sphere()
+                .scale(&glm::vec3(2.0, 2.0, 2.0))
+                .translate(&glm::vec3(1.2, -1.5, 8.0)),
+        )
+        .material(Material::light(hex_color(0xFFFFFF), 0.2)),
+    ));
+
+    let lens = AchromaticDoublet::new(AchromaticDoubletParams {
+        aperture: Aperture {
+            scale: 0.2,
+            shape: ApertureShape::Circle,
+            inner_scale: 0.0,
+            rotation: 0.0,
+            offset: [0.0, 0.0],
+        },
+        ..Default::default()
+    });
+    let camera = PhysicalCamera::builder()
+        .eye(glm::vec3(0.7166, -12.2992, 2.8803))
+        .look_at(glm::vec3(0.8673, 0.2095, 0.9557), glm::vec3(0.0, 0.0, 1.0))
+        .sensor(4., 3.)
+        .lens(lens)
+        .focus(10.0)
+        .anamorphic_squeeze(2.)
+        .build();
+
+    let squeezed = Renderer::new(&scene, Arc::new(camera))
+        .width(800)
+        .height(600)
+        .max_bounces(1)
+        .num_samples(400)
+        .seed_from_env()
+        .render();
+    squeezed.save("anamorphic_squeezed.png")?;
+
+    // Stretch the image back out horizontally by the squeeze factor
+    image::imageops::resize(&squeezed, 1600, 600, image::imageops::FilterType::Triangle)
+        .save("anamorphic.png")?;
+
+    Ok(())
+}
@@ -1,8 +1,11 @@
+use common::SeedFromEnv;
 use rand_distr::num_traits::Pow;
 use rpt::lens::{Lens, SingleLens};
 use rpt::*;
 use std::sync::Arc;
 
+mod common;
+
 fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
 
@@ -30,35 +33,52 @@ fn main() -> color_eyre::Result<()> {
     scene.add(Light::Point(
         glm::vec3(100.0, 100.0, 100.0),
         glm::vec3(0.0, 5.0, 1.0),
+        Falloff::Quadratic,
     ));
 
-    for i in 0..4 {
+    let new_camera = || {
         let lens = SingleLens {
             aperture: Aperture {
                 scale: 0.2,
                 shape: ApertureShape::Circle,
+                inner_scale: 0.0,
+                rotation: 0.0,
+                offset: [0.0, 0.0],
             },
             ..Default::default()
         };
         let lens_system = lens.lens_system(10.);
-        let mut camera = PhysicalCamera {
+        PhysicalCamera {
             eye: glm::vec3(0., -0.5, 15.),
             sensor_width: 0.8,
             sensor_height: 0.6,
             lens,
             lens_system,
+            focus_distance: 10.,
             ..Default::default()
-        };
-
-        camera.focus(30. - 5. * i as f64);
-
+        }
+    };
+    let render = |camera, filename: String| {
         Renderer::new(&scene, Arc::new(camera))
             .num_samples(128)
             .width(800)
             .height(600)
+            .seed_from_env()
             .render()
-            .save(format!("output_{i}.png"))?;
+            .save(filename)
+    };
+
+    for i in 0..4 {
+        let mut camera = new_camera();
+        camera.focus(30. - 5. * i as f64);
+        render(camera, format!("output_{i}.png"))?;
+    }
+
+    let mut camera = new_camera();
+    if let Some(distance) = camera.autofocus(&scene) {
+        println!("Autofocused at distance {distance:.2}");
     }
+    render(camera, "output_autofocus.png".into())?;
 
     Ok(())
 }
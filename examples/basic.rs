@@ -1,7 +1,10 @@
+use common::SeedFromEnv;
 use rpt::lens::SingleLens;
 use rpt::*;
 use std::sync::Arc;
 
+mod common;
+
 fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
 
@@ -48,12 +51,14 @@ fn main() -> color_eyre::Result<()> {
     scene.add(Light::Point(
         glm::vec3(100.0, 100.0, 100.0),
         glm::vec3(0.0, 5.0, 5.0),
+        Falloff::Quadratic,
     ));
 
     Renderer::new(&scene, Arc::new(PhysicalCamera::<SingleLens>::default()))
         .num_samples(128)
         .width(800)
         .height(600)
+        .seed_from_env()
         .render()
         .save("output.png")?;
 
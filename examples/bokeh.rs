@@ -2,9 +2,12 @@
 
 use std::sync::Arc;
 
-use rpt::lens::{AchromaticDoublet, AchromaticDoubletParams, Lens};
+use common::SeedFromEnv;
+use rpt::lens::{AchromaticDoublet, AchromaticDoubletParams};
 use rpt::*;
 
+mod common;
+
 fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
 
@@ -58,6 +61,12 @@ fn main() -> color_eyre::Result<()> {
     let max_aperture = 0.2;
     let aperture_step_size = (max_aperture - min_aperture) / (aperture_steps - 1) as f64;
 
+    let mut camera = PhysicalCamera::builder()
+        .eye(eye)
+        .look_at(center, glm::vec3(0.0, 0.0, 1.0))
+        .sensor(4., 3.)
+        .build();
+
     for (shape_i, shape) in [
         ApertureShape::Circle,
         ApertureShape::Square,
@@ -88,27 +97,21 @@ fn main() -> color_eyre::Result<()> {
                     aperture: Aperture {
                         scale: aperture,
                         shape: shape.clone(),
+                        inner_scale: 0.0,
+                        rotation: 0.0,
+                        offset: [0.0, 0.0],
                     },
                     ..Default::default()
                 });
-                let lens_system = lens.lens_system(10.);
-                let mut camera = PhysicalCamera {
-                    eye: Default::default(),
-                    direction: Default::default(),
-                    up: Default::default(),
-                    sensor_width: 4.,
-                    sensor_height: 3.,
-                    lens,
-                    lens_system,
-                };
-                camera.look_at(eye, center, glm::vec3(0.0, 0.0, 1.0));
+                camera.set_lens(lens);
                 camera.focus(dist);
 
-                Renderer::new(&scene, Arc::new(camera))
+                Renderer::new(&scene, Arc::new(camera.clone()))
                     .width(800)
                     .height(600)
                     .max_bounces(1)
                     .num_samples(400)
+                    .seed_from_env()
                     .render()
                     .save(filename)?;
             }
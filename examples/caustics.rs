@@ -0,0 +1,52 @@
+//! This is an example of caustics, rendered with bidirectional path tracing. A glass
+//! sphere focuses light from a point light onto the floor, which path tracing from the
+//! camera alone cannot find.
+
+use common::SeedFromEnv;
+use rpt::*;
+use std::sync::Arc;
+
+mod common;
+
+fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+
+    let mut scene = Scene::new();
+
+    scene.add(Object::new(sphere()).material(Material::dielectric(1.5, 0.0)));
+    scene.add(
+        Object::new(
+            sphere()
+                .scale(&glm::vec3(0.4, 0.4, 0.4))
+                .translate(&glm::vec3(1.8, -0.6, 0.5)),
+        )
+        .material(Material::conductor(hex_color(0xE7A94D), 0.0)),
+    );
+    scene.add(
+        Object::new(plane(glm::vec3(0.0, 1.0, 0.0), -1.0))
+            .material(Material::diffuse(hex_color(0xAAAAAA))),
+    );
+    scene.add(Light::Point(
+        glm::vec3(12.0, 12.0, 12.0),
+        glm::vec3(3.0, 4.0, -2.0),
+        Falloff::Quadratic,
+    ));
+
+    let camera = PinholeCamera::look_at(
+        glm::vec3(-2.5, 4.0, 6.5),
+        glm::vec3(0.0, -0.25, 0.0),
+        glm::vec3(0.0, 1.0, 0.0),
+        std::f64::consts::FRAC_PI_4,
+    );
+
+    Renderer::new(&scene, Arc::new(camera))
+        .width(800)
+        .height(600)
+        .max_bounces(6)
+        .num_samples(256)
+        .seed_from_env()
+        .render_bdpt()
+        .save("caustics.png")?;
+
+    Ok(())
+}
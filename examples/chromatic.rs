@@ -1,8 +1,11 @@
+use common::SeedFromEnv;
 use glm::vec3;
 use rpt::lens::{AchromaticDoublet, Lens, SingleLens};
 use rpt::*;
 use std::sync::Arc;
 
+mod common;
+
 fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
 
@@ -49,12 +52,16 @@ fn main() -> color_eyre::Result<()> {
     scene.add(Light::Point(
         glm::vec3(100.0, 100.0, 100.0),
         glm::vec3(0.0, 5.0, 5.0),
+        Falloff::Quadratic,
     ));
 
     let lens = SingleLens {
         aperture: Aperture {
             scale: 0.1,
             shape: ApertureShape::Circle,
+            inner_scale: 0.0,
+            rotation: 0.0,
+            offset: [0.0, 0.0],
         },
         v_no: 3.,
         ..Default::default()
@@ -63,6 +70,8 @@ fn main() -> color_eyre::Result<()> {
     let mut cam = PhysicalCamera {
         lens,
         lens_system,
+        focus_distance: 10.,
+        spectral_mode: SpectralMode::Continuous,
         ..Default::default()
     };
     cam.focus_point(vec3(0., 0., -4.));
@@ -72,10 +81,14 @@ fn main() -> color_eyre::Result<()> {
         .num_samples(32)
         .width(800)
         .height(600)
+        .seed_from_env()
         .render()
         .save("single_lens.png")?;
 
-    let mut cam = PhysicalCamera::<AchromaticDoublet>::default();
+    let mut cam = PhysicalCamera::<AchromaticDoublet> {
+        spectral_mode: SpectralMode::Continuous,
+        ..Default::default()
+    };
     cam.focus_point(vec3(0., 0., -4.));
     let cam = Arc::new(cam);
 
@@ -83,6 +96,7 @@ fn main() -> color_eyre::Result<()> {
         .num_samples(32)
         .width(800)
         .height(600)
+        .seed_from_env()
         .render()
         .save("achromat.png")?;
 
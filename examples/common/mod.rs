@@ -0,0 +1,29 @@
+//! Helpers shared between the examples.
+
+use rpt::Renderer;
+
+/// Environment variable holding an optional random seed for the examples
+///
+/// Setting this makes example renders reproducible, e.g. for producing golden images:
+/// `RPT_SEED=42 cargo run --example sphere`
+pub const SEED_VAR: &str = "RPT_SEED";
+
+/// Extension trait for seeding a renderer from the environment
+pub trait SeedFromEnv {
+    /// Seed the renderer from `RPT_SEED`, if it is set
+    fn seed_from_env(self) -> Self;
+}
+
+impl SeedFromEnv for Renderer<'_> {
+    fn seed_from_env(self) -> Self {
+        match std::env::var(SEED_VAR) {
+            Ok(value) => {
+                let seed = value
+                    .parse()
+                    .unwrap_or_else(|_| panic!("{} must be an unsigned integer", SEED_VAR));
+                self.seed(seed)
+            }
+            Err(_) => self,
+        }
+    }
+}
@@ -1,8 +1,11 @@
 //! Compound of five cubes: https://en.wikipedia.org/wiki/Compound_of_five_cubes
 
+use common::SeedFromEnv;
 use rpt::*;
 use std::sync::Arc;
 
+mod common;
+
 #[allow(clippy::many_single_char_names)]
 fn lamp(x: f64, y: f64, z: f64, r: f64, e: f64) -> Light {
     Light::Object(
@@ -56,6 +59,7 @@ fn main() -> color_eyre::Result<()> {
         .height(1024)
         .max_bounces(5)
         .num_samples(50)
+        .seed_from_env()
         .render()
         .save("output.png")?;
 
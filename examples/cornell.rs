@@ -5,8 +5,11 @@
 use std::sync::Arc;
 use std::time::Instant;
 
+use common::SeedFromEnv;
 use rpt::*;
 
+mod common;
+
 fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
 
@@ -71,12 +74,12 @@ fn main() -> color_eyre::Result<()> {
         .rotate_y(glm::two_pi::<f64>() * (-197.0 / 360.0))
         .translate(&glm::vec3(185.0, 82.5, 169.0));
 
-    scene.add(Object::new(floor).material(white));
-    scene.add(Object::new(ceiling).material(white));
-    scene.add(Object::new(back_wall).material(white));
+    scene.add(Object::new(floor).material(white.clone()));
+    scene.add(Object::new(ceiling).material(white.clone()));
+    scene.add(Object::new(back_wall).material(white.clone()));
     scene.add(Object::new(left_wall).material(red));
     scene.add(Object::new(right_wall).material(green));
-    scene.add(Object::new(large_box).material(white));
+    scene.add(Object::new(large_box).material(white.clone()));
     scene.add(Object::new(small_box).material(white));
     scene.add(Light::Object(Object::new(light_rect).material(light_mtl)));
 
@@ -87,6 +90,7 @@ fn main() -> color_eyre::Result<()> {
         .filter(Filter::Box(1))
         .max_bounces(2)
         .num_samples(100)
+        .seed_from_env()
         .iterative_render(10, |iteration, buffer| {
             let millis = time.elapsed().as_millis();
             println!(
@@ -1,8 +1,11 @@
 use std::fs::File;
 use std::sync::Arc;
 
+use common::SeedFromEnv;
 use rpt::*;
 
+mod common;
+
 fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
 
@@ -23,6 +26,7 @@ fn main() -> color_eyre::Result<()> {
     scene.add(Light::Point(
         glm::vec3(80.0, 80.0, 80.0),
         glm::vec3(0.0, 5.0, 5.0),
+        Falloff::Quadratic,
     ));
     scene.add(Light::Directional(
         glm::vec3(2.0, 2.0, 2.0),
@@ -32,6 +36,7 @@ fn main() -> color_eyre::Result<()> {
     Renderer::new(&scene, Arc::new(PinholeCamera::default()))
         .width(512)
         .height(512)
+        .seed_from_env()
         .render()
         .save("output.png")?;
 
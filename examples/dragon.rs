@@ -5,8 +5,11 @@ use std::sync::Arc;
 use tempfile::tempfile;
 use zip::ZipArchive;
 
+use common::SeedFromEnv;
 use rpt::*;
 
+mod common;
+
 fn load_dragon() -> color_eyre::Result<Mesh> {
     let mut buf = Vec::new();
     ureq::get("http://casual-effects.com/g3d/data10/research/model/dragon/dragon.zip")
@@ -69,6 +72,7 @@ fn main() -> color_eyre::Result<()> {
     Renderer::new(&scene, Arc::new(camera))
         .max_bounces(2)
         .num_samples(1)
+        .seed_from_env()
         .render()
         .save("output.png")?;
 
@@ -0,0 +1,52 @@
+//! This is an example of a participating medium. A spotlight shines down through
+//! thin fog, which scatters some of its light toward the camera and reveals the beam.
+
+use common::SeedFromEnv;
+use rpt::*;
+use std::sync::Arc;
+
+mod common;
+
+fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+
+    let mut scene = Scene::new();
+
+    scene.add(
+        Object::new(plane(glm::vec3(0.0, 1.0, 0.0), -1.0))
+            .material(Material::diffuse(hex_color(0xAAAAAA))),
+    );
+    for (x, color) in [(-2.2, 0xE78999), (0.0, 0xE7E7E7), (2.2, 0x7CA3E7)] {
+        scene.add(
+            Object::new(sphere().translate(&glm::vec3(x, 0.0, 0.0)))
+                .material(Material::diffuse(hex_color(color))),
+        );
+    }
+
+    scene.add(Light::Spot {
+        position: glm::vec3(0.0, 8.0, 0.0),
+        direction: glm::vec3(0.0, -1.0, 0.0),
+        color: glm::vec3(150.0, 140.0, 120.0),
+        inner_angle: 0.2,
+        outer_angle: 0.25,
+    });
+    scene.medium = Some(Medium::new(0.01, 0.04, 0.5));
+
+    let camera = PinholeCamera::look_at(
+        glm::vec3(0.0, 2.5, 10.0),
+        glm::vec3(0.0, 1.5, 0.0),
+        glm::vec3(0.0, 1.0, 0.0),
+        std::f64::consts::FRAC_PI_3,
+    );
+
+    Renderer::new(&scene, Arc::new(camera))
+        .width(800)
+        .height(600)
+        .max_bounces(2)
+        .num_samples(128)
+        .seed_from_env()
+        .render()
+        .save("fog.png")?;
+
+    Ok(())
+}
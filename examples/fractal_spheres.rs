@@ -1,6 +1,9 @@
+use common::SeedFromEnv;
 use rpt::*;
 use std::sync::Arc;
 
+mod common;
+
 fn gen(
     spheres: &mut [Vec<Box<dyn Bounded>>],
     p: glm::DVec3,
@@ -60,6 +63,7 @@ fn main() -> color_eyre::Result<()> {
     scene.add(Light::Point(
         glm::vec3(100.0, 100.0, 100.0),
         glm::vec3(0.0, 5.0, 5.0),
+        Falloff::Quadratic,
     ));
 
     let camera = PinholeCamera {
@@ -72,6 +76,7 @@ fn main() -> color_eyre::Result<()> {
     Renderer::new(&scene, Arc::new(camera))
         .width(800)
         .height(600)
+        .seed_from_env()
         .render()
         .save("output.png")?;
 
@@ -3,22 +3,26 @@
 use std::fs::File;
 use std::sync::Arc;
 
+use common::SeedFromEnv;
 use rpt::*;
 
+mod common;
+
 fn gen(
-    obj: Arc<Mesh>,
+    obj: &Arc<dyn Bounded>,
     spheres: &mut [Vec<Box<dyn Bounded>>],
     p: glm::DVec3,
     rad: f64,
     depth: usize,
     last_dir: Option<usize>,
 ) {
-    spheres[depth].push(Box::new(
-        Arc::clone(&obj)
-            .scale(&glm::vec3(0.5, 0.5, 0.5))
-            .scale(&glm::vec3(rad, rad, rad))
-            .translate(&p),
-    ));
+    // Every teapot is an instance of the same mesh, sharing one kd-tree
+    let transform = glm::translate(&glm::identity(), &p)
+        * glm::scale(
+            &glm::identity(),
+            &glm::vec3(0.5 * rad, 0.5 * rad, 0.5 * rad),
+        );
+    spheres[depth].push(Box::new(instance(obj, transform)));
     if depth == spheres.len() - 1 {
         return;
     }
@@ -29,7 +33,7 @@ fn gen(
     for i in 0..6 {
         if last_dir.is_none() || i != (last_dir.unwrap() ^ 1) {
             gen(
-                Arc::clone(&obj),
+                obj,
                 spheres,
                 p + glm::vec3(dx[i], dy[i], dz[i]),
                 rad * 2.0 / 5.0,
@@ -46,8 +50,15 @@ fn main() -> color_eyre::Result<()> {
     let colors = [0x264653, 0x2A9D8F, 0xE9C46A, 0xF4A261, 0xE76F51];
     let mut spheres: Vec<_> = colors.iter().map(|_| Vec::new()).collect();
 
-    let teapot = Arc::new(load_obj(File::open("examples/teapot.obj")?)?);
-    gen(teapot, &mut spheres, glm::vec3(0.0, 0.0, 0.0), 1.0, 0, None);
+    let teapot: Arc<dyn Bounded> = Arc::new(load_obj(File::open("examples/teapot.obj")?)?);
+    gen(
+        &teapot,
+        &mut spheres,
+        glm::vec3(0.0, 0.0, 0.0),
+        1.0,
+        0,
+        None,
+    );
 
     let mut scene = Scene::new();
     for (i, sphere_group) in spheres.into_iter().enumerate() {
@@ -70,6 +81,7 @@ fn main() -> color_eyre::Result<()> {
     scene.add(Light::Point(
         glm::vec3(100.0, 100.0, 100.0),
         glm::vec3(0.0, 5.0, 5.0),
+        Falloff::Quadratic,
     ));
 
     let camera = PinholeCamera {
@@ -82,6 +94,7 @@ fn main() -> color_eyre::Result<()> {
     Renderer::new(&scene, Arc::new(camera))
         .width(800)
         .height(600)
+        .seed_from_env()
         .render()
         .save("output.png")?;
 
@@ -0,0 +1,77 @@
+//! This is an example of lens ghosts: faint, out-of-focus copies of a bright light,
+//! reflected between the surfaces of the lens, which appear across the frame from it.
+
+use std::sync::Arc;
+
+use common::SeedFromEnv;
+use rpt::lens::{AchromaticDoublet, AchromaticDoubletParams};
+use rpt::*;
+
+mod common;
+
+fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+
+    let mut scene = Scene::new();
+
+    scene.add(
+        Object::new(plane(glm::vec3(0.0, 0.0, 1.0), 0.0))
+            .material(Material::diffuse(hex_color(0x445566))),
+    );
+    for i in 0..5 {
+        let x = i as f64 * 1.2 - 2.4;
+        scene.add(
+            Object::new(
+                sphere()
+                    .scale(&glm::vec3(0.4, 0.4, 0.4))
+                    .translate(&glm::vec3(x, 0.5 * i as f64, 0.4)),
+            )
+            .material(Material::diffuse(hex_color(0xE7E7E7))),
+        );
+    }
+
+    // A small, very bright lamp near the corner of the frame, under dim moonlight
+    scene.add(
+        Object::new(
+            sphere()
+                .scale(&glm::vec3(0.5, 0.5, 0.5))
+                .translate(&glm::vec3(-2.5, 6.0, 2.8)),
+        )
+        .material(Material::light(hex_color(0xFFE0B0), 5000.0)),
+    );
+    scene.add(Light::Ambient(glm::vec3(0.02, 0.02, 0.03)));
+    scene.add(Light::Directional(
+        glm::vec3(0.3, 0.3, 0.4),
+        glm::vec3(0.5, 1.0, -1.0).normalize(),
+    ));
+
+    let lens = AchromaticDoublet::new(AchromaticDoubletParams {
+        aperture: Aperture {
+            scale: 0.2,
+            shape: ApertureShape::Circle,
+            inner_scale: 0.0,
+            rotation: 0.0,
+            offset: [0.0, 0.0],
+        },
+        ..Default::default()
+    });
+    let camera = PhysicalCamera::builder()
+        .eye(glm::vec3(0.0, -10.0, 2.0))
+        .look_at(glm::vec3(0.0, 0.0, 1.0), glm::vec3(0.0, 0.0, 1.0))
+        .sensor(4., 3.)
+        .lens(lens)
+        .focus(10.0)
+        .simulate_ghosts(true)
+        .build();
+
+    Renderer::new(&scene, Arc::new(camera))
+        .width(800)
+        .height(600)
+        .max_bounces(0)
+        .num_samples(400)
+        .seed_from_env()
+        .render()
+        .save("ghosts.png")?;
+
+    Ok(())
+}
@@ -7,8 +7,11 @@ use image::{
 use std::io::BufReader;
 use std::sync::Arc;
 
+use common::SeedFromEnv;
 use rpt::*;
 
+mod common;
+
 fn rgb_to_color(rgb: Rgb<f32>) -> Color {
     glm::vec3(rgb.0[0] as f64, rgb.0[1] as f64, rgb.0[2] as f64)
 }
@@ -46,6 +49,7 @@ fn main() -> color_eyre::Result<()> {
         .height(900)
         .max_bounces(5)
         .num_samples(200)
+        .seed_from_env()
         .render()
         .save("output.png")?;
 
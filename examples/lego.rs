@@ -7,8 +7,11 @@ use std::time::Instant;
 use tempfile::tempfile;
 use zip::ZipArchive;
 
+use common::SeedFromEnv;
 use rpt::*;
 
+mod common;
+
 fn load_lego_plane() -> color_eyre::Result<Vec<Object>> {
     let mut buf = Vec::new();
     File::open("examples/lego.zip")?.read_to_end(&mut buf)?;
@@ -71,6 +74,7 @@ fn main() -> color_eyre::Result<()> {
         .height(540)
         .max_bounces(5)
         .num_samples(20)
+        .seed_from_env()
         .iterative_render(1, |iteration, buffer| {
             let millis = time.elapsed().as_millis();
             println!(
@@ -8,8 +8,11 @@ use std::io::BufReader;
 use std::process::Command;
 use std::sync::Arc;
 
+use common::SeedFromEnv;
 use rpt::*;
 
+mod common;
+
 fn rgb_to_color(rgb: Rgb<f32>) -> Color {
     glm::vec3(rgb.0[0] as f64, rgb.0[1] as f64, rgb.0[2] as f64)
 }
@@ -110,6 +113,9 @@ fn main() -> color_eyre::Result<()> {
                 Some(Aperture {
                     scale: 0.02,
                     shape: ApertureShape::Circle,
+                    inner_scale: 0.0,
+                    rotation: 0.0,
+                    offset: [0.0, 0.0],
                 }),
             ),
         );
@@ -120,6 +126,7 @@ fn main() -> color_eyre::Result<()> {
                 .height(150)
                 .max_bounces(7)
                 .num_samples(1)
+                .seed_from_env()
                 .render()
                 .save(format!("video/image_{}.png", frame))?;
         } else {
@@ -128,6 +135,7 @@ fn main() -> color_eyre::Result<()> {
                 .height(600)
                 .max_bounces(9)
                 .num_samples(2000)
+                .seed_from_env()
                 .render()
                 .save(format!("video/image_{}.png", frame))?;
         }
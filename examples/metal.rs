@@ -8,8 +8,11 @@ use std::fs::File;
 use std::io::BufReader;
 use std::sync::Arc;
 
+use common::SeedFromEnv;
 use rpt::*;
 
+mod common;
+
 fn rgb_to_color(rgb: Rgb<f32>) -> Color {
     glm::vec3(rgb.0[0] as f64, rgb.0[1] as f64, rgb.0[2] as f64)
 }
@@ -57,6 +60,7 @@ fn main() -> color_eyre::Result<()> {
         .height(900)
         .max_bounces(5)
         .num_samples(20)
+        .seed_from_env()
         .render()
         .save("output.png")?;
 
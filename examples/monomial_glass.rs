@@ -5,8 +5,11 @@ use image::{
 use std::io::BufReader;
 use std::sync::Arc;
 
+use common::SeedFromEnv;
 use rpt::*;
 
+mod common;
+
 fn rgb_to_color(rgb: Rgb<f32>) -> Color {
     glm::vec3(rgb.0[0] as f64, rgb.0[1] as f64, rgb.0[2] as f64)
 }
@@ -69,12 +72,14 @@ fn main() -> color_eyre::Result<()> {
     scene.add(Light::Point(
         glm::vec3(100.0, 100.0, 100.0),
         glm::vec3(0.0, 5.0, 5.0),
+        Falloff::Quadratic,
     ));
 
     /*
     scene.add(Light::Point(
         glm::vec3(100.0, 100.0, 100.0),
         glm::vec3(0.0, 0.0, -20.0),
+        Falloff::Quadratic,
     ));
      */
 
@@ -83,6 +88,7 @@ fn main() -> color_eyre::Result<()> {
         .height(600)
         .max_bounces(1)
         .num_samples(100)
+        .seed_from_env()
         .render()
         .save("output.png")?;
 
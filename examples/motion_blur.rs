@@ -0,0 +1,50 @@
+//! This is an example that demonstrates motion blur on a translating sphere.
+
+use common::SeedFromEnv;
+use rpt::*;
+use std::sync::Arc;
+
+mod common;
+
+fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+
+    let mut scene = Scene::new();
+
+    scene.add(
+        Object::new(sphere().scale(&glm::vec3(0.5, 0.5, 0.5)))
+            .material(Material::specular(hex_color(0x0000ff), 0.1))
+            .motion(glm::vec3(-1.5, -0.5, 0.0), glm::vec3(1.5, -0.5, 0.0)),
+    );
+    scene.add(
+        Object::new(plane(glm::vec3(0.0, 1.0, 0.0), -1.0))
+            .material(Material::diffuse(hex_color(0xAAAAAA))),
+    );
+    scene.add(Light::Object(
+        Object::new(
+            sphere()
+                .scale(&glm::vec3(2.0, 2.0, 2.0))
+                .translate(&glm::vec3(0.0, 12.0, 0.0)),
+        )
+        .material(Material::light(hex_color(0xFFFFFF), 40.0)),
+    ));
+
+    let camera = PinholeCamera::look_at(
+        glm::vec3(0.0, 2.0, 8.0),
+        glm::vec3(0.0, -0.5, 0.0),
+        glm::vec3(0.0, 1.0, 0.0),
+        std::f64::consts::FRAC_PI_4,
+    );
+
+    Renderer::new(&scene, Arc::new(camera))
+        .width(800)
+        .height(600)
+        .max_bounces(2)
+        .num_samples(200)
+        .shutter_time(0.5)
+        .seed_from_env()
+        .render()
+        .save("motion_blur.png")?;
+
+    Ok(())
+}
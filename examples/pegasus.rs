@@ -13,8 +13,11 @@ use std::time::Instant;
 use tempfile::tempfile;
 use zip::ZipArchive;
 
+use common::SeedFromEnv;
 use rpt::*;
 
+mod common;
+
 fn load_pegasus() -> color_eyre::Result<Mesh> {
     let mut buf = Vec::new();
     File::open("examples/pegasus.zip")?.read_to_end(&mut buf)?;
@@ -83,6 +86,7 @@ fn main() -> color_eyre::Result<()> {
         .exposure_value(-1.5)
         .max_bounces(8)
         .num_samples(10)
+        .seed_from_env()
         .iterative_render(1, |iteration, buffer| {
             let millis = time.elapsed().as_millis();
             println!(
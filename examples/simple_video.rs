@@ -1,8 +1,11 @@
 use std::process::Command;
 use std::sync::Arc;
 
+use common::SeedFromEnv;
 use rpt::*;
 
+mod common;
+
 fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
 
@@ -46,12 +49,14 @@ fn main() -> color_eyre::Result<()> {
         scene.add(Light::Point(
             glm::vec3(100.0, 100.0, 100.0),
             glm::vec3(0.0, 5.0, 5.0),
+            Falloff::Quadratic,
         ));
         Renderer::new(&scene, Arc::new(PinholeCamera::default()))
             .width(800)
             .height(600)
             .num_samples(100)
             .max_bounces(1)
+            .seed_from_env()
             .render()
             .save(format!("video/image_{}.png", i))?;
     }
@@ -1,6 +1,9 @@
+use common::SeedFromEnv;
 use rpt::*;
 use std::sync::Arc;
 
+mod common;
+
 fn main() {
     let mut scene = Scene::new();
 
@@ -30,6 +33,7 @@ fn main() {
         .height(540)
         .max_bounces(2)
         .num_samples(100)
+        .seed_from_env()
         .render()
         .save("output.png")
         .unwrap();
@@ -5,8 +5,11 @@
 use std::sync::Arc;
 use std::time::Instant;
 
+use common::SeedFromEnv;
 use rpt::*;
 
+mod common;
+
 fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
 
@@ -62,6 +65,9 @@ fn main() -> color_eyre::Result<()> {
         Some(Aperture {
             scale: 0.15,
             shape: ApertureShape::Circle,
+            inner_scale: 0.0,
+            rotation: 0.0,
+            offset: [0.0, 0.0],
         }),
     );
 
@@ -71,6 +77,7 @@ fn main() -> color_eyre::Result<()> {
         .height(600)
         .max_bounces(6)
         .num_samples(1000)
+        .seed_from_env()
         .iterative_render(10, |iteration, buffer| {
             let millis = time.elapsed().as_millis();
             println!(
@@ -1,8 +1,11 @@
 use std::fs::File;
 use std::sync::Arc;
 
+use common::SeedFromEnv;
 use rpt::*;
 
+mod common;
+
 fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
 
@@ -25,11 +28,13 @@ fn main() -> color_eyre::Result<()> {
     scene.add(Light::Point(
         glm::vec3(60.0, 60.0, 60.0),
         glm::vec3(0.0, 5.0, 5.0),
+        Falloff::Quadratic,
     ));
 
     Renderer::new(&scene, Arc::new(PinholeCamera::default()))
         .width(800)
         .height(800)
+        .seed_from_env()
         .render()
         .save("output.png")?;
 
@@ -0,0 +1,90 @@
+//! This is an example that maps a procedurally generated brick texture onto a
+//! floor, next to a checkered sphere.
+
+use common::SeedFromEnv;
+use rpt::*;
+use std::sync::Arc;
+
+mod common;
+
+/// Draw a running-bond brick pattern, with one brick per half-width of the image
+fn bricks(size: u32) -> image::RgbImage {
+    let brick_height = size / 4;
+    let brick_width = size / 2;
+    let mortar = size / 64;
+    image::RgbImage::from_fn(size, size, |x, y| {
+        let row = y / brick_height;
+        let x = (x + (row % 2) * brick_width / 2) % brick_width;
+        let y = y % brick_height;
+        if x < mortar || y < mortar {
+            image::Rgb([200, 200, 190])
+        } else {
+            // Vary the shade of each brick slightly
+            let shade = ((row * 7 + x / brick_width * 13) % 5) as u8 * 6;
+            image::Rgb([150 + shade, 60 + shade / 2, 40])
+        }
+    })
+}
+
+fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+
+    let mut scene = Scene::new();
+
+    // A 10x10 floor with the texture repeated five times along each axis
+    let corners = [
+        glm::vec3(-5.0, -1.0, -5.0),
+        glm::vec3(-5.0, -1.0, 5.0),
+        glm::vec3(5.0, -1.0, 5.0),
+        glm::vec3(5.0, -1.0, -5.0),
+    ];
+    let uvs = [
+        glm::vec2(0.0, 5.0),
+        glm::vec2(0.0, 0.0),
+        glm::vec2(5.0, 0.0),
+        glm::vec2(5.0, 5.0),
+    ];
+    let floor = Mesh::new(vec![
+        Triangle::from_vertices(corners[0], corners[1], corners[2]).uvs(uvs[0], uvs[1], uvs[2]),
+        Triangle::from_vertices(corners[0], corners[2], corners[3]).uvs(uvs[0], uvs[2], uvs[3]),
+    ]);
+    scene.add(
+        Object::new(floor).material(Material::diffuse(hex_color(0xFFFFFF)).texture(bricks(256))),
+    );
+
+    scene.add(Object::new(sphere()).material(
+        Material::specular(hex_color(0xFFFFFF), 0.3).texture(Texture::Checker(
+            hex_color(0x1E88E5),
+            hex_color(0xFDD835),
+            8.0,
+        )),
+    ));
+
+    scene.add(Light::Object(
+        Object::new(
+            sphere()
+                .scale(&glm::vec3(2.0, 2.0, 2.0))
+                .translate(&glm::vec3(0.0, 12.0, 0.0)),
+        )
+        .material(Material::light(hex_color(0xFFFFFF), 40.0)),
+    ));
+    scene.add(Light::Ambient(glm::vec3(0.05, 0.05, 0.05)));
+
+    let camera = PinholeCamera::look_at(
+        glm::vec3(0.0, 2.0, 6.0),
+        glm::vec3(0.0, -0.5, 0.0),
+        glm::vec3(0.0, 1.0, 0.0),
+        std::f64::consts::FRAC_PI_4,
+    );
+
+    Renderer::new(&scene, Arc::new(camera))
+        .width(800)
+        .height(600)
+        .max_bounces(2)
+        .num_samples(100)
+        .seed_from_env()
+        .render()
+        .save("texture.png")?;
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+//! This is an example of the "toy world" effect from a tilt-shift lens. Tilting the
+//! lens swings the plane of focus up, so only a thin band across a town viewed from
+//! above is sharp, which makes the scene look like a miniature model.
+
+use common::SeedFromEnv;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rpt::*;
+use std::sync::Arc;
+
+mod common;
+
+fn main() -> color_eyre::Result<()> {
+    color_eyre::install()?;
+
+    let mut scene = Scene::new();
+    let mut rng = StdRng::seed_from_u64(0);
+
+    scene.add(
+        Object::new(plane(glm::vec3(0.0, 1.0, 0.0), 0.0))
+            .material(Material::diffuse(hex_color(0x7CB342))),
+    );
+
+    // A grid of houses, with a tree beside each one
+    let roofs = [0xD84315, 0x6D4C41, 0x37474F, 0xC62828];
+    for i in -4..=4 {
+        for j in -8..=2 {
+            let (x, z) = (i as f64 * 3.0, j as f64 * 3.0);
+            let height = rng.gen_range(0.8..2.0);
+            scene.add(
+                Object::new(
+                    cube()
+                        .scale(&glm::vec3(1.4, height, 1.4))
+                        .translate(&glm::vec3(x, height / 2.0, z)),
+                )
+                .material(Material::diffuse(hex_color(0xECEFF1))),
+            );
+            scene.add(
+                Object::new(
+                    cone()
+                        .scale(&glm::vec3(1.1, 0.6, 1.1))
+                        .translate(&glm::vec3(x, height, z)),
+                )
+                .material(Material::diffuse(hex_color(roofs[rng.gen_range(0..4)]))),
+            );
+            scene.add(
+                Object::new(
+                    sphere()
+                        .scale(&glm::vec3(0.4, 0.4, 0.4))
+                        .translate(&glm::vec3(x + 1.2, 0.4, z + 1.2)),
+                )
+                .material(Material::diffuse(hex_color(0x2E7D32))),
+            );
+        }
+    }
+
+    scene.add(Light::Ambient(glm::vec3(0.1, 0.1, 0.1)));
+    scene.add(Light::Directional(
+        glm::vec3(1.0, 0.95, 0.85),
+        glm::vec3(-0.5, -1.0, -0.3).normalize(),
+    ));
+
+    let eye = glm::vec3(0.0, 14.0, 14.0);
+    let center = glm::vec3(0.0, 0.0, -6.0);
+    let camera = TiltShiftCamera::look_at(eye, center, glm::vec3(0.0, 1.0, 0.0), 0.9)
+        .focus(
+            center,
+            Some(Aperture {
+                scale: 0.25,
+                shape: ApertureShape::Circle,
+                inner_scale: 0.0,
+                rotation: 0.0,
+                offset: [0.0, 0.0],
+            }),
+        )
+        .tilt(0.002);
+
+    Renderer::new(&scene, Arc::new(camera))
+        .width(800)
+        .height(600)
+        .max_bounces(1)
+        .num_samples(64)
+        .seed_from_env()
+        .render()
+        .save("tilt_shift.png")?;
+
+    Ok(())
+}
@@ -7,8 +7,11 @@ use std::io::BufReader;
 use std::sync::Arc;
 use std::time::Instant;
 
+use common::SeedFromEnv;
 use rpt::*;
 
+mod common;
+
 fn rgb_to_color(rgb: Rgb<f32>) -> Color {
     glm::vec3(rgb.0[0] as f64, rgb.0[1] as f64, rgb.0[2] as f64)
 }
@@ -70,6 +73,7 @@ fn main() -> color_eyre::Result<()> {
         .height(1080)
         .max_bounces(6)
         .num_samples(1000)
+        .seed_from_env()
         .iterative_render(10, |iteration, buffer| {
             let millis = time.elapsed().as_millis();
             println!(
@@ -0,0 +1,84 @@
+use image::{GrayImage, ImageBuffer, ImageResult, RgbImage};
+
+use crate::color::{color_bytes, Color};
+
+/// Auxiliary per-pixel buffers ("arbitrary output variables") from the first hit of
+/// each primary ray, useful as guides for external denoisers
+///
+/// All buffers have `width * height` entries in row-major order. Pixels whose primary ray
+/// does not hit anything have infinite depth, a zero normal, and zero albedo.
+#[derive(Clone, Debug)]
+pub struct Aovs {
+    /// Width of the buffers
+    pub width: u32,
+
+    /// Height of the buffers
+    pub height: u32,
+
+    /// Distance along the primary ray to the first hit
+    pub depth: Vec<f32>,
+
+    /// World-space surface normal at the first hit
+    pub normal: Vec<[f32; 3]>,
+
+    /// Material color at the first hit
+    pub albedo: Vec<[f32; 3]>,
+}
+
+impl Aovs {
+    /// Visualize the depth buffer, with near hits bright and misses black
+    pub fn depth_image(&self) -> GrayImage {
+        let max_depth = self
+            .depth
+            .iter()
+            .copied()
+            .filter(|d| d.is_finite())
+            .fold(0.0, f32::max);
+        let buf = self
+            .depth
+            .iter()
+            .map(|&d| {
+                if d.is_finite() && max_depth > 0.0 {
+                    (255.0 * (1.0 - d / max_depth)).round() as u8
+                } else {
+                    0
+                }
+            })
+            .collect();
+        ImageBuffer::from_raw(self.width, self.height, buf)
+            .expect("Image buffer has incorrect size")
+    }
+
+    /// Visualize the normal buffer, mapping each component from [-1, 1] to [0, 255]
+    pub fn normal_image(&self) -> RgbImage {
+        let buf = self
+            .normal
+            .iter()
+            .flat_map(|n| n.iter().map(|c| ((c * 0.5 + 0.5) * 255.0).round() as u8))
+            .collect();
+        ImageBuffer::from_raw(self.width, self.height, buf)
+            .expect("Image buffer has incorrect size")
+    }
+
+    /// Visualize the albedo buffer, with sRGB gamma correction
+    pub fn albedo_image(&self) -> RgbImage {
+        let buf = self
+            .albedo
+            .iter()
+            .flat_map(|&[r, g, b]| {
+                let color: Color = glm::vec3(r.into(), g.into(), b.into());
+                color_bytes(&color)
+            })
+            .collect();
+        ImageBuffer::from_raw(self.width, self.height, buf)
+            .expect("Image buffer has incorrect size")
+    }
+
+    /// Save visualizations of each buffer to `{prefix}_depth.png`, `{prefix}_normal.png`,
+    /// and `{prefix}_albedo.png`
+    pub fn save(&self, prefix: &str) -> ImageResult<()> {
+        self.depth_image().save(format!("{}_depth.png", prefix))?;
+        self.normal_image().save(format!("{}_normal.png", prefix))?;
+        self.albedo_image().save(format!("{}_albedo.png", prefix))
+    }
+}
@@ -1,13 +1,41 @@
-use image::{ImageBuffer, RgbImage};
+use std::fs::File;
+use std::io::{self, prelude::*, BufReader, BufWriter};
+use std::path::Path;
 
-use crate::color::{color_bytes, Color};
+use image::{GrayImage, ImageBuffer, RgbImage};
+use rayon::prelude::*;
+
+use crate::aov::Aovs;
+use crate::camera::ApertureShape;
+use crate::color::{color_bytes, luminance, Color};
+
+/// Standard deviation of relative depth differences in guided filters
+const GUIDE_DEPTH_SIGMA: f64 = 0.05;
+
+/// Standard deviation of normal differences in guided filters
+const GUIDE_NORMAL_SIGMA: f64 = 0.2;
+
+/// Standard deviation of albedo differences in guided filters
+const GUIDE_ALBEDO_SIGMA: f64 = 0.1;
+
+/// Number of batches that each pass is split into for outlier rejection
+pub(crate) const OUTLIER_BATCHES: u32 = 16;
+
+/// Identifies checkpoint files written by `Buffer::save_checkpoint`
+const CHECKPOINT_MAGIC: &[u8; 8] = b"RPTCKPT1";
 
 /// A buffer that stores sample results from path tracing
 pub struct Buffer {
     width: u32,
     height: u32,
-    samples: Vec<Vec<Color>>,
+    /// Estimates added to each pixel, with the number of samples each one averages
+    samples: Vec<Vec<(Color, u32)>>,
+    taken: u32,
     filter: Filter,
+    glare: Option<Glare>,
+    bloom: Option<Bloom>,
+    tone_map: ToneMap,
+    guide: Option<Aovs>,
 }
 
 impl Buffer {
@@ -17,15 +45,52 @@ impl Buffer {
             width,
             height,
             samples: vec![vec![]; (width * height) as usize],
+            taken: 0,
             filter,
+            glare: None,
+            bloom: None,
+            tone_map: ToneMap::default(),
+            guide: None,
         }
     }
 
+    /// Set a glare post-process, applied when converting to an image (builder pattern)
+    pub fn glare(mut self, glare: Glare) -> Self {
+        self.glare = Some(glare);
+        self
+    }
+
+    /// Set a bloom post-process, applied when converting to an image (builder pattern)
+    pub fn bloom(mut self, bloom: Bloom) -> Self {
+        self.bloom = Some(bloom);
+        self
+    }
+
+    /// Set the tone-mapping operator used when converting to an image (builder pattern)
+    pub fn tone_map(mut self, tone_map: ToneMap) -> Self {
+        self.tone_map = tone_map;
+        self
+    }
+
+    /// Set auxiliary buffers that guide edge-preserving filters (builder pattern)
+    ///
+    /// Pixels with differing depths, normals, or albedos are not averaged together by
+    /// the `Bilateral` and `NonLocalMeans` filters, which keeps geometric and texture
+    /// edges sharp even when the colors are too noisy to detect them.
+    pub fn guide(mut self, aovs: Aovs) -> Self {
+        assert!(
+            aovs.width == self.width && aovs.height == self.height,
+            "Guide buffers have incorrect size"
+        );
+        self.guide = Some(aovs);
+        self
+    }
+
     /// Add a sample to the buffer, at a given pixel location
     pub fn add_sample(&mut self, x: u32, y: u32, sample: Color) {
         assert!(x < self.width && y < self.height, "Invalid pixel location");
         let index = (y * self.width + x) as usize;
-        self.samples[index].push(sample);
+        self.samples[index].push((sample, 1));
     }
 
     /// Add a uniform matrix of samples to the buffer
@@ -35,35 +100,195 @@ impl Buffer {
             "Invalid sample dimension"
         );
         for (index, sample) in samples.iter().enumerate() {
-            self.samples[index].push(*sample);
+            self.samples[index].push((*sample, 1));
+        }
+    }
+
+    /// Add a matrix of pixel estimates, each averaging `count` samples
+    ///
+    /// Estimates are weighted by their sample counts when the buffer is averaged, so that
+    /// passes of different lengths combine into the correct Monte Carlo estimate.
+    pub(crate) fn add_estimates(&mut self, estimates: &[Color], count: u32) {
+        assert!(
+            estimates.len() == (self.width * self.height) as usize,
+            "Invalid sample dimension"
+        );
+        for (index, estimate) in estimates.iter().enumerate() {
+            self.samples[index].push((*estimate, count));
+        }
+        self.taken += count;
+    }
+
+    /// Returns the width and height of the buffer, in pixels
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the number of samples per pixel that have been traced into the buffer
+    pub fn samples_taken(&self) -> u32 {
+        self.taken
+    }
+
+    /// Take the samples of another buffer, keeping the settings of this one
+    pub(crate) fn replace_samples(&mut self, other: Buffer) {
+        assert!(
+            other.width == self.width && other.height == self.height,
+            "Buffer has incorrect size"
+        );
+        self.samples = other.samples;
+        self.taken = other.taken;
+    }
+
+    /// Save the accumulated samples to a checkpoint file
+    ///
+    /// Unlike `image`, this stores the raw per-pixel estimates and their sample counts,
+    /// so that rendering can be resumed later with `Renderer::resume`. Filters and
+    /// post-processing settings are not saved.
+    pub fn save_checkpoint(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(CHECKPOINT_MAGIC)?;
+        for value in [self.width, self.height, self.taken] {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        for pix_samples in &self.samples {
+            writer.write_all(&(pix_samples.len() as u32).to_le_bytes())?;
+            for (color, count) in pix_samples {
+                writer.write_all(&count.to_le_bytes())?;
+                for value in color.iter() {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+        writer.flush()
+    }
+
+    /// Load a buffer from a checkpoint file written by `save_checkpoint`
+    ///
+    /// The buffer uses the default filter and tone mapping, which can be changed with
+    /// the builder methods. `Renderer::resume` applies the settings of the renderer.
+    pub fn load_checkpoint(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != CHECKPOINT_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a render checkpoint",
+            ));
+        }
+        let (width, height) = (read_u32(&mut reader)?, read_u32(&mut reader)?);
+        if width.checked_mul(height).is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Checkpoint dimensions are too large",
+            ));
+        }
+        let mut buffer = Buffer::new(width, height, Filter::default());
+        buffer.taken = read_u32(&mut reader)?;
+        for pix_samples in &mut buffer.samples {
+            let len = read_u32(&mut reader)?;
+            for _ in 0..len {
+                let count = read_u32(&mut reader)?;
+                let mut color = glm::vec3(0.0, 0.0, 0.0);
+                for value in color.iter_mut() {
+                    let mut bytes = [0; 8];
+                    reader.read_exact(&mut bytes)?;
+                    *value = f64::from_le_bytes(bytes);
+                }
+                pix_samples.push((color, count));
+            }
         }
+        Ok(buffer)
     }
 
     /// Converts the current buffer to an image
     pub fn image(&self) -> RgbImage {
         let mut buf = Vec::new();
-        for y in 0..self.height {
-            for x in 0..self.width {
-                let color = self.get_filtered_color(x, y);
-                let [r, g, b] = color_bytes(&color);
-                buf.push(r);
-                buf.push(g);
-                buf.push(b);
-            }
+        for color in self.colors() {
+            let [r, g, b] = color_bytes(&self.tone_map.apply(&color));
+            buf.push(r);
+            buf.push(g);
+            buf.push(b);
         }
         ImageBuffer::from_raw(self.width, self.height, buf)
             .expect("Image buffer has incorrect size")
     }
 
-    /// Return the average color variance of samples in each pixel
+    /// Return the filtered and post-processed linear colors, in row-major order
+    pub(crate) fn colors(&self) -> Vec<Color> {
+        let mut colors = match self.filter {
+            Filter::Box(radius) => {
+                let mut colors = Vec::with_capacity((self.width * self.height) as usize);
+                for y in 0..self.height {
+                    for x in 0..self.width {
+                        colors.push(self.box_filtered_color(x, y, radius));
+                    }
+                }
+                colors
+            }
+            Filter::Bilateral {
+                spatial_sigma,
+                range_sigma,
+            } => {
+                let radius = (2.0 * spatial_sigma).ceil() as u32;
+                let means = self.means();
+                self.denoise(&means, radius, |(x, y), (i, j)| {
+                    let (dx, dy) = (x as f64 - i as f64, y as f64 - j as f64);
+                    let color = (means[self.index(x, y)] - means[self.index(i, j)]).norm_squared();
+                    -(dx * dx + dy * dy) / (2.0 * spatial_sigma * spatial_sigma)
+                        - color / (2.0 * range_sigma * range_sigma)
+                })
+            }
+            Filter::NonLocalMeans {
+                search_radius,
+                patch_radius,
+                strength,
+            } => {
+                let means = self.means();
+                let patch = patch_radius as i64;
+                self.denoise(&means, search_radius, |(x, y), (i, j)| {
+                    // Mean squared difference between the patches around both pixels
+                    let (mut distance, mut count) = (0.0, 0);
+                    for dy in -patch..=patch {
+                        for dx in -patch..=patch {
+                            let p = self.clamped_index(x as i64 + dx, y as i64 + dy);
+                            let q = self.clamped_index(i as i64 + dx, j as i64 + dy);
+                            distance += (means[p] - means[q]).norm_squared();
+                            count += 1;
+                        }
+                    }
+                    -distance / count as f64 / (strength * strength)
+                })
+            }
+            Filter::OutlierReject { k } => self
+                .samples
+                .iter()
+                .map(|pix_samples| reject_outliers(pix_samples, k))
+                .collect(),
+        };
+        if let Some(ref bloom) = self.bloom {
+            bloom.apply(self.width, self.height, &mut colors);
+        }
+        if let Some(ref glare) = self.glare {
+            glare.apply(self.width, self.height, &mut colors);
+        }
+        colors
+    }
+
+    /// Return the average color variance of a single sample in each pixel
+    ///
+    /// This is estimated from the spread of the estimates added to each pixel, weighted
+    /// by their sample counts as in `pixel_variances`, so passes of different lengths
+    /// can be mixed.
     pub fn variance(&self) -> f64 {
         let mut variance = 0.0;
         let mut count = 0.0;
         for pix_samples in &self.samples {
-            let mean: Color = pix_samples.iter().sum::<Color>() / (pix_samples.len() as f64);
+            let total: u64 = pix_samples.iter().map(|&(_, count)| u64::from(count)).sum();
+            let mean = weighted_mean(pix_samples, total);
             let mut sum_of_squares = 0.0;
-            for sample in pix_samples {
-                sum_of_squares += (sample - mean).magnitude_squared();
+            for (sample, samples) in pix_samples {
+                sum_of_squares += f64::from(*samples) * (sample - mean).magnitude_squared();
             }
             // Sample variance: n - 1 degrees of freedom
             variance += sum_of_squares / (pix_samples.len() as f64 - 1.0);
@@ -72,32 +297,228 @@ impl Buffer {
         variance / count
     }
 
-    fn get_filtered_color(&self, x: u32, y: u32) -> Color {
-        match self.filter {
-            Filter::Box(radius) => {
+    /// Estimate the variance of the mean luminance of each pixel, in row-major order
+    ///
+    /// This measures the noise that remains in each pixel, which shrinks in proportion
+    /// to the number of samples. It is estimated from the spread of the estimates added
+    /// to the pixel, weighted by their sample counts, so pixels with fewer than two
+    /// estimates report zero.
+    pub fn pixel_variances(&self) -> Vec<f64> {
+        self.samples
+            .iter()
+            .map(|pix_samples| {
+                if pix_samples.len() < 2 {
+                    return 0.0;
+                }
+                let total: u64 = pix_samples.iter().map(|&(_, count)| u64::from(count)).sum();
+                let mean = luminance(&weighted_mean(pix_samples, total));
+                // Each estimate averages `count` samples, so its variance is that of a
+                // single sample divided by `count`
+                let sample_variance = pix_samples
+                    .iter()
+                    .map(|(color, count)| f64::from(*count) * (luminance(color) - mean).powi(2))
+                    .sum::<f64>()
+                    / (pix_samples.len() as f64 - 1.0);
+                sample_variance / total as f64
+            })
+            .collect()
+    }
+
+    /// Visualize where noise remains, with the variance of each pixel's mean (see
+    /// `pixel_variances`) scaled so that the noisiest pixel is white
+    pub fn variance_image(&self) -> GrayImage {
+        let variances = self.pixel_variances();
+        let max_variance = variances.iter().copied().fold(0.0, f64::max);
+        let buf = variances
+            .iter()
+            .map(|&v| {
+                if max_variance > 0.0 {
+                    (255.0 * v / max_variance).round() as u8
+                } else {
+                    0
+                }
+            })
+            .collect();
+        ImageBuffer::from_raw(self.width, self.height, buf)
+            .expect("Image buffer has incorrect size")
+    }
+
+    fn box_filtered_color(&self, x: u32, y: u32, radius: u32) -> Color {
+        let x_range = x.saturating_sub(radius)..=(x + radius).min(self.width - 1);
+        let y_range = y.saturating_sub(radius)..=(y + radius).min(self.height - 1);
+        let window = || {
+            x_range.clone().flat_map(|i| {
+                y_range
+                    .clone()
+                    .flat_map(move |j| &self.samples[self.index(i, j)])
+            })
+        };
+        let total: u64 = window().map(|&(_, count)| u64::from(count)).sum();
+        assert!(total != 0, "Pixel found with no samples");
+        weighted_mean(window(), total)
+    }
+
+    /// Average the samples of each pixel
+    fn means(&self) -> Vec<Color> {
+        self.samples
+            .iter()
+            .map(|pix_samples| {
+                let total: u64 = pix_samples.iter().map(|&(_, count)| u64::from(count)).sum();
+                assert!(total != 0, "Pixel found with no samples");
+                weighted_mean(pix_samples, total)
+            })
+            .collect()
+    }
+
+    /// Replace each pixel with a weighted average of its neighbors within a radius
+    ///
+    /// The `log_weight` of a pair of pixels is combined with the similarity of their
+    /// guide buffers, if any.
+    fn denoise<F>(&self, means: &[Color], radius: u32, log_weight: F) -> Vec<Color>
+    where
+        F: Fn((u32, u32), (u32, u32)) -> f64 + Sync,
+    {
+        (0..self.width * self.height)
+            .into_par_iter()
+            .map(|index| {
+                let (x, y) = (index % self.width, index / self.width);
                 let mut color = glm::vec3(0.0, 0.0, 0.0);
-                let mut count = 0;
-                for i in x.saturating_sub(radius)..=(x + radius) {
-                    for j in y.saturating_sub(radius)..=(y + radius) {
-                        if i < self.width && j < self.height {
-                            let index = (j * self.width + i) as usize;
-                            color += self.samples[index].iter().sum::<Color>();
-                            count += self.samples[index].len();
-                        }
+                let mut total = 0.0;
+                for j in y.saturating_sub(radius)..=(y + radius).min(self.height - 1) {
+                    for i in x.saturating_sub(radius)..=(x + radius).min(self.width - 1) {
+                        let other = self.index(i, j);
+                        let weight = (log_weight((x, y), (i, j))
+                            + self.guide_log_weight(index as usize, other))
+                        .exp();
+                        color += means[other] * weight;
+                        total += weight;
                     }
                 }
-                assert!(count != 0, "Pixel found with no samples");
-                color / (count as f64)
+                color / total
+            })
+            .collect()
+    }
+
+    /// Log-weight penalizing differences in the guide buffers between two pixels
+    fn guide_log_weight(&self, a: usize, b: usize) -> f64 {
+        let guide = match self.guide {
+            Some(ref guide) => guide,
+            None => return 0.0,
+        };
+        let (da, db) = (guide.depth[a] as f64, guide.depth[b] as f64);
+        let depth = if da.is_infinite() || db.is_infinite() {
+            if da == db {
+                0.0
+            } else {
+                f64::INFINITY
             }
-        }
+        } else {
+            ((da - db) / da.max(db).max(1e-9) / GUIDE_DEPTH_SIGMA).powi(2)
+        };
+        let difference = |u: &[f32; 3], v: &[f32; 3]| {
+            (0..3)
+                .map(|k| (u[k] - v[k]) as f64)
+                .map(|d| d * d)
+                .sum::<f64>()
+        };
+        let normal = difference(&guide.normal[a], &guide.normal[b]) / GUIDE_NORMAL_SIGMA.powi(2);
+        let albedo = difference(&guide.albedo[a], &guide.albedo[b]) / GUIDE_ALBEDO_SIGMA.powi(2);
+        -0.5 * (depth + normal + albedo)
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    fn clamped_index(&self, x: i64, y: i64) -> usize {
+        let x = x.clamp(0, self.width as i64 - 1) as u32;
+        let y = y.clamp(0, self.height as i64 - 1) as u32;
+        self.index(x, y)
     }
 }
 
+/// Average the estimates of a pixel, leaving out those that are more than `k` standard
+/// deviations in luminance from the mean of the others
+fn reject_outliers(estimates: &[(Color, u32)], k: f64) -> Color {
+    let everything = || {
+        let total: u64 = estimates.iter().map(|&(_, count)| u64::from(count)).sum();
+        assert!(total != 0, "Pixel found with no samples");
+        weighted_mean(estimates, total)
+    };
+    let n = estimates.len() as f64;
+    if estimates.len() < 3 {
+        return everything();
+    }
+    let luminances: Vec<f64> = estimates.iter().map(|(c, _)| luminance(c)).collect();
+    let sum: f64 = luminances.iter().sum();
+    let sum_of_squares: f64 = luminances.iter().map(|l| l * l).sum();
+    let kept: Vec<_> = estimates
+        .iter()
+        .zip(&luminances)
+        .filter(|&(_, &l)| {
+            // Statistics of the other estimates, so an outlier does not mask itself
+            let mean = (sum - l) / (n - 1.0);
+            let variance = ((sum_of_squares - l * l) - (n - 1.0) * mean * mean) / (n - 2.0);
+            (l - mean).abs() <= k * variance.max(0.0).sqrt()
+        })
+        .map(|(estimate, _)| *estimate)
+        .collect();
+    let total: u64 = kept.iter().map(|&(_, count)| u64::from(count)).sum();
+    if total == 0 {
+        return everything();
+    }
+    weighted_mean(&kept, total)
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Average estimates weighted by their sample counts, out of `total` samples
+fn weighted_mean<'a>(estimates: impl IntoIterator<Item = &'a (Color, u32)>, total: u64) -> Color {
+    estimates
+        .into_iter()
+        .map(|(color, count)| color * (f64::from(*count) / total as f64))
+        .sum()
+}
+
 /// A noise reduction filter applied to the rendered image
 #[derive(Copy, Clone)]
 pub enum Filter {
     /// Box filter with a given radius
     Box(u32),
+
+    /// Edge-preserving bilateral filter, which averages nearby pixels with similar colors
+    Bilateral {
+        /// Standard deviation of the spatial Gaussian, in pixels
+        spatial_sigma: f64,
+        /// Standard deviation of the Gaussian on color differences
+        range_sigma: f64,
+    },
+
+    /// Non-local means filter, which averages pixels whose surrounding patches look alike
+    NonLocalMeans {
+        /// Radius of the window searched for similar pixels
+        search_radius: u32,
+        /// Radius of the patches compared between pixels
+        patch_radius: u32,
+        /// Filtering strength, the scale of patch color differences that are averaged
+        strength: f64,
+    },
+
+    /// Firefly rejection, which leaves out estimates of a pixel that are more than `k`
+    /// standard deviations in luminance from the mean of its other estimates
+    ///
+    /// The renderer accumulates each pass in 16 separate batches, so that
+    /// a rare bright sample stands out in its batch. Unlike a hard clamp, the threshold
+    /// adapts to the noise of each pixel, but the result is still slightly biased, since
+    /// the rejected samples are legitimate and would eventually average out.
+    OutlierReject {
+        /// Number of standard deviations beyond which an estimate is rejected
+        k: f64,
+    },
 }
 
 impl Default for Filter {
@@ -106,3 +527,346 @@ impl Default for Filter {
         Self::Box(0)
     }
 }
+
+/// Operator mapping linear radiance to displayable values in [0, 1]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ToneMap {
+    /// Clamp each channel to [0, 1], saturating highlights
+    #[default]
+    Clamp,
+
+    /// Reinhard's `L / (1 + L)` curve, applied to the luminance to preserve hue
+    Reinhard,
+
+    /// Narkowicz's fit of the ACES filmic reference rendering transform
+    AcesFilmic,
+}
+
+impl ToneMap {
+    /// Apply the operator to a linear color
+    pub fn apply(&self, color: &Color) -> Color {
+        match self {
+            ToneMap::Clamp => *color,
+            ToneMap::Reinhard => {
+                let lum = luminance(color);
+                if lum > 0.0 {
+                    color / (1.0 + lum)
+                } else {
+                    *color
+                }
+            }
+            ToneMap::AcesFilmic => color.map(|x| {
+                let x = x.max(0.0);
+                x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)
+            }),
+        }
+    }
+}
+
+/// Diffraction glare post-process, which draws spikes out of bright highlights
+///
+/// The spike directions are derived from the straight edges of an aperture shape, so
+/// the glare matches the bokeh of the camera used in the render.
+#[derive(Clone, Debug)]
+pub struct Glare {
+    /// Luminance above which pixels produce glare
+    pub threshold: f64,
+
+    /// Length of each spike, in pixels
+    pub length: u32,
+
+    /// Fraction of the energy above the threshold that is spread into the spikes
+    pub intensity: f64,
+
+    /// Directions of the spikes, as angles in radians (counterclockwise from the x-axis)
+    pub spikes: Vec<f64>,
+}
+
+impl Glare {
+    /// Construct a glare post-process with spikes matching an aperture shape
+    pub fn new(shape: &ApertureShape, threshold: f64, length: u32, intensity: f64) -> Self {
+        Self {
+            threshold,
+            length,
+            intensity,
+            spikes: shape.spike_angles(),
+        }
+    }
+
+    /// Add glare to a row-major buffer of linear colors
+    fn apply(&self, width: u32, height: u32, colors: &mut [Color]) {
+        if self.spikes.is_empty() || self.length == 0 {
+            return;
+        }
+        // Quadratic falloff along each spike, normalized over all spikes
+        let falloff: Vec<f64> = (1..=self.length)
+            .map(|k| (1.0 - k as f64 / (self.length + 1) as f64).powi(2))
+            .collect();
+        let total = falloff.iter().sum::<f64>() * self.spikes.len() as f64;
+        let directions: Vec<(f64, f64)> = self.spikes.iter().map(|a| a.sin_cos()).collect();
+
+        let source = colors.to_vec();
+        for y in 0..height {
+            for x in 0..width {
+                let color = source[(y * width + x) as usize];
+                let lum = luminance(&color);
+                if lum <= self.threshold {
+                    continue;
+                }
+                let excess = color * (1.0 - self.threshold / lum) * self.intensity / total;
+                for &(sin, cos) in &directions {
+                    for (k, weight) in falloff.iter().enumerate() {
+                        let dist = (k + 1) as f64;
+                        // Image rows increase downwards
+                        let px = (x as f64 + dist * cos).round();
+                        let py = (y as f64 - dist * sin).round();
+                        if px < 0.0 || py < 0.0 || px >= width as f64 || py >= height as f64 {
+                            break;
+                        }
+                        colors[(py as u32 * width + px as u32) as usize] += excess * *weight;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Bloom post-process, which spreads the light of bright highlights into a soft glow
+#[derive(Copy, Clone, Debug)]
+pub struct Bloom {
+    /// Luminance above which pixels produce bloom
+    pub threshold: f64,
+
+    /// Standard deviation of the Gaussian glow, in pixels
+    pub radius: f64,
+
+    /// Fraction of the energy above the threshold that is spread into the glow
+    pub intensity: f64,
+}
+
+impl Bloom {
+    /// Spread bloom through a row-major buffer of linear colors
+    ///
+    /// Energy moves from each highlight into its glow, so the total is conserved,
+    /// except for the part of the glow that falls outside the image.
+    fn apply(&self, width: u32, height: u32, colors: &mut [Color]) {
+        if self.radius <= 0.0 || self.intensity <= 0.0 {
+            return;
+        }
+        let mut glow: Vec<Color> = colors
+            .iter_mut()
+            .map(|color| {
+                let lum = luminance(color);
+                if lum <= self.threshold {
+                    return glm::zero();
+                }
+                let excess = *color * (1.0 - self.threshold / lum) * self.intensity;
+                *color -= excess;
+                excess
+            })
+            .collect();
+
+        // Separable Gaussian blur, truncated at three standard deviations
+        let extent = (3.0 * self.radius).ceil() as i64;
+        let kernel: Vec<f64> = (-extent..=extent)
+            .map(|k| (-(k * k) as f64 / (2.0 * self.radius * self.radius)).exp())
+            .collect();
+        let total: f64 = kernel.iter().sum();
+        let kernel: Vec<f64> = kernel.iter().map(|weight| weight / total).collect();
+        let (w, h) = (width as i64, height as i64);
+        for (stride, len, lines) in [(1, w, h), (w, h, w)] {
+            let source = glow.clone();
+            for line in 0..lines {
+                // Start of the row or column, and the step between its pixels
+                let start = if stride == 1 { line * w } else { line };
+                for i in 0..len {
+                    let mut sum = glm::vec3(0.0, 0.0, 0.0);
+                    for (k, weight) in kernel.iter().enumerate() {
+                        let j = i + k as i64 - extent;
+                        if 0 <= j && j < len {
+                            sum += source[(start + j * stride) as usize] * *weight;
+                        }
+                    }
+                    glow[(start + i * stride) as usize] = sum;
+                }
+            }
+        }
+        for (color, glow) in colors.iter_mut().zip(glow) {
+            *color += glow;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn reinhard_keeps_highlights_below_white() {
+        let render = |tone_map| {
+            let mut buffer = Buffer::new(1, 1, Filter::default()).tone_map(tone_map);
+            buffer.add_sample(0, 0, glm::vec3(50.0, 50.0, 50.0));
+            buffer.image().get_pixel(0, 0).0
+        };
+        assert_eq!(render(ToneMap::Clamp), [255, 255, 255]);
+        let [r, g, b] = render(ToneMap::Reinhard);
+        assert!(r < 255 && r == g && g == b);
+
+        // ACES maps middle gray to roughly 0.27
+        let gray = ToneMap::AcesFilmic.apply(&glm::vec3(0.18, 0.18, 0.18));
+        assert!((gray.x - 0.267).abs() < 1e-3, "{}", gray.x);
+    }
+
+    #[test]
+    fn denoising_preserves_edges() {
+        // A dark left half and a bright right half, with noisy samples
+        let (width, height) = (24, 16);
+        let truth = |x: u32| if x < width / 2 { 0.2 } else { 0.8 };
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut noisy = |filter| {
+            let mut buffer = Buffer::new(width, height, filter);
+            for y in 0..height {
+                for x in 0..width {
+                    let value = truth(x) + rng.gen_range(-0.1..0.1);
+                    buffer.add_sample(x, y, glm::vec3(value, value, value));
+                }
+            }
+            buffer.colors()
+        };
+        let error = |colors: &[Color], columns: std::ops::Range<u32>| {
+            let mut sum = 0.0;
+            for y in 0..height {
+                for x in columns.clone() {
+                    sum += (colors[(y * width + x) as usize].x - truth(x)).powi(2);
+                }
+            }
+            sum / (height * columns.len() as u32) as f64
+        };
+
+        let unfiltered = error(&noisy(Filter::default()), 0..width);
+        for filter in [
+            Filter::Bilateral {
+                spatial_sigma: 2.0,
+                range_sigma: 0.15,
+            },
+            Filter::NonLocalMeans {
+                search_radius: 4,
+                patch_radius: 1,
+                strength: 0.15,
+            },
+        ] {
+            let colors = noisy(filter);
+            assert!(error(&colors, 0..width) < unfiltered / 3.0);
+            // The columns on either side of the edge keep their own values
+            let edge = width / 2;
+            assert!(error(&colors, edge - 1..edge + 1) < 0.01);
+        }
+    }
+
+    #[test]
+    fn outlier_rejection_removes_fireflies() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut buffer = Buffer::new(2, 1, Filter::OutlierReject { k: 4.0 });
+        for i in 0..16 {
+            let a = 0.5 + rng.gen_range(-0.05..0.05);
+            let b = 0.5 + rng.gen_range(-0.05..0.05);
+            let firefly = if i == 5 { 500.0 } else { a };
+            buffer.add_estimates(&[glm::vec3(firefly, a, a), glm::vec3(b, b, b)], 4);
+        }
+        let colors = buffer.colors();
+        assert!((colors[0].x - 0.5).abs() < 0.05, "{}", colors[0].x);
+        // Without an outlier, every estimate is kept
+        let mean = buffer.samples[1].iter().map(|s| s.0).sum::<Color>() / 16.0;
+        assert!((colors[1] - mean).norm() < 1e-12);
+    }
+
+    #[test]
+    fn bloom_spreads_bright_pixels() {
+        let (width, height) = (41, 41);
+        let mut buffer = Buffer::new(width, height, Filter::default()).bloom(Bloom {
+            threshold: 1.0,
+            radius: 3.0,
+            intensity: 0.5,
+        });
+        let mut colors = vec![glm::vec3(0.1, 0.1, 0.1); (width * height) as usize];
+        colors[20 * 41 + 20] = glm::vec3(100.0, 100.0, 100.0);
+        buffer.add_samples(&colors);
+
+        let bloomed = buffer.colors();
+        let total = |colors: &[Color]| colors.iter().map(|c| c.x).sum::<f64>();
+        assert!((total(&bloomed) - total(&colors)).abs() < 1e-6 * total(&colors));
+        // Half of the energy above the threshold leaves the highlight, and the glow is
+        // more than half its peak brightness one radius away
+        let center = bloomed[20 * 41 + 20].x;
+        assert!(center > 50.5 && center < 52.0, "{}", center);
+        assert!(bloomed[20 * 41 + 23].x > 0.4 && bloomed[23 * 41 + 20].x > 0.4);
+        assert!(bloomed[0].x == 0.1);
+    }
+
+    #[test]
+    fn variance_image_shows_remaining_noise() {
+        // A flat region, and an edge pixel where samples land on either side at random
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut buffer = Buffer::new(3, 1, Filter::default());
+        let mut edge_variance = |buffer: &mut Buffer, samples: u32| {
+            for _ in 0..samples {
+                let edge = if rng.gen::<bool>() { 1.0 } else { 0.0 };
+                buffer.add_estimates(
+                    &[
+                        glm::vec3(0.5, 0.5, 0.5),
+                        glm::vec3(0.5, 0.5, 0.5),
+                        glm::vec3(edge, edge, edge),
+                    ],
+                    1,
+                );
+            }
+            buffer.pixel_variances()[2]
+        };
+        let early = edge_variance(&mut buffer, 64);
+        let late = edge_variance(&mut buffer, 960);
+        // The variance of the mean shrinks with the number of samples, from 1/4 per sample
+        assert!((late * 1024.0 - 0.25).abs() < 0.02, "{}", late * 1024.0);
+        assert!(late < early / 8.0);
+
+        let image = buffer.variance_image();
+        assert_eq!(image.get_pixel(0, 0).0, [0]);
+        assert_eq!(image.get_pixel(1, 0).0, [0]);
+        assert_eq!(image.get_pixel(2, 0).0, [255]);
+    }
+
+    #[test]
+    fn variance_weights_estimates_by_sample_count() {
+        // The same samples, added one at a time or in passes of different lengths
+        let mut rng = StdRng::seed_from_u64(0);
+        let samples: Vec<f64> = (0..2048).map(|_| rng.gen()).collect();
+        let mut single = Buffer::new(1, 1, Filter::default());
+        for &value in &samples {
+            single.add_estimates(&[glm::vec3(value, value, value)], 1);
+        }
+        let mut passes = Buffer::new(1, 1, Filter::default());
+        // Alternate passes of one and three samples
+        for pass in samples.chunks(4).flat_map(|c| [&c[..1], &c[1..]]) {
+            let mean = pass.iter().sum::<f64>() / pass.len() as f64;
+            passes.add_estimates(&[glm::vec3(mean, mean, mean)], pass.len() as u32);
+        }
+        // Uniform samples have a variance of 1/12 in each channel
+        let (a, b) = (single.variance(), passes.variance());
+        assert!((a - 0.25).abs() < 0.02, "{}", a);
+        assert!((b - 0.25).abs() < 0.03, "{}", b);
+    }
+
+    #[test]
+    fn corrupt_checkpoint_dimensions_are_rejected() {
+        let path = std::env::temp_dir().join("rpt_corrupt_checkpoint.bin");
+        let mut bytes = CHECKPOINT_MAGIC.to_vec();
+        for value in [u32::MAX, 2, 0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        std::fs::write(&path, bytes).unwrap();
+        let error = Buffer::load_checkpoint(&path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
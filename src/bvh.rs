@@ -0,0 +1,220 @@
+use crate::kdtree::BoundingBox;
+use crate::shape::{HitRecord, Ray};
+
+/// Estimated cost of traversing one interior node, relative to `INTERSECT_COST`
+const TRAVERSAL_COST: f64 = 1.0;
+
+/// Estimated cost of intersecting a ray with one object
+const INTERSECT_COST: f64 = 80.0;
+
+/// Number of buckets used to approximate the surface area heuristic
+const NUM_BUCKETS: usize = 12;
+
+/// Maximum number of objects in a leaf that will not be split further
+const MAX_LEAF_SIZE: usize = 4;
+
+/// A bounding volume hierarchy over a set of indexed bounding boxes
+///
+/// Unlike `KdTree`, this does not own the objects it accelerates. Each leaf stores the
+/// indices of its objects, and the caller is asked to intersect them during traversal.
+/// This lets the hierarchy be built over things that are not shapes themselves, such as
+/// the objects of a `Scene`. Construction uses a bucketed surface area heuristic.
+#[derive(Clone, Debug)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct BvhNode {
+    bounds: BoundingBox,
+    kind: BvhNodeKind,
+}
+
+#[derive(Clone, Debug)]
+enum BvhNodeKind {
+    /// Range `start..start + count` into the index buffer
+    Leaf(usize, usize),
+    /// Split axis and position of the second child (the first child follows directly)
+    Interior(usize, usize),
+}
+
+impl Bvh {
+    /// Construct a new hierarchy from a collection of indices and their bounding boxes
+    pub fn new(items: Vec<(usize, BoundingBox)>) -> Self {
+        let mut bvh = Self {
+            nodes: Vec::with_capacity(2 * items.len()),
+            indices: Vec::with_capacity(items.len()),
+        };
+        if !items.is_empty() {
+            let mut items: Vec<_> = items
+                .into_iter()
+                .map(|(index, bbox)| (index, bbox, (bbox.p_min + bbox.p_max) / 2.0))
+                .collect();
+            bvh.construct(&mut items);
+        }
+        bvh
+    }
+
+    /// Returns the number of indices stored in the hierarchy
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Returns true if the hierarchy contains no indices
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Intersect a ray with the hierarchy, for `t >= t_min`
+    ///
+    /// The callback is invoked with the index of every object whose bounding box might
+    /// contain a closer hit than `record`, and should intersect it in the same way as
+    /// `Shape::intersect`. Returns the index of the closest hit, if one was found.
+    pub fn intersect<F>(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        record: &mut HitRecord,
+        mut intersect: F,
+    ) -> Option<usize>
+    where
+        F: FnMut(usize, &mut HitRecord) -> bool,
+    {
+        let mut closest = None;
+        if self.nodes.is_empty() {
+            return closest;
+        }
+        let mut stack = vec![0];
+        while let Some(node) = stack.pop() {
+            let BvhNode { bounds, kind } = &self.nodes[node];
+            let (b_min, b_max) = bounds.intersect(ray);
+            if f64::max(b_min, t_min) > f64::min(b_max, record.time) {
+                continue;
+            }
+            match *kind {
+                BvhNodeKind::Leaf(start, count) => {
+                    for &index in &self.indices[start..start + count] {
+                        if intersect(index, record) {
+                            closest = Some(index);
+                        }
+                    }
+                }
+                BvhNodeKind::Interior(axis, second) => {
+                    // Visit the nearer child first, so that its hits can prune the other
+                    if ray.dir[axis] < 0.0 {
+                        stack.push(node + 1);
+                        stack.push(second);
+                    } else {
+                        stack.push(second);
+                        stack.push(node + 1);
+                    }
+                }
+            }
+        }
+        closest
+    }
+
+    /// Recursively build the subtree over `items`, returning the position of its root
+    fn construct(&mut self, items: &mut [(usize, BoundingBox, glm::DVec3)]) -> usize {
+        let bounds = items
+            .iter()
+            .fold(BoundingBox::default(), |b, item| b.merge(&item.1));
+        let node = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds,
+            kind: BvhNodeKind::Leaf(self.indices.len(), items.len()),
+        });
+
+        let (axis, mid) = match split(items, &bounds) {
+            Some(split) => split,
+            None => {
+                self.indices.extend(items.iter().map(|item| item.0));
+                return node;
+            }
+        };
+        let (left, right) = items.split_at_mut(mid);
+        self.construct(left);
+        let second = self.construct(right);
+        self.nodes[node].kind = BvhNodeKind::Interior(axis, second);
+        node
+    }
+}
+
+/// Choose a split of `items` by the surface area heuristic, partitioning them in place
+///
+/// Returns the split axis and the number of items on the first side, or `None` if the
+/// items should be kept together in a leaf.
+fn split(
+    items: &mut [(usize, BoundingBox, glm::DVec3)],
+    bounds: &BoundingBox,
+) -> Option<(usize, usize)> {
+    let n = items.len();
+    if n <= MAX_LEAF_SIZE {
+        return None;
+    }
+    let centroids = items.iter().fold(BoundingBox::default(), |b, item| {
+        b.merge(&BoundingBox {
+            p_min: item.2,
+            p_max: item.2,
+        })
+    });
+    let extent = centroids.p_max - centroids.p_min;
+    let axis = extent.imax();
+    if extent[axis] <= 0.0 {
+        // All centroids coincide, so no split can separate the items
+        return None;
+    }
+
+    let bucket = |centroid: &glm::DVec3| {
+        let offset = (centroid[axis] - centroids.p_min[axis]) / extent[axis];
+        ((offset * NUM_BUCKETS as f64) as usize).min(NUM_BUCKETS - 1)
+    };
+    let mut counts = [0; NUM_BUCKETS];
+    let mut boxes = [BoundingBox::default(); NUM_BUCKETS];
+    for item in items.iter() {
+        let b = bucket(&item.2);
+        counts[b] += 1;
+        boxes[b] = boxes[b].merge(&item.1);
+    }
+
+    // Sweep from both ends to evaluate the cost of splitting after each bucket
+    let inv_area = bounds.surface_area().recip();
+    let mut below = [(0, BoundingBox::default()); NUM_BUCKETS];
+    let mut acc = (0, BoundingBox::default());
+    for b in 0..NUM_BUCKETS {
+        acc = (acc.0 + counts[b], acc.1.merge(&boxes[b]));
+        below[b] = acc;
+    }
+    let mut best: Option<(f64, usize)> = None;
+    let mut above = (0, BoundingBox::default());
+    for b in (0..NUM_BUCKETS - 1).rev() {
+        above = (above.0 + counts[b + 1], above.1.merge(&boxes[b + 1]));
+        let (count_below, box_below) = below[b];
+        if count_below == 0 || above.0 == 0 {
+            continue;
+        }
+        let cost = TRAVERSAL_COST
+            + INTERSECT_COST
+                * inv_area
+                * (box_below.surface_area() * count_below as f64
+                    + above.1.surface_area() * above.0 as f64);
+        if best.is_none_or(|(best_cost, _)| cost < best_cost) {
+            best = Some((cost, b));
+        }
+    }
+
+    let (cost, b) = best?;
+    if cost >= INTERSECT_COST * n as f64 {
+        // No split is cheaper than intersecting everything here
+        return None;
+    }
+    let mut mid = 0;
+    for i in 0..n {
+        if bucket(&items[i].2) <= b {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    Some((axis, mid))
+}
@@ -0,0 +1,143 @@
+use super::{assert_valid, build_basis, PinholeCamera};
+
+/// Distance to the near clipping plane of exported cameras, in world units
+const GLTF_ZNEAR: f64 = 0.01;
+
+/// Parameters of a glTF 2.0 perspective camera, with the transform of the node it is
+/// attached to
+///
+/// A glTF camera looks down the local -z axis of its node, with +y up and +x to the
+/// right. glTF has no depth of field, so the focus and aperture of a camera are not
+/// exported.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GltfCamera {
+    /// Vertical field of view, in radians
+    pub yfov: f64,
+
+    /// Width of the image divided by its height
+    pub aspect_ratio: f64,
+
+    /// Distance to the near clipping plane
+    pub znear: f64,
+
+    /// Local-to-world transform of the camera node, in column-major order
+    pub matrix: [f64; 16],
+}
+
+impl GltfCamera {
+    /// Field of view in the longer direction of the image, in radians, as used by
+    /// `PinholeCamera`
+    pub fn fov(&self) -> f64 {
+        2.0 * ((self.yfov / 2.0).tan() * self.aspect_ratio.max(1.0)).atan()
+    }
+
+    /// Write a minimal glTF document whose only node is this camera, which tools such as
+    /// Blender can import directly
+    pub fn to_json(&self) -> String {
+        let matrix: Vec<String> = self.matrix.iter().map(|x| x.to_string()).collect();
+        format!(
+            concat!(
+                r#"{{"asset":{{"version":"2.0"}},"scene":0,"scenes":[{{"nodes":[0]}}],"#,
+                r#""nodes":[{{"camera":0,"matrix":[{}]}}],"#,
+                r#""cameras":[{{"type":"perspective","perspective":"#,
+                r#"{{"yfov":{},"aspectRatio":{},"znear":{}}}}}]}}"#
+            ),
+            matrix.join(","),
+            self.yfov,
+            self.aspect_ratio,
+            self.znear
+        )
+    }
+}
+
+impl PinholeCamera {
+    /// Convert to a glTF perspective camera, for an image with the given aspect ratio
+    /// (width divided by height)
+    ///
+    /// The field of view of this camera spans the longer side of the image, while glTF
+    /// uses the vertical field of view, so the two only agree for portrait and square images.
+    /// glTF has no lens distortion, so that is not exported either.
+    pub fn to_gltf_camera(&self, aspect_ratio: f64) -> GltfCamera {
+        assert!(aspect_ratio > 0.0, "Aspect ratio must be positive");
+        let yfov = 2.0 * ((self.fov / 2.0).tan() / aspect_ratio.max(1.0)).atan();
+        let (right, up) = build_basis(&self.direction, &self.up);
+        let back = -self.direction.normalize();
+        #[rustfmt::skip]
+        let matrix = [
+            right.x, right.y, right.z, 0.0,
+            up.x, up.y, up.z, 0.0,
+            back.x, back.y, back.z, 0.0,
+            self.eye.x, self.eye.y, self.eye.z, 1.0,
+        ];
+        GltfCamera {
+            yfov,
+            aspect_ratio,
+            znear: GLTF_ZNEAR,
+            matrix,
+        }
+    }
+
+    /// Construct a camera from a glTF perspective camera and its node transform
+    ///
+    /// The camera has no depth of field or distortion. Any scale in the node transform
+    /// is ignored.
+    pub fn from_gltf_camera(camera: &GltfCamera) -> Self {
+        let m = &camera.matrix;
+        let direction = -glm::vec3(m[8], m[9], m[10]).normalize();
+        let up = glm::vec3(m[4], m[5], m[6]);
+        let up = (up - up.dot(&direction) * direction).normalize();
+        let result = Self {
+            eye: glm::vec3(m[12], m[13], m[14]),
+            direction,
+            up,
+            fov: camera.fov(),
+            focal_distance: 0.0,
+            aperture: None,
+            distortion: Default::default(),
+        };
+        assert_valid(&result);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::{Camera, SampleCursor};
+
+    #[test]
+    fn gltf_camera_round_trip() {
+        let camera = PinholeCamera::look_at(
+            glm::vec3(1.0, 2.0, 3.0),
+            glm::vec3(-2.0, 0.5, -4.0),
+            glm::vec3(0.1, 1.0, 0.0),
+            0.8,
+        );
+
+        // For portrait and square images, the longer side is vertical
+        for aspect_ratio in [0.5, 1.0] {
+            let gltf = camera.to_gltf_camera(aspect_ratio);
+            assert!((gltf.yfov - 0.8).abs() < 1e-12);
+            assert!((gltf.fov() - 0.8).abs() < 1e-12);
+        }
+        let gltf = camera.to_gltf_camera(16.0 / 9.0);
+        assert!(gltf.yfov < 0.8);
+        assert!((gltf.fov() - 0.8).abs() < 1e-12);
+
+        // The imported camera casts the same rays
+        let imported = PinholeCamera::from_gltf_camera(&gltf);
+        let mut rng = StdRng::seed_from_u64(0);
+        for (x, y) in [(0.0, 0.0), (1.0, -0.5), (-0.3, 0.4)] {
+            let (a, ..) = camera.cast_ray(x, y, 0.0, &SampleCursor::random(), &mut rng);
+            let (b, ..) = imported.cast_ray(x, y, 0.0, &SampleCursor::random(), &mut rng);
+            assert!((a.origin - b.origin).norm() < 1e-12);
+            assert!((a.dir - b.dir).norm() < 1e-12);
+        }
+
+        let json = gltf.to_json();
+        assert!(json.contains(&format!(r#""yfov":{}"#, gltf.yfov)));
+        assert!(json.starts_with(r#"{"asset":{"version":"2.0"}"#) && json.ends_with("}]}"));
+    }
+}
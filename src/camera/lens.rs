@@ -23,10 +23,68 @@ pub struct LensSurface {
     pub thickness: f64,
     /// Aperture
     pub aperture: Aperture,
-    /// Element index of refraction for sodium `d` line
-    pub n_d: Option<f64>,
-    /// V number, characterizing dispersion.
-    pub v_no: f64,
+    /// Dispersion of the element behind this surface, or `None` for the imaging medium
+    pub dispersion: Option<Dispersion>,
+    /// Aspheric departure from the sphere given by `radius`, or `None` for a spherical
+    /// surface
+    pub asphere: Option<Asphere>,
+}
+
+/// Even-asphere profile of a lens surface
+///
+/// The sag, or axial depth of the surface at a distance `r` from the axis, is
+/// `c r² / (1 + sqrt(1 - (1 + k) c² r²)) + Σ Aᵢ r²ⁱ⁺⁴`, where `c` is the curvature
+/// `1 / radius` of the surface.
+#[derive(Clone, Debug, Default)]
+pub struct Asphere {
+    /// Conic constant `k`: 0 for a sphere, -1 for a paraboloid
+    pub conic: f64,
+    /// Polynomial coefficients `Aᵢ` of r⁴, r⁶, r⁸, ...
+    pub coefficients: Vec<f64>,
+}
+
+/// A model for how the refractive index of a lens element varies with wavelength
+#[derive(Clone, Debug)]
+pub enum Dispersion {
+    /// Linear interpolation from the index at the sodium `d` line, using the Abbe number.
+    Abbe {
+        /// Index of refraction for sodium `d` line
+        n_d: f64,
+        /// V number, characterizing dispersion.
+        v_no: f64,
+    },
+    /// Sellmeier equation, n² = 1 + Σ Bᵢλ² / (λ² - Cᵢ), with λ in micrometers.
+    ///
+    /// The coefficients can be taken directly from glass catalogs, such as Schott or Ohara.
+    Sellmeier {
+        /// B coefficients
+        b: [f64; 3],
+        /// C coefficients, in square micrometers
+        c: [f64; 3],
+    },
+}
+
+impl Dispersion {
+    /// Coefficients for Schott N-BK7 borosilicate crown glass.
+    pub const BK7: Dispersion = Dispersion::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+
+    /// The index of refraction at the given wavelength, in meters.
+    pub fn n(&self, wavelength: f64) -> f64 {
+        match *self {
+            Dispersion::Abbe { n_d, v_no } => {
+                let k = (n_d - 1.) / (v_no * (WAVELENGTH_F_LINE - WAVELENGTH_C_LINE));
+                n_d + k * (wavelength - WAVELENGTH_D_LINE)
+            }
+            Dispersion::Sellmeier { b, c } => {
+                let l2 = (wavelength * 1e6).powi(2);
+                let sum: f64 = b.iter().zip(&c).map(|(b, c)| b * l2 / (l2 - c)).sum();
+                (1. + sum).sqrt()
+            }
+        }
+    }
 }
 
 /// A lens system
@@ -47,6 +105,12 @@ pub trait Lens: Send + Sync {
     ///
     /// If the lens does not support this distance, returns the best valid lens configuration.
     fn lens_system(&self, object_distance: f64) -> LensSystem;
+
+    /// Open up or stop down the aperture to an f-number, the ratio of the focal length to
+    /// the diameter of the aperture.
+    ///
+    /// Lenses without an adjustable aperture ignore this.
+    fn set_f_number(&mut self, _f_number: f64) {}
 }
 
 /// A single lens
@@ -78,6 +142,9 @@ impl Default for SingleLens {
             aperture: Aperture {
                 scale: 0.035,
                 shape: ApertureShape::Circle,
+                inner_scale: 0.0,
+                rotation: 0.0,
+                offset: [0.0, 0.0],
             },
             thickness: 0.01,
             n_d: 1.8,
@@ -102,6 +169,10 @@ impl Lens for SingleLens {
     fn focus_min(&self) -> Option<f64> {
         Some(4. * self.focal_length())
     }
+
+    fn set_f_number(&mut self, f_number: f64) {
+        self.aperture.scale = self.focal_length() / (2. * f_number);
+    }
     fn lens_system(&self, object_distance: f64) -> LensSystem {
         let object_distance = object_distance.max(4. * self.focal_length());
         let a = 1.;
@@ -129,15 +200,18 @@ impl Lens for SingleLens {
                     radius: self.r1,
                     thickness: self.thickness,
                     aperture: self.aperture.clone(),
-                    n_d: Some(self.n_d),
-                    v_no: self.v_no,
+                    dispersion: Some(Dispersion::Abbe {
+                        n_d: self.n_d,
+                        v_no: self.v_no,
+                    }),
+                    asphere: None,
                 },
                 LensSurface {
                     radius: -self.r2,
                     thickness: image_distance - self.thickness / 2.,
                     aperture: self.aperture.clone(),
-                    n_d: None,
-                    v_no: 0.0,
+                    dispersion: None,
+                    asphere: None,
                 },
             ],
         }
@@ -147,15 +221,104 @@ impl Lens for SingleLens {
 impl LensSurface {
     /// The index of refraction at the given wavelength.
     pub fn n(&self, wavelength: f64) -> Option<f64> {
-        if let Some(n_d) = self.n_d {
-            let k = (n_d - 1.) / (self.v_no * (WAVELENGTH_F_LINE - WAVELENGTH_C_LINE));
-            Some(n_d + k * (wavelength - WAVELENGTH_D_LINE))
-        } else {
-            None
+        self.dispersion.as_ref().map(|d| d.n(wavelength))
+    }
+
+    /// Depth of the surface toward the image at distance `r` from the axis, relative to
+    /// its vertex, or `None` beyond the extent of the surface.
+    pub fn sag(&self, r: f64) -> Option<f64> {
+        self.sag_slope(r).map(|(sag, _)| sag)
+    }
+
+    /// The sag and its derivative with respect to `r`.
+    fn sag_slope(&self, r: f64) -> Option<(f64, f64)> {
+        let c = self.radius.recip();
+        let k = self.asphere.as_ref().map_or(0., |a| a.conic);
+        let root = 1. - (1. + k) * c * c * r * r;
+        if root < 0. {
+            return None;
+        }
+        let root = root.sqrt();
+        let mut sag = c * r * r / (1. + root);
+        let mut slope = c * r / root;
+        if let Some(asphere) = &self.asphere {
+            for (i, a) in asphere.coefficients.iter().enumerate() {
+                let power = 2 * i as i32 + 4;
+                sag += a * r.powi(power);
+                slope += a * power as f64 * r.powi(power - 1);
+            }
         }
+        Some((sag, slope))
+    }
+
+    /// Intersect a ray with the surface, given the position of its vertex and the
+    /// direction of the optical axis toward the object.
+    ///
+    /// The ray may travel in either direction along the axis. Returns the point of
+    /// intersection and the surface normal there, which may face either way along the
+    /// axis.
+    pub fn intersect(
+        &self,
+        origin: &glm::DVec3,
+        dir: &glm::DVec3,
+        vertex: &glm::DVec3,
+        axis: &glm::DVec3,
+    ) -> Option<(glm::DVec3, glm::DVec3)> {
+        if self.asphere.is_none() {
+            // Closed-form intersection with the sphere
+            let center = vertex - self.radius * axis;
+            let a = dir.dot(dir);
+            let v = origin - center;
+            let b = 2. * v.dot(dir);
+            let c = v.dot(&v) - self.radius * self.radius;
+            let discriminant = b * b - 4. * a * c;
+            if discriminant < 0. {
+                return None;
+            }
+            // Of the two intersections with the sphere, the one on the lens is the far
+            // one for a convex surface seen from behind, and the near one otherwise
+            let sign = if (self.radius < 0.) != (dir.dot(axis) < 0.) {
+                -1.
+            } else {
+                1.
+            };
+            let t = (-b + sign * discriminant.sqrt()) / 2. / a;
+            let point = origin + dir * t;
+            return Some((point, (point - center).normalize()));
+        }
+
+        // Newton's method on the signed axial distance from the surface, starting from
+        // the plane of the vertex
+        let axial_speed = dir.dot(axis);
+        let mut t = (vertex - origin).dot(axis) / axial_speed;
+        for _ in 0..MAX_ASPHERE_ITERATIONS {
+            let point = origin + dir * t;
+            let offset = point - vertex;
+            let transverse = offset - offset.dot(axis) * axis;
+            let r = transverse.norm();
+            let (sag, slope) = self.sag_slope(r)?;
+            let error = offset.dot(axis) + sag;
+            let radial = if r > 0. { transverse / r } else { transverse };
+            let normal = axis + slope * radial;
+            if error.abs() < ASPHERE_TOLERANCE {
+                return Some((point, normal.normalize()));
+            }
+            let derivative = normal.dot(dir);
+            if derivative == 0. {
+                return None;
+            }
+            t -= error / derivative;
+        }
+        None
     }
 }
 
+/// Maximum Newton iterations when intersecting an aspheric surface.
+const MAX_ASPHERE_ITERATIONS: usize = 32;
+
+/// Axial distance within which a ray is considered to hit an aspheric surface.
+const ASPHERE_TOLERANCE: f64 = 1e-12;
+
 /// An achromatic doublet.
 ///
 /// Lens 1: positive (convex, high vno, low n).
@@ -216,6 +379,9 @@ impl Default for AchromaticDoubletParams {
             aperture: Aperture {
                 scale: 0.100,
                 shape: ApertureShape::Circle,
+                inner_scale: 0.0,
+                rotation: 0.0,
+                offset: [0.0, 0.0],
             },
             r1: 4.,
         }
@@ -265,6 +431,10 @@ impl Lens for AchromaticDoublet {
         None
     }
 
+    fn set_f_number(&mut self, f_number: f64) {
+        self.aperture.scale = self.focal_length() / (2. * f_number);
+    }
+
     fn lens_system(&self, object_distance: f64) -> LensSystem {
         let object_distance = object_distance.max(4. * self.focal_length());
         let a = 1.;
@@ -292,24 +462,81 @@ impl Lens for AchromaticDoublet {
                     radius: self.r1,
                     thickness: self.thickness,
                     aperture: self.aperture.clone(),
-                    n_d: Some(self.n1),
-                    v_no: self.v1,
+                    dispersion: Some(Dispersion::Abbe {
+                        n_d: self.n1,
+                        v_no: self.v1,
+                    }),
+                    asphere: None,
                 },
                 LensSurface {
                     radius: -self.r2,
                     thickness: self.thickness,
                     aperture: self.aperture.clone(),
-                    n_d: Some(self.n2),
-                    v_no: self.v2,
+                    dispersion: Some(Dispersion::Abbe {
+                        n_d: self.n2,
+                        v_no: self.v2,
+                    }),
+                    asphere: None,
                 },
                 LensSurface {
                     radius: self.r3,
                     thickness: image_distance - self.thickness,
                     aperture: self.aperture.clone(),
-                    n_d: None,
-                    v_no: 0.0,
+                    dispersion: None,
+                    asphere: None,
                 },
             ],
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn spherical_asphere_matches_sphere() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let axis = glm::vec3(0., 0., 1.);
+        let vertex = glm::vec3(0., 0., 0.3);
+        for &radius in &[4., -4., 0.5] {
+            let sphere = LensSurface {
+                radius,
+                thickness: 0.01,
+                aperture: Aperture {
+                    scale: 0.2,
+                    shape: ApertureShape::Circle,
+                    inner_scale: 0.0,
+                    rotation: 0.0,
+                    offset: [0.0, 0.0],
+                },
+                dispersion: None,
+                asphere: None,
+            };
+            let asphere = LensSurface {
+                asphere: Some(Asphere::default()),
+                ..sphere.clone()
+            };
+            for _ in 0..100 {
+                let origin = glm::vec3(rng.gen_range(-0.1..0.1), rng.gen_range(-0.1..0.1), 0.);
+                let dir = glm::vec3(rng.gen_range(-0.2..0.2), rng.gen_range(-0.2..0.2), 1.);
+                let (p1, n1) = sphere.intersect(&origin, &dir, &vertex, &axis).unwrap();
+                let (p2, n2) = asphere.intersect(&origin, &dir, &vertex, &axis).unwrap();
+                assert!(glm::distance(&p1, &p2) < 1e-9);
+                assert!((n1.dot(&n2).abs() - 1.).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn sellmeier_bk7() {
+        // Published indices for N-BK7 at the F, D, and C lines (using the sodium D line
+        // rather than the helium d line, where n_d = 1.51680)
+        let bk7 = Dispersion::BK7;
+        assert!((bk7.n(WAVELENGTH_F_LINE) - 1.52238).abs() < 5e-5);
+        assert!((bk7.n(WAVELENGTH_D_LINE) - 1.51673).abs() < 5e-5);
+        assert!((bk7.n(WAVELENGTH_C_LINE) - 1.51432).abs() < 5e-5);
+    }
+}
@@ -1,20 +1,250 @@
+mod gltf;
 pub mod lens;
+mod tilt_shift;
 
-use crate::camera::lens::{Lens, LensSystem};
+use crate::camera::lens::{Lens, LensSurface, LensSystem};
 use crate::lens::IMAGING_MEDIUM_N_D;
-use crate::{Color, SRGB_GAMMA};
+use crate::material::{fresnel_dielectric, refract};
+use crate::sampler::{sample_disc, SampleCursor};
+use crate::{wavelength_to_xyz, xyz_to_rgb, Color, SRGB_GAMMA};
 use glm::vec3;
+use image::GrayImage;
 use rand::distributions::Uniform;
-use rand::{rngs::StdRng, Rng};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use rand_distr::num_traits::Pow;
-use rand_distr::{UnitDisc, UnitSphere};
+use rand_distr::UnitSphere;
+use std::sync::{Arc, OnceLock};
 
+use crate::scene::Scene;
 use crate::shape::Ray;
 
+pub use gltf::GltfCamera;
+pub use tilt_shift::TiltShiftCamera;
+
 /// A camera that can cast rays into the scene
 pub trait Camera: Send + Sync {
     /// Cast a ray, where (x, y) are normalized to the standard [-1, 1] box
-    fn cast_ray(&self, x: f64, y: f64, rng: &mut StdRng) -> (Ray, Color, f64);
+    ///
+    /// The `time` is the moment within the frame at which the ray is cast, in [0, 1].
+    /// Cameras with a lens or aperture find the point on it from `sample.lens`, so that
+    /// depth of field is stratified along with the pixel offsets.
+    fn cast_ray(
+        &self,
+        x: f64,
+        y: f64,
+        time: f64,
+        sample: &SampleCursor,
+        rng: &mut StdRng,
+    ) -> (Ray, Color, f64);
+
+    /// Cast all of the rays of a single sensor sample, whose estimates are summed
+    ///
+    /// Cameras that trace several wavelengths along separate paths can return a ray
+    /// for each of them. The default implementation casts a single ray.
+    fn cast_rays(
+        &self,
+        x: f64,
+        y: f64,
+        time: f64,
+        sample: &SampleCursor,
+        rng: &mut StdRng,
+    ) -> Vec<(Ray, Color, f64)> {
+        vec![self.cast_ray(x, y, time, sample, rng)]
+    }
+
+    /// Cast a ray through the center of pixel (x, y) in an image of the given size
+    ///
+    /// This uses the same mapping from pixel coordinates to the [-1, 1] box as the renderer,
+    /// and casts the ray at the start of the frame.
+    fn ray_for_pixel(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        rng: &mut StdRng,
+    ) -> (Ray, Color, f64) {
+        let (xn, yn) = normalize_pixel(x, y, width, height);
+        self.cast_ray(xn, yn, 0.0, &SampleCursor::random(), rng)
+    }
+
+    /// Project a point in the scene onto the image, so that light paths can be connected
+    /// directly to the camera
+    ///
+    /// Returns the normalized coordinates (x, y) that the point appears at, the origin of
+    /// the ray that sees it, and the density of ray directions at that point per unit area
+    /// of normalized coordinates. Cameras that sample a lens or aperture cannot be
+    /// connected to, and return `None`, as does the default implementation.
+    fn project(&self, _point: &glm::DVec3) -> Option<(glm::DVec2, glm::DVec3, f64)> {
+        None
+    }
+
+    /// Cast the ray through the center of the lens at normalized coordinates (x, y), which
+    /// finds the surface seen at a point of the image without any depth of field
+    ///
+    /// The default implementation casts a ray with a fixed seed at the start of the frame.
+    fn chief_ray(&self, x: f64, y: f64) -> Ray {
+        let mut rng = StdRng::seed_from_u64(0);
+        self.cast_ray(x, y, 0.0, &SampleCursor::random(), &mut rng)
+            .0
+    }
+
+    /// Radius of the circle of confusion of the point at `distance` along a chief ray,
+    /// in normalized image coordinates, where the distance is infinite for rays that miss
+    ///
+    /// This is the blur that a thin lens gives the point, for depth of field applied as
+    /// a post-process. The default implementation returns zero, for a camera with
+    /// everything in focus.
+    fn circle_of_confusion(&self, _ray: &Ray, _distance: f64) -> f64 {
+        0.0
+    }
+
+    /// Precompute the quantities that stay fixed over a frame, returning a camera that
+    /// casts exactly the same rays with less work per ray
+    ///
+    /// The renderer prepares its camera once when it is constructed. The default
+    /// implementation returns `None`, meaning there is nothing to precompute.
+    fn prepare(&self) -> Option<Arc<dyn Camera>> {
+        None
+    }
+
+    /// Check that the parameters of the camera produce well-defined rays
+    ///
+    /// The renderer calls this once when it is constructed. The default implementation
+    /// accepts any camera.
+    fn validate(&self) -> Result<(), CameraError> {
+        Ok(())
+    }
+}
+
+/// A problem with the parameters of a camera, found by `Camera::validate`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CameraError {
+    /// The field of view, in radians, is not in (0, pi)
+    FieldOfView(f64),
+
+    /// The view direction or up vector is zero or not finite, or they are not orthogonal
+    Orientation,
+
+    /// The sensor or viewing rectangle does not have a positive width and height
+    SensorSize(f64, f64),
+
+    /// The width of a pixel divided by its height is not positive
+    PixelAspectRatio(f64),
+}
+
+impl std::fmt::Display for CameraError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::FieldOfView(fov) => {
+                write!(f, "field of view {} is not in (0, pi) radians", fov)
+            }
+            Self::Orientation => write!(
+                f,
+                "view direction and up vector must be nonzero and orthogonal"
+            ),
+            Self::SensorSize(width, height) => {
+                write!(f, "sensor size {} x {} is not positive", width, height)
+            }
+            Self::PixelAspectRatio(ratio) => {
+                write!(f, "pixel aspect ratio {} is not positive", ratio)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CameraError {}
+
+/// Check that a view direction and up vector are nonzero, finite, and orthogonal
+fn validate_orientation(direction: &glm::DVec3, up: &glm::DVec3) -> Result<(), CameraError> {
+    let (d, u) = (direction.magnitude(), up.magnitude());
+    let valid = d.is_finite() && u.is_finite() && d > 0.0 && u > 0.0;
+    if valid && direction.dot(up).abs() <= 1e-6 * d * u {
+        Ok(())
+    } else {
+        Err(CameraError::Orientation)
+    }
+}
+
+/// Check that a field of view is in (0, pi)
+fn validate_fov(fov: f64) -> Result<(), CameraError> {
+    if fov > 0.0 && fov < std::f64::consts::PI {
+        Ok(())
+    } else {
+        Err(CameraError::FieldOfView(fov))
+    }
+}
+
+/// Check that a sensor or viewing rectangle has a positive, finite size
+fn validate_size(width: f64, height: f64) -> Result<(), CameraError> {
+    let positive = |x: f64| x > 0.0 && x.is_finite();
+    if positive(width) && positive(height) {
+        Ok(())
+    } else {
+        Err(CameraError::SensorSize(width, height))
+    }
+}
+
+/// Check that a pixel aspect ratio is positive and finite
+fn validate_pixel_aspect_ratio(ratio: f64) -> Result<(), CameraError> {
+    if ratio > 0.0 && ratio.is_finite() {
+        Ok(())
+    } else {
+        Err(CameraError::PixelAspectRatio(ratio))
+    }
+}
+
+/// Build an orthonormal basis of (right, up) vectors for a nonzero view direction
+///
+/// The up vector is the component of `up` orthogonal to the direction. When `up` is zero
+/// or parallel to the direction, such as for a camera looking straight up or down, it
+/// falls back to the up vector of the default camera pitched by 90 degrees, which is +z
+/// when looking up and -z when looking down (or +y for any other direction).
+pub fn build_basis(direction: &glm::DVec3, up: &glm::DVec3) -> (glm::DVec3, glm::DVec3) {
+    let direction = direction.normalize();
+    let mut up_ortho = up - up.dot(&direction) * direction;
+    let magnitude = up_ortho.magnitude();
+    if magnitude.is_nan() || magnitude <= 1e-9 * up.magnitude() {
+        let hint = if direction.y.abs() < 0.9 {
+            vec3(0.0, 1.0, 0.0)
+        } else {
+            vec3(0.0, 0.0, direction.y.signum())
+        };
+        up_ortho = hint - hint.dot(&direction) * direction;
+    }
+    let right = direction.cross(&up_ortho).normalize();
+    (right, right.cross(&direction))
+}
+
+/// Normalized view direction and orthogonal up vector of a camera looking along
+/// `direction`, such as `center - eye` for a camera looking at `center`
+///
+/// Panics if the direction is zero, which happens when a camera looks at its own eye.
+fn orient(direction: glm::DVec3, up: &glm::DVec3) -> (glm::DVec3, glm::DVec3) {
+    assert!(
+        direction.magnitude() > 0.0 && direction.magnitude().is_finite(),
+        "Camera must not look at its own eye"
+    );
+    let direction = direction.normalize();
+    let (_, up) = build_basis(&direction, up);
+    (direction, up)
+}
+
+/// Panic with a readable message if a newly constructed camera is invalid
+fn assert_valid(camera: &impl Camera) {
+    if let Err(err) = camera.validate() {
+        panic!("Invalid camera: {}", err);
+    }
+}
+
+/// Map the center of pixel (x, y) to normalized camera coordinates
+///
+/// The longer image dimension spans [-1, 1], and y points up (row 0 is the top of the image).
+pub(crate) fn normalize_pixel(x: u32, y: u32, width: u32, height: u32) -> (f64, f64) {
+    let dim = std::cmp::max(width, height) as f64;
+    let xn = ((2 * x + 1) as f64 - width as f64) / dim;
+    let yn = ((2 * (height - y) - 1) as f64 - height as f64) / dim;
+    (xn, yn)
 }
 
 /// A simple thin-lens perspective camera
@@ -37,6 +267,9 @@ pub struct PinholeCamera {
 
     /// The camera aperture size and shape
     pub aperture: Option<Aperture>,
+
+    /// Radial distortion of the lens
+    pub distortion: RadialDistortion,
 }
 
 /// A simple aperture of various shape
@@ -47,6 +280,72 @@ pub struct Aperture {
 
     /// The shape of the aperture
     pub shape: ApertureShape,
+
+    /// Radius of a circular obstruction at the center of the aperture, or 0 for none
+    ///
+    /// This is in the same units as `scale`. Mirror lenses have such an obstruction
+    /// from their secondary mirror, which turns out-of-focus highlights into rings.
+    pub inner_scale: f64,
+
+    /// Counterclockwise rotation of the shape and obstruction, in radians
+    pub rotation: f64,
+
+    /// Offset of the center of the aperture from the optical axis, in units of `scale`
+    ///
+    /// This simulates a decentered aperture, which makes bokeh lopsided.
+    pub offset: [f64; 2],
+}
+
+/// Number of rejected samples after which an aperture falls back to its center
+///
+/// Obstructions and apodization masks can block nearly all of an aperture, which would
+/// otherwise make rejection sampling stall.
+const MAX_APERTURE_ATTEMPTS: usize = 1024;
+
+impl Aperture {
+    /// Sample a point uniformly from the aperture, in units of `scale`, from a point `u`
+    /// of the unit square
+    ///
+    /// Rejected samples, which are blocked by the obstruction, are retried with `rng`.
+    fn sample(&self, mut u: [f64; 2], rng: &mut StdRng) -> [f64; 2] {
+        for _ in 0..MAX_APERTURE_ATTEMPTS {
+            let [x, y] = self.shape.sample(u, rng);
+            if self.contains_local(x, y) {
+                return self.to_aperture(x, y);
+            }
+            u = rng.gen();
+        }
+        let [x, y] = self.shape.center();
+        self.to_aperture(x, y)
+    }
+
+    /// Whether a point, in units of `scale`, passes through the aperture
+    fn contains(&self, x: f64, y: f64) -> bool {
+        let [x, y] = self.to_local(x, y);
+        self.contains_local(x, y)
+    }
+
+    /// Whether a point in the frame of the unrotated, centered shape passes through
+    fn contains_local(&self, x: f64, y: f64) -> bool {
+        let inner = self.inner_scale / self.scale;
+        self.shape.contains(x, y) && x * x + y * y >= inner * inner
+    }
+
+    /// Rotate and offset a point from the frame of the shape
+    fn to_aperture(&self, x: f64, y: f64) -> [f64; 2] {
+        let (sin, cos) = self.rotation.sin_cos();
+        [
+            cos * x - sin * y + self.offset[0],
+            sin * x + cos * y + self.offset[1],
+        ]
+    }
+
+    /// Undo the offset and rotation of a point, returning it in the frame of the shape
+    fn to_local(&self, x: f64, y: f64) -> [f64; 2] {
+        let (sin, cos) = self.rotation.sin_cos();
+        let (x, y) = (x - self.offset[0], y - self.offset[1]);
+        [cos * x + sin * y, -sin * x + cos * y]
+    }
 }
 
 /// Various shape options for aperture
@@ -64,12 +363,169 @@ pub enum ApertureShape {
     ///
     /// The points of the polygon must lie within a [-1, 1] box.
     Poly(Polygon),
+    /// A circular aperture whose transmission varies, given by a grayscale mask.
+    ///
+    /// The image spans the [-1, 1] box, with white fully transparent and black opaque,
+    /// and must not be black everywhere inside the unit circle. Points are sampled in
+    /// proportion to the transmission, which softens the edges of bokeh. Like the other
+    /// shapes, the mask does not change the overall brightness of the image, and lens
+    /// surfaces only block rays where it is fully opaque.
+    Apodized(Arc<GrayImage>),
+}
+
+/// Photographic parameters of a thin lens, used to configure a `PinholeCamera`
+/// with `PinholeCamera::photographic`
+#[derive(Copy, Clone, Debug)]
+pub struct ThinLens {
+    /// Focal length of the lens, in millimeters
+    pub focal_length_mm: f64,
+
+    /// Ratio of the focal length to the diameter of the aperture
+    pub f_number: f64,
+
+    /// Width of the sensor (its longer side), in millimeters
+    pub sensor_width_mm: f64,
+
+    /// Number of millimeters in one world unit, so 1000 when the scene is in meters
+    pub mm_per_unit: f64,
+}
+
+impl Default for ThinLens {
+    /// A 50mm lens at f/2.8 on a full-frame sensor, in a scene measured in meters
+    fn default() -> Self {
+        Self {
+            focal_length_mm: 50.0,
+            f_number: 2.8,
+            sensor_width_mm: 36.0,
+            mm_per_unit: 1000.0,
+        }
+    }
+}
+
+impl ThinLens {
+    /// Field of view in the longer direction of the sensor, in radians
+    pub fn fov(&self) -> f64 {
+        2.0 * (self.sensor_width_mm / (2.0 * self.focal_length_mm)).atan()
+    }
+
+    /// Radius of the circular aperture, in world units
+    pub fn aperture_radius(&self) -> f64 {
+        self.focal_length_mm / (2.0 * self.f_number) / self.mm_per_unit
+    }
+}
+
+/// Brown-Conrady radial distortion of a lens, to match the distortion of plate
+/// photography
+///
+/// A point at radius `r` from the center of the image, in normalized coordinates, sees
+/// the scene through the point of an undistorted image at `1 + k1 r² + k2 r⁴` times its
+/// radius. Positive coefficients squeeze more of the scene into the edges of the image,
+/// bowing straight lines outward (barrel distortion), and negative coefficients bow them
+/// inward (pincushion distortion). The default has no distortion.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RadialDistortion {
+    /// Coefficient of the squared radius
+    pub k1: f64,
+
+    /// Coefficient of the fourth power of the radius
+    pub k2: f64,
+}
+
+impl RadialDistortion {
+    /// Scale factor of a point at squared radius `r2`
+    fn scale(&self, r2: f64) -> f64 {
+        1.0 + self.k1 * r2 + self.k2 * r2 * r2
+    }
+
+    /// Derivative of the undistorted radius with respect to the distorted radius, at
+    /// squared radius `r2`
+    fn slope(&self, r2: f64) -> f64 {
+        1.0 + 3.0 * self.k1 * r2 + 5.0 * self.k2 * r2 * r2
+    }
+
+    /// Map a point of the distorted image to the point of the undistorted image that
+    /// it sees
+    pub fn undistort(&self, point: &glm::DVec2) -> glm::DVec2 {
+        point * self.scale(point.magnitude_squared())
+    }
+
+    /// Map a point of the undistorted image to where it appears in the distorted image,
+    /// the inverse of `undistort`
+    ///
+    /// This is found with Newton's method. Returns `None` if no point within the radius
+    /// at which the distortion folds back on itself maps to the point, which happens
+    /// past the edge of a strongly pincushioned image.
+    pub fn distort(&self, point: &glm::DVec2) -> Option<glm::DVec2> {
+        let target = point.magnitude();
+        if target == 0.0 || *self == Self::default() {
+            return Some(*point);
+        }
+        let mut r = target;
+        for _ in 0..32 {
+            let step = (r * self.scale(r * r) - target) / self.slope(r * r);
+            r -= step;
+            if step.is_nan() || step.abs() <= 1e-14 * target {
+                break;
+            }
+        }
+        let residual = r * self.scale(r * r) - target;
+        if r > 0.0 && self.slope(r * r) > 0.0 && residual.abs() < 1e-9 * target {
+            Some(point * (r / target))
+        } else {
+            None
+        }
+    }
+
+    /// Area of the undistorted image per unit area of the distorted image at a point
+    fn area_scale(&self, point: &glm::DVec2) -> f64 {
+        let r2 = point.magnitude_squared();
+        self.scale(r2) * self.slope(r2)
+    }
+}
+
+/// Photographic exposure settings, which set the brightness of a render from the shutter
+/// speed, film speed, and f-number together
+///
+/// Brightness is proportional to `shutter_s * iso / f_number²`. Pass this to
+/// `Renderer::exposure` to scale the image, and to `PhysicalCamera::set_f_number` so
+/// that the aperture of the lens, and with it the depth of field, matches.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Exposure {
+    /// Time that the shutter is open, in seconds
+    pub shutter_s: f64,
+
+    /// Sensitivity of the sensor, where ISO 100 is the reference
+    pub iso: f64,
+
+    /// Ratio of the focal length to the diameter of the aperture
+    pub f_number: f64,
+}
+
+impl Exposure {
+    /// Exposure value at ISO 100, `log2(N² / t) - log2(ISO / 100)`
+    ///
+    /// Larger values are darker, with each step of one halving the light captured.
+    pub fn ev100(&self) -> f64 {
+        (self.f_number * self.f_number / self.shutter_s).log2() - (self.iso / 100.0).log2()
+    }
+
+    /// Brightness in stops relative to an exposure of 1 second at ISO 100 and f/1, which
+    /// leaves radiance unscaled
+    pub fn stops(&self) -> f64 {
+        -self.ev100()
+    }
 }
 
 /// Polygon composed of points
 #[derive(Clone, Debug)]
 pub struct Polygon {
     pts: Vec<[f64; 2]>,
+    /// Bounding box of the points, as the minimum and maximum corners
+    bounds: [[f64; 2]; 2],
+    /// Triangulation of the polygon, as indices into `pts`
+    triangles: Vec<[usize; 3]>,
+    /// Cumulative areas of the triangles, used for sampling
+    cdf: Vec<f64>,
 }
 
 impl Default for PinholeCamera {
@@ -81,6 +537,7 @@ impl Default for PinholeCamera {
             fov: std::f64::consts::FRAC_PI_6,
             focal_distance: 0.0,
             aperture: None,
+            distortion: RadialDistortion::default(),
         }
     }
 }
@@ -88,16 +545,27 @@ impl Default for PinholeCamera {
 impl PinholeCamera {
     /// Perspective camera looking at a point, with a given field of view
     pub fn look_at(eye: glm::DVec3, center: glm::DVec3, up: glm::DVec3, fov: f64) -> Self {
-        let direction = (center - eye).normalize();
-        let up = (up - up.dot(&direction) * direction).normalize();
-        Self {
+        let (direction, up) = orient(center - eye, &up);
+        let camera = Self {
             eye,
             direction,
             up,
             fov,
             focal_distance: 0.0,
             aperture: None,
-        }
+            distortion: RadialDistortion::default(),
+        };
+        assert_valid(&camera);
+        camera
+    }
+
+    /// Distort the image with the Brown-Conrady radial coefficients `k1` and `k2`
+    ///
+    /// Positive coefficients give barrel distortion and negative ones give pincushion
+    /// distortion, as described in `RadialDistortion`.
+    pub fn distortion(mut self, k1: f64, k2: f64) -> Self {
+        self.distortion = RadialDistortion { k1, k2 };
+        self
     }
 
     /// Focus the camera on a position, with simulated depth-of-field
@@ -106,20 +574,260 @@ impl PinholeCamera {
         self.aperture = aperture;
         self
     }
+
+    /// Set the field of view and depth of field from the focal length and f-number of
+    /// a lens, focused on a position
+    ///
+    /// The aperture is a circle with a diameter of the focal length over the f-number,
+    /// so stopping down to a larger f-number brings more of the scene into focus.
+    pub fn photographic(mut self, lens: ThinLens, focal_point: glm::DVec3) -> Self {
+        self.fov = lens.fov();
+        self.focus(
+            focal_point,
+            Some(Aperture {
+                scale: lens.aperture_radius(),
+                shape: ApertureShape::Circle,
+                inner_scale: 0.0,
+                rotation: 0.0,
+                offset: [0.0, 0.0],
+            }),
+        )
+    }
 }
 
-impl Camera for PinholeCamera {
-    fn cast_ray(&self, x: f64, y: f64, rng: &mut StdRng) -> (Ray, Color, f64) {
+impl PinholeCamera {
+    /// Distance from the eye to the image plane, and the horizontal and vertical
+    /// directions
+    fn basis(&self) -> (f64, glm::DVec3, glm::DVec3) {
         // cot(f / 2) = depth / radius
         let d = (self.fov / 2.0).tan().recip();
-        let right = glm::cross(&self.direction, &self.up).normalize();
-        let mut origin = self.eye;
-        let mut new_dir = d * self.direction + x * right + y * self.up;
+        let (right, up) = build_basis(&self.direction, &self.up);
+        (d, right, up)
+    }
+
+    fn cast_ray_with(
+        &self,
+        (d, right, up): (f64, glm::DVec3, glm::DVec3),
+        x: f64,
+        y: f64,
+        sample: &SampleCursor,
+        rng: &mut StdRng,
+    ) -> (Ray, Color, f64) {
+        let mut ray = self.chief_ray_with((d, right, up), x, y);
         if let Some(ref aperture) = self.aperture {
             // Depth of field
-            let focal_point = origin + new_dir.normalize() * self.focal_distance;
-            let [x, y]: [f64; 2] = aperture.shape.sample(rng);
-            origin += (x * right + y * self.up) * aperture.scale;
+            let focal_point = ray.at(self.focal_distance);
+            let [x, y]: [f64; 2] = aperture.sample(sample.lens(rng), rng);
+            ray.origin += (x * right + y * up) * aperture.scale;
+            ray.dir = (focal_point - ray.origin).normalize();
+        }
+        (ray, vec3(1., 1., 1.), 1.)
+    }
+
+    fn chief_ray_with(&self, (d, right, up): (f64, glm::DVec3, glm::DVec3), x: f64, y: f64) -> Ray {
+        let p = self.distortion.undistort(&glm::vec2(x, y));
+        Ray {
+            origin: self.eye,
+            dir: (d * self.direction + p.x * right + p.y * up).normalize(),
+        }
+    }
+
+    fn circle_of_confusion_with(&self, d: f64, ray: &Ray, distance: f64) -> f64 {
+        let aperture = match &self.aperture {
+            Some(aperture) if self.focal_distance > 0.0 => aperture,
+            _ => return 0.0,
+        };
+        let cosine = ray.dir.dot(&self.direction);
+        let depth = (ray.origin - self.eye).dot(&self.direction) + distance * cosine;
+        // Blur at the focal plane, as a fraction of the aperture, seen from the eye
+        let defocus = if depth.is_finite() {
+            (depth - self.focal_distance).abs() / depth
+        } else {
+            1.0
+        };
+        d * aperture.scale * defocus / self.focal_distance
+    }
+
+    fn project_with(
+        &self,
+        (d, right, up): (f64, glm::DVec3, glm::DVec3),
+        point: &glm::DVec3,
+    ) -> Option<(glm::DVec2, glm::DVec3, f64)> {
+        if self.aperture.is_some() {
+            return None;
+        }
+        let disp = point - self.eye;
+        let depth = disp.dot(&self.direction);
+        if depth <= 0.0 {
+            return None;
+        }
+        // Scale the displacement onto the image plane, at distance `d` from the eye
+        let v = disp * (d / depth);
+        let cosine = depth / disp.magnitude();
+        let p = self
+            .distortion
+            .distort(&glm::vec2(v.dot(&right), v.dot(&up)))?;
+        let density = d * d / cosine.powi(3) * self.distortion.area_scale(&p);
+        Some((p, self.eye, density))
+    }
+}
+
+impl Camera for PinholeCamera {
+    fn cast_ray(
+        &self,
+        x: f64,
+        y: f64,
+        _time: f64,
+        sample: &SampleCursor,
+        rng: &mut StdRng,
+    ) -> (Ray, Color, f64) {
+        self.cast_ray_with(self.basis(), x, y, sample, rng)
+    }
+
+    fn project(&self, point: &glm::DVec3) -> Option<(glm::DVec2, glm::DVec3, f64)> {
+        self.project_with(self.basis(), point)
+    }
+
+    fn chief_ray(&self, x: f64, y: f64) -> Ray {
+        self.chief_ray_with(self.basis(), x, y)
+    }
+
+    fn circle_of_confusion(&self, ray: &Ray, distance: f64) -> f64 {
+        self.circle_of_confusion_with(self.basis().0, ray, distance)
+    }
+
+    fn prepare(&self) -> Option<Arc<dyn Camera>> {
+        Some(Arc::new(PreparedPinhole {
+            basis: self.basis(),
+            camera: self.clone(),
+        }))
+    }
+
+    fn validate(&self) -> Result<(), CameraError> {
+        validate_fov(self.fov)?;
+        validate_orientation(&self.direction, &self.up)
+    }
+}
+
+/// A `PinholeCamera` with the basis of its image plane computed ahead of time
+struct PreparedPinhole {
+    camera: PinholeCamera,
+    basis: (f64, glm::DVec3, glm::DVec3),
+}
+
+impl Camera for PreparedPinhole {
+    fn cast_ray(
+        &self,
+        x: f64,
+        y: f64,
+        _time: f64,
+        sample: &SampleCursor,
+        rng: &mut StdRng,
+    ) -> (Ray, Color, f64) {
+        self.camera.cast_ray_with(self.basis, x, y, sample, rng)
+    }
+
+    fn project(&self, point: &glm::DVec3) -> Option<(glm::DVec2, glm::DVec3, f64)> {
+        self.camera.project_with(self.basis, point)
+    }
+
+    fn chief_ray(&self, x: f64, y: f64) -> Ray {
+        self.camera.chief_ray_with(self.basis, x, y)
+    }
+
+    fn circle_of_confusion(&self, ray: &Ray, distance: f64) -> f64 {
+        self.camera
+            .circle_of_confusion_with(self.basis.0, ray, distance)
+    }
+}
+
+/// An orthographic camera with parallel projection
+#[derive(Clone, Debug)]
+pub struct OrthographicCamera {
+    /// Location of the camera (center of the viewing rectangle)
+    pub eye: glm::DVec3,
+
+    /// Direction that the camera is facing (normalized).
+    pub direction: glm::DVec3,
+
+    /// Direction of "up" for screen, must be orthogonal to `direction` (normalized).
+    pub up: glm::DVec3,
+
+    /// Width of the viewing rectangle, in world units
+    pub width: f64,
+
+    /// Height of the viewing rectangle, in world units
+    pub height: f64,
+
+    /// Focal distance
+    pub focal_distance: f64,
+
+    /// The camera aperture size and shape
+    pub aperture: Option<Aperture>,
+}
+
+impl Default for OrthographicCamera {
+    fn default() -> Self {
+        Self {
+            eye: glm::vec3(0.0, 0.0, 10.0),
+            direction: glm::vec3(0.0, 0.0, -1.0),
+            up: glm::vec3(0.0, 1.0, 0.0),
+            width: 4.0,
+            height: 4.0,
+            focal_distance: 0.0,
+            aperture: None,
+        }
+    }
+}
+
+impl OrthographicCamera {
+    /// Orthographic camera looking at a point, with a given viewing rectangle size
+    pub fn look_at(
+        eye: glm::DVec3,
+        center: glm::DVec3,
+        up: glm::DVec3,
+        width: f64,
+        height: f64,
+    ) -> Self {
+        let (direction, up) = orient(center - eye, &up);
+        let camera = Self {
+            eye,
+            direction,
+            up,
+            width,
+            height,
+            focal_distance: 0.0,
+            aperture: None,
+        };
+        assert_valid(&camera);
+        camera
+    }
+
+    /// Focus the camera on a position, with simulated depth-of-field
+    pub fn focus(mut self, focal_point: glm::DVec3, aperture: Option<Aperture>) -> Self {
+        self.focal_distance = (focal_point - self.eye).dot(&self.direction);
+        self.aperture = aperture;
+        self
+    }
+}
+
+impl Camera for OrthographicCamera {
+    fn cast_ray(
+        &self,
+        x: f64,
+        y: f64,
+        _time: f64,
+        sample: &SampleCursor,
+        rng: &mut StdRng,
+    ) -> (Ray, Color, f64) {
+        let (right, up) = build_basis(&self.direction, &self.up);
+        let mut origin = self.eye + x * self.width / 2.0 * right + y * self.height / 2.0 * up;
+        let mut new_dir = self.direction;
+        if let Some(ref aperture) = self.aperture {
+            // Depth of field, with a focal plane perpendicular to the view direction
+            let focal_point = origin + self.direction * self.focal_distance;
+            let [x, y]: [f64; 2] = aperture.sample(sample.lens(rng), rng);
+            origin += (x * right + y * up) * aperture.scale;
             new_dir = focal_point - origin;
         }
         (
@@ -131,9 +839,15 @@ impl Camera for PinholeCamera {
             1.,
         )
     }
+
+    fn validate(&self) -> Result<(), CameraError> {
+        validate_size(self.width, self.height)?;
+        validate_orientation(&self.direction, &self.up)
+    }
 }
 
 /// A physical camera
+#[derive(Clone)]
 pub struct PhysicalCamera<L> {
     /// Location of the camera
     pub eye: glm::DVec3,
@@ -141,85 +855,815 @@ pub struct PhysicalCamera<L> {
     /// Direction that the camera is facing
     pub direction: glm::DVec3,
 
-    /// Direction of "up" for screen, must be orthogonal to `direction`
-    pub up: glm::DVec3,
+    /// Direction of "up" for screen, must be orthogonal to `direction`
+    pub up: glm::DVec3,
+
+    /// Width of image sensor.
+    pub sensor_width: f64,
+
+    /// Height of image sensor.
+    pub sensor_height: f64,
+
+    /// Width of each pixel divided by its height, 1 for square pixels.
+    ///
+    /// The rendered image is assumed to cover the whole sensor, so it has an aspect
+    /// ratio of `sensor_width / (sensor_height * pixel_aspect_ratio)`. Along each axis,
+    /// the edges of such an image map to the edges of the sensor. Rendering to an image
+    /// of another aspect ratio crops or overscans the sensor vertically when the image
+    /// is wider, or horizontally when it is taller, keeping the pixel shape. Anything
+    /// circular on the sensor then appears stretched vertically by this ratio.
+    pub pixel_aspect_ratio: f64,
+
+    /// Lens.
+    pub lens: L,
+
+    /// Current lens system.
+    pub lens_system: LensSystem,
+
+    /// Object distance that the lens system is focused at.
+    pub focus_distance: f64,
+
+    /// How wavelengths are sampled and converted to color.
+    pub spectral_mode: SpectralMode,
+
+    /// Number of wavelengths carried by each ray in `SpectralMode::Continuous`.
+    ///
+    /// These are a uniformly sampled hero wavelength and evenly spaced rotations of it
+    /// across the visible range. Wavelengths that take the same path through the lens
+    /// share the ray, which reduces color noise when the lens is not dispersive.
+    pub spectral_samples: usize,
+
+    /// Whether to apply natural vignetting, the `cos^4` falloff of irradiance with the
+    /// angle between the chief ray and the optical axis.
+    pub vignetting: bool,
+
+    /// Whether rays blocked by a lens aperture are lost rather than resampled.
+    ///
+    /// This darkens the corners of the frame where the lens barrel cuts off the exit
+    /// pupil, but also darkens the whole image by the fraction of rays that are blocked.
+    pub mechanical_vignetting: bool,
+
+    /// Horizontal squeeze factor of an anamorphic lens, or 1 for a spherical lens.
+    ///
+    /// The sensor captures a field of view this many times wider than its aspect ratio,
+    /// so the rendered image must be stretched horizontally by this factor to de-squeeze
+    /// it. Every aperture is narrowed horizontally by the same factor, which gives the
+    /// oval bokeh of anamorphic lenses in the de-squeezed image.
+    pub anamorphic_squeeze: f64,
+
+    /// Whether to simulate lens ghosts, the faint images of bright lights formed by
+    /// light reflecting off two lens surfaces before reaching the sensor.
+    ///
+    /// A fraction of the rays are traced along these reflected paths, weighted by the
+    /// Fresnel reflectance of both surfaces. That is on the order of 0.1% for uncoated
+    /// glass, so ghosts only show up next to very bright lights.
+    pub simulate_ghosts: bool,
+
+    /// Whether rays lose the light reflected at each lens surface they refract through.
+    ///
+    /// Each uncoated air-glass interface reflects about 4% of the light at normal
+    /// incidence, so lenses with many elements transmit noticeably less. Disabling this
+    /// gives the brightness of older renders, where every surface is perfectly clear.
+    pub fresnel_losses: bool,
+
+    /// Spectral transmission of the lens, flat by default.
+    ///
+    /// The color carried by each wavelength is scaled by the transmission at that
+    /// wavelength, which models the warm or cool cast of coated and apochromatic lenses.
+    pub transmission: LensTransmission,
+}
+
+/// Probability that a ray of a `PhysicalCamera` simulating ghosts follows a ghost path
+const GHOST_PROBABILITY: f64 = 0.25;
+
+/// Wavelength sampling strategy of a [`PhysicalCamera`]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum SpectralMode {
+    /// Sample wavelengths in [400nm, 700nm] and weight them by an approximate RGB hue.
+    #[default]
+    Rgb,
+    /// Sample wavelengths uniformly in [380nm, 700nm] and convert them to color through
+    /// the CIE color matching functions, normalized so that a flat spectrum is white.
+    Continuous,
+    /// Trace a red, green, and blue primary wavelength, each carrying one color channel.
+    ///
+    /// With `spectral_samples` of 3 or more, every sensor sample traces all three from
+    /// the same sensor and aperture point, which greatly reduces color noise at the cost
+    /// of three traces through the lens and scene. Otherwise, each sample picks one of
+    /// them uniformly at random.
+    Trichromatic,
+}
+
+/// Red, green, and blue wavelengths traced by `SpectralMode::Trichromatic`.
+const PRIMARY_WAVELENGTHS: [f64; 3] = [610.0e-9, 550.0e-9, 465.0e-9];
+
+/// The color channel carried by a primary wavelength, or black for any other.
+fn primary_color(wavelength: f64) -> Color {
+    let mut color = vec3(0., 0., 0.);
+    if let Some(channel) = PRIMARY_WAVELENGTHS.iter().position(|&w| w == wavelength) {
+        color[channel] = 1.;
+    }
+    color
+}
+
+/// Visible range of wavelengths sampled by `SpectralMode::Continuous`.
+const SPECTRAL_RANGE: (f64, f64) = (380.0e-9, 700.0e-9);
+
+/// Linear RGB response to a single wavelength, normalized so that the response to a
+/// flat spectrum over `SPECTRAL_RANGE` averages to white.
+fn spectral_rgb(wavelength: f64) -> Color {
+    static WHITE: OnceLock<Color> = OnceLock::new();
+    let white = WHITE.get_or_init(|| {
+        let (lo, hi) = SPECTRAL_RANGE;
+        let steps = 1000;
+        let sum: Color = (0..steps)
+            .map(|i| {
+                xyz_to_rgb(&wavelength_to_xyz(
+                    lo + (hi - lo) * (i as f64 + 0.5) / steps as f64,
+                ))
+            })
+            .sum();
+        sum / steps as f64
+    });
+    xyz_to_rgb(&wavelength_to_xyz(wavelength)).component_div(white)
+}
+
+/// Spectral transmission of a lens, which tints the image with a warm or cool cast
+///
+/// The transmission is linearly interpolated between samples of (wavelength,
+/// transmission), and clamped to the first and last sample outside of them. Without any
+/// samples, the lens transmits every wavelength equally.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LensTransmission {
+    samples: Vec<(f64, f64)>,
+}
+
+impl LensTransmission {
+    /// Number of samples taken by `LensTransmission::from_fn`
+    const FN_SAMPLES: usize = 64;
+
+    /// A lens that transmits every wavelength equally
+    pub fn flat() -> Self {
+        Self::default()
+    }
+
+    /// Construct a transmission curve from (wavelength, transmission) samples
+    ///
+    /// Panics if the wavelengths are not increasing, or a transmission is not in [0, 1].
+    pub fn new(samples: Vec<(f64, f64)>) -> Self {
+        assert!(
+            samples.windows(2).all(|w| w[0].0 < w[1].0),
+            "Transmission wavelengths must be increasing"
+        );
+        assert!(
+            samples.iter().all(|&(_, t)| (0.0..=1.0).contains(&t)),
+            "Transmission must be in [0, 1]"
+        );
+        Self { samples }
+    }
+
+    /// Construct a transmission curve by sampling a function of wavelength over
+    /// [380nm, 700nm]
+    pub fn from_fn(f: impl Fn(f64) -> f64) -> Self {
+        let (lo, hi) = SPECTRAL_RANGE;
+        let n = Self::FN_SAMPLES;
+        Self::new(
+            (0..n)
+                .map(|i| {
+                    let w = lo + (hi - lo) * i as f64 / (n - 1) as f64;
+                    (w, f(w))
+                })
+                .collect(),
+        )
+    }
+
+    /// Fraction of light transmitted at a wavelength
+    pub fn at(&self, wavelength: f64) -> f64 {
+        let samples = &self.samples;
+        match samples.partition_point(|&(w, _)| w < wavelength) {
+            _ if samples.is_empty() => 1.,
+            0 => samples[0].1,
+            i if i == samples.len() => samples[i - 1].1,
+            i => {
+                let ((w0, t0), (w1, t1)) = (samples[i - 1], samples[i]);
+                t0 + (t1 - t0) * (wavelength - w0) / (w1 - w0)
+            }
+        }
+    }
+}
+
+/// A physical camera
+impl<L: Lens + Default> Default for PhysicalCamera<L> {
+    fn default() -> Self {
+        let lens = L::default();
+        let lens_system = lens.lens_system(11.);
+        Self {
+            eye: glm::vec3(0.0, -0.5, 14.0),
+            direction: glm::vec3(0.0, 0.0, -1.0),
+            up: glm::vec3(0.0, 1.0, 0.0), // we live in a y-up world...
+            sensor_width: 1.6,
+            sensor_height: 1.2,
+            pixel_aspect_ratio: 1.,
+            lens,
+            lens_system,
+            focus_distance: 11.,
+            spectral_mode: SpectralMode::Rgb,
+            spectral_samples: 1,
+            vignetting: true,
+            mechanical_vignetting: false,
+            anamorphic_squeeze: 1.,
+            simulate_ghosts: false,
+            fresnel_losses: true,
+            transmission: LensTransmission::flat(),
+        }
+    }
+}
+
+impl<L: Lens> PhysicalCamera<L> {
+    /// Points the camera in the given direction.
+    pub fn look_at(&mut self, eye: glm::DVec3, center: glm::DVec3, up: glm::DVec3) {
+        (self.direction, self.up) = orient(center - eye, &up);
+        self.eye = eye;
+    }
+
+    /// Focuses the camera at an object at the given distance.
+    pub fn focus(&mut self, object_distance: f64) {
+        self.lens_system = self.lens.lens_system(object_distance);
+        self.focus_distance = object_distance;
+    }
+
+    /// Sets the f-number of the lens, keeping the camera focused at the same distance.
+    ///
+    /// This only changes the depth of field, since the brightness of a physical camera
+    /// does not depend on its aperture. Use `Renderer::exposure` for the brightness.
+    pub fn set_f_number(&mut self, f_number: f64) {
+        self.lens.set_f_number(f_number);
+        self.focus(self.focus_distance);
+    }
+
+    /// Swaps in a new lens, keeping the camera focused at the same distance.
+    ///
+    /// This is cheaper than building a new camera for every lens in a parameter sweep.
+    pub fn set_lens(&mut self, lens: L) {
+        self.lens = lens;
+        self.focus(self.focus_distance);
+    }
+
+    /// Focuses the camera on the given point, returning its distance along the view direction.
+    ///
+    /// Returns `None` if the point is not in front of the camera, in which case the focus
+    /// is left unchanged.
+    pub fn focus_point(&mut self, focal_point: glm::DVec3) -> Option<f64> {
+        let distance = (focal_point - self.eye).dot(&self.direction);
+        if distance <= 0. {
+            return None;
+        }
+        self.focus(distance);
+        Some(distance)
+    }
+
+    /// Focuses the camera on whatever is at the center of the frame.
+    ///
+    /// Returns the chosen object distance, or `None` if the center ray does not hit any
+    /// object, in which case the focus is left unchanged.
+    pub fn autofocus(&mut self, scene: &Scene) -> Option<f64> {
+        let ray = Ray {
+            origin: self.eye,
+            dir: self.direction.normalize(),
+        };
+        let (hit, _) = scene.intersect(ray, 0.0)?;
+        self.focus_point(ray.at(hit.time))
+    }
+}
+
+impl<L: Lens + Default> PhysicalCamera<L> {
+    /// Start building a physical camera, see [`PhysicalCameraBuilder`].
+    pub fn builder() -> PhysicalCameraBuilder<L> {
+        PhysicalCameraBuilder::default()
+    }
+}
+
+/// Builder for a [`PhysicalCamera`] that keeps the lens system in sync with the lens
+///
+/// The lens system is only derived from the lens and focus distance in [`build`], so
+/// the order in which the lens and focus are set does not matter.
+///
+/// [`build`]: PhysicalCameraBuilder::build
+pub struct PhysicalCameraBuilder<L> {
+    eye: glm::DVec3,
+    direction: glm::DVec3,
+    up: glm::DVec3,
+    center: Option<glm::DVec3>,
+    sensor_width: f64,
+    sensor_height: f64,
+    pixel_aspect_ratio: f64,
+    lens: L,
+    focus_distance: f64,
+    spectral_mode: SpectralMode,
+    spectral_samples: usize,
+    vignetting: bool,
+    mechanical_vignetting: bool,
+    anamorphic_squeeze: f64,
+    simulate_ghosts: bool,
+    fresnel_losses: bool,
+    transmission: LensTransmission,
+}
+
+impl<L: Lens + Default> Default for PhysicalCameraBuilder<L> {
+    fn default() -> Self {
+        let camera = PhysicalCamera::<L>::default();
+        Self {
+            eye: camera.eye,
+            direction: camera.direction,
+            up: camera.up,
+            center: None,
+            sensor_width: camera.sensor_width,
+            sensor_height: camera.sensor_height,
+            pixel_aspect_ratio: camera.pixel_aspect_ratio,
+            lens: camera.lens,
+            focus_distance: camera.focus_distance,
+            spectral_mode: camera.spectral_mode,
+            spectral_samples: camera.spectral_samples,
+            vignetting: camera.vignetting,
+            mechanical_vignetting: camera.mechanical_vignetting,
+            anamorphic_squeeze: camera.anamorphic_squeeze,
+            simulate_ghosts: camera.simulate_ghosts,
+            fresnel_losses: camera.fresnel_losses,
+            transmission: camera.transmission,
+        }
+    }
+}
+
+impl<L: Lens> PhysicalCameraBuilder<L> {
+    /// Set the location of the camera
+    pub fn eye(mut self, eye: glm::DVec3) -> Self {
+        self.eye = eye;
+        self
+    }
+
+    /// Point the camera at a target, with the given "up" direction
+    ///
+    /// The up vector does not need to be orthogonal to the view direction. If it is
+    /// parallel to it, a stable up vector is chosen as in [`build_basis`].
+    pub fn look_at(mut self, center: glm::DVec3, up: glm::DVec3) -> Self {
+        self.center = Some(center);
+        self.up = up;
+        self
+    }
+
+    /// Set the width and height of the image sensor
+    pub fn sensor(mut self, width: f64, height: f64) -> Self {
+        self.sensor_width = width;
+        self.sensor_height = height;
+        self
+    }
+
+    /// Set the width of each pixel divided by its height, for non-square pixels
+    pub fn pixel_aspect_ratio(mut self, ratio: f64) -> Self {
+        self.pixel_aspect_ratio = ratio;
+        self
+    }
+
+    /// Set the lens
+    pub fn lens(mut self, lens: L) -> Self {
+        self.lens = lens;
+        self
+    }
+
+    /// Focus at an object at the given distance from the sensor
+    pub fn focus(mut self, object_distance: f64) -> Self {
+        self.focus_distance = object_distance;
+        self
+    }
+
+    /// Set the wavelength sampling strategy and number of wavelengths per ray
+    pub fn spectral(mut self, mode: SpectralMode, samples: usize) -> Self {
+        self.spectral_mode = mode;
+        self.spectral_samples = samples;
+        self
+    }
+
+    /// Set whether natural and mechanical vignetting are applied
+    pub fn vignetting(mut self, natural: bool, mechanical: bool) -> Self {
+        self.vignetting = natural;
+        self.mechanical_vignetting = mechanical;
+        self
+    }
+
+    /// Set the horizontal squeeze factor of an anamorphic lens
+    pub fn anamorphic_squeeze(mut self, squeeze: f64) -> Self {
+        self.anamorphic_squeeze = squeeze;
+        self
+    }
+
+    /// Set whether lens ghosts are simulated
+    pub fn simulate_ghosts(mut self, simulate_ghosts: bool) -> Self {
+        self.simulate_ghosts = simulate_ghosts;
+        self
+    }
+
+    /// Set whether rays lose the light reflected at each lens surface
+    pub fn fresnel_losses(mut self, fresnel_losses: bool) -> Self {
+        self.fresnel_losses = fresnel_losses;
+        self
+    }
+
+    /// Set the spectral transmission of the lens
+    pub fn transmission(mut self, transmission: LensTransmission) -> Self {
+        self.transmission = transmission;
+        self
+    }
+
+    /// Build the camera, deriving its lens system from the lens and focus distance
+    ///
+    /// Panics if the camera looks at its own eye, or if the sensor or pixel aspect ratio
+    /// is not positive. An up direction parallel to the view direction is replaced as
+    /// in [`build_basis`].
+    pub fn build(self) -> PhysicalCamera<L> {
+        let direction = match self.center {
+            Some(center) => center - self.eye,
+            None => self.direction,
+        };
+        let (direction, up) = orient(direction, &self.up);
+        if let Err(err) = validate_size(self.sensor_width, self.sensor_height)
+            .and_then(|_| validate_pixel_aspect_ratio(self.pixel_aspect_ratio))
+        {
+            panic!("Invalid camera: {}", err);
+        }
+        let lens_system = self.lens.lens_system(self.focus_distance);
+        PhysicalCamera {
+            eye: self.eye,
+            direction,
+            up,
+            sensor_width: self.sensor_width,
+            sensor_height: self.sensor_height,
+            pixel_aspect_ratio: self.pixel_aspect_ratio,
+            lens: self.lens,
+            lens_system,
+            focus_distance: self.focus_distance,
+            spectral_mode: self.spectral_mode,
+            spectral_samples: self.spectral_samples,
+            vignetting: self.vignetting,
+            mechanical_vignetting: self.mechanical_vignetting,
+            anamorphic_squeeze: self.anamorphic_squeeze,
+            simulate_ghosts: self.simulate_ghosts,
+            fresnel_losses: self.fresnel_losses,
+            transmission: self.transmission,
+        }
+    }
+}
+
+impl<L: Lens> PhysicalCamera<L> {
+    /// Trace a ray from a point on the sensor toward a point on the rear lens surface,
+    /// through the lens system, returning `None` if it is blocked by an aperture.
+    ///
+    /// Also returns the fraction of light transmitted through every surface, which is
+    /// one unless `fresnel_losses` is set.
+    fn trace_lens(
+        &self,
+        mut p: glm::DVec3,
+        rear: glm::DVec3,
+        wavelength: f64,
+        right: &glm::DVec3,
+        up: &glm::DVec3,
+    ) -> Option<(Ray, f64)> {
+        let mut dir = (rear - p).normalize();
+        let mut axial_loc = 0.;
+        let mut transmittance = 1.;
+
+        for i in (0..self.lens_system.surfaces.len()).rev() {
+            let surface = &self.lens_system.surfaces[i];
+            axial_loc += surface.thickness;
+            let next_n = if i == 0 {
+                IMAGING_MEDIUM_N_D
+            } else {
+                self.lens_system.surfaces[i - 1]
+                    .n(wavelength)
+                    .unwrap_or(IMAGING_MEDIUM_N_D)
+            };
+
+            // Find intersection with lens.
+            let vertex = self.eye + axial_loc * self.direction;
+            let (intersect, normal) = surface.intersect(&p, &dir, &vertex, &self.direction)?;
+            let intersect2camera = intersect - self.eye;
+            let intersect_transverse =
+                intersect2camera - (intersect2camera).dot(&self.direction) * self.direction;
+            let intersect_y = intersect_transverse.dot(up) / surface.aperture.scale;
+            let intersect_x =
+                intersect_transverse.dot(right) * self.anamorphic_squeeze / surface.aperture.scale;
+            if !surface.aperture.contains(intersect_x, intersect_y) {
+                return None;
+            }
+
+            // Calculate refracted ray.
+            let n = surface.n(wavelength).unwrap_or(IMAGING_MEDIUM_N_D);
+            if self.fresnel_losses {
+                transmittance *= 1. - fresnel_dielectric(normal.dot(&dir), next_n / n);
+            }
+            let sin_theta1 = normal.cross(&dir).norm();
+            if sin_theta1 > 0. {
+                let sin_theta2 = n / next_n * sin_theta1;
+                let dir_norm = normal.dot(&dir) * normal;
+                let dir_perp = dir - dir_norm;
+                let new_dir_perp = sin_theta2 / sin_theta1 * dir_perp;
+                dir = (dir_norm + new_dir_perp).normalize();
+            }
+
+            // Update ray origin to next surface plane.
+            p = intersect;
+        }
+
+        Some((Ray { origin: p, dir }, transmittance))
+    }
+
+    /// Trace a ray from a point on the sensor toward a point on the rear lens surface,
+    /// reflecting off surface `a` on the way out, then off surface `b` behind it on the
+    /// way back, before leaving the lens system.
+    ///
+    /// Returns the ray and the product of the Fresnel reflectances at both surfaces, or
+    /// `None` if it is blocked by an aperture or totally internally reflected.
+    fn trace_ghost(
+        &self,
+        mut p: glm::DVec3,
+        rear: glm::DVec3,
+        wavelength: f64,
+        (a, b): (usize, usize),
+        right: &glm::DVec3,
+        up: &glm::DVec3,
+    ) -> Option<(Ray, f64)> {
+        let surfaces = &self.lens_system.surfaces;
+        // Index of refraction between surface `i - 1` and surface `i`
+        let n = |i: usize| match i {
+            0 => IMAGING_MEDIUM_N_D,
+            i => surfaces[i - 1].n(wavelength).unwrap_or(IMAGING_MEDIUM_N_D),
+        };
+        let mut events = Vec::new();
+        events.extend((a..surfaces.len()).rev().map(|i| (i, true)));
+        events.extend((a + 1..=b).map(|i| (i, false)));
+        events.extend((0..b).rev().map(|i| (i, true)));
+
+        let mut dir = (rear - p).normalize();
+        let mut reflectance = 1.;
+        for (k, &(i, outward)) in events.iter().enumerate() {
+            let surface = &surfaces[i];
+            let axial_loc: f64 = surfaces[i..].iter().map(|s| s.thickness).sum();
+            let vertex = self.eye + axial_loc * self.direction;
+            let (intersect, normal) = surface.intersect(&p, &dir, &vertex, &self.direction)?;
+            let intersect2camera = intersect - self.eye;
+            let intersect_transverse =
+                intersect2camera - (intersect2camera).dot(&self.direction) * self.direction;
+            let intersect_y = intersect_transverse.dot(up) / surface.aperture.scale;
+            let intersect_x =
+                intersect_transverse.dot(right) * self.anamorphic_squeeze / surface.aperture.scale;
+            if !surface.aperture.contains(intersect_x, intersect_y) {
+                return None;
+            }
+
+            let inside = surface.n(wavelength).unwrap_or(IMAGING_MEDIUM_N_D);
+            let (n_from, n_to) = if outward {
+                (inside, n(i))
+            } else {
+                (n(i), inside)
+            };
+            let wo = -dir;
+            let h = if normal.dot(&wo) > 0. {
+                normal
+            } else {
+                -normal
+            };
+            let reflect = events.get(k + 1).is_some_and(|&(_, next)| next != outward);
+            if reflect {
+                reflectance *= fresnel_dielectric(h.dot(&wo), n_to / n_from);
+                dir = glm::reflect_vec(&dir, &h);
+            } else {
+                dir = refract(&wo, &h, n_to / n_from)?;
+            }
+            p = intersect;
+        }
+
+        Some((Ray { origin: p, dir }, reflectance))
+    }
 
-    /// Width of image sensor.
-    pub sensor_width: f64,
+    /// Cast a ray along a ghost path between a uniformly chosen pair of lens surfaces.
+    ///
+    /// Blocked rays are lost rather than resampled, so that ghosts are cut off by the
+    /// apertures they pass through.
+    #[allow(clippy::too_many_arguments)]
+    fn cast_ghost_ray(
+        &self,
+        x: f64,
+        y: f64,
+        wavelength: f64,
+        right: &glm::DVec3,
+        up: &glm::DVec3,
+        u: [f64; 2],
+        rng: &mut StdRng,
+    ) -> (Ray, Color, f64) {
+        let surfaces = &self.lens_system.surfaces;
+        let count = surfaces.len();
+        let (a, mut b) = (rng.gen_range(0..count), rng.gen_range(0..count - 1));
+        if b >= a {
+            b += 1;
+        }
+        let pairs = (count * (count - 1) / 2) as f64;
 
-    /// Height of image sensor.
-    pub sensor_height: f64,
+        let p = self.sensor_point(x, y, right, up);
+        let traced = self
+            .sample_rear(&surfaces[count - 1], right, up, u, rng)
+            .and_then(|rear| {
+                self.trace_ghost(p, rear, wavelength, (a.min(b), a.max(b)), right, up)
+            });
+        match traced {
+            Some((ray, reflectance)) => {
+                let (color, pdf) = self.spectral_weight(&[wavelength]);
+                let weight = reflectance * pairs / GHOST_PROBABILITY;
+                (ray, color * self.vignetting_factor(&p) * weight, pdf)
+            }
+            None => {
+                let ray = Ray {
+                    origin: p,
+                    dir: self.direction,
+                };
+                (ray, vec3(0., 0., 0.), 1.)
+            }
+        }
+    }
 
-    /// Lens.
-    pub lens: L,
+    /// The point on the sensor at normalized coordinates (x, y).
+    ///
+    /// The longer side of an image covering the sensor spans [-1, 1] in normalized
+    /// coordinates (see `normalize_pixel`), and the shorter side proportionally less, so
+    /// each axis is scaled to reach the sensor edge at the edge of that image.
+    fn sensor_point(&self, x: f64, y: f64, right: &glm::DVec3, up: &glm::DVec3) -> glm::DVec3 {
+        let image_aspect = self.sensor_width / (self.sensor_height * self.pixel_aspect_ratio);
+        let (x_extent, y_extent) = (image_aspect.min(1.), image_aspect.recip().min(1.));
+        self.eye
+            + x / x_extent * self.sensor_width / 2. * self.anamorphic_squeeze * right
+            + y / y_extent * self.sensor_height / 2. * up
+    }
 
-    /// Current lens system.
-    pub lens_system: LensSystem,
-}
+    /// Sample a point on the rear lens surface, within its aperture, from a point `u` of
+    /// the unit square.
+    fn sample_rear(
+        &self,
+        surface: &LensSurface,
+        right: &glm::DVec3,
+        up: &glm::DVec3,
+        u: [f64; 2],
+        rng: &mut StdRng,
+    ) -> Option<glm::DVec3> {
+        let [x, y]: [f64; 2] = surface.aperture.sample(u, rng);
+        let x = x * surface.aperture.scale / self.anamorphic_squeeze;
+        let y = y * surface.aperture.scale;
+        let sag = surface.sag((x * x + y * y).sqrt())?;
+        Some(self.eye + self.direction * (surface.thickness - sag) + x * right + y * up)
+    }
 
-/// A physical camera
-impl<L: Lens + Default> Default for PhysicalCamera<L> {
-    fn default() -> Self {
-        let lens = L::default();
-        let lens_system = lens.lens_system(11.);
-        Self {
-            eye: glm::vec3(0.0, -0.5, 14.0),
-            direction: glm::vec3(0.0, 0.0, -1.0),
-            up: glm::vec3(0.0, 1.0, 0.0), // we live in a y-up world...
-            sensor_width: 1.6,
-            sensor_height: 1.2,
-            lens,
-            lens_system,
-        }
+    /// Natural vignetting of a sensor point, from the angle of its chief ray.
+    fn vignetting_factor(&self, p: &glm::DVec3) -> f64 {
+        let rear = match self.lens_system.surfaces.last() {
+            Some(surface) if self.vignetting => surface,
+            _ => return 1.,
+        };
+        let chief = (self.eye + self.direction * rear.thickness - p).normalize();
+        chief.dot(&self.direction.normalize()).powi(4)
     }
-}
 
-impl<L: Lens> PhysicalCamera<L> {
-    /// Points the camera in the given direction.
-    pub fn look_at(&mut self, eye: glm::DVec3, center: glm::DVec3, up: glm::DVec3) {
-        self.eye = eye;
-        self.direction = (center - eye).normalize();
-        self.up = (up - up.dot(&self.direction) * self.direction).normalize();
+    /// Sample the wavelengths carried by a ray, with the hero wavelength first.
+    fn sample_wavelengths(&self, rng: &mut StdRng) -> Vec<f64> {
+        match self.spectral_mode {
+            SpectralMode::Rgb => vec![rng.sample(Uniform::new(400.0e-9, 700.0e-9))],
+            SpectralMode::Continuous => {
+                let (lo, hi) = SPECTRAL_RANGE;
+                let count = self.spectral_samples.max(1);
+                let hero = rng.gen::<f64>();
+                (0..count)
+                    .map(|i| lo + (hi - lo) * (hero + i as f64 / count as f64).fract())
+                    .collect()
+            }
+            SpectralMode::Trichromatic => vec![PRIMARY_WAVELENGTHS[rng.gen_range(0..3)]],
+        }
     }
 
-    /// Focuses the camera at an object at the given distance.
-    pub fn focus(&mut self, object_distance: f64) {
-        self.lens_system = self.lens.lens_system(object_distance);
+    /// The (color, PDF) weight of a ray carrying the given wavelengths, including the
+    /// transmission of the lens.
+    fn spectral_weight(&self, wavelengths: &[f64]) -> (Color, f64) {
+        let transmission = &self.transmission;
+        match self.spectral_mode {
+            SpectralMode::Rgb => {
+                // The PDF depends only on the sampled hue, not on the lens
+                let color = wavelength_to_rgb(wavelengths[0]);
+                let pdf = color.norm() / 2.;
+                (color * transmission.at(wavelengths[0]), pdf)
+            }
+            SpectralMode::Continuous => {
+                // The response is normalized against the uniform wavelength density
+                let color: Color = wavelengths
+                    .iter()
+                    .map(|&w| spectral_rgb(w) * transmission.at(w))
+                    .sum();
+                (color / wavelengths.len() as f64, 1.)
+            }
+            SpectralMode::Trichromatic => {
+                let w = wavelengths[0];
+                (primary_color(w) * transmission.at(w), 1. / 3.)
+            }
+        }
     }
 
-    /// Focuses the camera on the given point.
-    pub fn focus_point(&mut self, focal_point: glm::DVec3) {
-        self.lens_system = self
-            .lens
-            .lens_system((focal_point - self.eye).dot(&self.direction).abs());
+    /// Trace all three primary wavelengths of `SpectralMode::Trichromatic` through the
+    /// same sensor and rear lens points.
+    #[allow(clippy::too_many_arguments)]
+    fn cast_primaries(
+        &self,
+        x: f64,
+        y: f64,
+        right: &glm::DVec3,
+        up: &glm::DVec3,
+        weight: f64,
+        mut u: [f64; 2],
+        rng: &mut StdRng,
+    ) -> Vec<(Ray, Color, f64)> {
+        loop {
+            let p = self.sensor_point(x, y, right, up);
+            let rear = self.lens_system.surfaces.last();
+            let new_p = match rear.map(|surface| self.sample_rear(surface, right, up, u, rng)) {
+                Some(Some(new_p)) => new_p,
+                Some(None) => {
+                    // Retry points that miss the rear surface with independent numbers
+                    u = rng.gen();
+                    continue;
+                }
+                None => {
+                    // Without a lens, the wavelengths share a single ray
+                    let [x, y, z]: [f64; 3] = rng.sample(UnitSphere);
+                    let dir = vec3(x, y, z);
+                    return vec![(Ray { origin: p, dir }, vec3(1., 1., 1.), 1.)];
+                }
+            };
+            let traced: Vec<_> = PRIMARY_WAVELENGTHS
+                .iter()
+                .map(|&w| self.trace_lens(p, new_p, w, right, up))
+                .collect();
+            if !self.mechanical_vignetting && traced.iter().any(Option::is_none) {
+                u = rng.gen();
+                continue;
+            }
+            let vignetting = self.vignetting_factor(&p) * weight;
+            return traced
+                .into_iter()
+                .zip(PRIMARY_WAVELENGTHS)
+                .map(|(ray, w)| match ray {
+                    Some((ray, transmittance)) => {
+                        let weight = vignetting * transmittance * self.transmission.at(w);
+                        (ray, primary_color(w) * weight, 1.)
+                    }
+                    None => {
+                        let dir = (new_p - p).normalize();
+                        (Ray { origin: p, dir }, vec3(0., 0., 0.), 1.)
+                    }
+                })
+                .collect();
+        }
     }
 }
 
 impl<L: Lens> Camera for PhysicalCamera<L> {
-    fn cast_ray(&self, x: f64, y: f64, rng: &mut StdRng) -> (Ray, Color, f64) {
-        let right = glm::cross(&self.direction, &self.up).normalize();
-        let up = glm::cross(&right, &self.direction).normalize();
-        let wavelength = rng.sample(Uniform::new(400.0e-9, 700.0e-9));
-        let color = wavelength_to_rgb(wavelength);
-        let pdf = color.norm() / 2.;
+    fn cast_ray(
+        &self,
+        x: f64,
+        y: f64,
+        _time: f64,
+        sample: &SampleCursor,
+        rng: &mut StdRng,
+    ) -> (Ray, Color, f64) {
+        let (right, up) = build_basis(&self.direction, &self.up);
+        let mut wavelengths = self.sample_wavelengths(rng);
+        let mut u = sample.lens(rng);
+
+        let mut main_weight = 1.;
+        if self.simulate_ghosts && self.lens_system.surfaces.len() >= 2 {
+            if rng.gen::<f64>() < GHOST_PROBABILITY {
+                return self.cast_ghost_ray(x, y, wavelengths[0], &right, &up, u, rng);
+            }
+            main_weight = (1. - GHOST_PROBABILITY).recip();
+        }
 
         loop {
-            let dim = self.sensor_width.max(self.sensor_height);
-            let mut p = self.eye + dim * x / 2. * right + dim * y / 2. * up;
+            let p = self.sensor_point(x, y, &right, &up);
 
             let new_p = if let Some(surface) = self.lens_system.surfaces.last() {
-                let [x, y]: [f64; 2] = surface.aperture.shape.sample(rng);
-                let x = x * surface.aperture.scale;
-                let y = y * surface.aperture.scale;
-                let z = (surface.radius * surface.radius - x * x - y * y).sqrt();
-                self.eye
-                    + self.direction
-                        * (surface.thickness
-                            - surface.radius * (surface.radius.abs() - z) / surface.radius.abs())
-                    + x * right
-                    + y * up
+                match self.sample_rear(surface, &right, &up, u, rng) {
+                    Some(new_p) => new_p,
+                    None => {
+                        // Retry points that miss the rear surface with independent numbers
+                        u = rng.gen();
+                        continue;
+                    }
+                }
             } else {
                 let [x, y, z]: [f64; 3] = rng.sample(UnitSphere);
+                let (color, pdf) = self.spectral_weight(&wavelengths);
                 return (
                     Ray {
                         origin: p,
@@ -230,102 +1674,260 @@ impl<L: Lens> Camera for PhysicalCamera<L> {
                 );
             };
 
-            let mut dir = (new_p - p).normalize();
-            let mut axial_loc = 0.;
-            let mut valid = true;
-
-            for i in (0..self.lens_system.surfaces.len()).rev() {
-                let surface = &self.lens_system.surfaces[i];
-                axial_loc += surface.thickness;
-                let next_n = if i == 0 {
-                    IMAGING_MEDIUM_N_D
-                } else {
-                    self.lens_system.surfaces[i - 1]
-                        .n(wavelength)
-                        .unwrap_or(IMAGING_MEDIUM_N_D)
+            let traced = self.trace_lens(p, new_p, wavelengths[0], &right, &up);
+            if traced.is_none() && self.mechanical_vignetting {
+                let ray = Ray {
+                    origin: p,
+                    dir: (new_p - p).normalize(),
                 };
-
-                // Find intersection with lens.
-                let lens_center = (axial_loc - surface.radius) * self.direction + self.eye;
-                let a = dir.dot(&dir);
-                let v = p - lens_center;
-                let b = 2. * v.dot(&dir);
-                let c = v.dot(&v) - surface.radius * surface.radius;
-                let discriminant = b * b - 4. * a * c;
-                if discriminant < 0. {
-                    valid = false;
-                    break;
-                }
-                let t = (-b
-                    + if surface.radius < 0. { -1. } else { 1. } * (b * b - 4. * a * c).sqrt())
-                    / 2.
-                    / a;
-                let intersect = p + dir * t;
-                let intersect2camera = intersect - self.eye;
-                let intersect_transverse =
-                    intersect2camera - (intersect2camera).dot(&self.direction) * self.direction;
-                let intersect_y = intersect_transverse.dot(&up) / surface.aperture.scale;
-                let intersect_x = intersect_transverse.dot(&right) / surface.aperture.scale;
-                if !surface.aperture.shape.contains(intersect_x, intersect_y) {
-                    valid = false;
-                    break;
-                }
-
-                // Calculate refracted ray.
-                let normal = (intersect - lens_center).normalize();
-                let sin_theta1 = normal.cross(&dir).norm();
-                let sin_theta2 =
-                    surface.n(wavelength).unwrap_or(IMAGING_MEDIUM_N_D) / next_n * sin_theta1;
-                let dir_norm = normal.dot(&dir) * normal;
-                let dir_perp = dir - dir_norm;
-                let new_dir_perp = sin_theta2 / sin_theta1 * dir_perp;
-                dir = (dir_norm + new_dir_perp).normalize();
-
-                // Update ray origin to next surface plane.
-                p = intersect;
+                break (ray, vec3(0., 0., 0.), 1.);
             }
+            if let Some((ray, transmittance)) = traced {
+                // Keep the other wavelengths only if they follow the hero's path exactly
+                let hero = wavelengths[0];
+                wavelengths.retain(|&w| {
+                    w == hero
+                        || self
+                            .trace_lens(p, new_p, w, &right, &up)
+                            .is_some_and(|(other, _)| {
+                                glm::distance(&other.origin, &ray.origin) < 1e-12
+                                    && glm::distance(&other.dir, &ray.dir) < 1e-12
+                            })
+                });
+                let (color, pdf) = self.spectral_weight(&wavelengths);
+                let weight = self.vignetting_factor(&p) * main_weight * transmittance;
+                break (ray, color * weight, pdf);
+            }
+            u = rng.gen();
+        }
+    }
 
-            if valid {
-                break (Ray { origin: p, dir }, color, pdf);
+    fn cast_rays(
+        &self,
+        x: f64,
+        y: f64,
+        time: f64,
+        sample: &SampleCursor,
+        rng: &mut StdRng,
+    ) -> Vec<(Ray, Color, f64)> {
+        if self.spectral_mode != SpectralMode::Trichromatic || self.spectral_samples < 3 {
+            return vec![self.cast_ray(x, y, time, sample, rng)];
+        }
+        let (right, up) = build_basis(&self.direction, &self.up);
+        let u = sample.lens(rng);
+        let mut main_weight = 1.;
+        if self.simulate_ghosts && self.lens_system.surfaces.len() >= 2 {
+            if rng.gen::<f64>() < GHOST_PROBABILITY {
+                // Ghosts are faint, so they are traced for one channel at a time
+                let wavelength = self.sample_wavelengths(rng)[0];
+                return vec![self.cast_ghost_ray(x, y, wavelength, &right, &up, u, rng)];
             }
+            main_weight = (1. - GHOST_PROBABILITY).recip();
         }
+        self.cast_primaries(x, y, &right, &up, main_weight, u, rng)
     }
+
+    fn validate(&self) -> Result<(), CameraError> {
+        validate_size(self.sensor_width, self.sensor_height)?;
+        validate_pixel_aspect_ratio(self.pixel_aspect_ratio)?;
+        validate_orientation(&self.direction, &self.up)
+    }
+}
+
+/// Transmission of an apodization mask at a point in the [-1, 1] box, in [0, 1]
+fn mask_value(mask: &GrayImage, x: f64, y: f64) -> f64 {
+    let (width, height) = mask.dimensions();
+    let i = ((x + 1.) / 2. * width as f64) as u32;
+    let j = ((1. - y) / 2. * height as f64) as u32;
+    f64::from(mask.get_pixel(i.min(width - 1), j.min(height - 1)).0[0]) / 255.
 }
 
 impl ApertureShape {
-    fn sample(&self, rng: &mut StdRng) -> [f64; 2] {
+    /// Sample a point of the shape from a point `u` of the unit square, using `rng` for
+    /// any samples that an apodization mask rejects
+    fn sample(&self, mut u: [f64; 2], rng: &mut StdRng) -> [f64; 2] {
         match self {
-            ApertureShape::Circle => rng.sample(UnitDisc),
-            ApertureShape::Square => {
-                let uniform = Uniform::new_inclusive(-1.0, 1.0);
-                let x = rng.sample(uniform);
-                let y = rng.sample(uniform);
-                [x, y]
-            }
-            ApertureShape::Poly(ref poly) => {
-                let uniform = Uniform::new_inclusive(-1.0, 1.0);
-                loop {
-                    let x = rng.sample(uniform);
-                    let y = rng.sample(uniform);
-
-                    if poly.contains(x, y) {
-                        break [x, y];
+            ApertureShape::Circle => sample_disc(u),
+            ApertureShape::Square => [2. * u[0] - 1., 2. * u[1] - 1.],
+            ApertureShape::Poly(ref poly) => poly.sample(u),
+            ApertureShape::Apodized(ref mask) => {
+                for _ in 0..MAX_APERTURE_ATTEMPTS {
+                    let [x, y] = sample_disc(u);
+                    let transmission = mask_value(mask, x, y);
+                    if transmission >= 1. || rng.gen::<f64>() < transmission {
+                        return [x, y];
                     }
+                    u = rng.gen();
                 }
+                self.center()
             }
         }
     }
 
+    /// Center of the bounding box of the shape
+    fn center(&self) -> [f64; 2] {
+        match self {
+            ApertureShape::Poly(poly) => {
+                let [min, max] = poly.bounds();
+                [(min[0] + max[0]) / 2., (min[1] + max[1]) / 2.]
+            }
+            _ => [0., 0.],
+        }
+    }
+
     fn contains(&self, x: f64, y: f64) -> bool {
         match self {
             ApertureShape::Circle => x * x + y * y < 1.,
             ApertureShape::Square => x.abs() < 1. && y.abs() < 1.,
             ApertureShape::Poly(poly) => poly.contains(x, y),
+            ApertureShape::Apodized(mask) => x * x + y * y < 1. && mask_value(mask, x, y) > 0.,
+        }
+    }
+
+    /// Directions of the diffraction spikes produced by this aperture, as angles in radians
+    ///
+    /// Each straight edge of the aperture diffracts light perpendicular to itself, in both
+    /// directions. Parallel edges share spikes, so a square has 4 spikes, a hexagon 6, and
+    /// a pentagon 10. A circular aperture has no straight edges, so it produces no spikes.
+    pub fn spike_angles(&self) -> Vec<f64> {
+        let edges: Vec<[f64; 2]> = match self {
+            ApertureShape::Circle | ApertureShape::Apodized(_) => return Vec::new(),
+            ApertureShape::Square => vec![[1., 0.], [0., 1.], [-1., 0.], [0., -1.]],
+            ApertureShape::Poly(poly) => {
+                let n = poly.pts.len();
+                (0..n)
+                    .map(|i| {
+                        let [x1, y1] = poly.pts[i];
+                        let [x2, y2] = poly.pts[(i + 1) % n];
+                        [x2 - x1, y2 - y1]
+                    })
+                    .collect()
+            }
+        };
+        let pi = std::f64::consts::PI;
+        let mut orientations: Vec<f64> = Vec::new();
+        for [dx, dy] in edges {
+            if dx.hypot(dy) < 1e-12 {
+                continue;
+            }
+            let angle = (dy.atan2(dx) + pi / 2.).rem_euclid(pi);
+            let duplicate = orientations.iter().any(|&other| {
+                let diff = (angle - other).abs();
+                diff < 1e-6 || pi - diff < 1e-6
+            });
+            if !duplicate {
+                orientations.push(angle);
+            }
         }
+        orientations
+            .iter()
+            .flat_map(|&angle| vec![angle, angle + pi])
+            .collect()
     }
 }
 
 impl Polygon {
+    /// Construct a polygon from its vertices, in order
+    pub fn new(pts: Vec<[f64; 2]>) -> Self {
+        assert!(pts.len() >= 3, "Polygon must have at least 3 points");
+        let triangles = triangulate(&pts);
+        let mut cdf = Vec::with_capacity(triangles.len());
+        let mut total = 0.0;
+        for &[a, b, c] in &triangles {
+            total += triangle_area(pts[a], pts[b], pts[c]).abs();
+            cdf.push(total);
+        }
+        let mut bounds = [pts[0], pts[0]];
+        for &[x, y] in &pts {
+            bounds[0] = [bounds[0][0].min(x), bounds[0][1].min(y)];
+            bounds[1] = [bounds[1][0].max(x), bounds[1][1].max(y)];
+        }
+        Self {
+            pts,
+            bounds,
+            triangles,
+            cdf,
+        }
+    }
+
+    /// The area enclosed by the polygon
+    pub fn area(&self) -> f64 {
+        self.cdf.last().copied().unwrap_or(0.0)
+    }
+
+    /// Bounding box of the polygon, as the minimum and maximum corners
+    pub fn bounds(&self) -> [[f64; 2]; 2] {
+        self.bounds
+    }
+
+    /// Map a point `u` of the unit square to a uniformly distributed point inside the
+    /// polygon
+    ///
+    /// The first coordinate picks a triangle of the triangulation with probability
+    /// proportional to its area, and is then rescaled to pick, with the second, a uniform
+    /// point inside that triangle, so no samples are rejected.
+    pub fn sample(&self, [u0, u1]: [f64; 2]) -> [f64; 2] {
+        let target = u0 * self.area();
+        let index = self
+            .cdf
+            .partition_point(|&c| c < target)
+            .min(self.triangles.len() - 1);
+        let [a, b, c] = self.triangles[index];
+        let (pa, pb, pc) = (self.pts[a], self.pts[b], self.pts[c]);
+        let start = if index == 0 { 0.0 } else { self.cdf[index - 1] };
+        let width = self.cdf[index] - start;
+        let u0 = if width > 0.0 {
+            ((target - start) / width).min(1.0)
+        } else {
+            0.0
+        };
+
+        // Uniform barycentric coordinates, see PBRT 13.6.5
+        let su = u0.sqrt();
+        let u = 1.0 - su;
+        let v = u1 * su;
+        let w = 1.0 - u - v;
+        [
+            u * pa[0] + v * pb[0] + w * pc[0],
+            u * pa[1] + v * pb[1] + w * pc[1],
+        ]
+    }
+
+    /// Regular polygon with `n_blades` vertices on the unit circle, rotated
+    /// counterclockwise by `rotation` radians
+    ///
+    /// This is the opening of a diaphragm with `n_blades` straight blades. At zero
+    /// rotation, the first vertex lies on the positive x axis.
+    pub fn regular(n_blades: usize, rotation: f64) -> Self {
+        Self::rounded(n_blades, rotation, 0.0)
+    }
+
+    /// Regular polygon like `regular`, with its edges bowed outward by `curvature`
+    ///
+    /// A curvature of 0 gives straight edges, and 1 pushes every edge out to the unit
+    /// circle, as with the curved blades of many lenses that keep out-of-focus
+    /// highlights round.
+    pub fn rounded(n_blades: usize, rotation: f64, curvature: f64) -> Self {
+        assert!(n_blades >= 3, "Aperture must have at least 3 blades");
+        /// Number of segments that each curved edge is divided into
+        const EDGE_SEGMENTS: usize = 8;
+        let segments = if curvature == 0.0 { 1 } else { EDGE_SEGMENTS };
+        let half_angle = std::f64::consts::PI / n_blades as f64;
+        let mut pts = Vec::with_capacity(n_blades * segments);
+        for i in 0..n_blades {
+            for j in 0..segments {
+                // Angle from the middle of the edge, whose distance from the center is
+                // the apothem cos(half_angle)
+                let offset = half_angle * (2.0 * j as f64 / segments as f64 - 1.0);
+                let straight = half_angle.cos() / offset.cos();
+                let radius = straight + curvature * (1.0 - straight);
+                let angle = rotation + 2.0 * half_angle * i as f64 + offset + half_angle;
+                pts.push([radius * angle.cos(), radius * angle.sin()]);
+            }
+        }
+        Self::new(pts)
+    }
+
     /// Generate points for a star with n points
     pub fn get_star(n: f64) -> Self {
         // https://math.stackexchange.com/questions/2135982/math-behind-creating-a-perfect-star
@@ -343,7 +1945,7 @@ impl Polygon {
             let i_y = 0.5 * i_a.sin();
             pts.push([i_x, i_y]);
         }
-        Self { pts }
+        Self::new(pts)
     }
     /// Generate points for a heart scaled by xscale and yscale
     pub fn get_heart(xscale: f64, yscale: f64) -> Self {
@@ -355,11 +1957,15 @@ impl Polygon {
             let y = 13. * t.cos() - 5. * (2. * t).cos() - 2. * (3. * t).cos() - (4. * t).cos();
             pts.push([x * xscale, y * yscale]);
         }
-        Self { pts }
+        Self::new(pts)
     }
 
     /// Taken from https://stackoverflow.com/questions/217578/how-can-i-determine-whether-a-2d-point-is-within-a-polygon
     pub fn contains(&self, x: f64, y: f64) -> bool {
+        let [min, max] = self.bounds;
+        if x < min[0] || x > max[0] || y < min[1] || y > max[1] {
+            return false;
+        }
         let num_points = self.pts.len();
         let mut i = 0;
         let mut j = num_points - 1;
@@ -382,6 +1988,68 @@ impl Polygon {
     }
 }
 
+/// Signed area of a triangle, positive if the points are in counterclockwise order
+fn triangle_area(a: [f64; 2], b: [f64; 2], c: [f64; 2]) -> f64 {
+    ((b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])) / 2.0
+}
+
+/// Triangulate a simple polygon by ear clipping
+fn triangulate(pts: &[[f64; 2]]) -> Vec<[usize; 3]> {
+    let n = pts.len();
+    let signed_area: f64 = (0..n)
+        .map(|i| {
+            let [x1, y1] = pts[i];
+            let [x2, y2] = pts[(i + 1) % n];
+            x1 * y2 - x2 * y1
+        })
+        .sum();
+    // Work in counterclockwise order, so that ears are convex corners
+    let mut remaining: Vec<usize> = if signed_area >= 0.0 {
+        (0..n).collect()
+    } else {
+        (0..n).rev().collect()
+    };
+
+    let mut triangles = Vec::with_capacity(n - 2);
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let ear = (0..m).find(|&i| {
+            let (a, b, c) = (
+                remaining[(i + m - 1) % m],
+                remaining[i],
+                remaining[(i + 1) % m],
+            );
+            if triangle_area(pts[a], pts[b], pts[c]) <= 0.0 {
+                return false;
+            }
+            // No other vertex may lie inside the ear
+            remaining.iter().all(|&p| {
+                p == a
+                    || p == b
+                    || p == c
+                    || triangle_area(pts[a], pts[b], pts[p]) < 0.0
+                    || triangle_area(pts[b], pts[c], pts[p]) < 0.0
+                    || triangle_area(pts[c], pts[a], pts[p]) < 0.0
+            })
+        });
+        match ear {
+            Some(i) => {
+                triangles.push([
+                    remaining[(i + m - 1) % m],
+                    remaining[i],
+                    remaining[(i + 1) % m],
+                ]);
+                remaining.remove(i);
+            }
+            None => break, // Degenerate polygon, fall back to a fan below
+        }
+    }
+    for i in 1..(remaining.len() - 1) {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+    triangles
+}
+
 /// Converts wavelength to an RGB color.
 // Adapted from https://stackoverflow.com/questions/1472514/convert-light-frequency-to-rgb
 fn wavelength_to_rgb(wavelength: f64) -> Color {
@@ -432,3 +2100,972 @@ fn wavelength_to_rgb(wavelength: f64) -> Color {
 
     vec3(r, g, b)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::luminance;
+    use rand::SeedableRng;
+
+    #[test]
+    fn wide_apertures_blur_more() {
+        // Rays through the center of the image converge at the focal plane 5m away,
+        // and spread out again by the plane at 10m by an amount set by the f-number
+        let blur_radius = |f_number| {
+            let lens = ThinLens {
+                f_number,
+                ..Default::default()
+            };
+            let camera = PinholeCamera::look_at(
+                glm::vec3(0.0, 0.0, 0.0),
+                glm::vec3(0.0, 0.0, -1.0),
+                glm::vec3(0.0, 1.0, 0.0),
+                1.0,
+            )
+            .photographic(lens, glm::vec3(0.0, 0.0, -5.0));
+            assert!((camera.fov - 0.6911).abs() < 1e-4);
+            let mut rng = StdRng::seed_from_u64(0);
+            (0..1000)
+                .map(|_| {
+                    let (ray, _, _) =
+                        camera.cast_ray(0.0, 0.0, 0.0, &SampleCursor::random(), &mut rng);
+                    let hit = ray.at(-10.0 / ray.dir.z);
+                    hit.xy().magnitude()
+                })
+                .fold(0.0, f64::max)
+        };
+        let (wide, narrow) = (blur_radius(1.4), blur_radius(8.0));
+        // The blur circle has the radius of the aperture, 50mm / (2 N)
+        assert!((wide - 0.01786).abs() < 1e-3, "{}", wide);
+        assert!((narrow - 0.003125).abs() < 2e-4, "{}", narrow);
+        assert!(wide > narrow);
+    }
+
+    #[test]
+    fn continuous_spectrum_is_neutral() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut camera = PhysicalCamera::<lens::SingleLens> {
+            spectral_mode: SpectralMode::Continuous,
+            vignetting: false,
+            fresnel_losses: false,
+            ..Default::default()
+        };
+        let mean = |camera: &PhysicalCamera<_>, rng: &mut StdRng| {
+            let samples = 20_000;
+            let sum: Color = (0..samples)
+                .map(|_| {
+                    let (_, color, pdf) =
+                        camera.cast_ray(0.1, -0.2, 0.0, &SampleCursor::random(), rng);
+                    color / pdf
+                })
+                .sum();
+            sum / samples as f64
+        };
+
+        // Without dispersion, all wavelengths share each ray and cancel out to gray
+        camera.lens.v_no = f64::INFINITY;
+        camera.lens_system = camera.lens.lens_system(11.);
+        camera.spectral_samples = 8;
+        let gray = mean(&camera, &mut rng);
+        assert!((gray - glm::vec3(1., 1., 1.)).amax() < 0.01, "{}", gray);
+
+        // With dispersion, each ray carries a single wavelength, but is still unbiased
+        camera.lens.v_no = 30.;
+        camera.lens_system = camera.lens.lens_system(11.);
+        let gray = mean(&camera, &mut rng);
+        assert!((gray - glm::vec3(1., 1., 1.)).amax() < 0.05, "{}", gray);
+    }
+
+    #[test]
+    fn blue_attenuating_lens_warms_image() {
+        use crate::{Environment, Renderer};
+
+        let mut scene = Scene::new();
+        scene.environment = Environment::Color(glm::vec3(1., 1., 1.));
+        let render = |transmission: LensTransmission| {
+            let camera = PhysicalCamera::<lens::SingleLens>::builder()
+                .spectral(SpectralMode::Continuous, 4)
+                .vignetting(false, false)
+                .transmission(transmission)
+                .build();
+            let image = Renderer::new(&scene, Arc::new(camera))
+                .width(8)
+                .height(6)
+                .num_samples(512)
+                .seed(0)
+                .render();
+            let sum: Color = image
+                .pixels()
+                .map(|p| glm::vec3(p.0[0].into(), p.0[1].into(), p.0[2].into()))
+                .sum();
+            sum / image.pixels().len() as f64
+        };
+
+        // A neutral scene stays neutral through a flat lens, but turns warm when the
+        // lens absorbs blue light
+        let flat = render(LensTransmission::flat());
+        let warm = render(LensTransmission::new(vec![(450.0e-9, 0.3), (600.0e-9, 1.)]));
+        assert!((flat.x - flat.z).abs() < 0.05 * flat.z, "{}", flat);
+        assert!(warm.x > 1.2 * warm.z, "{}", warm);
+        assert!(warm.z < flat.z, "{} {}", warm, flat);
+    }
+
+    #[test]
+    fn lens_transmission_interpolates_samples() {
+        let curve = LensTransmission::new(vec![(400.0e-9, 0.2), (600.0e-9, 1.)]);
+        assert_eq!(curve.at(300.0e-9), 0.2);
+        assert!((curve.at(500.0e-9) - 0.6).abs() < 1e-12);
+        assert_eq!(curve.at(700.0e-9), 1.);
+        assert_eq!(LensTransmission::flat().at(450.0e-9), 1.);
+        let f = LensTransmission::from_fn(|w| if w < 500.0e-9 { 0.5 } else { 1. });
+        assert_eq!(f.at(400.0e-9), 0.5);
+        assert_eq!(f.at(650.0e-9), 1.);
+    }
+
+    #[test]
+    fn trichromatic_channels_reduce_color_noise() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut camera = PhysicalCamera::<lens::SingleLens> {
+            spectral_mode: SpectralMode::Trichromatic,
+            vignetting: false,
+            fresnel_losses: false,
+            ..Default::default()
+        };
+        camera.lens.v_no = 30.;
+        camera.lens_system = camera.lens.lens_system(11.);
+        // Mean and variance of each channel over sensor samples
+        let stats = |camera: &PhysicalCamera<_>, rng: &mut StdRng| {
+            let samples = 4000;
+            let estimates: Vec<Color> = (0..samples)
+                .map(|_| {
+                    let rays = camera.cast_rays(0.1, -0.2, 0.0, &SampleCursor::random(), rng);
+                    rays.iter().map(|(_, color, pdf)| color / *pdf).sum()
+                })
+                .collect();
+            let mean = estimates.iter().sum::<Color>() / samples as f64;
+            let variance = estimates
+                .iter()
+                .map(|e| (e - mean).component_mul(&(e - mean)))
+                .sum::<Color>()
+                / samples as f64;
+            (mean, variance)
+        };
+
+        camera.spectral_samples = 1;
+        let (stochastic_mean, stochastic) = stats(&camera, &mut rng);
+        camera.spectral_samples = 3;
+        let (all_mean, all) = stats(&camera, &mut rng);
+        assert!((stochastic_mean - glm::vec3(1., 1., 1.)).amax() < 0.1);
+        assert!((all_mean - glm::vec3(1., 1., 1.)).amax() < 1e-9);
+        for channel in 0..3 {
+            assert!(
+                all[channel] < 0.01 * stochastic[channel],
+                "{} {}",
+                all,
+                stochastic
+            );
+        }
+    }
+
+    #[test]
+    fn vignetting_darkens_corners() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut camera = PhysicalCamera::<lens::SingleLens> {
+            spectral_mode: SpectralMode::Continuous,
+            sensor_width: 4.,
+            sensor_height: 3.,
+            ..Default::default()
+        };
+        let mut brightness = |camera: &PhysicalCamera<_>, x: f64, y: f64| {
+            let samples = 4000;
+            let sum: f64 = (0..samples)
+                .map(|_| {
+                    let (_, color, pdf) =
+                        camera.cast_ray(x, y, 0.0, &SampleCursor::random(), &mut rng);
+                    luminance(&color) / pdf
+                })
+                .sum();
+            sum / samples as f64
+        };
+
+        let center = brightness(&camera, 0., 0.);
+        let edge = brightness(&camera, 0.5, 0.);
+        let corner = brightness(&camera, 1., 0.75);
+        assert!(
+            center > edge && edge > corner,
+            "{} {} {}",
+            center,
+            edge,
+            corner
+        );
+
+        camera.vignetting = false;
+        let center = brightness(&camera, 0., 0.);
+        let corner = brightness(&camera, 1., 0.75);
+        assert!((center - corner).abs() < 0.05, "{} {}", center, corner);
+
+        // Losing blocked rays can only make the corners darker still
+        camera.vignetting = true;
+        let corner = brightness(&camera, 1., 0.75);
+        camera.mechanical_vignetting = true;
+        assert!(brightness(&camera, 1., 0.75) < corner);
+    }
+
+    #[test]
+    fn sensor_mapping_follows_pixel_aspect_ratio() {
+        let mut camera = PhysicalCamera::<lens::SingleLens> {
+            sensor_width: 2.,
+            sensor_height: 1.,
+            ..Default::default()
+        };
+        let right = glm::cross(&camera.direction, &camera.up).normalize();
+        let up = camera.up;
+        // Sensor offsets of the centers of two pixels, from the image center
+        let offsets = |camera: &PhysicalCamera<_>, width: u32, height: u32| {
+            let at = |x, y| {
+                let (xn, yn) = normalize_pixel(x, y, width, height);
+                camera.sensor_point(xn, yn, &right, &up) - camera.eye
+            };
+            let step_x = (at(1, 0) - at(0, 0)).dot(&right);
+            let step_y = (at(0, 0) - at(0, 1)).dot(&up);
+            (step_x, step_y)
+        };
+
+        // On a 2:1 image, the pixels are square on the sensor and tile it exactly
+        let (step_x, step_y) = offsets(&camera, 200, 100);
+        assert!((step_x - step_y).abs() < 1e-12);
+        assert!((step_x * 200. - 2.).abs() < 1e-12);
+        assert!((step_y * 100. - 1.).abs() < 1e-12);
+
+        // On a 1:1 image of pixels twice as wide as they are tall, the pixels still tile
+        // the sensor, so a circle on the sensor is twice as tall as it is wide in pixels
+        camera.pixel_aspect_ratio = 2.;
+        let (step_x, step_y) = offsets(&camera, 100, 100);
+        assert!((step_x - 2. * step_y).abs() < 1e-12);
+        assert!((step_x * 100. - 2.).abs() < 1e-12);
+        assert!((step_y * 100. - 1.).abs() < 1e-12);
+
+        camera.pixel_aspect_ratio = 0.;
+        assert_eq!(camera.validate(), Err(CameraError::PixelAspectRatio(0.)));
+    }
+
+    #[test]
+    fn anamorphic_bokeh_is_oval() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut camera = PhysicalCamera::<lens::SingleLens>::default();
+        // Spread of the beam from the center of the sensor, far out of focus
+        let mut spread = |camera: &PhysicalCamera<_>| {
+            let (mut xx, mut yy) = (0., 0.);
+            for _ in 0..4000 {
+                let (ray, _, _) = camera.cast_ray(0., 0., 0., &SampleCursor::random(), &mut rng);
+                let t = (30. - (ray.origin - camera.eye).dot(&camera.direction))
+                    / ray.dir.dot(&camera.direction);
+                let offset = ray.at(t) - camera.eye;
+                xx += offset.x * offset.x;
+                yy += offset.y * offset.y;
+            }
+            (xx / yy).sqrt()
+        };
+        assert!((spread(&camera) - 1.).abs() < 0.05);
+        camera.anamorphic_squeeze = 2.;
+        assert!((spread(&camera) - 0.5).abs() < 0.03);
+    }
+
+    #[test]
+    fn builder_matches_manual_camera() {
+        let (eye, center) = (glm::vec3(1.0, 2.0, 12.0), glm::vec3(0.0, 0.0, 0.0));
+        let lens = lens::SingleLens {
+            v_no: 30.,
+            ..Default::default()
+        };
+        let mut manual = PhysicalCamera {
+            sensor_width: 4.,
+            sensor_height: 3.,
+            lens: lens.clone(),
+            ..Default::default()
+        };
+        manual.look_at(eye, center, glm::vec3(0.0, 1.0, 0.0));
+        manual.focus(9.);
+
+        // Focus before the lens, which must still use the new lens's system
+        let built = PhysicalCamera::builder()
+            .eye(eye)
+            .focus(9.)
+            .look_at(center, glm::vec3(0.0, 1.0, 0.0))
+            .sensor(4., 3.)
+            .lens(lens)
+            .build();
+
+        assert_eq!(built.eye, manual.eye);
+        assert!((built.direction - manual.direction).magnitude() < 1e-12);
+        assert!((built.up - manual.up).magnitude() < 1e-12);
+        assert!(built.up.dot(&built.direction).abs() < 1e-12);
+        assert_eq!(
+            built.lens_system.surfaces.len(),
+            manual.lens_system.surfaces.len()
+        );
+        for (a, b) in built
+            .lens_system
+            .surfaces
+            .iter()
+            .zip(&manual.lens_system.surfaces)
+        {
+            assert_eq!(a.radius, b.radius);
+            assert_eq!(a.thickness, b.thickness);
+        }
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let (a, ..) = built.cast_ray(0.3, -0.2, 0.0, &SampleCursor::random(), &mut rng);
+        let mut rng = StdRng::seed_from_u64(0);
+        let (b, ..) = manual.cast_ray(0.3, -0.2, 0.0, &SampleCursor::random(), &mut rng);
+        assert_eq!((a.origin, a.dir), (b.origin, b.dir));
+    }
+
+    #[test]
+    fn set_lens_keeps_focus() {
+        let lens = |v_no| lens::SingleLens {
+            v_no,
+            ..Default::default()
+        };
+        let builder = || {
+            PhysicalCamera::builder()
+                .eye(glm::vec3(1.0, 2.0, 12.0))
+                .look_at(glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, 1.0, 0.0))
+                .sensor(4., 3.)
+                .focus(9.)
+        };
+        let mut swapped = builder().lens(lens(60.)).build();
+        swapped.set_lens(lens(30.));
+        let fresh = builder().lens(lens(30.)).build();
+        assert_eq!(swapped.focus_distance, 9.);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let (a, ..) = swapped.cast_ray(0.3, -0.2, 0.0, &SampleCursor::random(), &mut rng);
+        let mut rng = StdRng::seed_from_u64(0);
+        let (b, ..) = fresh.cast_ray(0.3, -0.2, 0.0, &SampleCursor::random(), &mut rng);
+        assert_eq!((a.origin, a.dir), (b.origin, b.dir));
+    }
+
+    #[test]
+    fn builder_replaces_parallel_up() {
+        let camera = PhysicalCamera::<lens::SingleLens>::builder()
+            .eye(glm::vec3(0.0, 0.0, 0.0))
+            .look_at(glm::vec3(0.0, 5.0, 0.0), glm::vec3(0.0, 1.0, 0.0))
+            .build();
+        assert_eq!(camera.up, glm::vec3(0.0, 0.0, 1.0));
+        assert_eq!(camera.validate(), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "own eye")]
+    fn builder_rejects_looking_at_eye() {
+        PhysicalCamera::<lens::SingleLens>::builder()
+            .eye(glm::vec3(1.0, 2.0, 3.0))
+            .look_at(glm::vec3(1.0, 2.0, 3.0), glm::vec3(0.0, 1.0, 0.0))
+            .build();
+    }
+
+    #[test]
+    fn straight_up_cameras_have_orthonormal_bases() {
+        let y = glm::vec3(0.0, 1.0, 0.0);
+        for direction in [y, -y, glm::vec3(1e-12, 3.0, 0.0)] {
+            for hint in [y, -y, glm::vec3(0.0, 0.0, 0.0), direction] {
+                let (right, up) = build_basis(&direction, &hint);
+                let direction = direction.normalize();
+                for v in [right, up] {
+                    assert!(v.iter().all(|c| c.is_finite()), "{}", v);
+                    assert!((v.magnitude() - 1.0).abs() < 1e-12);
+                    assert!(v.dot(&direction).abs() < 1e-12);
+                }
+                assert!(right.dot(&up).abs() < 1e-12);
+                assert!((right.cross(&up) + direction).magnitude() < 1e-12);
+            }
+        }
+
+        // Cameras looking straight up cast finite rays
+        let mut rng = StdRng::seed_from_u64(0);
+        let eye = glm::vec3(0.0, 0.0, 0.0);
+        let pinhole = PinholeCamera::look_at(eye, glm::vec3(0.0, 10.0, 0.0), y, 0.5);
+        let orthographic = OrthographicCamera::look_at(eye, glm::vec3(0.0, 10.0, 0.0), y, 4.0, 3.0);
+        let tilt_shift = TiltShiftCamera::look_at(eye, glm::vec3(0.0, -10.0, 0.0), y, 0.5);
+        let cameras: [&dyn Camera; 3] = [&pinhole, &orthographic, &tilt_shift];
+        for camera in cameras {
+            assert_eq!(camera.validate(), Ok(()));
+            let (ray, ..) = camera.cast_ray(0.5, -0.3, 0.0, &SampleCursor::random(), &mut rng);
+            assert!(ray
+                .origin
+                .iter()
+                .chain(ray.dir.iter())
+                .all(|c| c.is_finite()));
+        }
+        // Pitching the default camera up by 90 degrees turns its up vector to +z
+        assert_eq!(pinhole.up, glm::vec3(0.0, 0.0, 1.0));
+        assert_eq!(tilt_shift.up, glm::vec3(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn prepared_pinhole_casts_identical_rays() {
+        let mut cameras = vec![PinholeCamera::look_at(
+            glm::vec3(1.0, 2.0, 3.0),
+            glm::vec3(-2.0, 0.5, -4.0),
+            glm::vec3(0.1, 1.0, 0.0),
+            0.8,
+        )];
+        cameras.push(cameras[0].clone().focus(
+            glm::vec3(0.0, 0.0, 0.0),
+            Some(Aperture {
+                scale: 0.3,
+                shape: ApertureShape::Poly(Polygon::get_star(5.0)),
+                inner_scale: 0.0,
+                rotation: 0.0,
+                offset: [0.0, 0.0],
+            }),
+        ));
+        cameras.push(cameras[0].clone().distortion(0.2, -0.05));
+        for camera in cameras {
+            let prepared = camera.prepare().unwrap();
+            for i in 0..100 {
+                let (x, y) = ((i % 10) as f64 / 5.0 - 1.0, (i / 10) as f64 / 5.0 - 1.0);
+                let (a, ..) = camera.cast_ray(
+                    x,
+                    y,
+                    0.0,
+                    &SampleCursor::random(),
+                    &mut StdRng::seed_from_u64(i),
+                );
+                let (b, ..) = prepared.cast_ray(
+                    x,
+                    y,
+                    0.0,
+                    &SampleCursor::random(),
+                    &mut StdRng::seed_from_u64(i),
+                );
+                assert_eq!((a.origin, a.dir), (b.origin, b.dir));
+                let point = a.at(5.0);
+                assert_eq!(camera.project(&point), prepared.project(&point));
+            }
+        }
+    }
+
+    #[test]
+    fn radial_distortion_bows_straight_lines() {
+        let plain = PinholeCamera::default();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // Zero coefficients reproduce the undistorted camera
+        let zero = plain.clone().distortion(0.0, 0.0);
+        for (x, y) in [(0.0, 0.0), (0.7, -0.4), (-1.0, 0.9)] {
+            let (a, ..) = plain.cast_ray(x, y, 0.0, &SampleCursor::random(), &mut rng);
+            let (b, ..) = zero.cast_ray(x, y, 0.0, &SampleCursor::random(), &mut rng);
+            assert_eq!((a.origin, a.dir), (b.origin, b.dir));
+            let point = a.at(7.0);
+            assert_eq!(plain.project(&point), zero.project(&point));
+        }
+
+        // Project a grid of horizontal and vertical lines on the plane z = 0, returning
+        // how far the middle of each line is from the center of the image, minus the
+        // same for its ends
+        let bowing = |camera: &PinholeCamera| -> Vec<f64> {
+            let mut result = Vec::new();
+            for offset in [-1.0, -0.5, 0.5, 1.0] {
+                for horizontal in [true, false] {
+                    let at = |t: f64| {
+                        let point = if horizontal {
+                            glm::vec3(t, offset, 0.0)
+                        } else {
+                            glm::vec3(offset, t, 0.0)
+                        };
+                        let (p, ..) = camera.project(&point).unwrap();
+                        // The projection is seen by the ray cast through it
+                        let (ray, ..) = camera.cast_ray(
+                            p.x,
+                            p.y,
+                            0.0,
+                            &SampleCursor::random(),
+                            &mut StdRng::seed_from_u64(0),
+                        );
+                        assert!((ray.dir - (point - ray.origin).normalize()).norm() < 1e-9);
+                        if horizontal {
+                            p.y.abs()
+                        } else {
+                            p.x.abs()
+                        }
+                    };
+                    result.push(at(0.0) - (at(-1.5) + at(1.5)) / 2.0);
+                }
+            }
+            result
+        };
+        assert!(bowing(&plain).iter().all(|b| b.abs() < 1e-12));
+        let barrel = plain.clone().distortion(0.2, 0.0);
+        assert!(bowing(&barrel).iter().all(|&b| b > 0.005));
+        let quartic = plain.clone().distortion(0.0, 0.3);
+        assert!(bowing(&quartic).iter().all(|&b| b > 0.005));
+        let pincushion = plain.clone().distortion(-0.2, 0.0);
+        assert!(bowing(&pincushion).iter().all(|&b| b < -0.005));
+
+        // Distorting and undistorting are inverses
+        let distortion = RadialDistortion { k1: 0.3, k2: -0.1 };
+        for p in [
+            glm::vec2(0.0, 0.0),
+            glm::vec2(0.3, -0.8),
+            glm::vec2(-1.0, 0.5),
+        ] {
+            let distorted = distortion.distort(&p).unwrap();
+            assert!((distortion.undistort(&distorted) - p).norm() < 1e-12);
+        }
+        // Points far past the edge of a pincushioned image are never seen
+        let pincushion = RadialDistortion { k1: -0.5, k2: 0.0 };
+        assert_eq!(pincushion.distort(&glm::vec2(1.0, 0.0)), None);
+    }
+
+    #[test]
+    fn f_number_sets_lens_aperture() {
+        let mut camera = PhysicalCamera::<lens::SingleLens>::default();
+        camera.focus(9.);
+        let focal_length = camera.lens.focal_length();
+        camera.set_f_number(4.);
+        assert!((camera.lens.aperture.scale - focal_length / 8.).abs() < 1e-12);
+        assert_eq!(camera.focus_distance, 9.);
+        // The rebuilt lens system uses the new aperture
+        let rear = camera.lens_system.surfaces.last().unwrap();
+        assert_eq!(rear.aperture.scale, camera.lens.aperture.scale);
+    }
+
+    #[test]
+    fn fresnel_losses_grow_with_surfaces() {
+        let transmittance = |count: usize, fresnel_losses: bool| {
+            let mut camera = PhysicalCamera::<lens::SingleLens> {
+                fresnel_losses,
+                ..Default::default()
+            };
+            // Alternating air-glass interfaces, starting from glass at the front
+            camera.lens_system.surfaces = (0..count)
+                .map(|i| lens::LensSurface {
+                    radius: if i % 2 == 0 { 4. } else { -4. },
+                    thickness: 0.1,
+                    aperture: camera.lens.aperture.clone(),
+                    dispersion: (i % 2 == 0).then_some(lens::Dispersion::Abbe {
+                        n_d: 1.5,
+                        v_no: f64::INFINITY,
+                    }),
+                    asphere: None,
+                })
+                .collect();
+            let (right, up) = (vec3(1., 0., 0.), camera.up);
+            // Along the axis, every surface is at normal incidence
+            let (_, transmittance) = camera
+                .trace_lens(
+                    camera.eye,
+                    camera.eye + camera.direction * 0.1,
+                    lens::WAVELENGTH_D_LINE,
+                    &right,
+                    &up,
+                )
+                .unwrap();
+            transmittance
+        };
+        let r = (0.5_f64 / 2.5).powi(2);
+        assert!((transmittance(1, true) - (1. - r)).abs() < 1e-12);
+        assert!((transmittance(6, true) - (1. - r).powi(6)).abs() < 1e-12);
+        assert!(transmittance(6, true) < transmittance(1, true));
+        assert_eq!(transmittance(6, false), 1.);
+    }
+
+    #[test]
+    fn ghost_energy_follows_fresnel_reflectance() {
+        let ghost = |n_d: f64| {
+            let mut camera = PhysicalCamera::<lens::SingleLens>::default();
+            camera.lens.n_d = n_d;
+            camera.lens.v_no = f64::INFINITY;
+            camera.focus(11.);
+            let rear = camera.lens_system.surfaces[1].thickness;
+            let (right, up) = (vec3(1., 0., 0.), camera.up);
+            // Along the axis, both reflections are at normal incidence
+            let (ray, reflectance) = camera
+                .trace_ghost(
+                    camera.eye,
+                    camera.eye + camera.direction * rear,
+                    lens::WAVELENGTH_D_LINE,
+                    (0, 1),
+                    &right,
+                    &up,
+                )
+                .unwrap();
+            assert!((ray.dir - camera.direction).magnitude() < 1e-9);
+            reflectance
+        };
+        for n_d in [1.5_f64, 1.8] {
+            let r = ((n_d - 1.) / (n_d + 1.)).powi(2);
+            assert!(
+                (ghost(n_d) - r * r).abs() < 1e-12,
+                "{} {}",
+                ghost(n_d),
+                r * r
+            );
+        }
+
+        // Ghost rays are rare but heavily weighted, so the camera stays unbiased
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut camera = PhysicalCamera::<lens::SingleLens> {
+            vignetting: false,
+            ..Default::default()
+        };
+        let mut mean = |camera: &PhysicalCamera<_>| {
+            let samples = 20_000;
+            let sum: f64 = (0..samples)
+                .map(|_| {
+                    let (_, color, pdf) =
+                        camera.cast_ray(0.1, 0.0, 0.0, &SampleCursor::random(), &mut rng);
+                    luminance(&color) / pdf
+                })
+                .sum();
+            sum / samples as f64
+        };
+        let plain = mean(&camera);
+        camera.simulate_ghosts = true;
+        let ghosts = mean(&camera);
+        assert!((ghosts / plain - 1.).abs() < 0.02, "{} {}", plain, ghosts);
+    }
+
+    #[test]
+    fn invalid_cameras_are_rejected() {
+        use crate::{Renderer, Scene};
+
+        assert_eq!(PinholeCamera::default().validate(), Ok(()));
+        for fov in [0.0, std::f64::consts::PI, -0.5, f64::NAN] {
+            let camera = PinholeCamera {
+                fov,
+                ..Default::default()
+            };
+            assert!(matches!(
+                camera.validate(),
+                Err(CameraError::FieldOfView(_))
+            ));
+            let tilt_shift = TiltShiftCamera {
+                fov,
+                ..Default::default()
+            };
+            assert!(matches!(
+                tilt_shift.validate(),
+                Err(CameraError::FieldOfView(_))
+            ));
+        }
+
+        let skewed = PinholeCamera {
+            up: glm::vec3(0.0, 1.0, 0.5),
+            ..Default::default()
+        };
+        assert_eq!(skewed.validate(), Err(CameraError::Orientation));
+        let result = std::panic::catch_unwind(|| {
+            PinholeCamera::look_at(
+                glm::vec3(1.0, 2.0, 3.0),
+                glm::vec3(1.0, 2.0, 3.0),
+                glm::vec3(0.0, 1.0, 0.0),
+                0.5,
+            )
+        });
+        assert!(result.is_err());
+
+        let flat = OrthographicCamera {
+            height: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(flat.validate(), Err(CameraError::SensorSize(4.0, 0.0)));
+        let sensorless = PhysicalCamera::<lens::SingleLens> {
+            sensor_width: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            sensorless.validate(),
+            Err(CameraError::SensorSize(0.0, 1.2))
+        );
+
+        // The renderer reports the problem instead of rendering NaNs
+        let scene = Scene::new();
+        let error = Renderer::try_new(&scene, Arc::new(skewed)).err();
+        assert_eq!(error, Some(CameraError::Orientation));
+        assert!(Renderer::try_new(&scene, Arc::new(PinholeCamera::default())).is_ok());
+    }
+
+    #[test]
+    fn rotated_aperture_rotates_samples() {
+        let triangle = Polygon::new(vec![[-0.5, -0.5], [0.9, -0.5], [-0.5, 0.3]]);
+        let aperture = Aperture {
+            scale: 1.0,
+            shape: ApertureShape::Poly(triangle),
+            inner_scale: 0.0,
+            rotation: 0.0,
+            offset: [0.0, 0.0],
+        };
+        let rotated = Aperture {
+            rotation: std::f64::consts::FRAC_PI_2,
+            offset: [0.1, -0.2],
+            ..aperture.clone()
+        };
+        let (mut rng1, mut rng2) = (StdRng::seed_from_u64(3), StdRng::seed_from_u64(3));
+        for _ in 0..100 {
+            let [x, y] = aperture.sample(rng1.gen(), &mut rng1);
+            let [rx, ry] = rotated.sample(rng2.gen(), &mut rng2);
+            // A quarter turn counterclockwise maps (x, y) to (-y, x)
+            assert!((rx - (-y + 0.1)).abs() < 1e-12 && (ry - (x - 0.2)).abs() < 1e-12);
+            assert!(rotated.contains(rx, ry));
+        }
+        // The long tip of the triangle now points up instead of right
+        assert!(aperture.contains(0.8, -0.45) && !aperture.contains(0.05, 0.6));
+        assert!(rotated.contains(0.55, 0.6) && !rotated.contains(0.9, -0.65));
+    }
+
+    #[test]
+    fn annular_aperture_renders_rings() {
+        use crate::{sphere, Material, Object, Renderer, SceneAdd, Transformable};
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let aperture = Aperture {
+            scale: 0.5,
+            shape: ApertureShape::Circle,
+            inner_scale: 0.3,
+            rotation: 0.0,
+            offset: [0.0, 0.0],
+        };
+        for _ in 0..10_000 {
+            let [x, y] = aperture.sample(rng.gen(), &mut rng);
+            let r = (x * x + y * y).sqrt();
+            assert!((0.6..1.0).contains(&r), "{}", r);
+        }
+
+        // A small light far behind the plane of focus blurs into a ring
+        let mut scene = Scene::new();
+        scene.add(
+            Object::new(sphere().scale(&glm::vec3(0.1, 0.1, 0.1)))
+                .material(Material::light(glm::vec3(1.0, 1.0, 1.0), 10.0)),
+        );
+        let camera = PinholeCamera::look_at(
+            glm::vec3(0.0, 0.0, 10.0),
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0),
+            2.0 * (0.8_f64 / 10.0).atan(),
+        )
+        .focus(glm::vec3(0.0, 0.0, 5.0), Some(aperture));
+        let image = Renderer::new(&scene, Arc::new(camera))
+            .width(32)
+            .height(32)
+            .num_samples(64)
+            .seed(0)
+            .render();
+        // The ring spans radii of 0.3 to 0.5 at the light, or 6 to 10 pixels, widened by
+        // the 2 pixel radius of the light itself
+        let mean = |r0: f64, r1: f64| {
+            let pixels: Vec<_> = image
+                .enumerate_pixels()
+                .filter(|(x, y, _)| {
+                    let r = (*x as f64 - 15.5).hypot(*y as f64 - 15.5);
+                    r0 <= r && r < r1
+                })
+                .map(|(_, _, p)| p.0[0] as f64)
+                .collect();
+            pixels.iter().sum::<f64>() / pixels.len() as f64
+        };
+        assert_eq!(mean(0.0, 3.0), 0.0);
+        assert!(mean(6.5, 9.5) > 50.0, "{}", mean(6.5, 9.5));
+        assert_eq!(mean(13.0, 16.0), 0.0);
+    }
+
+    #[test]
+    fn apodized_aperture_softens_edges() {
+        let sample = |shape: &ApertureShape, seed: u64| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            (0..10_000)
+                .map(|_| shape.sample(rng.gen(), &mut rng))
+                .collect::<Vec<_>>()
+        };
+        let white =
+            ApertureShape::Apodized(Arc::new(GrayImage::from_pixel(16, 16, image::Luma([255]))));
+        assert_eq!(sample(&white, 0), sample(&ApertureShape::Circle, 0));
+
+        // Transmission falling off linearly to the rim
+        let gradient = ApertureShape::Apodized(Arc::new(GrayImage::from_fn(64, 64, |i, j| {
+            let (x, y) = ((i as f64 + 0.5) / 32. - 1., 1. - (j as f64 + 0.5) / 32.);
+            image::Luma([(255. * (1. - x.hypot(y)).max(0.)) as u8])
+        })));
+        let rim = |points: Vec<[f64; 2]>| {
+            let outer = points.iter().filter(|[x, y]| x.hypot(*y) > 0.7).count();
+            outer as f64 / points.len() as f64
+        };
+        // The fraction of light through the outer ring drops from 51% to 22%
+        assert!((rim(sample(&ApertureShape::Circle, 1)) - 0.51).abs() < 0.02);
+        assert!((rim(sample(&gradient, 1)) - 0.216).abs() < 0.02);
+    }
+
+    #[test]
+    fn autofocus_finds_center_subject() {
+        use crate::{sphere, Object, SceneAdd, Transformable};
+
+        let mut scene = Scene::new();
+        scene.add(Object::new(sphere().translate(&glm::vec3(0.0, -0.5, 4.0))));
+        let mut camera = PhysicalCamera::<lens::SingleLens>::default();
+        assert_eq!(camera.autofocus(&scene), Some(9.0));
+
+        let expected = camera.lens.lens_system(9.0);
+        assert_eq!(camera.lens_system.surfaces.len(), expected.surfaces.len());
+        for (a, b) in camera.lens_system.surfaces.iter().zip(&expected.surfaces) {
+            assert_eq!(a.radius, b.radius);
+            assert_eq!(a.thickness, b.thickness);
+        }
+
+        // Nothing in view leaves the focus alone
+        assert_eq!(camera.autofocus(&Scene::new()), None);
+    }
+
+    #[test]
+    fn focus_point_rejects_points_behind_camera() {
+        let mut camera = PhysicalCamera::<lens::SingleLens>::default();
+        let (eye, direction) = (camera.eye, camera.direction);
+        assert_eq!(camera.focus_point(eye + 20.0 * direction), Some(20.0));
+        let focused = camera.lens_system.surfaces[1].thickness;
+        assert_ne!(camera.lens.lens_system(40.0).surfaces[1].thickness, focused);
+
+        // A point behind the camera is not mirrored in front of it
+        assert_eq!(camera.focus_point(eye - 40.0 * direction), None);
+        assert_eq!(
+            camera.focus_point(eye + glm::vec3(1.0, 1.0, 1.0).cross(&direction)),
+            None
+        );
+        assert_eq!(camera.lens_system.surfaces[1].thickness, focused);
+    }
+
+    #[test]
+    fn ray_for_pixel_matches_normalization() {
+        let camera = PinholeCamera::default();
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // The center pixel of an odd-sized image looks straight ahead
+        let (ray, _, _) = camera.ray_for_pixel(2, 1, 5, 3, &mut rng);
+        assert!(glm::distance(&ray.dir, &camera.direction) < 1e-12);
+        assert!(glm::distance(&ray.origin, &camera.eye) < 1e-12);
+
+        // Corner pixels map to the corners of the [-1, 1] box, up to half a pixel
+        assert_eq!(normalize_pixel(0, 0, 4, 2), (-0.75, 0.25));
+        assert_eq!(normalize_pixel(3, 1, 4, 2), (0.75, -0.25));
+        let (ray, _, _) = camera.ray_for_pixel(3, 1, 4, 2, &mut rng);
+        let (expected, _, _) = camera.cast_ray(0.75, -0.25, 0.0, &SampleCursor::random(), &mut rng);
+        assert!(glm::distance(&ray.dir, &expected.dir) < 1e-12);
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let camera = OrthographicCamera::look_at(
+            glm::vec3(1.0, 2.0, 3.0),
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0),
+            4.0,
+            3.0,
+        );
+        let mut rng = StdRng::seed_from_u64(0);
+        let (r1, _, _) = camera.cast_ray(-0.5, 0.25, 0.0, &SampleCursor::random(), &mut rng);
+        let (r2, _, _) = camera.cast_ray(0.9, -0.8, 0.0, &SampleCursor::random(), &mut rng);
+        assert!(glm::distance(&r1.dir, &r2.dir) < 1e-12);
+        assert!(glm::distance(&r1.origin, &r2.origin) > 1.0);
+    }
+
+    #[test]
+    fn aperture_spike_counts() {
+        assert_eq!(ApertureShape::Circle.spike_angles().len(), 0);
+        assert_eq!(ApertureShape::Square.spike_angles().len(), 4);
+        let regular = |n: usize| {
+            let pts = (0..n)
+                .map(|i| {
+                    let a = std::f64::consts::TAU * i as f64 / n as f64;
+                    [a.cos(), a.sin()]
+                })
+                .collect();
+            ApertureShape::Poly(Polygon::new(pts))
+        };
+        // Even blade counts pair up into n spikes, odd blade counts give 2n
+        assert_eq!(regular(6).spike_angles().len(), 6);
+        assert_eq!(regular(8).spike_angles().len(), 8);
+        assert_eq!(regular(5).spike_angles().len(), 10);
+        assert_eq!(regular(7).spike_angles().len(), 14);
+    }
+
+    #[test]
+    fn polygon_sampling_is_uniform() {
+        let heart = Polygon::get_heart(0.05, 0.05);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        // Analytic centroid of the polygon
+        let n = heart.pts.len();
+        let (mut cx, mut cy) = (0.0, 0.0);
+        for i in 0..n {
+            let [x1, y1] = heart.pts[i];
+            let [x2, y2] = heart.pts[(i + 1) % n];
+            let cross = x1 * y2 - x2 * y1;
+            cx += (x1 + x2) * cross;
+            cy += (y1 + y2) * cross;
+        }
+        let signed_area = (0..n)
+            .map(|i| {
+                let [x1, y1] = heart.pts[i];
+                let [x2, y2] = heart.pts[(i + 1) % n];
+                x1 * y2 - x2 * y1
+            })
+            .sum::<f64>()
+            / 2.0;
+        assert!((signed_area.abs() - heart.area()).abs() < 1e-9);
+        let (cx, cy) = (cx / (6.0 * signed_area), cy / (6.0 * signed_area));
+
+        let count = 100_000;
+        let (mut sx, mut sy) = (0.0, 0.0);
+        for _ in 0..count {
+            let [x, y] = heart.sample(rng.gen());
+            assert!(heart.contains(x, y));
+            sx += x;
+            sy += y;
+        }
+        assert!((sx / count as f64 - cx).abs() < 5e-3);
+        assert!((sy / count as f64 - cy).abs() < 5e-3);
+    }
+
+    #[test]
+    fn tiny_polygon_aperture_sampling_terminates() {
+        let heart = Polygon::get_heart(0.05, 0.05);
+        let [min, max] = heart.bounds();
+        assert!(max[0] - min[0] < 2.0 && max[1] - min[1] < 2.0);
+        let mut aperture = Aperture {
+            scale: 1.0,
+            shape: ApertureShape::Poly(heart.clone()),
+            inner_scale: 0.0,
+            rotation: 0.0,
+            offset: [0.0, 0.0],
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10_000 {
+            let [x, y] = aperture.sample(rng.gen(), &mut rng);
+            assert!(aperture.contains(x, y));
+        }
+
+        // An obstruction covering the whole heart falls back to the center of its box
+        aperture.inner_scale = 2.0;
+        let center = [(min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0];
+        assert_eq!(aperture.sample(rng.gen(), &mut rng), center);
+    }
+
+    #[test]
+    fn regular_polygon_is_inscribed_in_unit_circle() {
+        let hexagon = Polygon::regular(6, 0.0);
+        assert_eq!(hexagon.pts.len(), 6);
+        assert!(hexagon.contains(0.0, 0.0));
+        for [x, y] in &hexagon.pts {
+            assert!((x.hypot(*y) - 1.0).abs() < 1e-12);
+        }
+        let area = 1.5 * 3f64.sqrt();
+        assert!((hexagon.area() - area).abs() < 1e-12);
+
+        // Curved blades bulge out past the straight edges, but stay within the circle
+        let rounded = Polygon::rounded(6, 0.0, 0.5);
+        assert!(rounded.area() > area && rounded.area() < std::f64::consts::PI);
+        let (sin, cos) = std::f64::consts::FRAC_PI_6.sin_cos();
+        assert!(!hexagon.contains(0.99 * cos, 0.99 * sin));
+        assert!(Polygon::rounded(6, 0.0, 1.0).contains(0.99 * cos, 0.99 * sin));
+    }
+}
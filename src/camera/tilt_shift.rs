@@ -0,0 +1,208 @@
+use glm::vec3;
+use rand::rngs::StdRng;
+
+use super::{
+    assert_valid, build_basis, orient, validate_fov, validate_orientation, Aperture, Camera,
+    CameraError,
+};
+use crate::color::Color;
+use crate::sampler::SampleCursor;
+use crate::shape::Ray;
+
+/// A thin-lens perspective camera with a tilting and shifting lens
+///
+/// Shifting the lens moves the image window off-center without turning the camera, which
+/// keeps parallel lines parallel. Tilting the lens by a small angle tilts the plane of
+/// focus by a much larger angle, following the Scheimpflug principle, so the in-focus
+/// region becomes a wedge rather than a slab parallel to the sensor.
+///
+/// With no tilt or shift, this casts exactly the same rays as a `PinholeCamera`.
+#[derive(Clone, Debug)]
+pub struct TiltShiftCamera {
+    /// Location of the camera
+    pub eye: glm::DVec3,
+
+    /// Direction that the camera is facing (normalized).
+    pub direction: glm::DVec3,
+
+    /// Direction of "up" for screen, must be orthogonal to `direction` (normalized).
+    pub up: glm::DVec3,
+
+    /// Field of view in the longer direction as an angle in radians, in (0, pi)
+    pub fov: f64,
+
+    /// Focal distance along the view direction
+    pub focal_distance: f64,
+
+    /// Focal length of the lens, in scene units, which sets how strongly a lens tilt
+    /// tilts the plane of focus
+    pub focal_length: f64,
+
+    /// The camera aperture size and shape
+    pub aperture: Option<Aperture>,
+
+    /// Rotation of the lens plane about the horizontal axis, in radians
+    ///
+    /// Negative tilts swing the plane of focus down toward the ground, and positive
+    /// tilts swing it up.
+    pub tilt: f64,
+
+    /// Decentering of the lens, in the same normalized units as the image window
+    pub shift: glm::DVec2,
+}
+
+impl Default for TiltShiftCamera {
+    fn default() -> Self {
+        Self {
+            eye: glm::vec3(0.0, 0.0, 10.0),
+            direction: glm::vec3(0.0, 0.0, -1.0),
+            up: glm::vec3(0.0, 1.0, 0.0),
+            fov: std::f64::consts::FRAC_PI_6,
+            focal_distance: 0.0,
+            focal_length: 0.05,
+            aperture: None,
+            tilt: 0.0,
+            shift: glm::vec2(0.0, 0.0),
+        }
+    }
+}
+
+impl TiltShiftCamera {
+    /// Perspective camera looking at a point, with a given field of view
+    pub fn look_at(eye: glm::DVec3, center: glm::DVec3, up: glm::DVec3, fov: f64) -> Self {
+        let (direction, up) = orient(center - eye, &up);
+        let camera = Self {
+            eye,
+            direction,
+            up,
+            fov,
+            ..Default::default()
+        };
+        assert_valid(&camera);
+        camera
+    }
+
+    /// Focus the camera on a position, with simulated depth-of-field
+    pub fn focus(mut self, focal_point: glm::DVec3, aperture: Option<Aperture>) -> Self {
+        self.focal_distance = (focal_point - self.eye).dot(&self.direction);
+        self.aperture = aperture;
+        self
+    }
+
+    /// Set the focal length of the lens, in scene units
+    pub fn focal_length(mut self, focal_length: f64) -> Self {
+        self.focal_length = focal_length;
+        self
+    }
+
+    /// Tilt the lens about the horizontal axis, in radians
+    pub fn tilt(mut self, tilt: f64) -> Self {
+        self.tilt = tilt;
+        self
+    }
+
+    /// Shift the lens by (x, y) in normalized image units
+    pub fn shift(mut self, x: f64, y: f64) -> Self {
+        self.shift = glm::vec2(x, y);
+        self
+    }
+
+    /// Angle between the plane of focus and the sensor plane
+    ///
+    /// By the Scheimpflug principle, `tan(psi) = tan(tilt) * u / v`, where `u` and `v`
+    /// are the object and image distances of the thin lens.
+    pub fn focal_plane_angle(&self) -> f64 {
+        let u = self.focal_distance;
+        let v = (self.focal_length.recip() - u.recip()).recip();
+        (self.tilt.tan() * u / v).atan()
+    }
+}
+
+impl Camera for TiltShiftCamera {
+    fn cast_ray(
+        &self,
+        x: f64,
+        y: f64,
+        _time: f64,
+        sample: &SampleCursor,
+        rng: &mut StdRng,
+    ) -> (Ray, Color, f64) {
+        let d = (self.fov / 2.0).tan().recip();
+        let (right, up) = build_basis(&self.direction, &self.up);
+        let (x, y) = (x + self.shift.x, y + self.shift.y);
+        let mut origin = self.eye;
+        let mut new_dir = d * self.direction + x * right + y * up;
+        if let Some(ref aperture) = self.aperture {
+            // As with `PinholeCamera`, focus is measured along each ray, so the surface of
+            // focus is the tilted plane scaled by the cosine to the view direction
+            let dir = new_dir.normalize();
+            let psi = if self.tilt == 0.0 {
+                0.0
+            } else {
+                self.focal_plane_angle()
+            };
+            let normal = glm::rotate_vec3(&self.direction, psi, &right);
+            let cosine = normal.dot(&dir);
+            let [ax, ay]: [f64; 2] = aperture.sample(sample.lens(rng), rng);
+            let offset = (ax * right + ay * up) * aperture.scale;
+            new_dir = if cosine > 0.0 {
+                let t = self.focal_distance * psi.cos() * (self.direction.dot(&dir) / cosine);
+                origin + dir * t - (origin + offset)
+            } else {
+                // The plane of focus is behind the camera, so this ray focuses at infinity
+                dir
+            };
+            origin += offset;
+        }
+        (
+            Ray {
+                origin,
+                dir: new_dir.normalize(),
+            },
+            vec3(1., 1., 1.),
+            1.,
+        )
+    }
+
+    fn validate(&self) -> Result<(), CameraError> {
+        validate_fov(self.fov)?;
+        validate_orientation(&self.direction, &self.up)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{ApertureShape, PinholeCamera};
+
+    #[test]
+    fn untilted_camera_matches_pinhole() {
+        let eye = glm::vec3(1.0, 2.0, 8.0);
+        let center = glm::vec3(0.0, 0.0, 0.0);
+        let up = glm::vec3(0.0, 1.0, 0.0);
+        let aperture = Some(Aperture {
+            scale: 0.2,
+            shape: ApertureShape::Circle,
+            inner_scale: 0.0,
+            rotation: 0.0,
+            offset: [0.0, 0.0],
+        });
+        let pinhole = PinholeCamera::look_at(eye, center, up, 0.6).focus(center, aperture.clone());
+        let tilt_shift = TiltShiftCamera::look_at(eye, center, up, 0.6).focus(center, aperture);
+
+        let (mut rng1, mut rng2) = (StdRng::seed_from_u64(0), StdRng::seed_from_u64(0));
+        for &(x, y) in &[(0.0, 0.0), (0.7, -0.3), (-1.0, 0.75)] {
+            let (ray1, _, _) = pinhole.cast_ray(x, y, 0.0, &SampleCursor::random(), &mut rng1);
+            let (ray2, _, _) = tilt_shift.cast_ray(x, y, 0.0, &SampleCursor::random(), &mut rng2);
+            assert_eq!(ray1.origin, ray2.origin);
+            assert_eq!(ray1.dir, ray2.dir);
+        }
+
+        // Tilting the lens brings the near ground and the far ground into focus together
+        let tilted = tilt_shift.tilt(-0.01);
+        let psi = tilted.focal_plane_angle();
+        assert!(psi < -0.5, "{}", psi);
+    }
+}
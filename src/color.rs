@@ -4,24 +4,108 @@ pub type Color = glm::DVec3;
 /// Gamma value.
 pub const SRGB_GAMMA: f64 = 2.2;
 
-/// Construct a new color from an sRGB hex integer, applying gamma correction to
-/// return linear intensities
+/// Decode an sRGB-encoded value in [0, 1] to a linear intensity, with the exact
+/// piecewise sRGB transfer function
+pub fn srgb_to_linear(x: f64) -> f64 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encode a linear intensity in [0, 1] with the sRGB transfer function, the inverse of
+/// `srgb_to_linear`
+pub fn linear_to_srgb(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Construct a new color from an sRGB hex integer, decoding it to return linear
+/// intensities
 ///
-/// Example of use: `hex_color(0xFFFFFF)` for white, or `hex_color(0xAB23F0)` for purple.
+/// Lighting is computed with linear intensities, so this is the right choice for colors
+/// picked in an image editor or on the web. Example of use: `hex_color(0xFFFFFF)` for
+/// white, or `hex_color(0xAB23F0)` for purple.
 pub fn hex_color(x: u32) -> Color {
+    hex_color_raw(x).map(srgb_to_linear)
+}
+
+/// Construct a new color from a hex integer, taking each byte literally as a linear
+/// intensity without decoding sRGB
+///
+/// For example, `hex_color_raw(0x808080)` is a gray that reflects about half of the
+/// light, while `hex_color(0x808080)` only looks half as bright as white on a screen.
+pub fn hex_color_raw(x: u32) -> Color {
     let r = ((x >> 16) & 0xff) as f64 / 255.0;
     let g = ((x >> 8) & 0xff) as f64 / 255.0;
     let b = (x & 0xff) as f64 / 255.0;
-    glm::vec3(r.powf(SRGB_GAMMA), g.powf(SRGB_GAMMA), b.powf(SRGB_GAMMA))
+    glm::vec3(r, g, b)
+}
+
+/// Relative luminance of a linear color, using Rec. 709 coefficients
+pub fn luminance(color: &Color) -> f64 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
 }
 
-/// Convert a color to a clamped triple of sRGB unsigned bytes
+/// CIE 1931 standard observer color matching functions at a wavelength in meters
+///
+/// This uses the multi-lobe Gaussian fit from Wyman, Sloan, and Shirley, "Simple Analytic
+/// Approximations to the CIE XYZ Color Matching Functions" (2013).
+pub fn wavelength_to_xyz(wavelength: f64) -> glm::DVec3 {
+    let nm = wavelength * 1e9;
+    let g = |mu: f64, sigma1: f64, sigma2: f64| {
+        let t = (nm - mu) / if nm < mu { sigma1 } else { sigma2 };
+        (-0.5 * t * t).exp()
+    };
+    glm::vec3(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+/// Convert CIE XYZ tristimulus values to linear sRGB (D65 white point)
+pub fn xyz_to_rgb(xyz: &glm::DVec3) -> Color {
+    let m = glm::mat3(
+        3.2406, -1.5372, -0.4986, -0.9689, 1.8758, 0.0415, 0.0557, -0.2040, 1.0570,
+    );
+    m * xyz
+}
+
+/// Linear color of a blackbody radiator at a temperature in kelvin, with unit luminance
+///
+/// Planck's law is integrated against the CIE color matching functions over the
+/// visible range. Out-of-gamut components of very hot or cold spectra are clipped.
+pub fn blackbody_color(temperature: f64) -> Color {
+    const PLANCK: f64 = 6.626_070_15e-34;
+    const LIGHT_SPEED: f64 = 2.997_924_58e8;
+    const BOLTZMANN: f64 = 1.380_649e-23;
+    let steps = 400;
+    let (lo, hi) = (380.0e-9, 780.0e-9);
+    let xyz: glm::DVec3 = (0..steps)
+        .map(|i| {
+            let wavelength = lo + (hi - lo) * (i as f64 + 0.5) / steps as f64;
+            // Spectral radiance, up to a constant factor that is normalized away
+            let radiance = 1.0
+                / (wavelength.powi(5)
+                    * ((PLANCK * LIGHT_SPEED / (wavelength * BOLTZMANN * temperature)).exp()
+                        - 1.0));
+            wavelength_to_xyz(wavelength) * radiance
+        })
+        .sum();
+    let rgb = xyz_to_rgb(&(xyz / xyz.y)).map(|c| c.max(0.0));
+    rgb / luminance(&rgb)
+}
+
+/// Convert a linear color to a clamped triple of sRGB unsigned bytes, the inverse of
+/// `hex_color`
 pub fn color_bytes(color: &Color) -> [u8; 3] {
-    [
-        (color.x.max(0.0).min(1.0).powf(1.0 / SRGB_GAMMA) * 255.0) as u8,
-        (color.y.max(0.0).min(1.0).powf(1.0 / SRGB_GAMMA) * 255.0) as u8,
-        (color.z.max(0.0).min(1.0).powf(1.0 / SRGB_GAMMA) * 255.0) as u8,
-    ]
+    let byte = |x: f64| (linear_to_srgb(x.clamp(0.0, 1.0)) * 255.0).round() as u8;
+    [byte(color.x), byte(color.y), byte(color.z)]
 }
 
 #[cfg(test)]
@@ -37,4 +121,40 @@ mod tests {
         assert_eq!(color_bytes(&white), [255, 255, 255]);
         assert_eq!(color_bytes(&red), [255, 0, 0]);
     }
+
+    #[test]
+    fn hex_color_decodes_srgb() {
+        let gray = hex_color(0x808080);
+        assert!((gray.x - 0.216).abs() < 1e-3, "{}", gray);
+        assert_eq!(gray.x, gray.z);
+        assert_eq!(
+            hex_color_raw(0x808080),
+            glm::vec3(1.0, 1.0, 1.0) * 128.0 / 255.0
+        );
+        for x in 0..=255 {
+            let value = x * 0x010101;
+            assert_eq!(color_bytes(&hex_color(value)), [x as u8; 3]);
+        }
+    }
+
+    #[test]
+    fn blackbody_shifts_toward_blue() {
+        let temperatures = [1800.0, 2700.0, 4000.0, 6500.0, 10000.0];
+        let colors: Vec<_> = temperatures.iter().map(|&t| blackbody_color(t)).collect();
+        for color in &colors {
+            assert!((luminance(color) - 1.0).abs() < 1e-9);
+        }
+        for pair in colors.windows(2) {
+            assert!(pair[1].z / pair[1].x > pair[0].z / pair[0].x);
+        }
+        // Incandescent bulbs are warm, and 6500K is close to the D65 white point
+        let warm = blackbody_color(2700.0);
+        assert!(warm.x > warm.y && warm.y > warm.z);
+        let daylight = blackbody_color(6500.0);
+        assert!(
+            (daylight - glm::vec3(1.0, 1.0, 1.0)).amax() < 0.1,
+            "{}",
+            daylight
+        );
+    }
 }
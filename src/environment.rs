@@ -1,4 +1,28 @@
-use crate::color::Color;
+use rand::{rngs::StdRng, Rng};
+use rayon::prelude::*;
+use std::sync::OnceLock;
+
+use crate::color::{blackbody_color, luminance, xyz_to_rgb, Color};
+use crate::material::local_to_world;
+
+/// Number of polar angle steps used to project an environment onto spherical harmonics
+const SH_RESOLUTION: usize = 128;
+
+/// Angular radius of the sun as seen from the ground, in radians
+const SUN_ANGULAR_RADIUS: f64 = 0.004_65;
+
+/// Luminance of the sun above the atmosphere, in kcd/m²
+const SUN_LUMINANCE: f64 = 2.0e6;
+
+/// Radiance of the scene per kcd/m² of sky luminance, so that a clear zenith is around 0.5
+const SKY_LUMINANCE_SCALE: f64 = 0.1;
+
+/// Wavelengths in micrometers at which the red, green, and blue channels of the sun are
+/// attenuated by the atmosphere
+const SUN_WAVELENGTHS: [f64; 3] = [0.61, 0.55, 0.465];
+
+/// Height of the table that the sky dome is baked into for importance sampling
+const SKY_TABLE_HEIGHT: u32 = 32;
 
 /// High-dynamic-range equirectangular image for lighting 3D scenes
 #[derive(Clone)]
@@ -11,6 +35,64 @@ pub struct Hdri {
 
     /// Buffer of floating-point RGB pixels
     buf: Vec<Color>,
+
+    /// Distribution for importance sampling, built on first use
+    distribution: OnceLock<HdriDistribution>,
+}
+
+/// Piecewise-constant distribution over the pixels of an HDRI
+#[derive(Clone)]
+struct HdriDistribution {
+    /// Sampling weight of each pixel, proportional to luminance times solid angle
+    weights: Vec<f64>,
+
+    /// Cumulative distribution of weights over rows
+    row_cdf: Vec<f64>,
+
+    /// Cumulative distribution of weights within each row
+    col_cdf: Vec<f64>,
+
+    /// Sum of all of the weights
+    total: f64,
+}
+
+impl HdriDistribution {
+    /// Build the distribution for an image, computing the rows in parallel
+    ///
+    /// Rows with zero weight, such as black rows or the poles, are left with zero width
+    /// in `row_cdf`, so they are never selected and sampling never searches an empty row.
+    fn new(width: usize, height: usize, buf: &[Color]) -> Self {
+        let mut weights = vec![0.0; width * height];
+        let mut col_cdf = vec![0.0; width * height];
+        weights
+            .par_chunks_mut(width)
+            .zip(col_cdf.par_chunks_mut(width))
+            .zip(buf.par_chunks(width))
+            .enumerate()
+            .for_each(|(y, ((weights, cdf), colors))| {
+                let polar = (y as f64 + 0.5) / height as f64 * std::f64::consts::PI;
+                let mut sum = 0.0;
+                for ((weight, c), color) in weights.iter_mut().zip(cdf).zip(colors) {
+                    *weight = luminance(color).max(0.0) * polar.sin();
+                    sum += *weight;
+                    *c = sum;
+                }
+            });
+        let row_cdf: Vec<f64> = col_cdf
+            .chunks(width)
+            .scan(0.0, |total, row| {
+                *total += row[width - 1];
+                Some(*total)
+            })
+            .collect();
+        let total = row_cdf[height - 1];
+        Self {
+            weights,
+            row_cdf,
+            col_cdf,
+            total,
+        }
+    }
 }
 
 impl Hdri {
@@ -18,7 +100,75 @@ impl Hdri {
     pub fn new(width: u32, height: u32, buf: Vec<Color>) -> Self {
         assert!(buf.len() == width as usize * height as usize);
         assert!(width > 0 && height > 0);
-        Self { width, height, buf }
+        Self {
+            width,
+            height,
+            buf,
+            distribution: OnceLock::new(),
+        }
+    }
+
+    /// Distribution for importance sampling the image, building it on first use
+    fn distribution(&self) -> &HdriDistribution {
+        self.distribution.get_or_init(|| {
+            HdriDistribution::new(self.width as usize, self.height as usize, &self.buf)
+        })
+    }
+
+    /// Sample a direction toward the environment proportionally to its luminance,
+    /// returning (direction, radiance, PDF), or `None` if the image is black
+    ///
+    /// The image is treated as piecewise-constant over pixels, and the PDF is
+    /// with respect to solid angle.
+    pub fn sample(&self, rng: &mut StdRng) -> Option<(glm::DVec3, Color, f64)> {
+        let distribution = self.distribution();
+        if distribution.total <= 0.0 {
+            return None;
+        }
+        let w = self.width as usize;
+        let target = rng.gen::<f64>() * distribution.total;
+        let row = distribution
+            .row_cdf
+            .partition_point(|&c| c <= target)
+            .min(distribution.row_cdf.len() - 1);
+        let cols = &distribution.col_cdf[row * w..(row + 1) * w];
+        let target = rng.gen::<f64>() * cols[w - 1];
+        let col = cols.partition_point(|&c| c <= target).min(w - 1);
+
+        let azimuth = (col as f64 + rng.gen::<f64>()) / self.width as f64 * std::f64::consts::TAU;
+        let polar = (row as f64 + rng.gen::<f64>()) / self.height as f64 * std::f64::consts::PI;
+        let (sin_p, cos_p) = polar.sin_cos();
+        let (sin_a, cos_a) = azimuth.sin_cos();
+        let dir = glm::vec3(-cos_a * sin_p, cos_p, -sin_a * sin_p);
+        let pdf = self.pdf(&dir);
+        if pdf > 0.0 {
+            Some((dir, self.get_color(&dir), pdf))
+        } else {
+            None
+        }
+    }
+
+    /// Probability density of sampling a direction with `sample`
+    pub fn pdf(&self, dir: &glm::DVec3) -> f64 {
+        let distribution = self.distribution();
+        if distribution.total <= 0.0 {
+            return 0.0;
+        }
+        let dir = dir.normalize();
+        let azimuth = dir.z.atan2(dir.x) + std::f64::consts::PI;
+        let polar = dir.y.clamp(-1.0, 1.0).acos();
+        let sin_p = polar.sin();
+        if sin_p <= 0.0 {
+            return 0.0;
+        }
+        let col = ((azimuth / std::f64::consts::TAU * self.width as f64) as usize)
+            .min(self.width as usize - 1);
+        let row = ((polar / std::f64::consts::PI * self.height as f64) as usize)
+            .min(self.height as usize - 1);
+        let p = distribution.weights[row * self.width as usize + col] / distribution.total;
+        // Each pixel spans (2π / width) * (π / height) in (azimuth, polar) space
+        p * (self.width * self.height) as f64
+            / (2.0 * std::f64::consts::PI * std::f64::consts::PI * sin_p)
     }
 
     /// Sample a color from a direction in the environment
@@ -52,13 +202,352 @@ impl Hdri {
     }
 }
 
+/// Analytic daylight from a clear sky and the sun, over a diffuse ground plane
+///
+/// The sky follows Preetham, Shirley, and Smits, "A Practical Analytic Model for
+/// Daylight" (1999), with the horizon regularization of Hosek and Wilkie, "An Analytic
+/// Model for Full Spectral Sky-Dome Radiance" (2012). The sun is a disc of blackbody
+/// light, reddened by Rayleigh and aerosol extinction along its path through the
+/// atmosphere. Below the horizon, the ground reflects the light of the sun and sky.
+#[derive(Clone)]
+pub struct Sky {
+    /// Unit direction toward the sun
+    sun_direction: glm::DVec3,
+
+    /// Perez distribution coefficients for luminance and the two chromaticities
+    perez: [[f64; 5]; 3],
+
+    /// Luminance and chromaticities at the zenith, divided by the Perez function there
+    zenith: [f64; 3],
+
+    /// Radiance of the sun disc, or black if the sun is below the horizon
+    sun_radiance: Color,
+
+    /// Radiance of the ground below the horizon
+    ground: Color,
+
+    /// Table of the sky and ground radiance, baked on first use for importance sampling
+    table: OnceLock<Hdri>,
+}
+
+impl Sky {
+    /// Create a sky lit by the sun in a direction, where the y axis points up
+    ///
+    /// Turbidity measures the haze in the atmosphere, from 2 for a very clear sky to 10
+    /// for a hazy one. The ground albedo is the fraction of light that the ground reflects.
+    pub fn new(sun_direction: glm::DVec3, turbidity: f64, ground_albedo: Color) -> Self {
+        assert!(
+            (2.0..=10.0).contains(&turbidity),
+            "Turbidity must be between 2 and 10"
+        );
+        let sun_direction = sun_direction.normalize();
+        let t = turbidity;
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ],
+            [
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ],
+            [
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        // The sky model is only valid with the sun above the horizon
+        let theta_s = sun_direction.y.clamp(0.0, 1.0).acos();
+        let chi = (4.0 / 9.0 - t / 120.0) * (std::f64::consts::PI - 2.0 * theta_s);
+        let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+        let chromaticity = |m: [[f64; 4]; 3]| {
+            let angles = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+            let row = |r: [f64; 4]| r.iter().zip(&angles).map(|(a, b)| a * b).sum::<f64>();
+            t * t * row(m[0]) + t * row(m[1]) + row(m[2])
+        };
+        let zenith_x = chromaticity([
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_y = chromaticity([
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let zenith_values = [zenith_luminance.max(0.0), zenith_x, zenith_y];
+        let mut zenith = [0.0; 3];
+        for i in 0..3 {
+            zenith[i] = zenith_values[i] / perez_function(&perez[i], 1.0, theta_s);
+        }
+
+        let sun_radiance = if sun_direction.y > 0.0 {
+            // Relative optical air mass, from Kasten and Young (1989)
+            let elevation = 90.0 - theta_s.to_degrees();
+            let air_mass = 1.0 / (sun_direction.y + 0.50572 * (elevation + 6.07995).powf(-1.6364));
+            let beta = 0.04608 * t - 0.04586;
+            let transmittance = SUN_WAVELENGTHS.map(|lambda: f64| {
+                let rayleigh = 0.008735 * lambda.powf(-4.08);
+                let aerosol = beta * lambda.powf(-1.3);
+                (-air_mass * (rayleigh + aerosol)).exp()
+            });
+            blackbody_color(5778.0).component_mul(&glm::vec3(
+                transmittance[0],
+                transmittance[1],
+                transmittance[2],
+            )) * (SUN_LUMINANCE * SKY_LUMINANCE_SCALE)
+        } else {
+            glm::vec3(0.0, 0.0, 0.0)
+        };
+
+        let mut sky = Self {
+            sun_direction,
+            perez,
+            zenith,
+            sun_radiance,
+            ground: glm::vec3(0.0, 0.0, 0.0),
+            table: OnceLock::new(),
+        };
+
+        // Irradiance on the ground from the sun and the upper hemisphere of the sky
+        let mut irradiance = sun_radiance * (sun_solid_angle() * sun_direction.y.max(0.0));
+        let steps = 64;
+        let (d_polar, d_azimuth) = (
+            std::f64::consts::FRAC_PI_2 / steps as f64,
+            std::f64::consts::TAU / (2 * steps) as f64,
+        );
+        for i in 0..steps {
+            let polar = (i as f64 + 0.5) * d_polar;
+            let (sin_p, cos_p) = polar.sin_cos();
+            for j in 0..2 * steps {
+                let azimuth = (j as f64 + 0.5) * d_azimuth;
+                let dir = glm::vec3(sin_p * azimuth.cos(), cos_p, sin_p * azimuth.sin());
+                irradiance += sky.sky_radiance(&dir) * (cos_p * sin_p * d_polar * d_azimuth);
+            }
+        }
+        sky.ground = ground_albedo.component_mul(&irradiance) / std::f64::consts::PI;
+        sky
+    }
+
+    /// Radiance of the sky or ground in a direction, without the sun disc
+    fn sky_radiance(&self, dir: &glm::DVec3) -> Color {
+        if dir.y <= 0.0 {
+            return self.ground;
+        }
+        let cos_theta = dir.y;
+        let gamma = dir.dot(&self.sun_direction).clamp(-1.0, 1.0).acos();
+        let [luminance, x, y] =
+            [0, 1, 2].map(|i| self.zenith[i] * perez_function(&self.perez[i], cos_theta, gamma));
+        if y <= 0.0 {
+            return glm::vec3(0.0, 0.0, 0.0);
+        }
+        let xyz = glm::vec3(x / y, 1.0, (1.0 - x - y) / y) * (luminance * SKY_LUMINANCE_SCALE);
+        xyz_to_rgb(&xyz).map(|c| c.max(0.0))
+    }
+
+    /// Sample a color from a direction in the sky
+    pub fn get_color(&self, dir: &glm::DVec3) -> Color {
+        let dir = dir.normalize();
+        let mut color = self.sky_radiance(&dir);
+        if dir.y > 0.0 && dir.dot(&self.sun_direction) >= SUN_ANGULAR_RADIUS.cos() {
+            color += self.sun_radiance;
+        }
+        color
+    }
+
+    /// Table of the sky for sampling the dome, baking it on first use
+    fn table(&self) -> &Hdri {
+        self.table.get_or_init(|| {
+            let (width, height) = (2 * SKY_TABLE_HEIGHT, SKY_TABLE_HEIGHT);
+            let buf = (0..width * height)
+                .into_par_iter()
+                .map(|i| {
+                    let polar = ((i / width) as f64 + 0.5) / height as f64 * std::f64::consts::PI;
+                    let azimuth = ((i % width) as f64 + 0.5) / width as f64 * std::f64::consts::TAU;
+                    let (sin_p, cos_p) = polar.sin_cos();
+                    let (sin_a, cos_a) = azimuth.sin_cos();
+                    self.sky_radiance(&glm::vec3(-cos_a * sin_p, cos_p, -sin_a * sin_p))
+                })
+                .collect();
+            Hdri::new(width, height, buf)
+        })
+    }
+
+    /// Probability of sampling the sun disc rather than the dome, proportional to power
+    fn sun_probability(&self) -> f64 {
+        let sun = luminance(&self.sun_radiance) * sun_solid_angle();
+        let distribution = self.table().distribution();
+        let dome = distribution.total * std::f64::consts::TAU * std::f64::consts::PI
+            / distribution.weights.len() as f64;
+        if sun + dome > 0.0 {
+            sun / (sun + dome)
+        } else {
+            0.0
+        }
+    }
+
+    /// Sample a direction toward the sun or the sky dome, returning (direction, radiance,
+    /// PDF), or `None` if the sky is black
+    ///
+    /// The sun is chosen in proportion to its share of the total power, and sampled
+    /// uniformly over its disc. The dome is sampled from a coarse table of its luminance.
+    pub fn sample(&self, rng: &mut StdRng) -> Option<(glm::DVec3, Color, f64)> {
+        let dir = if rng.gen::<f64>() < self.sun_probability() {
+            let (u, v): (f64, f64) = rng.gen();
+            let cos_t = 1.0 - u * (1.0 - SUN_ANGULAR_RADIUS.cos());
+            let sin_t = (1.0 - cos_t * cos_t).max(0.0).sqrt();
+            let phi = std::f64::consts::TAU * v;
+            local_to_world(&self.sun_direction)
+                * glm::vec3(sin_t * phi.cos(), sin_t * phi.sin(), cos_t)
+        } else {
+            self.table().sample(rng)?.0
+        };
+        let pdf = self.pdf(&dir);
+        if pdf > 0.0 {
+            Some((dir, self.get_color(&dir), pdf))
+        } else {
+            None
+        }
+    }
+
+    /// Probability density of sampling a direction with `sample`
+    pub fn pdf(&self, dir: &glm::DVec3) -> f64 {
+        let dir = dir.normalize();
+        let p_sun = self.sun_probability();
+        let sun = if dir.dot(&self.sun_direction) >= SUN_ANGULAR_RADIUS.cos() {
+            p_sun / sun_solid_angle()
+        } else {
+            0.0
+        };
+        sun + (1.0 - p_sun) * self.table().pdf(&dir)
+    }
+}
+
+/// Solid angle subtended by the sun
+fn sun_solid_angle() -> f64 {
+    std::f64::consts::TAU * (1.0 - SUN_ANGULAR_RADIUS.cos())
+}
+
+/// Perez sky distribution at a zenith angle cosine and angle `gamma` from the sun
+///
+/// The zenith angle cosine is offset by 0.01 as in Hosek and Wilkie, which keeps the
+/// distribution finite at the horizon.
+fn perez_function(coefficients: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coefficients;
+    (1.0 + a * (b / (cos_theta + 0.01)).exp())
+        * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+/// Irradiance from an environment, approximated by its first 9 spherical harmonics
+///
+/// Irradiance is a very smooth function of the surface normal, so these coefficients
+/// reproduce it to within a few percent for most environments. See Ramamoorthi and
+/// Hanrahan, "An Efficient Representation for Irradiance Environment Maps" (2001).
+#[derive(Clone, Debug)]
+pub struct ShIrradiance {
+    /// Coefficients of the radiance, for bands l = 0, 1, 2 in order
+    coefficients: [Color; 9],
+}
+
+impl ShIrradiance {
+    /// Project the radiance of an environment onto spherical harmonics
+    pub fn new(environment: &Environment) -> Self {
+        let (rows, cols) = (SH_RESOLUTION, 2 * SH_RESOLUTION);
+        let d_polar = std::f64::consts::PI / rows as f64;
+        let d_azimuth = std::f64::consts::TAU / cols as f64;
+        let mut coefficients = [glm::vec3(0.0, 0.0, 0.0); 9];
+        for row in 0..rows {
+            let polar = (row as f64 + 0.5) * d_polar;
+            let (sin_p, cos_p) = polar.sin_cos();
+            for col in 0..cols {
+                let azimuth = (col as f64 + 0.5) * d_azimuth;
+                let (sin_a, cos_a) = azimuth.sin_cos();
+                let dir = glm::vec3(-cos_a * sin_p, cos_p, -sin_a * sin_p);
+                let radiance = environment.get_color(&dir) * (sin_p * d_polar * d_azimuth);
+                for (coefficient, y) in coefficients.iter_mut().zip(sh_basis(&dir)) {
+                    *coefficient += radiance * y;
+                }
+            }
+        }
+        Self { coefficients }
+    }
+
+    /// Irradiance on a surface facing the given normal, ignoring occlusion
+    pub fn irradiance(&self, normal: &glm::DVec3) -> Color {
+        // Convolution of each band with the clamped cosine lobe
+        const BAND_SCALE: [f64; 9] = [
+            std::f64::consts::PI,
+            std::f64::consts::TAU / 3.0,
+            std::f64::consts::TAU / 3.0,
+            std::f64::consts::TAU / 3.0,
+            std::f64::consts::FRAC_PI_4,
+            std::f64::consts::FRAC_PI_4,
+            std::f64::consts::FRAC_PI_4,
+            std::f64::consts::FRAC_PI_4,
+            std::f64::consts::FRAC_PI_4,
+        ];
+        let irradiance = self
+            .coefficients
+            .iter()
+            .zip(sh_basis(&normal.normalize()))
+            .zip(BAND_SCALE)
+            .map(|((coefficient, y), scale)| coefficient * (y * scale))
+            .sum::<Color>();
+        irradiance.map(|c| c.max(0.0))
+    }
+}
+
+/// Real spherical harmonics of bands l = 0, 1, 2 evaluated at a unit direction
+fn sh_basis(dir: &glm::DVec3) -> [f64; 9] {
+    let (x, y, z) = (dir.x, dir.y, dir.z);
+    [
+        0.282095,
+        0.488603 * y,
+        0.488603 * z,
+        0.488603 * x,
+        1.092548 * x * y,
+        1.092548 * y * z,
+        0.315392 * (3.0 * z * z - 1.0),
+        1.092548 * x * z,
+        0.546274 * (x * x - y * y),
+    ]
+}
+
 /// An environment map for lighting 3D scenes
 pub enum Environment {
     /// Solid-color environment lighting
     Color(Color),
 
+    /// Vertical gradient, blending from `bottom` (looking down) to `top` (looking up)
+    Gradient {
+        /// Color in the +y direction
+        top: Color,
+        /// Color in the -y direction
+        bottom: Color,
+    },
+
     /// High-dynamic-range image environment lighting
     Hdri(Hdri),
+
+    /// Analytic daylight sky with a sun
+    Sky(Sky),
+
+    /// Another environment, with a cached approximation of its irradiance
+    ///
+    /// Rough diffuse surfaces at the last bounce of a path are lit by the cached
+    /// irradiance instead of by sampling the environment, which is cheaper and smoother
+    /// but ignores occlusion. See `with_sh_cache`.
+    ShCached(Box<Environment>, ShIrradiance),
 }
 
 impl Default for Environment {
@@ -68,11 +557,254 @@ impl Default for Environment {
 }
 
 impl Environment {
+    /// Cache a spherical harmonic approximation of the irradiance from this environment
+    ///
+    /// This is meant for fast previews, where diffuse surfaces get approximate
+    /// image-based lighting without a shadow ray and lookup into the environment.
+    pub fn with_sh_cache(self) -> Self {
+        match self {
+            Self::ShCached(..) => self,
+            environment => {
+                let irradiance = ShIrradiance::new(&environment);
+                Self::ShCached(Box::new(environment), irradiance)
+            }
+        }
+    }
+
+    /// Returns the cached irradiance of the environment, if any
+    pub fn sh_cache(&self) -> Option<&ShIrradiance> {
+        match self {
+            Self::ShCached(_, irradiance) => Some(irradiance),
+            _ => None,
+        }
+    }
+
     /// Sample a color from a direction in the environment
     pub fn get_color(&self, dir: &glm::DVec3) -> Color {
         match self {
+            Self::ShCached(environment, _) => environment.get_color(dir),
             Self::Color(color) => *color,
+            Self::Gradient { top, bottom } => {
+                let t = (dir.normalize().y + 1.0) / 2.0;
+                glm::mix(bottom, top, t)
+            }
             Self::Hdri(hdri) => hdri.get_color(dir),
+            Self::Sky(sky) => sky.get_color(dir),
+        }
+    }
+
+    /// Sample a direction for direct lighting, returning (direction, radiance, PDF)
+    ///
+    /// Only image and sky environments are importance sampled; solid colors and
+    /// gradients are left to be found by BSDF sampling.
+    pub fn sample(&self, rng: &mut StdRng) -> Option<(glm::DVec3, Color, f64)> {
+        match self {
+            Self::Color(_) | Self::Gradient { .. } => None,
+            Self::Hdri(hdri) => hdri.sample(rng),
+            Self::Sky(sky) => sky.sample(rng),
+            Self::ShCached(environment, _) => environment.sample(rng),
+        }
+    }
+
+    /// Probability density of sampling a direction with `sample`
+    pub fn pdf(&self, dir: &glm::DVec3) -> f64 {
+        match self {
+            Self::Color(_) | Self::Gradient { .. } => 0.0,
+            Self::Hdri(hdri) => hdri.pdf(dir),
+            Self::Sky(sky) => sky.pdf(dir),
+            Self::ShCached(environment, _) => environment.pdf(dir),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn hdri_sampling_follows_luminance() {
+        let (width, height) = (8, 4);
+        let buf = (0..width * height)
+            .map(|i| glm::vec3(1.0, 1.0, 1.0) * ((i * 7) % 5 + 1) as f64)
+            .collect();
+        let hdri = Hdri::new(width, height, buf);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let samples = 100_000;
+        let mut counts = vec![0.0; (width * height) as usize];
+        for _ in 0..samples {
+            let (dir, _, pdf) = hdri.sample(&mut rng).unwrap();
+            assert!((pdf - hdri.pdf(&dir)).abs() <= 1e-9 * pdf);
+            let azimuth = dir.z.atan2(dir.x) + std::f64::consts::PI;
+            let polar = dir.y.acos();
+            let col = (azimuth / std::f64::consts::TAU * width as f64) as usize;
+            let row = (polar / std::f64::consts::PI * height as f64) as usize;
+            counts[row.min(3) * width as usize + col.min(7)] += 1.0;
+        }
+
+        // Chi-square test against the expected luminance-weighted distribution,
+        // with 31 degrees of freedom (p = 0.001 at 61.1)
+        let chi2: f64 = counts
+            .iter()
+            .zip(&hdri.distribution().weights)
+            .map(|(count, weight)| {
+                let expected = samples as f64 * weight / hdri.distribution().total;
+                (count - expected).powi(2) / expected
+            })
+            .sum();
+        assert!(chi2 < 61.1, "chi-square statistic {}", chi2);
+    }
+
+    #[test]
+    fn gradient_blends_by_height() {
+        let top = glm::vec3(0.2, 0.4, 1.0);
+        let bottom = glm::vec3(1.0, 0.8, 0.6);
+        let env = Environment::Gradient { top, bottom };
+        assert_eq!(env.get_color(&glm::vec3(0.0, 3.0, 0.0)), top);
+        assert_eq!(env.get_color(&glm::vec3(0.0, -3.0, 0.0)), bottom);
+        let horizon = env.get_color(&glm::vec3(1.0, 0.0, -1.0));
+        assert!((horizon - (top + bottom) / 2.0).norm() < 1e-12);
+    }
+
+    #[test]
+    fn sh_irradiance_matches_integration() {
+        let buf = (0..32 * 16)
+            .map(|i| {
+                let (x, y) = ((i % 32) as f64, (i / 32) as f64);
+                glm::vec3(
+                    1.0 + (x / 5.0).sin(),
+                    2.0 - y / 8.0,
+                    0.5 + (x * y / 40.0).cos(),
+                )
+            })
+            .collect();
+        let env = Environment::Hdri(Hdri::new(32, 16, buf)).with_sh_cache();
+        let cache = env.sh_cache().unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..5 {
+            let n = glm::vec3(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            )
+            .normalize();
+            // Brute-force cosine-weighted integral over the hemisphere around n
+            let steps = 400;
+            let mut expected = glm::vec3(0.0, 0.0, 0.0);
+            for i in 0..steps {
+                for j in 0..2 * steps {
+                    let polar = (i as f64 + 0.5) / steps as f64 * std::f64::consts::PI;
+                    let azimuth = (j as f64 + 0.5) / steps as f64 * std::f64::consts::PI;
+                    let (sin_p, cos_p) = polar.sin_cos();
+                    let dir = glm::vec3(sin_p * azimuth.cos(), cos_p, sin_p * azimuth.sin());
+                    let cos = dir.dot(&n);
+                    if cos > 0.0 {
+                        let d_omega = sin_p * (std::f64::consts::PI / steps as f64).powi(2);
+                        expected += env.get_color(&dir) * (cos * d_omega);
+                    }
+                }
+            }
+            let irradiance = cache.irradiance(&n);
+            let error = (irradiance - expected).abs().max() / expected.max();
+            assert!(error < 0.05, "{} {}", irradiance, expected);
+        }
+    }
+
+    #[test]
+    fn hdri_cdf_is_monotonic() {
+        let (width, height) = (16, 8);
+        // Include black rows, which must not break the distribution
+        let buf = (0..width * height)
+            .map(|i| match i / width {
+                2 | 5 => glm::vec3(0.0, 0.0, 0.0),
+                _ => glm::vec3(1.0, 0.5, 0.25) * ((i * 11) % 7) as f64,
+            })
+            .collect();
+        let hdri = Hdri::new(width, height, buf);
+        let distribution = hdri.distribution();
+        for row in distribution.col_cdf.chunks(width as usize) {
+            assert!(row.windows(2).all(|w| w[0] <= w[1]));
+        }
+        assert!(distribution.row_cdf.windows(2).all(|w| w[0] <= w[1]));
+        let total: f64 = distribution.weights.iter().sum();
+        assert_eq!(
+            distribution.row_cdf[height as usize - 1],
+            distribution.total
+        );
+        assert!((distribution.total - total).abs() < 1e-12 * total);
+        // Sampling never lands in a black row
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..1000 {
+            let (dir, _, _) = hdri.sample(&mut rng).unwrap();
+            let row = (dir.y.acos() / std::f64::consts::PI * height as f64) as u32;
+            assert!(row != 2 && row != 5);
+        }
+    }
+
+    #[test]
+    fn sky_is_brightest_toward_the_sun() {
+        let sun = glm::vec3(1.0, 0.5, -0.3).normalize();
+        let sky = Sky::new(sun, 3.0, glm::vec3(0.3, 0.3, 0.3));
+        let toward_sun = luminance(&sky.get_color(&sun));
+        let zenith = luminance(&sky.get_color(&glm::vec3(0.0, 1.0, 0.0)));
+        assert!(zenith > 0.0);
+        assert!(toward_sun > 1000.0 * zenith);
+        // Just outside the disc, the circumsolar glow is still brighter than the zenith
+        let near_sun = (sun + glm::vec3(0.0, 0.02, 0.0)).normalize();
+        assert!(luminance(&sky.get_color(&near_sun)) > zenith);
+
+        for sun_y in [-0.2, 0.0, 0.05, 0.5, 1.0] {
+            let sky = Sky::new(glm::vec3(0.3, sun_y, 0.4), 8.0, glm::vec3(0.5, 0.4, 0.2));
+            for i in 0..64 {
+                for j in 0..128 {
+                    let polar = (i as f64 + 0.5) / 64.0 * std::f64::consts::PI;
+                    let azimuth = j as f64 / 128.0 * std::f64::consts::TAU;
+                    let (sin_p, cos_p) = polar.sin_cos();
+                    let dir = glm::vec3(sin_p * azimuth.cos(), cos_p, sin_p * azimuth.sin());
+                    let color = sky.get_color(&dir);
+                    assert!(
+                        color.iter().all(|c| c.is_finite() && *c >= 0.0),
+                        "{}",
+                        color
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sky_sampling_is_unbiased() {
+        let sun = glm::vec3(-0.4, 0.3, 0.8).normalize();
+        let sky = Sky::new(sun, 4.0, glm::vec3(0.2, 0.2, 0.2));
+
+        // Integrate the luminance of the dome numerically, and of the sun disc exactly
+        let steps = 512;
+        let mut expected = luminance(&sky.sun_radiance) * sun_solid_angle();
+        for i in 0..steps {
+            let polar = (i as f64 + 0.5) / steps as f64 * std::f64::consts::PI;
+            let (sin_p, cos_p) = polar.sin_cos();
+            for j in 0..2 * steps {
+                let azimuth = (j as f64 + 0.5) / steps as f64 * std::f64::consts::PI;
+                let dir = glm::vec3(sin_p * azimuth.cos(), cos_p, sin_p * azimuth.sin());
+                let d_omega = sin_p * (std::f64::consts::PI / steps as f64).powi(2);
+                expected += luminance(&sky.sky_radiance(&dir)) * d_omega;
+            }
+        }
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let samples = 100_000;
+        let mut estimate = 0.0;
+        for _ in 0..samples {
+            let (dir, color, pdf) = sky.sample(&mut rng).unwrap();
+            assert!((pdf - sky.pdf(&dir)).abs() <= 1e-9 * pdf);
+            estimate += luminance(&color) / pdf / samples as f64;
         }
+        assert!(
+            (estimate - expected).abs() < 0.02 * expected,
+            "{} {}",
+            estimate,
+            expected
+        );
     }
 }
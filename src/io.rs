@@ -2,7 +2,9 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::fs::File;
 use std::io::{self, prelude::*, BufReader, SeekFrom};
+use std::path::Path;
 
+use crate::light::IesProfile;
 use crate::material::Material;
 use crate::object::Object;
 use crate::shape::{Mesh, Triangle};
@@ -27,6 +29,7 @@ fn invalid_data(message: impl Into<Box<dyn Error + Send + Sync>>) -> io::Error {
 pub fn load_obj(file: File) -> io::Result<Mesh> {
     let mut vertices: Vec<glm::DVec3> = Vec::new();
     let mut normals: Vec<glm::DVec3> = Vec::new();
+    let mut texcoords: Vec<glm::DVec2> = Vec::new();
     let mut triangles = Vec::new();
 
     let reader = BufReader::new(file);
@@ -44,7 +47,8 @@ pub fn load_obj(file: File) -> io::Result<Mesh> {
             }
             "vt" => {
                 // vertex texture
-                eprintln!("Warning: Found 'vt' in .OBJ file, unimplemented, skipping...");
+                let vt = parse_obj_uv(&tokens)?;
+                texcoords.push(vt);
             }
             "vn" => {
                 // vertex normal
@@ -53,7 +57,7 @@ pub fn load_obj(file: File) -> io::Result<Mesh> {
             }
             "f" => {
                 // face
-                let face = parse_obj_face(&tokens, &vertices, &normals)?;
+                let face = parse_obj_face(&tokens, &vertices, &normals, &texcoords)?;
                 triangles.extend(face);
             }
             "mtllib" => {
@@ -85,6 +89,7 @@ pub fn load_obj_with_mtl(obj_file: File, mtl_file: File) -> io::Result<Vec<Objec
 
     let mut vertices: Vec<glm::DVec3> = Vec::new();
     let mut normals: Vec<glm::DVec3> = Vec::new();
+    let mut texcoords: Vec<glm::DVec2> = Vec::new();
     let mut objects = Vec::new();
 
     let mut current_triangles = Vec::new();
@@ -106,7 +111,8 @@ pub fn load_obj_with_mtl(obj_file: File, mtl_file: File) -> io::Result<Vec<Objec
             }
             "vt" => {
                 // vertex texture
-                eprintln!("Warning: Found 'vt' in .OBJ file, unimplemented, skipping...");
+                let vt = parse_obj_uv(&tokens)?;
+                texcoords.push(vt);
             }
             "vn" => {
                 // vertex normal
@@ -115,23 +121,21 @@ pub fn load_obj_with_mtl(obj_file: File, mtl_file: File) -> io::Result<Vec<Objec
             }
             "f" => {
                 // face
-                let face = parse_obj_face(&tokens, &vertices, &normals)?;
+                let face = parse_obj_face(&tokens, &vertices, &normals, &texcoords)?;
                 current_triangles.extend(face);
             }
-            "usemtl" => {
+            "usemtl" if last_usemtl.as_deref() != Some(tokens[1]) => {
                 // material
-                if last_usemtl.is_none() || last_usemtl.as_ref().unwrap() != tokens[1] {
-                    if !current_triangles.is_empty() {
-                        objects.push(
-                            Object::new(Mesh::new(std::mem::take(&mut current_triangles)))
-                                .material(current_material),
-                        );
-                    }
-                    current_material = *materials.get(tokens[1]).ok_or_else(|| {
-                        invalid_data(format!("Could not found `usemtl {}` in library", tokens[1]))
-                    })?;
-                    last_usemtl = Some(tokens[1].to_owned());
+                if !current_triangles.is_empty() {
+                    objects.push(
+                        Object::new(Mesh::new(std::mem::take(&mut current_triangles)))
+                            .material(current_material),
+                    );
                 }
+                current_material = materials.get(tokens[1]).cloned().ok_or_else(|| {
+                    invalid_data(format!("Could not found `usemtl {}` in library", tokens[1]))
+                })?;
+                last_usemtl = Some(tokens[1].to_owned());
             }
             // Ignore other unrecognized or non-standard commands
             _ => (),
@@ -160,12 +164,29 @@ fn parse_obj_point(line: &[&str]) -> io::Result<glm::DVec3> {
     ))
 }
 
+fn parse_obj_uv(line: &[&str]) -> io::Result<glm::DVec2> {
+    let parse_coord = |s: &str| {
+        s.parse()
+            .map_err(|_| invalid_data("Failed to parse texture coordinate in .OBJ"))
+    };
+    // The third coordinate, if any, is only used by 3D textures
+    Ok(glm::vec2::<f64>(
+        parse_coord(line[1])?,
+        match line.get(2) {
+            Some(v) => parse_coord(v)?,
+            None => 0.0,
+        },
+    ))
+}
+
 fn parse_obj_face(
     line: &[&str],
     vertices: &[glm::DVec3],
     normals: &[glm::DVec3],
+    texcoords: &[glm::DVec2],
 ) -> io::Result<Vec<Triangle>> {
     let mut vi = Vec::new();
+    let mut vti = Vec::new();
     let mut vni = Vec::new();
     for vertex in &line[1..] {
         let args: Vec<_> = vertex
@@ -175,26 +196,24 @@ fn parse_obj_face(
             .collect();
         let vert_index = parse_index(args[0], vertices.len());
         vi.push(vert_index.ok_or_else(|| invalid_data("Invalid vertex index"))?);
+        vti.push(parse_index(args[1], texcoords.len()));
         vni.push(parse_index(args[2], normals.len()));
     }
     let mut triangles = Vec::new();
     for i in 1..(vi.len() - 1) {
         let (a, b, c) = (0, i, i + 1);
-        let v1 = vertices[vi[a]];
-        let v2 = vertices[vi[b]];
-        let v3 = vertices[vi[c]];
-        if vni[a].is_none() || vni[b].is_none() || vni[c].is_none() {
-            triangles.push(Triangle::from_vertices(v1, v2, v3));
-        } else {
-            triangles.push(Triangle {
-                v1,
-                v2,
-                v3,
-                n1: normals[vni[a].unwrap()],
-                n2: normals[vni[b].unwrap()],
-                n3: normals[vni[c].unwrap()],
-            });
+        let mut triangle =
+            Triangle::from_vertices(vertices[vi[a]], vertices[vi[b]], vertices[vi[c]]);
+        if let (Some(na), Some(nb), Some(nc)) = (vni[a], vni[b], vni[c]) {
+            // Normals in .OBJ files need not have unit length
+            triangle.n1 = normals[na].normalize();
+            triangle.n2 = normals[nb].normalize();
+            triangle.n3 = normals[nc].normalize();
+        }
+        if let (Some(ta), Some(tb), Some(tc)) = (vti[a], vti[b], vti[c]) {
+            triangle = triangle.uvs(texcoords[ta], texcoords[tb], texcoords[tc]);
         }
+        triangles.push(triangle);
     }
     Ok(triangles)
 }
@@ -323,6 +342,9 @@ fn load_stl_ascii(file: File) -> io::Result<Mesh> {
             n1: vn,
             n2: vn,
             n3: vn,
+            uv1: glm::zero(),
+            uv2: glm::zero(),
+            uv3: glm::zero(),
         });
     }
     Ok(Mesh::new(triangles))
@@ -354,7 +376,524 @@ fn load_stl_binary(file: File, num_triangles: u64) -> io::Result<Mesh> {
             n1: vn,
             n2: vn,
             n3: vn,
+            uv1: glm::zero(),
+            uv2: glm::zero(),
+            uv3: glm::zero(),
         });
     }
     Ok(Mesh::new(triangles))
 }
+
+/// Load a mesh from a .PLY file, in either ASCII or binary little-endian format
+///
+/// Vertex positions are required, while normals (`nx`, `ny`, `nz`) and texture
+/// coordinates (`u`/`v` or `s`/`t`) are used if present. Polygonal faces are
+/// triangulated as fans, and when the file has no normals, they are computed by
+/// averaging the normals of the faces around each vertex, weighted by area.
+/// Other vertex properties, such as colors, are read but ignored.
+///
+/// See [here](http://paulbourke.net/dataformats/ply/) for details.
+pub fn load_ply(file: File) -> io::Result<Mesh> {
+    Ok(Mesh::new(load_ply_triangles(file)?))
+}
+
+fn load_ply_triangles(file: File) -> io::Result<Vec<Triangle>> {
+    let mut reader = BufReader::new(file);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim() != "ply" {
+        return Err(invalid_data(
+            "Loaded .PLY file, but it is missing the `ply` magic",
+        ));
+    }
+
+    let mut binary = None;
+    let mut elements: Vec<PlyElement> = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid_data("Malformed PLY file: expected `end_header`"));
+        }
+        let tokens: Vec<&str> = line.split_ascii_whitespace().collect();
+        match tokens.as_slice() {
+            ["end_header"] => break,
+            ["format", "ascii", _] => binary = Some(false),
+            ["format", "binary_little_endian", _] => binary = Some(true),
+            ["format", format, _] => {
+                return Err(invalid_data(format!("Unsupported PLY format `{}`", format)))
+            }
+            ["element", name, count] => elements.push(PlyElement {
+                name: name.to_string(),
+                count: count
+                    .parse()
+                    .map_err(|_| invalid_data("Could not parse element count in .PLY"))?,
+                properties: Vec::new(),
+            }),
+            ["property", "list", count, kind, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("PLY property was declared before any element"))?;
+                element.properties.push(PlyProperty {
+                    name: name.to_string(),
+                    kind: PlyType::parse(kind)?,
+                    list: Some(PlyType::parse(count)?),
+                });
+            }
+            ["property", kind, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| invalid_data("PLY property was declared before any element"))?;
+                element.properties.push(PlyProperty {
+                    name: name.to_string(),
+                    kind: PlyType::parse(kind)?,
+                    list: None,
+                });
+            }
+            // Ignore comments, `obj_info` and blank lines
+            _ => (),
+        }
+    }
+    let binary = binary.ok_or_else(|| invalid_data("Malformed PLY file: missing `format`"))?;
+
+    // Both formats are read as a stream of numbers, one property value at a time
+    let mut text = String::new();
+    if !binary {
+        reader.read_to_string(&mut text)?;
+    }
+    let mut tokens = text.split_ascii_whitespace();
+    let mut next = |kind: PlyType| -> io::Result<f64> {
+        if binary {
+            kind.read_le(&mut reader)
+        } else {
+            tokens
+                .next()
+                .ok_or_else(|| invalid_data("Unexpected end of .PLY file"))?
+                .parse()
+                .map_err(|_| invalid_data("Could not parse value in .PLY"))
+        }
+    };
+
+    let mut vertices = Vec::new();
+    let mut normals = Vec::new();
+    let mut texcoords = Vec::new();
+    let mut faces = Vec::new();
+    for element in &elements {
+        let index_of = |names: &[&str]| {
+            element
+                .properties
+                .iter()
+                .position(|property| names.contains(&property.name.as_str()))
+        };
+        let position = [index_of(&["x"]), index_of(&["y"]), index_of(&["z"])];
+        let normal = [index_of(&["nx"]), index_of(&["ny"]), index_of(&["nz"])];
+        let uv = [
+            index_of(&["u", "s", "texture_u", "texture_s"]),
+            index_of(&["v", "t", "texture_v", "texture_t"]),
+        ];
+        let indices = index_of(&["vertex_indices", "vertex_index"]);
+
+        for _ in 0..element.count {
+            let mut values = Vec::with_capacity(element.properties.len());
+            for property in &element.properties {
+                values.push(match property.list {
+                    Some(count) => {
+                        let count = next(count)? as usize;
+                        (0..count)
+                            .map(|_| next(property.kind))
+                            .collect::<io::Result<_>>()?
+                    }
+                    None => vec![next(property.kind)?],
+                });
+            }
+            let vec3 = |indices: [Option<usize>; 3]| match indices {
+                [Some(x), Some(y), Some(z)] => {
+                    Some(glm::vec3(values[x][0], values[y][0], values[z][0]))
+                }
+                _ => None,
+            };
+            match element.name.as_str() {
+                "vertex" => {
+                    vertices.push(vec3(position).ok_or_else(|| {
+                        invalid_data("Malformed PLY file: vertex is missing a position")
+                    })?);
+                    if let Some(n) = vec3(normal) {
+                        normals.push(n);
+                    }
+                    if let [Some(u), Some(v)] = uv {
+                        texcoords.push(glm::vec2(values[u][0], values[v][0]));
+                    }
+                }
+                "face" => {
+                    let face = indices.ok_or_else(|| {
+                        invalid_data("Malformed PLY file: face is missing vertex indices")
+                    })?;
+                    faces.push(values.swap_remove(face));
+                }
+                // Ignore other elements, such as edges and materials
+                _ => (),
+            }
+        }
+    }
+
+    let normals_present = normals.len() == vertices.len();
+    if !normals_present {
+        normals = vec![glm::zero(); vertices.len()];
+    }
+    let mut triangles = Vec::new();
+    for face in faces {
+        let face = face
+            .into_iter()
+            .map(|index| {
+                let index = index as usize;
+                if index < vertices.len() {
+                    Ok(index)
+                } else {
+                    Err(invalid_data("Invalid vertex index"))
+                }
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        for i in 1..face.len().saturating_sub(1) {
+            let (a, b, c) = (face[0], face[i], face[i + 1]);
+            let mut triangle = Triangle::from_vertices(vertices[a], vertices[b], vertices[c]);
+            if texcoords.len() == vertices.len() {
+                triangle = triangle.uvs(texcoords[a], texcoords[b], texcoords[c]);
+            }
+            if !normals_present {
+                // The unnormalized cross product is weighted by twice the area
+                let n = (triangle.v2 - triangle.v1).cross(&(triangle.v3 - triangle.v1));
+                for &vertex in &[a, b, c] {
+                    normals[vertex] += n;
+                }
+            }
+            triangles.push((triangle, [a, b, c]));
+        }
+    }
+
+    Ok(triangles
+        .into_iter()
+        .map(|(mut triangle, [a, b, c])| {
+            let face_normal = triangle.n1;
+            let normal = |n: glm::DVec3| {
+                if n.magnitude_squared() > 0.0 {
+                    n.normalize()
+                } else {
+                    face_normal
+                }
+            };
+            triangle.n1 = normal(normals[a]);
+            triangle.n2 = normal(normals[b]);
+            triangle.n3 = normal(normals[c]);
+            triangle
+        })
+        .collect())
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+struct PlyProperty {
+    name: String,
+    kind: PlyType,
+    /// The type of the length prefix, if this property is a list
+    list: Option<PlyType>,
+}
+
+#[derive(Copy, Clone)]
+enum PlyType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> io::Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => {
+                return Err(invalid_data(format!(
+                    "Unknown PLY property type `{}`",
+                    name
+                )))
+            }
+        })
+    }
+
+    fn read_le(self, reader: &mut impl Read) -> io::Result<f64> {
+        let mut buf = [0; 8];
+        Ok(match self {
+            Self::I8 | Self::U8 => {
+                reader.read_exact(&mut buf[..1])?;
+                match self {
+                    Self::I8 => buf[0] as i8 as f64,
+                    _ => buf[0] as f64,
+                }
+            }
+            Self::I16 | Self::U16 => {
+                reader.read_exact(&mut buf[..2])?;
+                let bytes = [buf[0], buf[1]];
+                match self {
+                    Self::I16 => i16::from_le_bytes(bytes) as f64,
+                    _ => u16::from_le_bytes(bytes) as f64,
+                }
+            }
+            Self::I32 | Self::U32 | Self::F32 => {
+                reader.read_exact(&mut buf[..4])?;
+                let bytes = [buf[0], buf[1], buf[2], buf[3]];
+                match self {
+                    Self::I32 => i32::from_le_bytes(bytes) as f64,
+                    Self::U32 => u32::from_le_bytes(bytes) as f64,
+                    _ => f32::from_le_bytes(bytes) as f64,
+                }
+            }
+            Self::F64 => {
+                reader.read_exact(&mut buf)?;
+                f64::from_le_bytes(buf)
+            }
+        })
+    }
+}
+
+/// Load a photometric profile of a light fixture from an IES LM-63 file
+///
+/// Only type C photometry is supported, which is used by nearly all architectural
+/// fixtures. Intensities are scaled by the candela multiplier and ballast factor of
+/// the file, and tilt data is skipped.
+///
+/// See [here](https://docs.agi32.com/PhotometricToolbox/Content/Open_Tool/iesna_lm-63_format.htm)
+/// for details.
+pub fn load_ies(file: File) -> io::Result<IesProfile> {
+    let mut lines = BufReader::new(file).lines();
+    // Skip the keyword header, up to and including the TILT line
+    let tilt = loop {
+        let line = lines
+            .next()
+            .ok_or_else(|| invalid_data("Missing TILT line in IES file"))??;
+        if let Some(tilt) = line.trim().strip_prefix("TILT=") {
+            break tilt.trim().to_string();
+        }
+    };
+
+    let rest = lines.collect::<io::Result<Vec<_>>>()?.join(" ");
+    let mut values = rest.split_whitespace().map(|value| {
+        value
+            .parse::<f64>()
+            .map_err(|_| invalid_data(format!("Invalid number {:?} in IES file", value)))
+    });
+    let mut next = || {
+        values
+            .next()
+            .unwrap_or_else(|| Err(invalid_data("Unexpected end of IES file")))
+    };
+
+    match tilt.as_str() {
+        "NONE" => {}
+        "INCLUDE" => {
+            // Lamp-to-luminaire geometry, then pairs of angles and multipliers
+            next()?;
+            let num_pairs = next()? as usize;
+            for _ in 0..2 * num_pairs {
+                next()?;
+            }
+        }
+        _ => return Err(invalid_data("Tilt data in separate files is not supported")),
+    }
+
+    let _num_lamps = next()?;
+    let _lumens_per_lamp = next()?;
+    let multiplier = next()?;
+    let num_vertical = next()? as usize;
+    let num_horizontal = next()? as usize;
+    let photometric_type = next()?;
+    let _units_type = next()?;
+    let _dimensions = (next()?, next()?, next()?);
+    let ballast_factor = next()?;
+    let _file_generation_type = next()?;
+    let _input_watts = next()?;
+    if photometric_type != 1.0 {
+        return Err(invalid_data("Only type C photometry is supported"));
+    }
+    if num_vertical == 0 || num_horizontal == 0 {
+        return Err(invalid_data("IES file has no measured angles"));
+    }
+
+    let vertical_angles = (0..num_vertical)
+        .map(|_| next())
+        .collect::<io::Result<_>>()?;
+    let horizontal_angles = (0..num_horizontal)
+        .map(|_| next())
+        .collect::<io::Result<_>>()?;
+    let candela = (0..num_vertical * num_horizontal)
+        .map(|_| Ok(next()? * multiplier * ballast_factor))
+        .collect::<io::Result<_>>()?;
+    Ok(IesProfile::new(vertical_angles, horizontal_angles, candela))
+}
+
+/// Save linear floating-point RGB pixels, in row-major order, to an OpenEXR file
+///
+/// The values are written as-is, without tone mapping or gamma encoding.
+pub fn save_exr(
+    path: impl AsRef<Path>,
+    width: u32,
+    height: u32,
+    pixels: &[[f32; 3]],
+) -> io::Result<()> {
+    if pixels.len() != (width * height) as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Pixel buffer does not match the image dimensions",
+        ));
+    }
+    exr::prelude::write_rgb_file(path, width as usize, height as usize, |x, y| {
+        let [r, g, b] = pixels[y * width as usize + x];
+        (r, g, b)
+    })
+    .map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exr_round_trip() {
+        let (width, height) = (3, 2);
+        let pixels: Vec<[f32; 3]> = (0..width * height)
+            .map(|i| [i as f32 * 10.0, 0.001 * i as f32, 1234.5])
+            .collect();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("round_trip.exr");
+        save_exr(&path, width, height, &pixels).unwrap();
+
+        let image = exr::prelude::read_first_rgba_layer_from_file(
+            &path,
+            |resolution, _| vec![[0.0f32; 3]; resolution.width() * resolution.height()],
+            move |pixels: &mut Vec<[f32; 3]>, pos, (r, g, b, _): (f32, f32, f32, f32)| {
+                pixels[pos.y() * width as usize + pos.x()] = [r, g, b];
+            },
+        )
+        .unwrap();
+        assert_eq!(image.layer_data.channel_data.pixels, pixels);
+    }
+
+    #[test]
+    fn ies_symmetric_profile() {
+        let contents = "IESNA:LM-63-2002\n[TEST] symmetric downlight\n[MANUFAC] none\n\
+            TILT=NONE\n1 1000 2.0 5 1 1 2 0.1 0.1 0.0\n1.0 1.0 20\n\
+            0 22.5 45 67.5 90\n0\n\
+            500 400 200\n100 0\n";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("downlight.ies");
+        std::fs::write(&path, contents).unwrap();
+
+        let profile = load_ies(File::open(&path).unwrap()).unwrap();
+        // Measured angles are scaled by the candela multiplier
+        assert_eq!(profile.intensity(0.0, 0.0), 1000.0);
+        assert_eq!(profile.intensity(45.0, 0.0), 400.0);
+        // Values in between are interpolated, and the same at every horizontal angle
+        assert_eq!(profile.intensity(11.25, 0.0), 900.0);
+        assert_eq!(profile.intensity(56.25, 137.0), 300.0);
+        assert_eq!(profile.intensity(78.75, 290.0), 100.0);
+        // Above the horizon, the fixture emits no light
+        assert_eq!(profile.intensity(120.0, 0.0), 0.0);
+        // Straight down in the photometric frame is the nadir
+        assert_eq!(profile.intensity_in(&glm::vec3(0.0, 0.0, -2.0)), 1000.0);
+    }
+
+    #[test]
+    fn ply_cube() {
+        let header = "ply\nformat ascii 1.0\ncomment unit cube\nelement vertex 8\n\
+            property float x\nproperty float y\nproperty float z\n\
+            element face 6\nproperty list uchar int vertex_indices\nend_header\n";
+        let body = "0 0 0\n1 0 0\n1 1 0\n0 1 0\n0 0 1\n1 0 1\n1 1 1\n0 1 1\n\
+            4 0 3 2 1\n4 4 5 6 7\n4 0 1 5 4\n4 2 3 7 6\n4 1 2 6 5\n4 0 4 7 3\n";
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cube.ply");
+        std::fs::write(&path, format!("{}{}", header, body)).unwrap();
+
+        let triangles = load_ply_triangles(File::open(&path).unwrap()).unwrap();
+        assert_eq!(triangles.len(), 12);
+        let corners: std::collections::HashSet<_> = triangles
+            .iter()
+            .flat_map(|t| vec![t.v1, t.v2, t.v3])
+            .map(|v| (v.x as i32, v.y as i32, v.z as i32))
+            .collect();
+        assert_eq!(corners.len(), 8);
+        // Missing normals are averaged from the faces, pointing out of the corner
+        let corner = triangles
+            .iter()
+            .find(|t| t.v1 == glm::vec3(0.0, 0.0, 0.0))
+            .unwrap();
+        assert!((corner.n1 - glm::vec3(-1.0, -1.0, -1.0).normalize()).magnitude() < 1e-9);
+
+        let mut binary = header.replace("ascii", "binary_little_endian").into_bytes();
+        for v in body.lines().take(8) {
+            for x in v.split(' ') {
+                binary.extend(x.parse::<f32>().unwrap().to_le_bytes());
+            }
+        }
+        for f in body.lines().skip(8) {
+            binary.push(4);
+            for i in f.split(' ').skip(1) {
+                binary.extend(i.parse::<i32>().unwrap().to_le_bytes());
+            }
+        }
+        std::fs::write(&path, binary).unwrap();
+        let binary_triangles = load_ply_triangles(File::open(&path).unwrap()).unwrap();
+        assert_eq!(binary_triangles.len(), 12);
+        assert!(binary_triangles
+            .iter()
+            .zip(&triangles)
+            .all(|(a, b)| a.v1 == b.v1 && a.v2 == b.v2 && a.v3 == b.v3 && a.n1 == b.n1));
+    }
+
+    #[test]
+    fn ply_vertex_colors_are_ignored() {
+        let header = "ply\nformat binary_little_endian 1.0\nelement vertex 3\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\n\
+            property float s\nproperty float t\n\
+            element face 1\nproperty list uchar int vertex_indices\nend_header\n";
+        let mut binary = header.as_bytes().to_vec();
+        let vertices = [
+            ([0.0_f32, 0.0, 0.0], [255_u8, 0, 0], [0.0_f32, 0.0]),
+            ([1.0, 0.0, 0.0], [0, 255, 0], [1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [0, 0, 255], [0.0, 1.0]),
+        ];
+        for (position, color, uv) in &vertices {
+            position.iter().for_each(|x| binary.extend(x.to_le_bytes()));
+            binary.extend(color);
+            uv.iter().for_each(|x| binary.extend(x.to_le_bytes()));
+        }
+        binary.push(3);
+        for i in 0..3_i32 {
+            binary.extend(i.to_le_bytes());
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("colors.ply");
+        std::fs::write(&path, binary).unwrap();
+
+        // The colors between the positions and texture coordinates are skipped over
+        let triangles = load_ply_triangles(File::open(&path).unwrap()).unwrap();
+        assert_eq!(triangles.len(), 1);
+        let triangle = &triangles[0];
+        assert_eq!(triangle.v2, glm::vec3(1.0, 0.0, 0.0));
+        assert_eq!(triangle.v3, glm::vec3(0.0, 1.0, 0.0));
+        assert_eq!(triangle.uv2, glm::vec2(1.0, 0.0));
+        assert_eq!(triangle.uv3, glm::vec2(0.0, 1.0));
+    }
+}
@@ -1,9 +1,19 @@
 use rand::{distributions::Uniform, rngs::StdRng, Rng};
 use std::sync::Arc;
 
-use crate::shape::{HitRecord, Ray, Shape};
+use crate::shape::{HitRecord, Ray, Shape, Triangle};
 
-const SCORE_THRESHOLD: f64 = 0.85;
+/// Estimated cost of traversing one interior node, relative to `INTERSECT_COST`
+const TRAVERSAL_COST: f64 = 1.0;
+
+/// Estimated cost of intersecting a ray with one object
+const INTERSECT_COST: f64 = 80.0;
+
+/// Default maximum number of objects in a leaf that will not be split further
+pub const DEFAULT_MAX_LEAF_SIZE: usize = 4;
+
+/// Number of objects in a node above which its children are built in parallel
+const PARALLEL_BUILD_THRESHOLD: usize = 1024;
 
 /// A geometric shape with a bounding box (needed for kd-tree intersections)
 pub trait Bounded: Shape {
@@ -50,17 +60,20 @@ impl BoundingBox {
         }
     }
 
+    /// Returns the total surface area of the box
+    pub fn surface_area(&self) -> f64 {
+        let d = self.p_max - self.p_min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
     /// Returns the minimum and maximum times of intersection with a ray
+    ///
+    /// A ray that lies in the plane of a face of the box counts as inside it along that
+    /// axis, so rays along the boundary between adjacent boxes intersect both of them.
     pub fn intersect(&self, ray: &Ray) -> (f64, f64) {
-        let x1 = (self.p_min.x - ray.origin.x) / ray.dir.x;
-        let x2 = (self.p_max.x - ray.origin.x) / ray.dir.x;
-        let (x1, x2) = (f64::min(x1, x2), f64::max(x1, x2));
-        let y1 = (self.p_min.y - ray.origin.y) / ray.dir.y;
-        let y2 = (self.p_max.y - ray.origin.y) / ray.dir.y;
-        let (y1, y2) = (f64::min(y1, y2), f64::max(y1, y2));
-        let z1 = (self.p_min.z - ray.origin.z) / ray.dir.z;
-        let z2 = (self.p_max.z - ray.origin.z) / ray.dir.z;
-        let (z1, z2) = (f64::min(z1, z2), f64::max(z1, z2));
+        let (x1, x2) = slab(self.p_min.x, self.p_max.x, ray.origin.x, ray.dir.x);
+        let (y1, y2) = slab(self.p_min.y, self.p_max.y, ray.origin.y, ray.dir.y);
+        let (z1, z2) = slab(self.p_min.z, self.p_max.z, ray.origin.z, ray.dir.z);
         (
             f64::max(f64::max(x1, y1), z1),
             f64::min(f64::min(x2, y2), z2),
@@ -86,6 +99,17 @@ impl BoundingBox {
     }
 }
 
+/// Times at which a ray along one axis enters and leaves the slab between two planes
+fn slab(min: f64, max: f64, origin: f64, dir: f64) -> (f64, f64) {
+    let t1 = (min - origin) / dir;
+    let t2 = (max - origin) / dir;
+    if t1.is_nan() || t2.is_nan() {
+        // The ray is parallel to the slab and lies in one of its planes
+        return (-f64::INFINITY, f64::INFINITY);
+    }
+    (f64::min(t1, t2), f64::max(t1, t2))
+}
+
 /// A kd-tree based on bounding boxes, used to accelerate ray intersections
 ///
 /// This is a simple implementation; we don't care about slight performance
@@ -94,8 +118,8 @@ impl BoundingBox {
 ///
 /// The tree construction & ray intersection code was largely adapted from
 /// [fogleman/pt](https://github.com/fogleman/pt/blob/master/pt/tree.go).
-/// Parts of the construction algorithm were also taken from PBRT, which helped
-/// optimize the code by a few orders of magnitude.
+/// Construction uses the surface area heuristic from PBRT, which helped optimize
+/// the code by a few orders of magnitude.
 #[derive(Clone)]
 pub struct KdTree<T> {
     root: Box<KdNode>,
@@ -106,13 +130,37 @@ pub struct KdTree<T> {
 impl<T: Bounded> KdTree<T> {
     /// Construct a new kd-tree from a collection of objects
     pub fn new(objects: Vec<T>) -> Self {
+        Self::with_max_leaf_size(objects, DEFAULT_MAX_LEAF_SIZE)
+    }
+
+    /// Construct a new kd-tree, only splitting nodes with more than
+    /// `max_leaf_size` objects
+    ///
+    /// Split planes are chosen by the surface area heuristic, which minimizes the
+    /// expected cost of traversing the tree and intersecting objects in its leaves.
+    ///
+    /// Subtrees over many objects are built in parallel, which gives the same tree as
+    /// building serially.
+    pub fn with_max_leaf_size(objects: Vec<T>, max_leaf_size: usize) -> Self {
+        Self::build(objects, max_leaf_size, PARALLEL_BUILD_THRESHOLD)
+    }
+
+    /// Build the tree, constructing the children of nodes with more than
+    /// `parallel_threshold` objects in parallel
+    fn build(objects: Vec<T>, max_leaf_size: usize, parallel_threshold: usize) -> Self {
+        let params = BuildParams {
+            max_leaf_size,
+            parallel_threshold,
+        };
         let indices = (0..objects.len()).collect();
         let bounds = objects
             .iter()
             .map(T::bounding_box)
             .fold(BoundingBox::default(), |b1, b2| b1.merge(&b2));
+        // Depth limit from PBRT, to bound the cost of pathological inputs
+        let max_depth = 8 + (1.3 * (objects.len().max(1) as f64).log2()).round() as usize;
         Self {
-            root: construct(&objects, indices),
+            root: construct(&objects, indices, &bounds, &params, max_depth),
             objects,
             bounds,
         }
@@ -127,11 +175,6 @@ impl<T: Bounded> Bounded for KdTree<T> {
 
 impl<T: Bounded> Shape for KdTree<T> {
     fn intersect(&self, ray: &Ray, t_min: f64, record: &mut HitRecord) -> bool {
-        let (b_min, b_max) = self.bounds.intersect(ray);
-        if f64::max(b_min, t_min) > f64::min(b_max, record.time) {
-            // No potential for intersecting, even the broader bounding box
-            return false;
-        }
         self.intersect_subtree(&self.root, &self.bounds, ray, t_min, record)
     }
 
@@ -141,12 +184,27 @@ impl<T: Bounded> Shape for KdTree<T> {
         let (v, n, p) = self.objects[index].sample(target, rng);
         (v, n, p / (num as f64))
     }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        Some(self.bounding_box())
+    }
+
+    fn triangles(&self) -> Option<Vec<Triangle>> {
+        let mut triangles = Vec::new();
+        for object in &self.objects {
+            triangles.extend(object.triangles()?);
+        }
+        Some(triangles)
+    }
 }
 
 impl<T: Bounded> KdTree<T> {
     /// Intersect the current ray with a given subtree.
     ///
     /// Guarantee: we always find the closest intersection in the current kd-cell, if any.
+    ///
+    /// Cells that the ray only enters after the closest hit so far in `record` are
+    /// skipped, so queries that are already occluded, such as shadow rays, visit few nodes.
     #[allow(clippy::float_cmp)]
     fn intersect_subtree(
         &self,
@@ -157,6 +215,10 @@ impl<T: Bounded> KdTree<T> {
         record: &mut HitRecord,
     ) -> bool {
         let (b_min, b_max) = bbox.intersect(ray);
+        if f64::max(b_min, t_min) > f64::min(b_max, record.time) {
+            // The ray misses this cell, or only reaches it beyond the current closest hit
+            return false;
+        }
 
         let (t_split, first, second, bbox_split) = match node {
             KdNode::Leaf(indices) => {
@@ -223,7 +285,7 @@ impl<T: Bounded> KdTree<T> {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 enum KdNode {
     SplitX(f64, Box<KdNode>, Box<KdNode>),
     SplitY(f64, Box<KdNode>, Box<KdNode>),
@@ -232,124 +294,238 @@ enum KdNode {
     Leaf(Vec<usize>),
 }
 
-fn construct<T: Bounded>(objects: &[T], indices: Vec<usize>) -> Box<KdNode> {
-    if indices.len() < 16 {
+/// Settings for building a kd-tree that stay fixed over the recursion
+struct BuildParams {
+    max_leaf_size: usize,
+    parallel_threshold: usize,
+}
+
+/// Recursively build a kd-tree node, choosing split planes by the surface area heuristic
+fn construct<T: Bounded>(
+    objects: &[T],
+    indices: Vec<usize>,
+    bounds: &BoundingBox,
+    params: &BuildParams,
+    depth: usize,
+) -> Box<KdNode> {
+    if indices.len() <= params.max_leaf_size || depth == 0 {
         return Box::new(KdNode::Leaf(indices));
     }
-    let (mut xs, mut ys, mut zs) = (Vec::new(), Vec::new(), Vec::new());
-    let mut bboxs = Vec::new();
-    for &index in indices.iter() {
-        let bbox = objects[index].bounding_box();
-        xs.push(bbox.p_min.x);
-        xs.push(bbox.p_max.x);
-        ys.push(bbox.p_min.y);
-        ys.push(bbox.p_max.y);
-        zs.push(bbox.p_min.z);
-        zs.push(bbox.p_max.z);
-        bboxs.push(bbox);
-    }
-    let float_cmp = |a: &f64, b: &f64| a.partial_cmp(b).unwrap();
-    xs.sort_by(float_cmp);
-    ys.sort_by(float_cmp);
-    zs.sort_by(float_cmp);
-    let (mx, my, mz) = (median(&xs), median(&ys), median(&zs));
-
-    let partition_score = |dim: usize, value: f64| {
-        let (mut left, mut right) = (0usize, 0usize);
+    let bboxs: Vec<_> = indices
+        .iter()
+        .map(|&index| objects[index].bounding_box())
+        .collect();
+
+    // Sweep over the bounding box edges along each axis to find the cheapest split
+    let n = indices.len();
+    let leaf_cost = INTERSECT_COST * n as f64;
+    let inv_area = bounds.surface_area().recip();
+    let mut best: Option<(f64, usize, f64)> = None;
+    for axis in 0..3 {
+        let mut edges = Vec::with_capacity(2 * n);
         for bbox in &bboxs {
-            if bbox.p_min[dim] <= value {
-                left += 1;
-            }
-            if bbox.p_max[dim] >= value {
-                right += 1;
-            }
+            edges.push((bbox.p_min[axis], true));
+            edges.push((bbox.p_max[axis], false));
         }
-        left.max(right)
-    };
+        // At equal positions, end edges come before start edges
+        edges.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap().then(a.1.cmp(&b.1)));
 
-    let partition = |dim: usize, value: f64| {
-        let (mut left, mut right) = (Vec::new(), Vec::new());
-        for (i, &index) in indices.iter().enumerate() {
-            if bboxs[i].p_min[dim] <= value {
-                left.push(index);
+        let (mut below, mut above) = (0, n);
+        for &(value, is_start) in &edges {
+            if !is_start {
+                above -= 1;
             }
-            if bboxs[i].p_max[dim] >= value {
-                right.push(index);
+            if value > bounds.p_min[axis] && value < bounds.p_max[axis] {
+                let (left, right) = bounds.split(axis, value);
+                let cost = TRAVERSAL_COST
+                    + INTERSECT_COST
+                        * inv_area
+                        * (left.surface_area() * below as f64
+                            + right.surface_area() * above as f64);
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, value));
+                }
+            }
+            if is_start {
+                below += 1;
             }
         }
-        (left, right)
+    }
+
+    let (axis, value) = match best {
+        Some((cost, axis, value)) if cost < leaf_cost => (axis, value),
+        // No split is cheaper than intersecting everything here
+        _ => return Box::new(KdNode::Leaf(indices)),
     };
+    let (mut left, mut right) = (Vec::new(), Vec::new());
+    for (i, &index) in indices.iter().enumerate() {
+        if bboxs[i].p_min[axis] <= value {
+            left.push(index);
+        }
+        if bboxs[i].p_max[axis] >= value {
+            right.push(index);
+        }
+    }
+    if left.len() == n && right.len() == n {
+        return Box::new(KdNode::Leaf(indices));
+    }
 
-    let sx = partition_score(0, mx);
-    let sy = partition_score(1, my);
-    let sz = partition_score(2, mz);
-    let threshold = (indices.len() as f64 * SCORE_THRESHOLD) as usize;
-    if sx.min(sy).min(sz) >= threshold {
-        // The split isn't worth it, so we just make this a leaf node
-        Box::new(KdNode::Leaf(indices))
+    let (bounds_left, bounds_right) = bounds.split(axis, value);
+    let build_left = || construct(objects, left, &bounds_left, params, depth - 1);
+    let build_right = || construct(objects, right, &bounds_right, params, depth - 1);
+    let (left, right) = if n > params.parallel_threshold {
+        rayon::join(build_left, build_right)
     } else {
-        let mut split_dir = -1;
+        (build_left(), build_right())
+    };
+    Box::new(match axis {
+        0 => KdNode::SplitX(value, left, right),
+        1 => KdNode::SplitY(value, left, right),
+        _ => KdNode::SplitZ(value, left, right),
+    })
+}
 
-        // First try the direction with maximum extent
-        let bounds = bboxs
-            .iter()
-            .fold(BoundingBox::default(), |b1, b2| b1.merge(b2));
-        let extent = bounds.p_max - bounds.p_min;
-        if extent.x > extent.y && extent.x > extent.z {
-            if sx < threshold {
-                split_dir = 0;
-            }
-        } else if extent.y > extent.z {
-            if sy < threshold {
-                split_dir = 1;
-            }
-        } else if sz < threshold {
-            split_dir = 2;
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::shape::Triangle;
+
+    /// A triangle that counts how many times it is intersected
+    struct Counted<'a>(Triangle, &'a AtomicUsize);
+
+    impl Shape for Counted<'_> {
+        fn intersect(&self, ray: &Ray, t_min: f64, record: &mut HitRecord) -> bool {
+            self.1.fetch_add(1, Ordering::Relaxed);
+            self.0.intersect(ray, t_min, record)
         }
 
-        // Then, try any split direction, with best possible score
-        if split_dir == -1 {
-            if sx < sy && sx < sz {
-                split_dir = 0;
-            } else if sy < sz {
-                split_dir = 1;
-            } else {
-                split_dir = 2;
+        fn sample(&self, target: &glm::DVec3, rng: &mut StdRng) -> (glm::DVec3, glm::DVec3, f64) {
+            self.0.sample(target, rng)
+        }
+    }
+
+    impl Bounded for Counted<'_> {
+        fn bounding_box(&self) -> BoundingBox {
+            self.0.bounding_box()
+        }
+    }
+
+    #[test]
+    fn kdtree_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut point =
+            |scale: f64| glm::vec3(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>()) * scale;
+        let triangles: Vec<_> = (0..1000)
+            .map(|_| {
+                let v = point(10.0);
+                Triangle::from_vertices(v, v + point(1.0), v + point(1.0))
+            })
+            .collect();
+        let tree = KdTree::new(triangles.clone());
+
+        for _ in 0..2000 {
+            let ray = Ray {
+                origin: point(14.0) - glm::vec3(2.0, 2.0, 2.0),
+                dir: (point(2.0) - glm::vec3(1.0, 1.0, 1.0)).normalize(),
+            };
+            let mut expected = HitRecord::new();
+            let mut hit = false;
+            for triangle in &triangles {
+                hit |= triangle.intersect(&ray, 1e-9, &mut expected);
+            }
+            let mut actual = HitRecord::new();
+            assert_eq!(tree.intersect(&ray, 1e-9, &mut actual), hit);
+            if hit {
+                assert_eq!(actual.time, expected.time);
+                assert_eq!(actual.normal, expected.normal);
             }
         }
+    }
 
-        if split_dir == 0 {
-            let (left, right) = partition(0, mx);
-            Box::new(KdNode::SplitX(
-                mx,
-                construct(objects, left),
-                construct(objects, right),
-            ))
-        } else if split_dir == 1 {
-            let (left, right) = partition(1, my);
-            Box::new(KdNode::SplitY(
-                my,
-                construct(objects, left),
-                construct(objects, right),
-            ))
-        } else {
-            assert!(split_dir == 2);
-            let (left, right) = partition(2, mz);
-            Box::new(KdNode::SplitZ(
-                mz,
-                construct(objects, left),
-                construct(objects, right),
-            ))
+    #[test]
+    fn parallel_build_matches_serial() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut point =
+            |scale: f64| glm::vec3(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>()) * scale;
+        let triangles: Vec<_> = (0..5000)
+            .map(|_| {
+                let v = point(20.0);
+                Triangle::from_vertices(v, v + point(1.0), v + point(1.0))
+            })
+            .collect();
+        let serial = KdTree::build(triangles.clone(), DEFAULT_MAX_LEAF_SIZE, usize::MAX);
+        let parallel = KdTree::build(triangles, DEFAULT_MAX_LEAF_SIZE, 16);
+        assert_eq!(serial.root, parallel.root);
+
+        for _ in 0..1000 {
+            let ray = Ray {
+                origin: point(24.0) - glm::vec3(2.0, 2.0, 2.0),
+                dir: (point(2.0) - glm::vec3(1.0, 1.0, 1.0)).normalize(),
+            };
+            let (mut a, mut b) = (HitRecord::new(), HitRecord::new());
+            assert_eq!(
+                serial.intersect(&ray, 1e-9, &mut a),
+                parallel.intersect(&ray, 1e-9, &mut b)
+            );
+            assert_eq!(a.time, b.time);
         }
     }
-}
 
-fn median(sorted_array: &[f64]) -> f64 {
-    assert!(!sorted_array.is_empty());
-    if sorted_array.len() % 2 == 1 {
-        sorted_array[sorted_array.len() / 2]
-    } else {
-        let mid = sorted_array.len() / 2;
-        (sorted_array[mid] + sorted_array[mid - 1]) / 2.0
+    #[test]
+    fn occluded_queries_skip_far_nodes() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut point =
+            |scale: f64| glm::vec3(rng.gen::<f64>(), rng.gen::<f64>(), rng.gen::<f64>()) * scale;
+        let tests = AtomicUsize::new(0);
+        let triangles: Vec<_> = (0..2000)
+            .map(|_| {
+                let v = point(10.0);
+                Counted(
+                    Triangle::from_vertices(v, v + point(1.0), v + point(1.0)),
+                    &tests,
+                )
+            })
+            .collect();
+        let tree = KdTree::new(triangles);
+
+        let (mut open_tests, mut occluded_tests, mut hits) = (0, 0, 0);
+        for _ in 0..1000 {
+            let ray = Ray {
+                origin: point(14.0) - glm::vec3(2.0, 2.0, 2.0),
+                dir: (point(2.0) - glm::vec3(1.0, 1.0, 1.0)).normalize(),
+            };
+            let mut open = HitRecord::new();
+            tests.store(0, Ordering::Relaxed);
+            if !tree.intersect(&ray, 1e-9, &mut open) {
+                continue;
+            }
+            hits += 1;
+            open_tests += tests.load(Ordering::Relaxed);
+
+            // An occluder just past the hit does not change the result
+            let mut record = HitRecord::new();
+            record.time = open.time * 1.001;
+            assert!(tree.intersect(&ray, 1e-9, &mut record));
+            assert_eq!(record.time, open.time);
+            assert_eq!(record.normal, open.normal);
+
+            // An occluder in front of every triangle hides them all, without testing many
+            let mut record = HitRecord::new();
+            record.time = open.time * 0.1;
+            tests.store(0, Ordering::Relaxed);
+            assert!(!tree.intersect(&ray, 1e-9, &mut record));
+            assert_eq!(record.time, open.time * 0.1);
+            occluded_tests += tests.load(Ordering::Relaxed);
+        }
+        assert!(hits > 100, "{}", hits);
+        assert!(
+            occluded_tests * 2 < open_tests,
+            "{} {}",
+            occluded_tests,
+            open_tests
+        );
     }
 }
@@ -6,30 +6,42 @@
 pub use glm;
 pub use image;
 
+pub use aov::*;
 pub use buffer::*;
+pub use bvh::*;
 pub use camera::*;
 pub use color::*;
 pub use environment::*;
 pub use io::*;
 pub use kdtree::*;
 pub use light::*;
+pub use light_tree::*;
 pub use material::*;
+pub use medium::*;
 pub use object::*;
 pub use ode::*;
 pub use renderer::*;
+pub use sampler::*;
 pub use scene::*;
 pub use shape::*;
+pub use texture::*;
 
+mod aov;
 mod buffer;
+mod bvh;
 mod camera;
 mod color;
 mod environment;
 mod io;
 mod kdtree;
 mod light;
+mod light_tree;
 mod material;
+mod medium;
 mod object;
 mod ode;
 mod renderer;
+mod sampler;
 mod scene;
 mod shape;
+mod texture;
@@ -1,12 +1,18 @@
-use rand::rngs::StdRng;
+use rand::{rngs::StdRng, SeedableRng};
+use std::sync::Arc;
 
-use crate::color::Color;
+use crate::color::{luminance, Color};
+use crate::kdtree::BoundingBox;
 use crate::object::Object;
 
 /// Type representing various forms of lighting
+#[allow(clippy::large_enum_variant)]
 pub enum Light {
-    /// Point light represented as (color, location)
-    Point(Color, glm::DVec3),
+    /// Point light represented as (color, location, falloff)
+    ///
+    /// Only `Falloff::Quadratic` is physically correct, and bidirectional path tracing
+    /// always treats point lights that way.
+    Point(Color, glm::DVec3, Falloff),
 
     /// Ambient light represented as (color)
     Ambient(Color),
@@ -16,17 +22,286 @@ pub enum Light {
 
     /// Light from an invisible, emissive object
     Object(Object),
+
+    /// Point light restricted to a cone, with a smooth falloff between the inner
+    /// and outer half-angles (in radians) around its direction
+    Spot {
+        /// Location of the light
+        position: glm::DVec3,
+        /// Direction of the cone axis
+        direction: glm::DVec3,
+        /// Intensity of the light, as with `Point`
+        color: Color,
+        /// Half-angle of the fully lit cone
+        inner_angle: f64,
+        /// Half-angle beyond which there is no light
+        outer_angle: f64,
+    },
+
+    /// Point light with the angular distribution of a measured fixture, see `IesProfile`
+    Ies {
+        /// Location of the light
+        position: glm::DVec3,
+        /// Rotation from the photometric frame of the fixture to world space
+        ///
+        /// In the photometric frame, the fixture points down the -z axis (a vertical
+        /// angle of 0), and a horizontal angle of 0 lies along the +x axis.
+        orientation: glm::DMat3,
+        /// Tint of the light, which scales the candela values of the profile
+        color: Color,
+        /// Measured intensity distribution of the fixture
+        profile: Arc<IesProfile>,
+    },
+}
+
+/// How the light from a point light falls off with distance
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Falloff {
+    /// Constant brightness at any distance, as for a stylized light
+    None,
+
+    /// Brightness in inverse proportion to the distance, as some other renderers offer
+    Linear,
+
+    /// Physically correct inverse-square falloff
+    #[default]
+    Quadratic,
+}
+
+impl Falloff {
+    /// Factor scaling the intensity of a light at a distance
+    pub fn attenuation(&self, distance: f64) -> f64 {
+        match self {
+            Falloff::None => 1.0,
+            Falloff::Linear => 1.0 / distance,
+            Falloff::Quadratic => 1.0 / (distance * distance),
+        }
+    }
+}
+
+/// Luminous intensity distribution of a light fixture, as measured in an IES file
+///
+/// Intensities are given in candela, over a grid of vertical angles (measured from the
+/// nadir) and horizontal angles (measured around the nadir), both in degrees. Profiles
+/// with a single horizontal angle are rotationally symmetric, and those ending at 90
+/// or 180 degrees are mirrored into the remaining quadrants or half.
+///
+/// See `load_ies` for reading a profile from an IES LM-63 file.
+#[derive(Clone, Debug)]
+pub struct IesProfile {
+    /// Vertical angles in increasing order
+    vertical_angles: Vec<f64>,
+
+    /// Horizontal angles in increasing order
+    horizontal_angles: Vec<f64>,
+
+    /// Intensities for each horizontal angle, each holding one value per vertical angle
+    candela: Vec<f64>,
+}
+
+impl IesProfile {
+    /// Create a new profile from its angles and a table of intensities, grouped by
+    /// horizontal angle
+    pub fn new(vertical_angles: Vec<f64>, horizontal_angles: Vec<f64>, candela: Vec<f64>) -> Self {
+        assert!(!vertical_angles.is_empty() && !horizontal_angles.is_empty());
+        assert!(candela.len() == vertical_angles.len() * horizontal_angles.len());
+        Self {
+            vertical_angles,
+            horizontal_angles,
+            candela,
+        }
+    }
+
+    /// Interpolated intensity at the given vertical and horizontal angles, in degrees
+    ///
+    /// Directions outside of the measured vertical angles receive no light.
+    pub fn intensity(&self, vertical: f64, horizontal: f64) -> f64 {
+        let first = self.vertical_angles[0];
+        let last = self.vertical_angles[self.vertical_angles.len() - 1];
+        if vertical < first || vertical > last {
+            return 0.0;
+        }
+        let mut horizontal = horizontal.rem_euclid(360.0);
+        let max_horizontal = self.horizontal_angles[self.horizontal_angles.len() - 1];
+        if max_horizontal <= 90.0 {
+            // Quadrant symmetry
+            if horizontal > 180.0 {
+                horizontal = 360.0 - horizontal;
+            }
+            if horizontal > 90.0 {
+                horizontal = 180.0 - horizontal;
+            }
+        } else if max_horizontal <= 180.0 && horizontal > 180.0 {
+            // Bilateral symmetry
+            horizontal = 360.0 - horizontal;
+        }
+
+        let n = self.vertical_angles.len();
+        let (v0, v1, tv) = interval(&self.vertical_angles, vertical);
+        let (h0, h1, th) = interval(&self.horizontal_angles, horizontal);
+        let at = |h: usize, v: usize| self.candela[h * n + v];
+        let lerp = |a: f64, b: f64, t: f64| a + (b - a) * t;
+        lerp(
+            lerp(at(h0, v0), at(h0, v1), tv),
+            lerp(at(h1, v0), at(h1, v1), tv),
+            th,
+        )
+    }
+
+    /// Interpolated intensity in a direction of the photometric frame (see `Light::Ies`)
+    pub fn intensity_in(&self, dir: &glm::DVec3) -> f64 {
+        let dir = dir.normalize();
+        let vertical = (-dir.z).clamp(-1.0, 1.0).acos().to_degrees();
+        let horizontal = dir.y.atan2(dir.x).to_degrees();
+        self.intensity(vertical, horizontal)
+    }
+
+    /// Total luminous flux of the profile, integrated numerically over the sphere
+    pub fn flux(&self) -> f64 {
+        use std::f64::consts::PI;
+        let (rows, cols) = (90, 180);
+        let mut total = 0.0;
+        for i in 0..rows {
+            let vertical = (i as f64 + 0.5) / rows as f64 * 180.0;
+            let solid_angle = vertical.to_radians().sin() * (PI / rows as f64);
+            for j in 0..cols {
+                let horizontal = (j as f64 + 0.5) / cols as f64 * 360.0;
+                total += self.intensity(vertical, horizontal) * solid_angle;
+            }
+        }
+        total * 2.0 * PI / cols as f64
+    }
+}
+
+/// Find the angles surrounding a value in a sorted list, returning their indices and
+/// the fraction of the way from the first to the second
+fn interval(angles: &[f64], value: f64) -> (usize, usize, f64) {
+    let i = angles.partition_point(|&a| a <= value);
+    if i == 0 {
+        (0, 0, 0.0)
+    } else if i == angles.len() {
+        (i - 1, i - 1, 0.0)
+    } else {
+        let (a0, a1) = (angles[i - 1], angles[i]);
+        (i - 1, i, (value - a0) / (a1 - a0))
+    }
+}
+
+/// Strategy for choosing which lights receive a shadow ray at each path vertex
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum LightSampling {
+    /// Sample every light, which is expensive in scenes with many lights
+    #[default]
+    All,
+
+    /// Sample a single light, chosen uniformly at random
+    Uniform,
+
+    /// Sample a single light, chosen with probability proportional to its power
+    Power,
+
+    /// Sample a single light, chosen by walking down a `LightTree` toward the lights
+    /// that contribute the most at each shading point
+    ///
+    /// This suits scenes with many lights spread through space, where most of them are
+    /// too far away to matter at any one point.
+    Tree,
 }
 
 impl Light {
+    /// Approximate luminous power of the light, used to choose lights for sampling
+    ///
+    /// Directional lights have no finite power, so this returns their power through a
+    /// unit area instead. Ambient lights are not sampled, and have zero power.
+    pub fn power(&self) -> f64 {
+        use std::f64::consts::PI;
+        match self {
+            Light::Ambient(_) => 0.0,
+            Light::Point(color, _, _) => 4.0 * PI * luminance(color),
+            Light::Directional(color, _) => luminance(color),
+            Light::Object(object) => {
+                // Estimate the area from the shape's sampling density, as seen from afar
+                let mut rng = StdRng::seed_from_u64(0);
+                let samples = 16;
+                let target = glm::vec3(1e6, 1e6, 1e6);
+                let area = (0..samples)
+                    .map(|_| object.shape.sample(&target, &mut rng).2.recip())
+                    .sum::<f64>()
+                    / samples as f64;
+                let radiance = object.material.color * object.material.emittance;
+                PI * luminance(&radiance) * area
+            }
+            Light::Spot {
+                color,
+                inner_angle,
+                outer_angle,
+                ..
+            } => {
+                let cone = 0.5 * (inner_angle + outer_angle);
+                2.0 * PI * (1.0 - cone.cos()) * luminance(color)
+            }
+            Light::Ies { color, profile, .. } => profile.flux() * luminance(color),
+        }
+    }
+
+    /// Bounding box of the light, or `None` for lights without a position, such as
+    /// ambient, directional, and unbounded object lights
+    pub fn bounds(&self) -> Option<BoundingBox> {
+        match self {
+            Light::Point(_, position, _)
+            | Light::Spot { position, .. }
+            | Light::Ies { position, .. } => Some(BoundingBox {
+                p_min: *position,
+                p_max: *position,
+            }),
+            Light::Object(object) => object.shape.bounds(),
+            Light::Ambient(_) | Light::Directional(..) => None,
+        }
+    }
+
+    /// Radiant intensity of a point, spot, or IES light in a direction, which is zero
+    /// for other kinds of lights
+    pub fn intensity(&self, dir: &glm::DVec3) -> Color {
+        match self {
+            Light::Point(color, _, _) => *color,
+            Light::Spot {
+                direction,
+                color,
+                inner_angle,
+                outer_angle,
+                ..
+            } => {
+                let cosine = dir.dot(direction) / (glm::length(dir) * glm::length(direction));
+                let (cos_inner, cos_outer) = (inner_angle.cos(), outer_angle.cos());
+                let falloff = if cosine >= cos_inner {
+                    1.0
+                } else if cosine <= cos_outer {
+                    0.0
+                } else {
+                    // Smoothstep between the two cones
+                    let t = (cosine - cos_outer) / (cos_inner - cos_outer);
+                    t * t * (3.0 - 2.0 * t)
+                };
+                color * falloff
+            }
+            Light::Ies {
+                orientation,
+                color,
+                profile,
+                ..
+            } => color * profile.intensity_in(&(orientation.transpose() * dir)),
+            _ => glm::vec3(0.0, 0.0, 0.0),
+        }
+    }
+
     /// Illuminates a point, returning (intensity, dir_to_light, dist_to_light)
     pub fn illuminate(&self, world_pos: &glm::DVec3, rng: &mut StdRng) -> (Color, glm::DVec3, f64) {
         match self {
             Light::Ambient(color) => (*color, glm::vec3(0.0, 0.0, 0.0), 0.0),
-            Light::Point(color, location) => {
+            Light::Point(color, location, falloff) => {
                 let disp = location - world_pos;
                 let len = glm::length(&disp);
-                (color / (len * len), disp / len, len)
+                (color * falloff.attenuation(len), disp / len, len)
             }
             Light::Directional(color, direction) => {
                 (*color, -glm::normalize(direction), f64::INFINITY)
@@ -43,6 +318,60 @@ impl Light {
                     len,
                 )
             }
+            Light::Spot { position, .. } | Light::Ies { position, .. } => {
+                let disp = position - world_pos;
+                let len = glm::length(&disp);
+                (
+                    self.intensity(&(-disp / len)) / (len * len),
+                    disp / len,
+                    len,
+                )
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spot_light_cone() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let spot = Light::Spot {
+            position: glm::vec3(0.0, 2.0, 0.0),
+            direction: glm::vec3(0.0, -1.0, 0.0),
+            color: glm::vec3(1.0, 1.0, 1.0),
+            inner_angle: 0.2,
+            outer_angle: 0.4,
+        };
+        let (on_axis, dir, dist) = spot.illuminate(&glm::vec3(0.0, 0.0, 0.0), &mut rng);
+        assert_eq!(on_axis, glm::vec3(0.25, 0.25, 0.25));
+        assert_eq!(dir, glm::vec3(0.0, 1.0, 0.0));
+        assert_eq!(dist, 2.0);
+
+        // tan(0.3) * 2 is halfway between the cones
+        let (edge, _, _) = spot.illuminate(&glm::vec3(0.62, 0.0, 0.0), &mut rng);
+        assert!(edge.x > 0.0 && edge.x < on_axis.x);
+
+        let (outside, _, dist) = spot.illuminate(&glm::vec3(1.0, 0.0, 0.0), &mut rng);
+        assert_eq!(outside, glm::vec3(0.0, 0.0, 0.0));
+        assert_eq!(dist, 5.0_f64.sqrt());
+    }
+
+    #[test]
+    fn point_light_falloff() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut ratio = |falloff| {
+            let light = Light::Point(glm::vec3(4.0, 4.0, 4.0), glm::vec3(0.0, 0.0, 0.0), falloff);
+            let (near, _, near_dist) = light.illuminate(&glm::vec3(0.0, 1.5, 0.0), &mut rng);
+            let (far, _, far_dist) = light.illuminate(&glm::vec3(0.0, 3.0, 0.0), &mut rng);
+            // Shadow rays always get the true distance
+            assert_eq!((near_dist, far_dist), (1.5, 3.0));
+            far.x / near.x
+        };
+        assert!((ratio(Falloff::Quadratic) - 0.25).abs() < 1e-12);
+        assert!((ratio(Falloff::Linear) - 0.5).abs() < 1e-12);
+        assert_eq!(ratio(Falloff::None), 1.0);
+    }
+}
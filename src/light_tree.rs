@@ -0,0 +1,232 @@
+use crate::kdtree::BoundingBox;
+use crate::light::Light;
+
+/// Lower bound on the squared distance used to estimate the importance of a cluster,
+/// which keeps lights at the shading point itself from getting infinite importance
+const MIN_DISTANCE_SQUARED: f64 = 1e-8;
+
+/// A bounding volume hierarchy over the lights of a scene, used to choose one light to
+/// sample in scenes with many lights
+///
+/// Each node stores the bounds and total power of the lights below it. Sampling walks
+/// down from the root, choosing each child with probability proportional to an estimate
+/// of its contribution at the shading point: its power divided by the squared distance
+/// to its bounds, which is never taken to be less than a quarter of the radius of the
+/// bounds squared. Nearby bright lights are then chosen much more often than under
+/// `LightSampling::Power`, while far away clusters are still chosen with a small
+/// probability, which keeps the estimate unbiased.
+///
+/// Lights without a position, such as directional lights, are kept out of the tree and
+/// chosen uniformly, each as often as a light would be under uniform sampling. Ambient
+/// lights and lights with no power are never chosen.
+#[derive(Clone, Debug)]
+pub struct LightTree {
+    nodes: Vec<LightNode>,
+    /// Indices of lights that are chosen apart from the tree
+    unbounded: Vec<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct LightNode {
+    bounds: BoundingBox,
+    power: f64,
+    kind: LightNodeKind,
+}
+
+#[derive(Clone, Debug)]
+enum LightNodeKind {
+    /// Index of the light in the scene
+    Leaf(usize),
+    /// Position of the second child (the first child follows directly)
+    Interior(usize),
+}
+
+impl LightTree {
+    /// Construct a new light tree over a list of lights
+    pub fn new(lights: &[Light]) -> Self {
+        let mut tree = Self {
+            nodes: Vec::new(),
+            unbounded: Vec::new(),
+        };
+        let mut items = Vec::new();
+        for (index, light) in lights.iter().enumerate() {
+            let power = light.power();
+            if matches!(light, Light::Ambient(_)) || power.is_nan() || power <= 0.0 {
+                continue;
+            }
+            match light.bounds() {
+                Some(bounds) => {
+                    let centroid = (bounds.p_min + bounds.p_max) / 2.0;
+                    items.push((index, bounds, centroid, power));
+                }
+                None => tree.unbounded.push(index),
+            }
+        }
+        tree.nodes.reserve(2 * items.len());
+        if !items.is_empty() {
+            tree.construct(&mut items);
+        }
+        tree
+    }
+
+    /// Number of lights that can be chosen
+    fn len(&self) -> usize {
+        // A tree over n lights has 2n - 1 nodes
+        self.unbounded.len() + self.nodes.len().div_ceil(2)
+    }
+
+    /// Choose a light to sample at a position with a number `u` in [0, 1), returning its
+    /// index with the probability that it was chosen, or `None` if there are no lights
+    /// to choose
+    ///
+    /// Each choice on the way down rescales `u` to [0, 1) for the next one, so nearby
+    /// values of `u` choose nearby lights.
+    pub fn sample(&self, pos: &glm::DVec3, u: f64) -> Option<(usize, f64)> {
+        let len = self.len();
+        if len == 0 {
+            return None;
+        }
+        let scaled = u * len as f64;
+        let chosen = scaled as usize;
+        if chosen < self.unbounded.len() {
+            return Some((self.unbounded[chosen], 1.0 / len as f64));
+        }
+        let unbounded = self.unbounded.len() as f64;
+        let mut u = (scaled - unbounded) / (len as f64 - unbounded);
+        let mut prob = 1.0 - unbounded / len as f64;
+        let mut node = 0;
+        loop {
+            match self.nodes[node].kind {
+                LightNodeKind::Leaf(index) => return Some((index, prob)),
+                LightNodeKind::Interior(second) => {
+                    let first = self.importance(node + 1, pos);
+                    let total = first + self.importance(second, pos);
+                    let p_first = if total > 0.0 { first / total } else { 0.5 };
+                    if u < p_first {
+                        prob *= p_first;
+                        u /= p_first;
+                        node += 1;
+                    } else {
+                        prob *= 1.0 - p_first;
+                        u = (u - p_first) / (1.0 - p_first);
+                        node = second;
+                    }
+                    u = u.min(1.0 - f64::EPSILON);
+                }
+            }
+        }
+    }
+
+    /// Estimated contribution of the lights below a node at a position
+    fn importance(&self, node: usize, pos: &glm::DVec3) -> f64 {
+        let LightNode { bounds, power, .. } = &self.nodes[node];
+        let outside = glm::max2(&(bounds.p_min - pos), &(pos - bounds.p_max));
+        let distance_squared = outside.map(|c| c.max(0.0)).magnitude_squared();
+        // A quarter of the radius of the bounds
+        let min_squared = (bounds.p_max - bounds.p_min).magnitude_squared() / 64.0;
+        power / distance_squared.max(min_squared).max(MIN_DISTANCE_SQUARED)
+    }
+
+    /// Recursively build the subtree over `items`, returning the position of its root
+    fn construct(&mut self, items: &mut [(usize, BoundingBox, glm::DVec3, f64)]) -> usize {
+        let bounds = items
+            .iter()
+            .fold(BoundingBox::default(), |b, item| b.merge(&item.1));
+        let power = items.iter().map(|item| item.3).sum();
+        let node = self.nodes.len();
+        self.nodes.push(LightNode {
+            bounds,
+            power,
+            kind: LightNodeKind::Leaf(items[0].0),
+        });
+        if items.len() == 1 {
+            return node;
+        }
+
+        // Split at the middle of the widest axis of the centroids, so that each cluster
+        // is as compact as possible, or at the median if that leaves one side empty
+        let centroids = items.iter().fold(BoundingBox::default(), |b, item| {
+            b.merge(&BoundingBox {
+                p_min: item.2,
+                p_max: item.2,
+            })
+        });
+        let axis = (centroids.p_max - centroids.p_min).imax();
+        let middle = (centroids.p_min[axis] + centroids.p_max[axis]) / 2.0;
+        items.sort_by(|a, b| a.2[axis].partial_cmp(&b.2[axis]).unwrap());
+        let mut mid = items.partition_point(|item| item.2[axis] < middle);
+        if mid == 0 || mid == items.len() {
+            mid = items.len() / 2;
+        }
+        let (left, right) = items.split_at_mut(mid);
+        self.construct(left);
+        let second = self.construct(right);
+        self.nodes[node].kind = LightNodeKind::Interior(second);
+        node
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::light::Falloff;
+
+    #[test]
+    fn light_tree_probabilities_sum_to_one() {
+        let mut lights: Vec<_> = (0..37)
+            .map(|i| {
+                let position = glm::vec3((i % 6) as f64, 0.0, (i / 6) as f64 * 1.5);
+                let color = glm::vec3(1.0, 1.0, 1.0) * (1 + i % 4) as f64;
+                Light::Point(color, position, Falloff::Quadratic)
+            })
+            .collect();
+        lights.push(Light::Ambient(glm::vec3(1.0, 1.0, 1.0)));
+        lights.push(Light::Directional(
+            glm::vec3(1.0, 1.0, 1.0),
+            glm::vec3(0.0, -1.0, 0.0),
+        ));
+        lights.push(Light::Point(
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(1.0, 0.0, 0.0),
+            Falloff::Quadratic,
+        ));
+        let tree = LightTree::new(&lights);
+        assert_eq!(tree.len(), 38);
+
+        // The frequency of each light matches the probability returned with it
+        let mut rng = StdRng::seed_from_u64(0);
+        let pos = glm::vec3(1.2, 0.5, 2.0);
+        let mut probs = vec![0.0; lights.len()];
+        let mut counts = vec![0; lights.len()];
+        let samples = 200_000;
+        for _ in 0..samples {
+            let (index, prob) = tree.sample(&pos, rng.gen()).unwrap();
+            if counts[index] > 0 {
+                assert!((probs[index] - prob).abs() < 1e-12);
+            }
+            probs[index] = prob;
+            counts[index] += 1;
+        }
+        assert_eq!(counts[37], 0);
+        assert_eq!(counts[39], 0);
+        assert!(counts.iter().filter(|&&c| c > 0).count() == 38);
+        assert!((probs.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        for (count, prob) in counts.iter().zip(&probs) {
+            let expected = prob * samples as f64;
+            assert!((*count as f64 - expected).abs() < 5.0 * expected.sqrt() + 1.0);
+        }
+        assert!((probs[38] - 1.0 / 38.0).abs() < 1e-12);
+
+        // Lights near the shading point are chosen more often than distant ones
+        let near = 6 + 1;
+        let far = 36;
+        assert!(
+            probs[near] > 5.0 * probs[far],
+            "{} {}",
+            probs[near],
+            probs[far]
+        );
+    }
+}
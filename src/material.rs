@@ -1,10 +1,13 @@
 use rand::{rngs::StdRng, Rng};
-use rand_distr::{UnitCircle, UnitDisc};
+use std::collections::HashMap;
 
-use crate::color::{hex_color, Color};
+use crate::color::{blackbody_color, hex_color, luminance, Color};
+use crate::sampler::{sample_cosine_hemisphere, sample_disc};
+use crate::shape::{HitRecord, Triangle};
+use crate::texture::Texture;
 
 /// Represents a shader material with some physical properties
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Material {
     /// Albedo color
     pub color: Color,
@@ -15,6 +18,10 @@ pub struct Material {
     /// Roughness parameter for Beckmann microfacet distribution
     pub roughness: f64,
 
+    /// Roughness along the bitangent of anisotropic conductors, where `roughness`
+    /// applies along the tangent, or `None` for the same roughness in all directions
+    pub roughness_v: Option<f64>,
+
     /// Metallic versus dielectric
     pub metallic: f64,
 
@@ -23,6 +30,90 @@ pub struct Material {
 
     /// Transmittance (e.g., glass)
     pub transparent: bool,
+
+    /// Scattering model used to interpret the other parameters
+    pub model: ShadingModel,
+
+    /// Albedo texture, which replaces `color` when present
+    pub texture: Option<Texture>,
+
+    /// Tangent-space normal map, with RGB values in [0, 1] encoding XYZ
+    /// components in [-1, 1]
+    pub normal_map: Option<Texture>,
+
+    /// Height map that displaces the surface of meshes and monomial surfaces along their
+    /// normals when they are added to a scene (see `Object::displaced`), with values in
+    /// [0, 1] scaled by `displacement_scale`
+    pub displacement: Option<Texture>,
+
+    /// Height of the surface where the displacement map is white, in world units
+    pub displacement_scale: f64,
+
+    /// Height map that perturbs the shading normal without moving the surface (see
+    /// `Material::bump`), with values in [0, 1] scaled by `bump_scale`
+    pub bump: Option<Texture>,
+
+    /// Height of the bumps where the bump map is white, in units of the surface's
+    /// tangent vectors
+    pub bump_scale: f64,
+
+    /// Cutout mask, where the surface is removed wherever the average of its channels
+    /// is below one half, as for leaves or chain-link fences on flat quads
+    pub opacity: Option<Texture>,
+
+    /// Roughness map, which replaces `roughness` with the average of its channels when
+    /// present
+    pub roughness_map: Option<Texture>,
+}
+
+/// Step in UV space for the finite differences of a bump map
+const BUMP_DELTA: f64 = 1e-3;
+
+/// Value of an opacity mask below which a surface is cut out
+const OPACITY_THRESHOLD: f64 = 0.5;
+
+/// A vertex shared by the triangles of a mesh, as it is displaced by a height map
+struct DisplacedVertex {
+    position: glm::DVec3,
+    uv: glm::DVec2,
+
+    /// Average of the vertex normals, along which the vertex is displaced
+    direction: glm::DVec3,
+
+    /// Largest UV extent of the triangles around the vertex
+    footprint: f64,
+
+    /// Sum of the displaced normals given by the triangles around the vertex
+    normal: glm::DVec3,
+}
+
+/// Scattering model of a material
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub enum ShadingModel {
+    /// Beckmann specular lobe over a Lambertian base, or a rough dielectric
+    /// when the material is transparent
+    #[default]
+    Standard,
+
+    /// GGX (Trowbridge-Reitz) conductor with Smith shadowing, where `color`
+    /// is the reflectance at normal incidence
+    Conductor,
+
+    /// Smooth or GGX-rough dielectric interface with exact Fresnel
+    /// reflectance, where `color` tints transmitted light
+    Dielectric,
+
+    /// Oren-Nayar rough diffuse reflection, where `roughness` is the standard
+    /// deviation of the microfacet slope angle in radians
+    OrenNayar,
+
+    /// GGX dielectric coat with exact Fresnel reflectance over a Lambertian base of
+    /// `color`, which only receives the light that the coat does not reflect
+    Layered,
+
+    /// Invisible surface that only darkens what is behind it where it is in shadow,
+    /// see `Material::shadow_catcher`
+    ShadowCatcher,
 }
 
 impl Default for Material {
@@ -32,75 +123,509 @@ impl Default for Material {
 }
 
 impl Material {
-    /// Perfect diffuse (Lambertian) material with a given color
-    pub fn diffuse(color: Color) -> Material {
+    /// Opaque, rough, non-emissive material of a given color without any textures,
+    /// which the other constructors start from
+    fn base(color: Color) -> Material {
         Material {
             color,
             index: 1.5,
             roughness: 1.0,
+            roughness_v: None,
             metallic: 0.0,
             emittance: 0.0,
             transparent: false,
+            model: ShadingModel::Standard,
+            texture: None,
+            normal_map: None,
+            displacement: None,
+            displacement_scale: 0.0,
+            bump: None,
+            bump_scale: 0.0,
+            opacity: None,
+            roughness_map: None,
         }
     }
 
+    /// Perfect diffuse (Lambertian) material with a given color
+    pub fn diffuse(color: Color) -> Material {
+        Self::base(color)
+    }
+
     /// Specular material with a given color and roughness
     pub fn specular(color: Color, roughness: f64) -> Material {
         Material {
-            color,
-            index: 1.5,
             roughness,
-            metallic: 0.0,
-            emittance: 0.0,
-            transparent: false,
+            ..Self::base(color)
         }
     }
 
     /// Clear material with a specified index of refraction and roughness (such as glass)
     pub fn clear(index: f64, roughness: f64) -> Material {
         Material {
-            color: glm::vec3(1.0, 1.0, 1.0),
             index,
             roughness,
-            metallic: 0.0,
-            emittance: 0.0,
             transparent: true,
+            ..Self::base(glm::vec3(1.0, 1.0, 1.0))
         }
     }
 
     /// Colored transparent material
     pub fn transparent(color: Color, index: f64, roughness: f64) -> Material {
         Material {
-            color,
             index,
             roughness,
-            metallic: 0.0,
-            emittance: 0.0,
             transparent: true,
+            ..Self::base(color)
         }
     }
 
     /// Metallic material (has extra tinted specular reflections)
     pub fn metallic(color: Color, roughness: f64) -> Material {
         Material {
-            color,
-            index: 1.5,
             roughness,
             metallic: 1.0,
-            emittance: 0.0,
-            transparent: false,
+            ..Self::base(color)
+        }
+    }
+
+    /// Rough conductor using a GGX microfacet distribution, suitable for
+    /// brushed or polished metals
+    ///
+    /// The reflectance at normal incidence is `color`, and `roughness` is
+    /// perceptual, so the GGX width is `roughness²`. A roughness of zero gives
+    /// a perfect mirror.
+    pub fn conductor(color: Color, roughness: f64) -> Material {
+        Material {
+            roughness,
+            metallic: 1.0,
+            model: ShadingModel::Conductor,
+            ..Self::base(color)
+        }
+    }
+
+    /// Brushed conductor using an anisotropic GGX distribution, with separate
+    /// roughness along the tangent (`roughness_u`) and bitangent (`roughness_v`)
+    ///
+    /// The tangent comes from the shape's surface parameterization, so highlights
+    /// stretch along the direction of increasing u when `roughness_u` is larger.
+    pub fn anisotropic_conductor(color: Color, roughness_u: f64, roughness_v: f64) -> Material {
+        Material {
+            roughness_v: Some(roughness_v),
+            ..Material::conductor(color, roughness_u)
+        }
+    }
+
+    /// Glass-like dielectric that reflects or refracts according to the Fresnel
+    /// equations, with GGX-distributed microfacets when `roughness` is positive
+    pub fn dielectric(index: f64, roughness: f64) -> Material {
+        Material {
+            index,
+            roughness,
+            transparent: true,
+            model: ShadingModel::Dielectric,
+            ..Self::base(glm::vec3(1.0, 1.0, 1.0))
+        }
+    }
+
+    /// Glossy coated material, such as plastic, lacquer, or varnished wood
+    ///
+    /// A dielectric coat with the given index of refraction and perceptual roughness
+    /// reflects light by Fresnel, and the diffuse base of `color` only sees the light
+    /// that passes through the coat on the way in and out. Unlike `specular`, this never
+    /// reflects more light than it receives. The roughness should be positive, as the
+    /// coat is kept slightly rough so that its lobe can be sampled.
+    pub fn layered(color: Color, index: f64, roughness: f64) -> Material {
+        Material {
+            index,
+            roughness,
+            model: ShadingModel::Layered,
+            ..Self::base(color)
+        }
+    }
+
+    /// Rough matte material using the Oren-Nayar model, suitable for surfaces
+    /// like concrete, clay, or the moon
+    ///
+    /// Rough diffuse surfaces scatter more light back toward the light source
+    /// than a Lambertian surface, so they look flatter when lit from the front.
+    /// The `roughness` is the standard deviation of microfacet angles in
+    /// radians, and zero is exactly Lambertian.
+    pub fn oren_nayar(color: Color, roughness: f64) -> Material {
+        Material {
+            roughness,
+            model: ShadingModel::OrenNayar,
+            ..Self::base(color)
         }
     }
 
     /// Perfect emissive material, useful for modeling area lights
     pub fn light(color: Color, emittance: f64) -> Material {
         Material {
-            color,
             index: 1.0,
-            roughness: 1.0,
-            metallic: 0.0,
             emittance,
-            transparent: false,
+            ..Self::base(color)
+        }
+    }
+
+    /// Emissive material with the color of a blackbody at a temperature in kelvin
+    ///
+    /// The color has unit luminance, so `emittance` sets the brightness as in `light`.
+    pub fn blackbody(temperature: f64, emittance: f64) -> Material {
+        Self::light(blackbody_color(temperature), emittance)
+    }
+
+    /// Matte for compositing rendered objects over a photograph, such as a ground plane
+    ///
+    /// Camera rays pass through the surface to the background, which is darkened in
+    /// proportion to how much of the light from the scene's lights is blocked at the
+    /// surface, so only the shadows cast onto it remain. Path tracing with
+    /// `Renderer::render` handles this specially, and other integrators shade the
+    /// surface as white and diffuse.
+    pub fn shadow_catcher() -> Material {
+        Material {
+            model: ShadingModel::ShadowCatcher,
+            ..Self::diffuse(glm::vec3(1.0, 1.0, 1.0))
+        }
+    }
+}
+
+impl Material {
+    /// Use a texture for the albedo color of this material
+    pub fn texture(mut self, texture: impl Into<Texture>) -> Self {
+        self.texture = Some(texture.into());
+        self
+    }
+
+    /// Evaluate any textures at the given surface coordinates, returning an
+    /// untextured material with the resulting parameters
+    pub fn at(&self, uv: &glm::DVec2) -> Material {
+        self.filtered_at(uv, 0.0)
+    }
+
+    /// Resolve textures averaged over a square footprint with side length `width` in
+    /// UV space, which avoids aliasing where textures are minified
+    pub fn filtered_at(&self, uv: &glm::DVec2, width: f64) -> Material {
+        Material {
+            color: match &self.texture {
+                Some(texture) => texture.filtered_color(uv, width),
+                None => self.color,
+            },
+            roughness: match &self.roughness_map {
+                Some(map) => map.filtered_value(uv, width).mean(),
+                None => self.roughness,
+            },
+            texture: None,
+            normal_map: None,
+            displacement: None,
+            bump: None,
+            opacity: None,
+            roughness_map: None,
+            ..*self
+        }
+    }
+
+    /// Whether the material has any texture that `filtered_at` averages over a footprint,
+    /// which needs ray differentials to avoid aliasing
+    pub fn has_filtered_textures(&self) -> bool {
+        self.texture.is_some() || self.roughness_map.is_some()
+    }
+
+    /// Whether the surface is cut out at the given surface coordinates by its opacity
+    /// mask, so that rays pass through it
+    pub fn is_cut_out(&self, uv: &glm::DVec2) -> bool {
+        match &self.opacity {
+            Some(opacity) => opacity.value(uv).mean() < OPACITY_THRESHOLD,
+            None => false,
+        }
+    }
+
+    /// Whether the BSDF is a delta distribution (a perfect mirror or smooth
+    /// glass), which cannot be reached by light sampling
+    pub fn is_delta(&self) -> bool {
+        self.roughness == 0.0
+            && self.roughness_v.is_none_or(|r| r == 0.0)
+            && matches!(
+                self.model,
+                ShadingModel::Conductor | ShadingModel::Dielectric
+            )
+    }
+
+    /// Whether the BSDF is a fully rough, opaque dielectric, such as `Material::diffuse`,
+    /// whose reflection is close to Lambertian
+    pub fn is_rough_diffuse(&self) -> bool {
+        self.roughness >= 1.0
+            && self.roughness_v.is_none()
+            && self.metallic == 0.0
+            && !self.transparent
+            && self.model == ShadingModel::Standard
+    }
+
+    /// Use a tangent-space normal map to perturb the shading normal
+    pub fn normal_map(mut self, normal_map: impl Into<Texture>) -> Self {
+        self.normal_map = Some(normal_map.into());
+        self
+    }
+
+    /// Use a height map to perturb the shading normal, as if the surface were raised by
+    /// up to `scale` where it is white
+    ///
+    /// This is cheaper than displacement and works on any shape with UV coordinates, but
+    /// the silhouette and shadows of the surface are unchanged.
+    pub fn bump(mut self, bump: impl Into<Texture>, scale: f64) -> Self {
+        self.bump = Some(bump.into());
+        self.bump_scale = scale;
+        self
+    }
+
+    /// Use a cutout mask that removes the surface wherever it is dark
+    ///
+    /// Unlike a transparent material, rays pass straight through the holes without
+    /// refracting, and the edges of the holes are hard rather than partially opaque.
+    pub fn opacity(mut self, opacity: impl Into<Texture>) -> Self {
+        self.opacity = Some(opacity.into());
+        self
+    }
+
+    /// Use a texture for the roughness of this material, such as the roughness map of
+    /// a PBR asset, read as raw values in [0, 1]
+    ///
+    /// This is interpreted like `roughness` by each shading model, so for anisotropic
+    /// conductors it replaces the roughness along the tangent only. A constant roughness
+    /// is `Texture::Solid` with equal channels.
+    pub fn roughness_map(mut self, roughness_map: impl Into<Texture>) -> Self {
+        self.roughness_map = Some(roughness_map.into());
+        self
+    }
+
+    /// Use a height map to displace the surface of meshes and monomial surfaces, up to
+    /// `scale` world units where it is white
+    ///
+    /// The displacement is applied when an object is added to a scene, which replaces a
+    /// monomial surface with a finely tessellated mesh.
+    pub fn displacement(mut self, displacement: impl Into<Texture>, scale: f64) -> Self {
+        self.displacement = Some(displacement.into());
+        self.displacement_scale = scale;
+        self
+    }
+
+    /// Displace the vertices of a triangle mesh along their normals by the height map,
+    /// returning the triangles unchanged if the material has none
+    ///
+    /// Unlike a normal map, this changes the geometry itself, including silhouettes, so
+    /// the mesh should be tessellated finely enough to resolve the height map. Vertices
+    /// with the same position and UV are displaced once, along their averaged normal and
+    /// with the heights filtered over the largest UV footprint of their triangles, so
+    /// that the surface stays closed. The new normals are found by finite differences of
+    /// the height map across that footprint, averaged over the triangles of each vertex.
+    pub fn displace(&self, triangles: &[Triangle]) -> Vec<Triangle> {
+        let displacement = match &self.displacement {
+            Some(displacement) => displacement,
+            None => return triangles.to_vec(),
+        };
+        let height = |uv: &glm::DVec2, width: f64| {
+            displacement.filtered_value(uv, width).mean() * self.displacement_scale
+        };
+
+        // Gather the corners of the triangles into shared vertices
+        let mut indices = HashMap::new();
+        let mut vertices: Vec<DisplacedVertex> = Vec::new();
+        let mut corners = Vec::with_capacity(triangles.len());
+        for tri in triangles {
+            let (du1, du2) = (tri.uv2 - tri.uv1, tri.uv3 - tri.uv1);
+            let footprint = du1.abs().max().max(du2.abs().max());
+            let mut corner = [0; 3];
+            for (k, &(v, n, uv)) in [
+                (tri.v1, tri.n1, tri.uv1),
+                (tri.v2, tri.n2, tri.uv2),
+                (tri.v3, tri.n3, tri.uv3),
+            ]
+            .iter()
+            .enumerate()
+            {
+                let key = [v.x, v.y, v.z, uv.x, uv.y].map(f64::to_bits);
+                let index = *indices.entry(key).or_insert_with(|| {
+                    vertices.push(DisplacedVertex {
+                        position: v,
+                        uv,
+                        direction: glm::vec3(0.0, 0.0, 0.0),
+                        footprint: 0.0,
+                        normal: glm::vec3(0.0, 0.0, 0.0),
+                    });
+                    vertices.len() - 1
+                });
+                let vertex = &mut vertices[index];
+                vertex.direction += n.normalize();
+                vertex.footprint = vertex.footprint.max(footprint);
+                corner[k] = index;
+            }
+            corners.push(corner);
+        }
+        for vertex in &mut vertices {
+            vertex.direction = vertex.direction.normalize();
+        }
+
+        // Sum the normals that each triangle gives its vertices, from the partial
+        // derivatives of position and central differences of height
+        for (tri, corner) in triangles.iter().zip(&corners) {
+            let (du1, du2) = (tri.uv2 - tri.uv1, tri.uv3 - tri.uv1);
+            let (dp1, dp2) = (tri.v2 - tri.v1, tri.v3 - tri.v1);
+            let det = du1.x * du2.y - du1.y * du2.x;
+            if det.abs() < 1e-12 {
+                // Without a UV parameterization, the normal cannot be updated
+                continue;
+            }
+            let dpdu = (dp1 * du2.y - dp2 * du1.y) / det;
+            let dpdv = (dp2 * du1.x - dp1 * du2.x) / det;
+            for &index in corner {
+                let vertex = &vertices[index];
+                let (n, uv, width) = (vertex.direction, vertex.uv, vertex.footprint);
+                let h = width / 2.0;
+                let dhdu = (height(&(uv + glm::vec2(h, 0.0)), width)
+                    - height(&(uv - glm::vec2(h, 0.0)), width))
+                    / (2.0 * h);
+                let dhdv = (height(&(uv + glm::vec2(0.0, h)), width)
+                    - height(&(uv - glm::vec2(0.0, h)), width))
+                    / (2.0 * h);
+                let normal = (dpdu + n * dhdu).cross(&(dpdv + n * dhdv)).normalize();
+                vertices[index].normal += if normal.dot(&n) < 0.0 {
+                    -normal
+                } else {
+                    normal
+                };
+            }
+        }
+
+        let displaced: Vec<(glm::DVec3, glm::DVec3)> = vertices
+            .iter()
+            .map(|vertex| {
+                let position =
+                    vertex.position + vertex.direction * height(&vertex.uv, vertex.footprint);
+                let normal = vertex
+                    .normal
+                    .try_normalize(1e-12)
+                    .unwrap_or(vertex.direction);
+                (position, normal)
+            })
+            .collect();
+        triangles
+            .iter()
+            .zip(&corners)
+            .map(|(tri, &[a, b, c])| Triangle {
+                v1: displaced[a].0,
+                v2: displaced[b].0,
+                v3: displaced[c].0,
+                n1: displaced[a].1,
+                n2: displaced[b].1,
+                n3: displaced[c].1,
+                ..*tri
+            })
+            .collect()
+    }
+
+    /// Compute the shading normal at a hit, applying the bump map and normal map if
+    /// present
+    ///
+    /// The bump map is applied first, tilting the normal by the gradient of its height
+    /// along the hit's tangent vectors. The normal map is then applied in a tangent
+    /// frame around the bumped normal, so the two combine. The frame is built from the
+    /// hit's tangent vectors, falling back to an arbitrary frame when the shape does
+    /// not provide them. If the perturbed normal would flip to the other side of the
+    /// surface from the viewer `wo`, the geometric normal is used instead.
+    pub fn shading_normal(&self, record: &HitRecord, wo: &glm::DVec3) -> glm::DVec3 {
+        let n = record.normal;
+        let bumped = self.bumped_normal(record);
+        let shading = match &self.normal_map {
+            Some(normal_map) => {
+                let local = normal_map.value(&record.uv) * 2.0 - glm::vec3(1.0, 1.0, 1.0);
+
+                // Gram-Schmidt orthogonalization of the tangent frame
+                let t = record.tangent - bumped * bumped.dot(&record.tangent);
+                let frame = if t.magnitude_squared() > 1e-16 {
+                    let t = t.normalize();
+                    let b = bumped.cross(&t);
+                    let b = if b.dot(&record.bitangent) < 0.0 {
+                        -b
+                    } else {
+                        b
+                    };
+                    glm::mat3(t.x, b.x, bumped.x, t.y, b.y, bumped.y, t.z, b.z, bumped.z)
+                } else {
+                    local_to_world(&bumped)
+                };
+                (frame * local).normalize()
+            }
+            None => bumped,
+        };
+        if shading.dot(wo).is_sign_positive() == n.dot(wo).is_sign_positive() {
+            shading
+        } else {
+            n
+        }
+    }
+
+    /// Tilt the normal of a hit by the gradient of the bump map, returning the normal
+    /// unchanged if there is no bump map
+    ///
+    /// The tangent and bitangent of the hit are taken as the derivatives of position
+    /// with respect to u and v, and the gradient is found by central differences.
+    fn bumped_normal(&self, record: &HitRecord) -> glm::DVec3 {
+        let n = record.normal;
+        let bump = match &self.bump {
+            Some(bump) => bump,
+            None => return n,
+        };
+        let (dpdu, dpdv) = if record.tangent.cross(&record.bitangent).magnitude_squared() > 1e-16 {
+            (record.tangent, record.bitangent)
+        } else {
+            let frame = local_to_world(&n);
+            (frame.column(0).into(), frame.column(1).into())
+        };
+        let height = |du: f64, dv: f64| {
+            bump.value(&(record.uv + glm::vec2(du, dv))).mean() * self.bump_scale
+        };
+        let dhdu = (height(BUMP_DELTA, 0.0) - height(-BUMP_DELTA, 0.0)) / (2.0 * BUMP_DELTA);
+        let dhdv = (height(0.0, BUMP_DELTA) - height(0.0, -BUMP_DELTA)) / (2.0 * BUMP_DELTA);
+        // Expanding (dpdu + n dh/du) × (dpdv + n dh/dv), with dpdu × dpdv replaced by
+        // the normal so that a flat bump map leaves smooth shading normals exactly alone
+        let area = dpdu.cross(&dpdv);
+        let sign = area.dot(&n).signum();
+        let bumped = n * area.magnitude() + (dpdu.cross(&n) * dhdv + n.cross(&dpdv) * dhdu) * sign;
+        bumped.normalize()
+    }
+}
+
+/// Shading frame at a surface point, which orients the BSDF
+#[derive(Copy, Clone, Debug)]
+pub struct Frame {
+    /// Shading normal
+    pub normal: glm::DVec3,
+
+    /// Shading tangent, used to orient anisotropic materials, or zero if unknown
+    pub tangent: glm::DVec3,
+}
+
+impl Frame {
+    /// Create a frame from a shading normal and tangent
+    pub fn new(normal: glm::DVec3, tangent: glm::DVec3) -> Self {
+        Self { normal, tangent }
+    }
+
+    /// Create a frame with an unknown tangent, for isotropic scattering
+    pub fn from_normal(normal: glm::DVec3) -> Self {
+        Self::new(normal, glm::vec3(0.0, 0.0, 0.0))
+    }
+
+    /// Orthonormal basis with columns (tangent, bitangent, normal), by Gram-Schmidt
+    /// orthogonalization of the tangent, or an arbitrary basis without one
+    fn basis(&self) -> glm::DMat3 {
+        let n = &self.normal;
+        let t = self.tangent - n * n.dot(&self.tangent);
+        if t.magnitude_squared() > 1e-16 {
+            let t = t.normalize();
+            let b = n.cross(&t);
+            glm::mat3(t.x, b.x, n.x, t.y, b.y, n.y, t.z, b.z, n.z)
+        } else {
+            local_to_world(n)
         }
     }
 }
@@ -109,7 +634,7 @@ impl Material {
 impl Material {
     /// Bidirectional scattering distribution function
     ///
-    /// - `n` - surface normal vector
+    /// - `frame` - shading normal and tangent of the surface
     /// - `wo` - unit direction vector toward the viewer
     /// - `wi` - unit direction vector toward the incident ray
     ///
@@ -122,7 +647,15 @@ impl Material {
     /// - https://graphics.stanford.edu/courses/cs148-10-summer/docs/2006--degreve--reflection_refraction.pdf
     /// - http://www.pbr-book.org/3ed-2018/Materials/BSDFs.html
     /// - https://www.cs.cornell.edu/~srm/publications/EGSR07-btdf.pdf
-    pub fn bsdf(&self, n: &glm::DVec3, wo: &glm::DVec3, wi: &glm::DVec3) -> Color {
+    pub fn bsdf(&self, frame: &Frame, wo: &glm::DVec3, wi: &glm::DVec3) -> Color {
+        let n = &frame.normal;
+        match self.model {
+            ShadingModel::Standard | ShadingModel::ShadowCatcher => {}
+            ShadingModel::Conductor => return self.ggx_bsdf(frame, wo, wi),
+            ShadingModel::Dielectric => return self.dielectric_bsdf(n, wo, wi),
+            ShadingModel::OrenNayar => return self.oren_nayar_bsdf(n, wo, wi),
+            ShadingModel::Layered => return self.layered_bsdf(n, wo, wi),
+        }
         let n_dot_wi = n.dot(wi);
         let n_dot_wo = n.dot(wo);
         let wi_outside = n_dot_wi.is_sign_positive();
@@ -221,18 +754,31 @@ impl Material {
     /// estimating the average magnitude of the Fresnel term.
     ///
     /// Reference: https://agraphicsguy.wordpress.com/2015/11/01/sampling-microfacet-brdf/
+    ///
+    /// The direction within the chosen lobe is found from the point `u` of the unit
+    /// square, and the choice of lobe from `rng`.
     pub fn sample_f(
         &self,
-        n: &glm::DVec3,
+        frame: &Frame,
         wo: &glm::DVec3,
+        u: [f64; 2],
         rng: &mut StdRng,
     ) -> Option<(glm::DVec3, f64)> {
+        let n = &frame.normal;
+        match self.model {
+            ShadingModel::Standard | ShadingModel::ShadowCatcher => {}
+            ShadingModel::Conductor => return self.ggx_sample_f(frame, wo, u),
+            ShadingModel::Dielectric => return self.dielectric_sample_f(n, wo, u, rng),
+            ShadingModel::Layered => return self.layered_sample_f(n, wo, u, rng),
+            ShadingModel::OrenNayar => {
+                if n.dot(wo) <= 0.0 {
+                    return None;
+                }
+                return Some(sample_cosine_hemisphere(n, u));
+            }
+        }
         let m2 = self.roughness * self.roughness;
-
-        // Estimate specular contribution using Fresnel term
-        let f0 = ((self.index - 1.0) / (self.index + 1.0)).powi(2);
-        let f = (1.0 - self.metallic) * f0 + self.metallic * self.color.mean();
-        let f = glm::mix_scalar(f, 1.0, 0.2);
+        let f = self.specular_weight();
 
         // Ratio of refractive indices
         let eta_t = if wo.dot(n) > 0.0 {
@@ -241,39 +787,28 @@ impl Material {
             1.0 / self.index
         };
 
-        let beckmann = |rng: &mut StdRng| {
+        let beckmann = || {
             // PIT for Beckmann distribution microfacet normal
             // θ = arctan √(-m^2 ln U)
-            let theta = (m2 * -rng.gen::<f64>().ln()).sqrt().atan();
+            let theta = (m2 * -(1.0 - u[0]).ln()).sqrt().atan();
             let (sin_t, cos_t) = theta.sin_cos();
 
             // Generate halfway vector by sampling azimuth uniformly
-            let [x, y]: [f64; 2] = rng.sample(UnitCircle);
+            let (y, x) = (2.0 * std::f64::consts::PI * u[1]).sin_cos();
             let h = glm::vec3(x * sin_t, y * sin_t, cos_t);
             local_to_world(n) * h
         };
 
-        let beckmann_pdf = |h: &glm::DVec3| {
-            // p = 1 / (πm^2 cos^3 θ) * e^(-tan^2(θ) / m^2)
-            let cos_t = h.dot(n).abs();
-            let sin_t = (1.0 - cos_t * cos_t).sqrt();
-            (std::f64::consts::PI * m2 * cos_t.powi(3)).recip()
-                * (-(sin_t / cos_t).powi(2) / m2).exp()
-        };
-
         let wi = if rng.gen_bool(f) {
             // Specular component
-            let h = beckmann(rng);
+            let h = beckmann();
             -glm::reflect_vec(wo, &h)
         } else if !self.transparent {
             // Diffuse component (Lambertian)
-            // Simple cosine-sampling using Malley's method
-            let [x, y]: [f64; 2] = rng.sample(UnitDisc);
-            let z = (1.0_f64 - x * x - y * y).sqrt();
-            local_to_world(n) * glm::vec3(x, y, z)
+            sample_cosine_hemisphere(n, u).0
         } else {
             // Transmitted component
-            let h = beckmann(rng);
+            let h = beckmann();
             let cos_to = h.dot(wo);
             let wo_perp = wo - h * cos_to;
             let wi_perp = -wo_perp / eta_t;
@@ -287,6 +822,42 @@ impl Material {
             -cos_to.signum() * cos_ti * h + wi_perp
         };
 
+        Some((wi, self.pdf(frame, wo, &wi)))
+    }
+
+    /// Probability density with which `sample_f` generates the direction `wi`,
+    /// measured with respect to solid angle
+    pub fn pdf(&self, frame: &Frame, wo: &glm::DVec3, wi: &glm::DVec3) -> f64 {
+        let n = &frame.normal;
+        match self.model {
+            ShadingModel::Standard | ShadingModel::ShadowCatcher => {}
+            ShadingModel::Conductor => return self.ggx_pdf(frame, wo, wi),
+            ShadingModel::Dielectric => return self.dielectric_pdf(n, wo, wi),
+            ShadingModel::Layered => return self.layered_pdf(n, wo, wi),
+            ShadingModel::OrenNayar => {
+                return if n.dot(wo) > 0.0 {
+                    wi.dot(n).max(0.0) * std::f64::consts::FRAC_1_PI
+                } else {
+                    0.0
+                };
+            }
+        }
+        let m2 = self.roughness * self.roughness;
+        let f = self.specular_weight();
+        let eta_t = if wo.dot(n) > 0.0 {
+            self.index
+        } else {
+            1.0 / self.index
+        };
+
+        let beckmann_pdf = |h: &glm::DVec3| {
+            // p = 1 / (πm^2 cos^3 θ) * e^(-tan^2(θ) / m^2)
+            let cos_t = h.dot(n).abs();
+            let sin_t = (1.0 - cos_t * cos_t).sqrt();
+            (std::f64::consts::PI * m2 * cos_t.powi(3)).recip()
+                * (-(sin_t / cos_t).powi(2) / m2).exp()
+        };
+
         // Multiple importance sampling - add up total probability
         let mut p = 0.0;
         p += {
@@ -303,17 +874,514 @@ impl Material {
             let h = (wi * eta_t + wo).normalize();
             let p_h = beckmann_pdf(&h);
             let h_dot_wo = h.dot(wo);
-            let h_dot_wi = h.dot(&wi);
+            let h_dot_wi = h.dot(wi);
             let jacobian = h_dot_wo.abs() / (eta_t * h_dot_wi + h_dot_wo).powi(2);
             (1.0 - f) * p_h * jacobian
         } else {
             0.0
         };
-        Some((wi, p))
+        p
+    }
+
+    /// Fraction of samples drawn from the specular lobe, estimated from the
+    /// average magnitude of the Fresnel term
+    fn specular_weight(&self) -> f64 {
+        let f0 = ((self.index - 1.0) / (self.index + 1.0)).powi(2);
+        let f = (1.0 - self.metallic) * f0 + self.metallic * self.color.mean();
+        glm::mix_scalar(f, 1.0, 0.2)
+    }
+
+    /// GGX microfacet BRDF for conductors, with separable Smith shadowing and
+    /// Schlick's Fresnel approximation tinted by `color`
+    ///
+    /// A roughness of zero is a perfect mirror. Its BRDF is a delta function,
+    /// which we represent by returning `F / (n • wi)` exactly along the
+    /// mirror direction (paired with a PDF of 1) and zero elsewhere.
+    ///
+    /// Reference: https://www.cs.cornell.edu/~srm/publications/EGSR07-btdf.pdf
+    fn ggx_bsdf(&self, frame: &Frame, wo: &glm::DVec3, wi: &glm::DVec3) -> Color {
+        let n = &frame.normal;
+        let n_dot_wi = n.dot(wi);
+        let n_dot_wo = n.dot(wo);
+        if n_dot_wi <= 0.0 || n_dot_wo <= 0.0 {
+            return glm::vec3(0.0, 0.0, 0.0);
+        }
+        let h = (wi + wo).normalize();
+        let f = self.schlick(wo.dot(&h));
+        let (alpha_u, alpha_v) = self.ggx_alphas();
+        if alpha_u == alpha_v {
+            let alpha = alpha_u;
+            if alpha == 0.0 {
+                return if is_mirror(n, wo, wi) {
+                    f / n_dot_wi
+                } else {
+                    glm::vec3(0.0, 0.0, 0.0)
+                };
+            }
+            let d = ggx_d(alpha, n.dot(&h));
+            let g = smith_g1(alpha, n_dot_wo) * smith_g1(alpha, n_dot_wi);
+            return f * (d * g / (4.0 * n_dot_wo * n_dot_wi));
+        }
+        let to_local = frame.basis().transpose();
+        let alphas = (alpha_u, alpha_v);
+        let d = ggx_d_anisotropic(alphas, &(to_local * h));
+        let g = smith_g1_anisotropic(alphas, &(to_local * wo))
+            * smith_g1_anisotropic(alphas, &(to_local * wi));
+        f * (d * g / (4.0 * n_dot_wo * n_dot_wi))
+    }
+
+    /// Sample the GGX distribution of visible normals (VNDF)
+    fn ggx_sample_f(
+        &self,
+        frame: &Frame,
+        wo: &glm::DVec3,
+        u: [f64; 2],
+    ) -> Option<(glm::DVec3, f64)> {
+        let n = &frame.normal;
+        let (alpha_u, alpha_v) = self.ggx_alphas();
+        if n.dot(wo) <= 0.0 {
+            return None;
+        }
+        if alpha_u == alpha_v && alpha_u == 0.0 {
+            return Some((-glm::reflect_vec(wo, n), 1.0));
+        }
+
+        let to_world = if alpha_u == alpha_v {
+            local_to_world(n)
+        } else {
+            frame.basis()
+        };
+        let h = sample_ggx_vndf(&to_world, wo, (alpha_u, alpha_v), u);
+        let wi = -glm::reflect_vec(wo, &h);
+        if wi.dot(n) <= 0.0 {
+            return None;
+        }
+        Some((wi, self.ggx_pdf(frame, wo, &wi)))
+    }
+
+    /// PDF of VNDF sampling, p = G1(wo) D(h) / (4 (n • wo))
+    fn ggx_pdf(&self, frame: &Frame, wo: &glm::DVec3, wi: &glm::DVec3) -> f64 {
+        let n = &frame.normal;
+        let n_dot_wo = n.dot(wo);
+        if n_dot_wo <= 0.0 || n.dot(wi) <= 0.0 {
+            return 0.0;
+        }
+        let (alpha_u, alpha_v) = self.ggx_alphas();
+        let h = (wi + wo).normalize();
+        if alpha_u == alpha_v {
+            let alpha = alpha_u;
+            if alpha == 0.0 {
+                return if is_mirror(n, wo, wi) { 1.0 } else { 0.0 };
+            }
+            return smith_g1(alpha, n_dot_wo) * ggx_d(alpha, n.dot(&h)) / (4.0 * n_dot_wo);
+        }
+        let to_local = frame.basis().transpose();
+        let alphas = (alpha_u, alpha_v);
+        smith_g1_anisotropic(alphas, &(to_local * wo)) * ggx_d_anisotropic(alphas, &(to_local * h))
+            / (4.0 * n_dot_wo)
+    }
+
+    /// BSDF of a dielectric interface, following Walter et al. for the rough
+    /// case
+    ///
+    /// Whether the ray is entering or exiting is determined by the sign of
+    /// `wo • n`. As with the mirror conductor, a smooth interface is a pair of
+    /// delta lobes, evaluated only along the exact reflected and refracted
+    /// directions. Transmitted radiance is scaled by `1 / η^2`.
+    ///
+    /// Reference: https://www.cs.cornell.edu/~srm/publications/EGSR07-btdf.pdf
+    fn dielectric_bsdf(&self, n: &glm::DVec3, wo: &glm::DVec3, wi: &glm::DVec3) -> Color {
+        let (n, eta) = self.orient(n, wo);
+        let n_dot_wo = n.dot(wo);
+        let n_dot_wi = n.dot(wi);
+        let alpha = self.ggx_alpha();
+        if n_dot_wi > 0.0 {
+            // Reflection
+            let h = (wi + wo).normalize();
+            let f = fresnel_dielectric(wo.dot(&h), eta);
+            let value = if alpha == 0.0 {
+                if is_mirror(&n, wo, wi) {
+                    f / n_dot_wi
+                } else {
+                    0.0
+                }
+            } else {
+                let g = smith_g1(alpha, n_dot_wo) * smith_g1(alpha, n_dot_wi);
+                ggx_d(alpha, n.dot(&h)) * f * g / (4.0 * n_dot_wo * n_dot_wi)
+            };
+            glm::vec3(value, value, value)
+        } else if n_dot_wi < 0.0 {
+            // Transmission
+            let value = if alpha == 0.0 {
+                match refract(wo, &n, eta) {
+                    Some(t) if t.dot(wi) > 1.0 - 1e-9 => {
+                        (1.0 - fresnel_dielectric(n_dot_wo, eta)) / (eta * eta * -n_dot_wi)
+                    }
+                    _ => 0.0,
+                }
+            } else {
+                let h = -(wo + wi * eta).normalize();
+                let h = if h.dot(&n) < 0.0 { -h } else { h };
+                let wo_dot_h = wo.dot(&h);
+                let wi_dot_h = wi.dot(&h);
+                if wo_dot_h <= 0.0 || wi_dot_h >= 0.0 {
+                    return glm::vec3(0.0, 0.0, 0.0);
+                }
+                let f = fresnel_dielectric(wo_dot_h, eta);
+                let g = smith_g1(alpha, n_dot_wo) * smith_g1(alpha, -n_dot_wi);
+                let denom = wo_dot_h + eta * wi_dot_h;
+                (wi_dot_h * wo_dot_h / (n_dot_wi * n_dot_wo)).abs()
+                    * (1.0 - f)
+                    * ggx_d(alpha, n.dot(&h))
+                    * g
+                    / (denom * denom)
+            };
+            value * self.color
+        } else {
+            glm::vec3(0.0, 0.0, 0.0)
+        }
+    }
+
+    /// BRDF of a GGX dielectric coat over a Lambertian base
+    ///
+    /// The base is weighted by the Fresnel transmittance of the coat in both directions,
+    /// `(1 - F(n • wo)) (1 - F(n • wi))`. Its reflectance is then at most `1 - F(n • wo)`,
+    /// which leaves room for the light the coat reflects, so energy is conserved.
+    fn layered_bsdf(&self, n: &glm::DVec3, wo: &glm::DVec3, wi: &glm::DVec3) -> Color {
+        let n_dot_wi = n.dot(wi);
+        let n_dot_wo = n.dot(wo);
+        if n_dot_wi <= 0.0 || n_dot_wo <= 0.0 {
+            return glm::vec3(0.0, 0.0, 0.0);
+        }
+        let alpha = self.layered_alpha();
+        let h = (wi + wo).normalize();
+        let f = fresnel_dielectric(wo.dot(&h), self.index);
+        let g = smith_g1(alpha, n_dot_wo) * smith_g1(alpha, n_dot_wi);
+        let specular = ggx_d(alpha, n.dot(&h)) * f * g / (4.0 * n_dot_wo * n_dot_wi);
+        let transmittance = (1.0 - fresnel_dielectric(n_dot_wo, self.index))
+            * (1.0 - fresnel_dielectric(n_dot_wi, self.index));
+        glm::vec3(specular, specular, specular)
+            + self.color * (transmittance * std::f64::consts::FRAC_1_PI)
+    }
+
+    /// Sample either the coat, by its visible normals, or the base, by the cosine,
+    /// choosing between them by how much light each reflects toward `wo`
+    fn layered_sample_f(
+        &self,
+        n: &glm::DVec3,
+        wo: &glm::DVec3,
+        u: [f64; 2],
+        rng: &mut StdRng,
+    ) -> Option<(glm::DVec3, f64)> {
+        if n.dot(wo) <= 0.0 {
+            return None;
+        }
+        let wi = if rng.gen_bool(self.layered_specular_probability(n, wo)) {
+            let alpha = self.layered_alpha();
+            let h = sample_ggx_vndf(&local_to_world(n), wo, (alpha, alpha), u);
+            -glm::reflect_vec(wo, &h)
+        } else {
+            sample_cosine_hemisphere(n, u).0
+        };
+        if wi.dot(n) <= 0.0 {
+            return None;
+        }
+        Some((wi, self.layered_pdf(n, wo, &wi)))
+    }
+
+    /// PDF of `layered_sample_f`, summing the densities of both lobes
+    fn layered_pdf(&self, n: &glm::DVec3, wo: &glm::DVec3, wi: &glm::DVec3) -> f64 {
+        let n_dot_wo = n.dot(wo);
+        let n_dot_wi = n.dot(wi);
+        if n_dot_wo <= 0.0 || n_dot_wi <= 0.0 {
+            return 0.0;
+        }
+        let alpha = self.layered_alpha();
+        let h = (wi + wo).normalize();
+        let specular = smith_g1(alpha, n_dot_wo) * ggx_d(alpha, n.dot(&h)) / (4.0 * n_dot_wo);
+        let diffuse = n_dot_wi * std::f64::consts::FRAC_1_PI;
+        let p = self.layered_specular_probability(n, wo);
+        p * specular + (1.0 - p) * diffuse
+    }
+
+    /// Probability of sampling the coat rather than the base of a layered material,
+    /// from the Fresnel reflectance of the coat against the light reaching the base
+    fn layered_specular_probability(&self, n: &glm::DVec3, wo: &glm::DVec3) -> f64 {
+        let f = fresnel_dielectric(n.dot(wo), self.index);
+        let base = (1.0 - f) * luminance(&self.color);
+        (f / (f + base).max(1e-12)).clamp(0.1, 0.9)
+    }
+
+    /// GGX width of the coat of a layered material, kept away from a delta lobe
+    fn layered_alpha(&self) -> f64 {
+        self.ggx_alpha().max(1e-3)
+    }
+
+    /// Oren-Nayar BRDF, using the qualitative model with the A and B terms
+    ///
+    /// Reference: https://www.pbr-book.org/3ed-2018/Reflection_Models/Microfacet_Models#OrenNayarDiffuseReflection
+    fn oren_nayar_bsdf(&self, n: &glm::DVec3, wo: &glm::DVec3, wi: &glm::DVec3) -> Color {
+        let cos_i = n.dot(wi);
+        let cos_o = n.dot(wo);
+        if cos_i <= 0.0 || cos_o <= 0.0 {
+            return glm::vec3(0.0, 0.0, 0.0);
+        }
+        let sigma2 = self.roughness * self.roughness;
+        let a = 1.0 - sigma2 / (2.0 * (sigma2 + 0.33));
+        let b = 0.45 * sigma2 / (sigma2 + 0.09);
+
+        // Cosine of the azimuthal angle between the directions, from their
+        // projections onto the tangent plane
+        let (perp_i, perp_o) = (wi - n * cos_i, wo - n * cos_o);
+        let (sin_i, sin_o) = (perp_i.magnitude(), perp_o.magnitude());
+        let cos_phi = if sin_i > 1e-8 && sin_o > 1e-8 {
+            (perp_i.dot(&perp_o) / (sin_i * sin_o)).max(0.0)
+        } else {
+            0.0
+        };
+
+        // sin(α) tan(β), where α and β are the larger and smaller polar angles
+        let (sin_alpha, tan_beta) = if cos_i > cos_o {
+            (sin_o, sin_i / cos_i)
+        } else {
+            (sin_i, sin_o / cos_o)
+        };
+        self.color * (a + b * cos_phi * sin_alpha * tan_beta) / std::f64::consts::PI
+    }
+
+    /// Sample a dielectric interface, choosing between reflection and
+    /// refraction stochastically in proportion to the Fresnel coefficient
+    fn dielectric_sample_f(
+        &self,
+        n: &glm::DVec3,
+        wo: &glm::DVec3,
+        u: [f64; 2],
+        rng: &mut StdRng,
+    ) -> Option<(glm::DVec3, f64)> {
+        let (n_o, eta) = self.orient(n, wo);
+        let alpha = self.ggx_alpha();
+        let h = if alpha == 0.0 {
+            n_o
+        } else {
+            sample_ggx_vndf(&local_to_world(&n_o), wo, (alpha, alpha), u)
+        };
+        let wo_dot_h = wo.dot(&h);
+        let f = fresnel_dielectric(wo_dot_h, eta);
+        let wi = if rng.gen_bool(f.clamp(0.0, 1.0)) {
+            -glm::reflect_vec(wo, &h)
+        } else {
+            // Snell's law; total internal reflection has f = 1, so this
+            // branch is only reached when refraction is possible
+            refract(wo, &h, eta)?
+        };
+        let p = if alpha == 0.0 {
+            if wi.dot(&n_o) > 0.0 {
+                f
+            } else {
+                1.0 - f
+            }
+        } else {
+            self.dielectric_pdf(n, wo, &wi)
+        };
+        if p > 0.0 {
+            Some((wi, p))
+        } else {
+            None
+        }
+    }
+
+    /// PDF of sampling `wi` from a dielectric interface
+    fn dielectric_pdf(&self, n: &glm::DVec3, wo: &glm::DVec3, wi: &glm::DVec3) -> f64 {
+        let (n, eta) = self.orient(n, wo);
+        let n_dot_wo = n.dot(wo);
+        let alpha = self.ggx_alpha();
+        if alpha == 0.0 {
+            let f = fresnel_dielectric(n_dot_wo, eta);
+            return if is_mirror(&n, wo, wi) {
+                f
+            } else {
+                match refract(wo, &n, eta) {
+                    Some(t) if t.dot(wi) > 1.0 - 1e-9 => 1.0 - f,
+                    _ => 0.0,
+                }
+            };
+        }
+        let reflect = wi.dot(&n) > 0.0;
+        let h = if reflect {
+            (wi + wo).normalize()
+        } else {
+            let h = -(wo + wi * eta).normalize();
+            if h.dot(&n) < 0.0 {
+                -h
+            } else {
+                h
+            }
+        };
+        let wo_dot_h = wo.dot(&h);
+        let wi_dot_h = wi.dot(&h);
+        if wo_dot_h <= 0.0 {
+            return 0.0;
+        }
+        let f = fresnel_dielectric(wo_dot_h, eta);
+        // Density of the visible microfacet normal
+        let p_h = smith_g1(alpha, n_dot_wo) * ggx_d(alpha, n.dot(&h)) * wo_dot_h / n_dot_wo;
+        if reflect {
+            f * p_h / (4.0 * wo_dot_h)
+        } else if wi_dot_h < 0.0 {
+            let denom = wo_dot_h + eta * wi_dot_h;
+            (1.0 - f) * p_h * eta * eta * -wi_dot_h / (denom * denom)
+        } else {
+            0.0
+        }
     }
+
+    /// Flip the normal to the side of `wo`, returning it along with the ratio
+    /// of refractive indices η_i / η_o across the interface
+    fn orient(&self, n: &glm::DVec3, wo: &glm::DVec3) -> (glm::DVec3, f64) {
+        if wo.dot(n) >= 0.0 {
+            (*n, self.index)
+        } else {
+            (-n, 1.0 / self.index)
+        }
+    }
+
+    /// GGX width parameter from the perceptual roughness
+    fn ggx_alpha(&self) -> f64 {
+        self.roughness * self.roughness
+    }
+
+    /// GGX width parameters along the tangent and bitangent
+    ///
+    /// Anisotropic widths are kept away from zero, where the distribution would
+    /// collapse onto a line.
+    fn ggx_alphas(&self) -> (f64, f64) {
+        let alpha_u = self.ggx_alpha();
+        match self.roughness_v {
+            Some(roughness_v) if roughness_v != self.roughness => {
+                (alpha_u.max(1e-4), (roughness_v * roughness_v).max(1e-4))
+            }
+            _ => (alpha_u, alpha_u),
+        }
+    }
+
+    /// Schlick's approximation with the material color as F0
+    fn schlick(&self, cos_theta: f64) -> Color {
+        let f0 = self.color;
+        f0 + (glm::vec3(1.0, 1.0, 1.0) - f0) * (1.0 - cos_theta.abs()).powi(5)
+    }
+}
+
+/// GGX normal distribution function
+/// D = α^2 / (π ((n • h)^2 (α^2 - 1) + 1)^2)
+fn ggx_d(alpha: f64, n_dot_h: f64) -> f64 {
+    if n_dot_h <= 0.0 {
+        return 0.0;
+    }
+    let a2 = alpha * alpha;
+    let t = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (std::f64::consts::PI * t * t)
+}
+
+/// Anisotropic GGX normal distribution function, for a microfacet normal `h` in
+/// the tangent frame
+/// D = 1 / (π α_u α_v (h_u^2 / α_u^2 + h_v^2 / α_v^2 + h_n^2)^2)
+fn ggx_d_anisotropic((alpha_u, alpha_v): (f64, f64), h: &glm::DVec3) -> f64 {
+    if h.z <= 0.0 {
+        return 0.0;
+    }
+    let (u, v) = (h.x / alpha_u, h.y / alpha_v);
+    let t = u * u + v * v + h.z * h.z;
+    1.0 / (std::f64::consts::PI * alpha_u * alpha_v * t * t)
+}
+
+/// Sample a GGX microfacet normal from the distribution of normals visible
+/// from `wo`, which must lie in the hemisphere of the normal, given a point `u` of the
+/// unit square
+///
+/// The distribution has widths `alpha` along the first two columns of the
+/// orthonormal frame `to_world`, whose last column is the normal.
+///
+/// Reference: Heitz, "Sampling the GGX Distribution of Visible Normals" (2018)
+fn sample_ggx_vndf(
+    to_world: &glm::DMat3,
+    wo: &glm::DVec3,
+    (alpha_u, alpha_v): (f64, f64),
+    u: [f64; 2],
+) -> glm::DVec3 {
+    // Transform the view direction into the hemisphere configuration
+    let v = to_world.transpose() * wo;
+    let vh = glm::vec3(alpha_u * v.x, alpha_v * v.y, v.z).normalize();
+
+    // Orthonormal basis around the stretched view direction
+    let len2 = vh.x * vh.x + vh.y * vh.y;
+    let t1 = if len2 > 0.0 {
+        glm::vec3(-vh.y, vh.x, 0.0) / len2.sqrt()
+    } else {
+        glm::vec3(1.0, 0.0, 0.0)
+    };
+    let t2 = vh.cross(&t1);
+
+    // Sample a point on the projected hemisphere
+    let [x, y] = sample_disc(u);
+    let s = 0.5 * (1.0 + vh.z);
+    let y = (1.0 - s) * (1.0 - x * x).sqrt() + s * y;
+    let nh = x * t1 + y * t2 + (1.0 - x * x - y * y).max(0.0).sqrt() * vh;
+
+    // Unstretch to get the microfacet normal
+    let h = glm::vec3(alpha_u * nh.x, alpha_v * nh.y, nh.z.max(0.0)).normalize();
+    to_world * h
+}
+
+/// Smith masking function for GGX
+/// G1 = 2 / (1 + √(1 + α^2 tan^2 θ))
+fn smith_g1(alpha: f64, n_dot_w: f64) -> f64 {
+    let cos2 = n_dot_w * n_dot_w;
+    let tan2 = (1.0 - cos2).max(0.0) / cos2;
+    2.0 / (1.0 + (1.0 + alpha * alpha * tan2).sqrt())
+}
+
+/// Smith masking function for anisotropic GGX, for a direction `w` in the
+/// tangent frame
+fn smith_g1_anisotropic((alpha_u, alpha_v): (f64, f64), w: &glm::DVec3) -> f64 {
+    let cos2 = w.z * w.z;
+    let (u, v) = (alpha_u * w.x, alpha_v * w.y);
+    2.0 / (1.0 + (1.0 + (u * u + v * v) / cos2).sqrt())
 }
 
-fn local_to_world(n: &glm::DVec3) -> glm::DMat3 {
+/// Unpolarized Fresnel reflectance of a dielectric interface, where `cos_o`
+/// is the cosine on the outgoing side and `eta` is the ratio η_i / η_o
+pub(crate) fn fresnel_dielectric(cos_o: f64, eta: f64) -> f64 {
+    let cos_o = cos_o.abs();
+    let sin2_i = (1.0 - cos_o * cos_o).max(0.0) / (eta * eta);
+    if sin2_i >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_i = (1.0 - sin2_i).sqrt();
+    let rs = (cos_o - eta * cos_i) / (cos_o + eta * cos_i);
+    let rp = (eta * cos_o - cos_i) / (eta * cos_o + cos_i);
+    0.5 * (rs * rs + rp * rp)
+}
+
+/// Refract `wo` through a surface with normal `h` on its side, by Snell's law,
+/// returning `None` on total internal reflection
+pub(crate) fn refract(wo: &glm::DVec3, h: &glm::DVec3, eta: f64) -> Option<glm::DVec3> {
+    let cos_o = h.dot(wo);
+    let wi_perp = -(wo - h * cos_o) / eta;
+    let sin2_i = wi_perp.magnitude_squared();
+    if sin2_i >= 1.0 {
+        return None;
+    }
+    Some(wi_perp - h * (1.0 - sin2_i).sqrt())
+}
+
+/// Whether `wi` is the mirror reflection of `wo` about `n`
+fn is_mirror(n: &glm::DVec3, wo: &glm::DVec3, wi: &glm::DVec3) -> bool {
+    let r = -glm::reflect_vec(wo, n);
+    r.dot(wi) > 1.0 - 1e-9
+}
+
+pub(crate) fn local_to_world(n: &glm::DVec3) -> glm::DMat3 {
     let ns = if n.x.is_normal() {
         glm::vec3(n.y, -n.x, 0.0).normalize()
     } else {
@@ -322,3 +1390,450 @@ fn local_to_world(n: &glm::DVec3) -> glm::DMat3 {
     let nss = n.cross(&ns);
     glm::mat3(ns.x, nss.x, n.x, ns.y, nss.y, n.y, ns.z, nss.z, n.z)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn ggx_conductor_furnace() {
+        // A white conductor under uniform illumination reflects at most all of the
+        // incident light (exactly all of it when it is a mirror), and importance sampling
+        // agrees with uniform hemisphere sampling, so `pdf` matches `sample_f`.
+        let mut rng = StdRng::seed_from_u64(0);
+        let frame = Frame::from_normal(glm::vec3(0.0, 0.0, 1.0));
+        let wo = glm::vec3(0.6, 0.0, 0.8);
+        for &roughness in &[0.0, 0.2, 0.5, 0.8, 1.0] {
+            let material = Material::conductor(glm::vec3(1.0, 1.0, 1.0), roughness);
+            let samples = 100_000;
+            let mut importance = 0.0;
+            let mut uniform = 0.0;
+            for _ in 0..samples {
+                if let Some((wi, pdf)) = material.sample_f(&frame, &wo, rng.gen(), &mut rng) {
+                    assert!((pdf - material.pdf(&frame, &wo, &wi)).abs() <= 1e-9 * pdf);
+                    importance += material.bsdf(&frame, &wo, &wi).x * wi.z / pdf;
+                }
+                let [x, y, z]: [f64; 3] = rng.sample(rand_distr::UnitSphere);
+                let wi = glm::vec3(x, y, z.abs());
+                uniform += material.bsdf(&frame, &wo, &wi).x * wi.z * 2.0 * std::f64::consts::PI;
+            }
+            let importance = importance / samples as f64;
+            let uniform = uniform / samples as f64;
+            assert!(importance <= 1.0 + 1e-6, "{} at {}", importance, roughness);
+            // Single-scattering microfacet models lose energy as roughness grows
+            assert!(importance > 0.3, "{} at {}", importance, roughness);
+            if roughness == 0.0 {
+                assert!((importance - 1.0).abs() < 1e-9);
+            } else if roughness >= 0.5 {
+                assert!(
+                    (importance - uniform).abs() < 0.02,
+                    "{} vs {}",
+                    importance,
+                    uniform
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn layered_furnace() {
+        // A white layered material never reflects more than it receives, from any
+        // direction, and its sampling densities agree with `pdf`
+        let mut rng = StdRng::seed_from_u64(0);
+        let frame = Frame::from_normal(glm::vec3(0.0, 0.0, 1.0));
+        for &roughness in &[0.05, 0.3, 0.7, 1.0] {
+            let material = Material::layered(glm::vec3(1.0, 1.0, 1.0), 1.5, roughness);
+            for &cos_o in &[1.0_f64, 0.7, 0.3, 0.05] {
+                let wo = glm::vec3((1.0 - cos_o * cos_o).sqrt(), 0.0, cos_o);
+                let samples = 100_000;
+                let mut albedo = 0.0;
+                for _ in 0..samples {
+                    if let Some((wi, pdf)) = material.sample_f(&frame, &wo, rng.gen(), &mut rng) {
+                        assert!((pdf - material.pdf(&frame, &wo, &wi)).abs() <= 1e-9 * pdf);
+                        albedo += material.bsdf(&frame, &wo, &wi).x * wi.z / pdf;
+                    }
+                }
+                let albedo = albedo / samples as f64;
+                assert!(albedo <= 1.0, "{} at {}, {}", albedo, roughness, cos_o);
+                // Away from grazing angles, little is lost to shadowing in the coat
+                if cos_o >= 0.7 {
+                    assert!(albedo > 0.85, "{} at {}, {}", albedo, roughness, cos_o);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn dielectric_rays_bend() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let frame = Frame::from_normal(glm::vec3(0.0, 0.0, 1.0));
+        let wo = glm::vec3(0.6, 0.0, 0.8);
+        let glass = Material::dielectric(1.5, 0.0);
+        let mut refracted = 0;
+        for _ in 0..1000 {
+            let (wi, pdf) = glass.sample_f(&frame, &wo, rng.gen(), &mut rng).unwrap();
+            if wi.z < 0.0 {
+                // Snell's law: sin θ_o = η sin θ_i
+                let sin_i = (wi.x * wi.x + wi.y * wi.y).sqrt();
+                assert!((0.6 - 1.5 * sin_i).abs() < 1e-9);
+                assert!(wi.x < 0.0);
+                refracted += 1;
+            }
+            assert_eq!(pdf, glass.pdf(&frame, &wo, &wi));
+        }
+        // Fresnel reflectance is about 4% at this angle
+        assert!(refracted > 900 && refracted < 1000);
+
+        // Exiting at a grazing angle is totally internally reflected
+        let wo = glm::vec3(0.8, 0.0, -0.6);
+        let (wi, _) = glass.sample_f(&frame, &wo, rng.gen(), &mut rng).unwrap();
+        assert!((wi - glm::vec3(-0.8, 0.0, -0.6)).magnitude() < 1e-9);
+
+        // Rough sampling agrees with its PDF
+        let rough = Material::dielectric(1.5, 0.5);
+        for _ in 0..1000 {
+            if let Some((wi, pdf)) = rough.sample_f(&frame, &wo, rng.gen(), &mut rng) {
+                assert!((pdf - rough.pdf(&frame, &wo, &wi)).abs() <= 1e-9 * pdf);
+            }
+        }
+    }
+
+    #[test]
+    fn anisotropic_ggx_stretches_highlight() {
+        use crate::{
+            disk, Falloff, Light, Object, PinholeCamera, Renderer, Scene, SceneAdd, Transformable,
+        };
+        use std::sync::Arc;
+
+        // Equal roughness in both directions is exactly the isotropic conductor
+        let mut rng = StdRng::seed_from_u64(0);
+        let color = glm::vec3(0.9, 0.8, 0.6);
+        let frame = Frame::from_normal(glm::vec3(0.0, 0.0, 1.0));
+        let wo = glm::vec3(0.6, 0.0, 0.8);
+        let isotropic = Material::conductor(color, 0.4);
+        let equal = Material::anisotropic_conductor(color, 0.4, 0.4);
+        let oriented = Frame::new(frame.normal, glm::vec3(1.0, 1.0, 0.0));
+        let u = rng.gen();
+        let (wi, pdf) = isotropic
+            .sample_f(&frame, &wo, u, &mut rng.clone())
+            .unwrap();
+        assert_eq!(
+            equal.sample_f(&oriented, &wo, u, &mut rng).unwrap(),
+            (wi, pdf)
+        );
+        assert_eq!(
+            equal.bsdf(&oriented, &wo, &wi),
+            isotropic.bsdf(&frame, &wo, &wi)
+        );
+        assert_eq!(equal.pdf(&oriented, &wo, &wi), pdf);
+
+        // Sampling agrees with the anisotropic density
+        let brushed = Material::anisotropic_conductor(color, 0.7, 0.2);
+        for _ in 0..100 {
+            if let Some((wi, pdf)) = brushed.sample_f(&oriented, &wo, rng.gen(), &mut rng) {
+                assert!((pdf - brushed.pdf(&oriented, &wo, &wi)).abs() <= 1e-9 * pdf);
+            }
+        }
+
+        // Looking down at a disk, whose tangent is along +x, with a light behind
+        // the camera, the highlight spreads further along x than along z
+        let mut scene = Scene::new();
+        scene.add(
+            Object::new(disk().scale(&glm::vec3(3.0, 1.0, 3.0)))
+                .material(Material::anisotropic_conductor(color, 0.6, 0.15)),
+        );
+        scene.add(Light::Point(
+            glm::vec3(25.0, 25.0, 25.0),
+            glm::vec3(0.0, 5.0, 0.0),
+            Falloff::Quadratic,
+        ));
+        let camera = PinholeCamera::look_at(
+            glm::vec3(0.0, 5.0, 0.0),
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(0.0, 0.0, -1.0),
+            0.8,
+        );
+        let size = 48;
+        let pixels = Renderer::new(&scene, Arc::new(camera))
+            .width(size)
+            .height(size)
+            .seed(0)
+            .render_hdr();
+        let (mut total, mut spread_x, mut spread_y) = (0.0, 0.0, 0.0);
+        for (i, pixel) in pixels.iter().enumerate() {
+            let x = (i % size as usize) as f64 - size as f64 / 2.0;
+            let y = (i / size as usize) as f64 - size as f64 / 2.0;
+            let value = pixel[1] as f64;
+            total += value;
+            spread_x += value * x * x;
+            spread_y += value * y * y;
+        }
+        let (spread_x, spread_y) = (spread_x / total, spread_y / total);
+        assert!(spread_x > 2.0 * spread_y, "{} vs {}", spread_x, spread_y);
+    }
+
+    #[test]
+    fn displacement_raises_bright_regions() {
+        use crate::shape::{Ray, Shape};
+        use crate::Mesh;
+
+        // A plane in the xz-plane, subdivided into a grid with UVs matching x and z
+        let size = 16;
+        let mut triangles = Vec::new();
+        let corner = |i: usize, j: usize| {
+            let (x, z) = (i as f64 / size as f64, j as f64 / size as f64);
+            (glm::vec3(x, 0.0, z), glm::vec2(x, z))
+        };
+        for i in 0..size {
+            for j in 0..size {
+                let (a, b, c, d) = (
+                    corner(i, j),
+                    corner(i + 1, j),
+                    corner(i + 1, j + 1),
+                    corner(i, j + 1),
+                );
+                triangles.push(Triangle::from_vertices(a.0, d.0, c.0).uvs(a.1, d.1, c.1));
+                triangles.push(Triangle::from_vertices(a.0, c.0, b.0).uvs(a.1, c.1, b.1));
+            }
+        }
+
+        // The height map is black on the left half and white on the right half
+        let image = image::RgbImage::from_fn(8, 8, |x, _| {
+            if x < 4 {
+                image::Rgb([0, 0, 0])
+            } else {
+                image::Rgb([255, 255, 255])
+            }
+        });
+        let material = Material::diffuse(glm::vec3(0.5, 0.5, 0.5)).displacement(image, 0.1);
+        let mesh = Mesh::new(material.displace(&triangles));
+
+        let hit = |x: f64| {
+            let ray = Ray {
+                origin: glm::vec3(x, 1.0, 0.37),
+                dir: glm::vec3(0.0, -1.0, 0.0),
+            };
+            let mut record = HitRecord::new();
+            assert!(mesh.intersect(&ray, 0.0, &mut record));
+            (ray.at(record.time).y, record.normal)
+        };
+        let (low, low_normal) = hit(0.2);
+        let (high, high_normal) = hit(0.8);
+        assert!(low.abs() < 1e-9, "{}", low);
+        assert!((high - 0.1).abs() < 1e-9, "{}", high);
+        assert!((low_normal - glm::vec3(0.0, 1.0, 0.0)).magnitude() < 1e-9);
+        assert!((high_normal - glm::vec3(0.0, 1.0, 0.0)).magnitude() < 1e-9);
+        // The step between the halves tilts the normals toward the lower side
+        let (_, edge_normal) = hit(0.5);
+        assert!(edge_normal.x < -0.1, "{}", edge_normal);
+
+        // Without a height map, the mesh is unchanged
+        let flat = Material::diffuse(glm::vec3(0.5, 0.5, 0.5)).displace(&triangles);
+        assert_eq!(flat[5].v2, triangles[5].v2);
+    }
+
+    #[test]
+    fn displacement_keeps_shared_edges_closed() {
+        use crate::shape::{Ray, Shape};
+        use crate::Mesh;
+
+        // Two triangles share the edge from a to b, with very different UV extents, so
+        // that they would filter the height map at different mip levels
+        let (a, b) = (glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, 0.0, 1.0));
+        let (c, d) = (glm::vec3(-1.0, 0.0, 0.5), glm::vec3(1.0, 0.0, 0.5));
+        let (uv_a, uv_b) = (glm::vec2(0.5, 0.5), glm::vec2(0.5, 0.52));
+        let triangles = vec![
+            Triangle::from_vertices(a, c, b).uvs(uv_a, glm::vec2(0.48, 0.51), uv_b),
+            Triangle::from_vertices(a, b, d).uvs(uv_a, uv_b, glm::vec2(0.9, 0.7)),
+        ];
+        let image = image::RgbImage::from_fn(64, 64, |x, y| {
+            let value = ((x * 37 + y * 91) % 256) as u8;
+            image::Rgb([value, value, value])
+        });
+        let material = Material::diffuse(glm::vec3(0.5, 0.5, 0.5)).displacement(image, 0.2);
+        let displaced = material.displace(&triangles);
+        let (left, right) = (&displaced[0], &displaced[1]);
+        assert_eq!(left.v1, right.v1);
+        assert_eq!(left.v3, right.v2);
+        assert_eq!(left.n1, right.n1);
+        assert_eq!(left.n3, right.n2);
+        assert!(left.v1.y != 0.0);
+
+        // Rays straight down along the shared edge find the surface
+        let mesh = Mesh::new(displaced);
+        for i in 1..20 {
+            let ray = Ray {
+                origin: glm::vec3(0.0, 1.0, i as f64 / 20.0),
+                dir: glm::vec3(0.0, -1.0, 0.0),
+            };
+            assert!(mesh.intersect(&ray, 0.0, &mut HitRecord::new()), "{}", i);
+        }
+    }
+
+    #[test]
+    fn bump_map_tilts_shading_normal() {
+        let record = HitRecord {
+            normal: glm::vec3(0.0, 1.0, 0.0),
+            uv: glm::vec2(0.5, 0.5),
+            tangent: glm::vec3(1.0, 0.0, 0.0),
+            bitangent: glm::vec3(0.0, 0.0, -1.0),
+            ..HitRecord::new()
+        };
+        let wo = glm::vec3(0.0, 1.0, 0.0);
+        let base = Material::diffuse(glm::vec3(0.5, 0.5, 0.5));
+
+        // A constant height map leaves the normal unchanged
+        let flat = base.clone().bump(glm::vec3(0.6, 0.6, 0.6), 0.5);
+        let normal = flat.shading_normal(&record, &wo);
+        assert!((normal - record.normal).magnitude() < 1e-12, "{}", normal);
+
+        // Heights rising along u tilt the normal back toward -u
+        let ramp = image::RgbImage::from_fn(64, 1, |x, _| image::Rgb([x as u8 * 4; 3]));
+        let ramped = base.bump(ramp, 0.5);
+        let normal = ramped.shading_normal(&record, &wo);
+        assert!(normal.x < -0.1 && normal.z.abs() < 1e-9, "{}", normal);
+        assert!((normal.magnitude() - 1.0).abs() < 1e-12);
+
+        // A flat normal map on top of the bump map keeps the bumped normal
+        let combined = ramped.normal_map(glm::vec3(0.5, 0.5, 1.0));
+        let both = combined.shading_normal(&record, &wo);
+        assert!((both - normal).magnitude() < 1e-9, "{} vs {}", both, normal);
+    }
+
+    #[test]
+    fn roughness_map_varies_highlight_width() {
+        use crate::{plane, Falloff, Light, Object, PinholeCamera, Renderer, Scene, SceneAdd};
+        use std::sync::Arc;
+
+        // A solid roughness map is the same as a scalar roughness
+        let color = glm::vec3(0.9, 0.8, 0.6);
+        let frame = Frame::from_normal(glm::vec3(0.0, 0.0, 1.0));
+        let (wo, wi) = (
+            glm::vec3(0.6, 0.0, 0.8),
+            glm::vec3(-0.5, 0.1, 0.86).normalize(),
+        );
+        let uv = glm::vec2(0.3, 0.7);
+        let scalar = Material::conductor(color, 0.25);
+        let solid = Material::conductor(color, 0.9).roughness_map(glm::vec3(0.25, 0.25, 0.25));
+        assert_eq!(solid.at(&uv).roughness, 0.25);
+        assert_eq!(
+            solid.at(&uv).bsdf(&frame, &wo, &wi),
+            scalar.bsdf(&frame, &wo, &wi)
+        );
+
+        // Looking down at a plane whose roughness map is smooth where x > 0 and rough
+        // where x < 0, with a light above each half, the highlights on the two halves
+        // differ in width
+        let (smooth, rough) = (0.1, 0.5);
+        let roughness = Texture::Checker(
+            glm::vec3(smooth, smooth, smooth),
+            glm::vec3(rough, rough, rough),
+            0.1,
+        );
+        let mut scene = Scene::new();
+        scene.add(
+            Object::new(plane(glm::vec3(0.0, 1.0, 0.0), 0.0))
+                .material(Material::conductor(color, 1.0).roughness_map(roughness)),
+        );
+        for x in [-2.5, 2.5] {
+            scene.add(Light::Point(
+                glm::vec3(20.0, 20.0, 20.0),
+                glm::vec3(x, 6.0, -2.5),
+                Falloff::Quadratic,
+            ));
+        }
+        let camera = PinholeCamera::look_at(
+            glm::vec3(0.0, 6.0, -2.5),
+            glm::vec3(0.0, 0.0, -2.5),
+            glm::vec3(0.0, 0.0, -1.0),
+            1.4,
+        );
+        let size = 64;
+        let pixels = Renderer::new(&scene, Arc::new(camera))
+            .width(size)
+            .height(size)
+            .num_samples(16)
+            .seed(0)
+            .render_hdr();
+
+        // Peak brightness and spread of the highlight in each half of the image
+        let highlight = |right: bool| {
+            let (mut total, mut peak, mut center) = (0.0, 0.0f64, glm::vec2(0.0, 0.0));
+            let mut moment = 0.0;
+            for (i, pixel) in pixels.iter().enumerate() {
+                let p = glm::vec2((i % size as usize) as f64, (i / size as usize) as f64);
+                if (p.x >= size as f64 / 2.0) == right {
+                    let value = pixel[1] as f64;
+                    total += value;
+                    peak = peak.max(value);
+                    center += value * p;
+                    moment += value * p.magnitude_squared();
+                }
+            }
+            center /= total;
+            (peak, moment / total - center.magnitude_squared())
+        };
+        let (smooth_peak, smooth_spread) = highlight(true);
+        let (rough_peak, rough_spread) = highlight(false);
+        assert!(
+            smooth_peak > 4.0 * rough_peak,
+            "{} vs {}",
+            smooth_peak,
+            rough_peak
+        );
+        assert!(
+            rough_spread > 3.0 * smooth_spread,
+            "{} vs {}",
+            rough_spread,
+            smooth_spread
+        );
+    }
+
+    #[test]
+    fn oren_nayar_flattens_terminator() {
+        use crate::{sphere, Light, Object, PinholeCamera, Renderer, Scene, SceneAdd};
+        use std::sync::Arc;
+
+        // With zero roughness, the model is exactly Lambertian
+        let color = glm::vec3(0.8, 0.6, 0.4);
+        let frame = Frame::from_normal(glm::vec3(0.0, 0.0, 1.0));
+        let smooth = Material::oren_nayar(color, 0.0);
+        for (wo, wi) in [
+            (glm::vec3(0.6, 0.0, 0.8), glm::vec3(0.0, 0.6, 0.8)),
+            (glm::vec3(0.0, 0.0, 1.0), glm::vec3(-0.8, 0.0, 0.6)),
+        ] {
+            assert_eq!(smooth.bsdf(&frame, &wo, &wi), color / std::f64::consts::PI);
+        }
+
+        // A sphere lit from the camera is nearly uniformly bright when rough, like
+        // the full moon, while a Lambertian sphere darkens toward its edge
+        let limb_ratio = |roughness| {
+            let mut scene = Scene::new();
+            scene.add(Object::new(sphere()).material(Material::oren_nayar(color, roughness)));
+            scene.add(Light::Directional(
+                glm::vec3(1.0, 1.0, 1.0),
+                glm::vec3(0.0, 0.0, -1.0),
+            ));
+            let camera = PinholeCamera::look_at(
+                glm::vec3(0.0, 0.0, 10.0),
+                glm::vec3(0.0, 0.0, 0.0),
+                glm::vec3(0.0, 1.0, 0.0),
+                0.25,
+            );
+            let pixels = Renderer::new(&scene, Arc::new(camera))
+                .width(32)
+                .height(32)
+                .num_samples(4)
+                .seed(0)
+                .render_hdr();
+            let center = pixels[16 * 32 + 16][0];
+            let limb = pixels[16 * 32 + 27][0];
+            limb / center
+        };
+        let lambertian = limb_ratio(0.0);
+        let rough = limb_ratio(1.0);
+        assert!(lambertian < 0.7, "{}", lambertian);
+        assert!(rough > 0.9, "{}", rough);
+    }
+}
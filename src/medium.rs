@@ -0,0 +1,125 @@
+use rand::{rngs::StdRng, Rng};
+
+use crate::material::local_to_world;
+
+/// A homogeneous participating medium, such as fog or smoke
+///
+/// Light traveling through the medium is absorbed and scattered at constant rates per
+/// unit distance, so the fraction that passes through a distance `d` unscattered is
+/// `exp(-sigma_t * d)`, where `sigma_t = sigma_a + sigma_s`. Scattering follows the
+/// Henyey-Greenstein phase function.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Medium {
+    /// Absorption coefficient, per unit distance
+    pub sigma_a: f64,
+
+    /// Scattering coefficient, per unit distance
+    pub sigma_s: f64,
+
+    /// Asymmetry of the phase function in (-1, 1), where positive values scatter light
+    /// forward, negative values scatter it backward, and zero is isotropic
+    pub g: f64,
+}
+
+impl Medium {
+    /// Construct a new medium from absorption and scattering coefficients
+    pub fn new(sigma_a: f64, sigma_s: f64, g: f64) -> Self {
+        Self {
+            sigma_a,
+            sigma_s,
+            g,
+        }
+    }
+
+    /// Extinction coefficient, the total rate of absorption and scattering
+    pub fn sigma_t(&self) -> f64 {
+        self.sigma_a + self.sigma_s
+    }
+
+    /// Fraction of extinction events that scatter, rather than absorb, light
+    pub fn albedo(&self) -> f64 {
+        if self.sigma_t() > 0.0 {
+            self.sigma_s / self.sigma_t()
+        } else {
+            0.0
+        }
+    }
+
+    /// Fraction of light that travels a distance through the medium without being
+    /// absorbed or scattered
+    pub fn transmittance(&self, distance: f64) -> f64 {
+        if self.sigma_t() > 0.0 {
+            (-self.sigma_t() * distance).exp()
+        } else {
+            1.0
+        }
+    }
+
+    /// Sample the distance that light travels before its next extinction event, with
+    /// density `sigma_t * transmittance(d)`
+    pub fn sample_distance(&self, rng: &mut StdRng) -> f64 {
+        -(1.0 - rng.gen::<f64>()).ln() / self.sigma_t()
+    }
+
+    /// Density of the phase function for light arriving from `wi` and leaving toward `wo`
+    pub fn phase(&self, wo: &glm::DVec3, wi: &glm::DVec3) -> f64 {
+        // Cosine between the directions that light travels before and after scattering
+        let cosine = -wo.dot(wi);
+        let denom = 1.0 + self.g * self.g - 2.0 * self.g * cosine;
+        (1.0 - self.g * self.g) / (4.0 * std::f64::consts::PI * denom * denom.sqrt())
+    }
+
+    /// Sample a direction `wi` that light scatters from toward `wo`, given a point of the
+    /// unit square, returning it with its density, which is equal to the phase function
+    pub fn sample_phase(&self, wo: &glm::DVec3, [u, v]: [f64; 2]) -> (glm::DVec3, f64) {
+        let g = self.g;
+        let cosine = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            // Invert the cumulative distribution of the Henyey-Greenstein function
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            (1.0 + g * g - s * s) / (2.0 * g)
+        };
+        let sine = (1.0 - cosine * cosine).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * v;
+        let local = glm::vec3(sine * phi.cos(), sine * phi.sin(), cosine);
+        let wi = local_to_world(&-wo) * local;
+        (wi, self.phase(wo, &wi))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{hex_color, plane, Material, Object, PinholeCamera, Renderer, Scene, SceneAdd};
+
+    #[test]
+    fn transmittance_matches_beer_lambert() {
+        // Looking through purely absorbing fog at a glowing wall 10 units away
+        let mut scene = Scene::new();
+        scene.add(
+            Object::new(plane(glm::vec3(0.0, 0.0, 1.0), 0.0))
+                .material(Material::light(hex_color(0xFFFFFF), 1.0)),
+        );
+        let medium = Medium::new(0.1, 0.0, 0.0);
+        scene.medium = Some(medium);
+        let camera = PinholeCamera::look_at(
+            glm::vec3(0.0, 0.0, 10.0),
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0),
+            0.01,
+        );
+        let pixels = Renderer::new(&scene, Arc::new(camera))
+            .width(16)
+            .height(16)
+            .num_samples(64)
+            .seed(0)
+            .render_hdr();
+        let mean = pixels.iter().map(|p| p[0] as f64).sum::<f64>() / pixels.len() as f64;
+        let expected = (-medium.sigma_t() * 10.0).exp();
+        assert!((medium.transmittance(10.0) - expected).abs() < 1e-12);
+        assert!((mean - expected).abs() < 0.02, "{} vs {}", mean, expected);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::material::Material;
-use crate::shape::Shape;
+use crate::shape::{Mesh, Shape, Transformable};
 
 /// An object rendered in a scene
 ///
@@ -13,6 +13,26 @@ pub struct Object {
 
     /// Material of the object (possibly simple or complex)
     pub material: Material,
+
+    /// Optional linear motion of the object over the course of a frame
+    pub motion: Option<Motion>,
+}
+
+/// A linear translation over the course of a frame, used for motion blur
+#[derive(Copy, Clone, Debug)]
+pub struct Motion {
+    /// Translation applied at the start of the frame (time 0)
+    pub start: glm::DVec3,
+
+    /// Translation applied at the end of the frame (time 1)
+    pub end: glm::DVec3,
+}
+
+impl Motion {
+    /// Translation at a given time in the frame, in [0, 1]
+    pub fn at(&self, time: f64) -> glm::DVec3 {
+        glm::lerp(&self.start, &self.end, time)
+    }
 }
 
 impl Object {
@@ -21,6 +41,7 @@ impl Object {
         Self {
             shape: Box::new(shape),
             material: Material::default(),
+            motion: None,
         }
     }
 
@@ -29,4 +50,58 @@ impl Object {
         self.material = material;
         self
     }
+
+    /// Translate the object linearly from `start` to `end` over the frame (builder pattern)
+    pub fn motion(mut self, start: glm::DVec3, end: glm::DVec3) -> Self {
+        self.motion = Some(Motion { start, end });
+        self
+    }
+
+    /// Displace the surface of the object by the height map of its material, if it has
+    /// one, replacing the shape with a displaced triangle mesh
+    ///
+    /// Shapes without a triangle form (see `Shape::triangles`) are left unchanged. Scenes
+    /// call this on the objects added to them, once they are placed in world space.
+    pub fn displaced(self) -> Self {
+        if self.material.displacement.is_none() {
+            return self;
+        }
+        match self.shape.triangles() {
+            Some(triangles) => Self {
+                shape: Box::new(Mesh::new(self.material.displace(&triangles))),
+                ..self
+            },
+            None => self,
+        }
+    }
+
+    /// Apply a homogeneous transform to the object, including its motion (builder pattern)
+    ///
+    /// Use `glm::translate`, `glm::scale`, or `glm::rotate` on `glm::identity()` to build
+    /// the transform.
+    pub fn transform(self, transform: glm::DMat4) -> Self {
+        let linear = glm::mat4_to_mat3(&transform);
+        Self {
+            shape: Box::new(self.shape.transform(transform)),
+            material: self.material,
+            motion: self.motion.map(|motion| Motion {
+                start: linear * motion.start,
+                end: linear * motion.end,
+            }),
+        }
+    }
+}
+
+/// Apply the same homogeneous transform to every object in a batch
+///
+/// This places a group of objects built around the origin, such as one branch of a
+/// procedural scene, as a unit. See `Object::transform`.
+pub fn transform_objects(
+    objects: impl IntoIterator<Item = Object>,
+    transform: glm::DMat4,
+) -> Vec<Object> {
+    objects
+        .into_iter()
+        .map(|object| object.transform(transform))
+        .collect()
 }
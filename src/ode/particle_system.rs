@@ -24,8 +24,10 @@ pub trait ParticleSystem {
     }
 }
 
-pub struct SimpleCircleSystem;
+#[cfg(test)]
+struct SimpleCircleSystem;
 
+#[cfg(test)]
 impl ParticleSystem for SimpleCircleSystem {
     fn time_derivative(&self, state: &ParticleState) -> ParticleState {
         ParticleState {
@@ -84,10 +86,7 @@ impl ParticleSystem for MarblesSystem {
                 }
             }
         }
-        let surf = MonomialSurface {
-            height: 2.,
-            exp: 4.,
-        };
+        let surf = MonomialSurface::new(2., 4.);
         // Surface physics
         for (i, pos_i) in state.pos.iter().enumerate() {
             let closest = surf.closest_point(pos_i);
@@ -1,26 +1,52 @@
 use image::RgbImage;
 use rand::{rngs::StdRng, Rng, SeedableRng};
 use rayon::prelude::*;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 
-use crate::buffer::{Buffer, Filter};
-use crate::color::Color;
-use crate::light::Light;
-use crate::material::Material;
+use crate::aov::Aovs;
+use crate::buffer::{Bloom, Buffer, Filter, Glare, ToneMap, OUTLIER_BATCHES};
+use crate::camera::{normalize_pixel, Camera, CameraError, Exposure};
+use crate::color::{color_bytes, luminance, Color};
+use crate::light::{Light, LightSampling};
+use crate::light_tree::LightTree;
+use crate::material::{Frame, Material, ShadingModel};
+use crate::medium::Medium;
 use crate::object::Object;
+use crate::sampler::{
+    FilterTable, PixelFilter, SampleCursor, Sampler, DIM_PIXEL_X, DIM_PIXEL_Y, DIM_TIME,
+    SAMPLER_DIMENSIONS,
+};
 use crate::scene::Scene;
 use crate::shape::{HitRecord, Ray};
-use crate::Camera;
+use caustics::CausticGrid;
 
-const EPSILON: f64 = 1e-12;
-const FIREFLY_CLAMP: f64 = 100.0;
+mod bdpt;
+mod caustics;
+mod wavefront;
+
+/// Side length of the square tiles that the image is divided into for rendering
+const TILE_SIZE: u32 = 32;
+
+/// Renders with fewer tiles than this are also parallelized over batches of samples,
+/// when there are at least `MIN_BATCHES` batches
+const MIN_PARALLEL_TILES: usize = 16;
+
+/// Number of samples per pixel in each batch, when parallelizing over samples
+const SAMPLE_BATCH_SIZE: u32 = 256;
+
+/// Minimum number of batches of samples that are worth parallelizing over
+const MIN_BATCHES: u32 = 4;
+
+/// Callback reporting the number of completed tiles out of the total
+type ProgressCallback = Mutex<Box<dyn FnMut(usize, usize) + Send>>;
 
 /// Builder object for rendering a scene
 pub struct Renderer<'a> {
     /// The scene to be rendered
     pub scene: &'a Scene,
 
-    /// The camera to use
+    /// The camera to use, prepared for rendering when the renderer is constructed
     pub camera: Arc<dyn Camera>,
 
     /// The width of the output image
@@ -38,23 +64,131 @@ pub struct Renderer<'a> {
     /// The maximum number of ray bounces
     pub max_bounces: u32,
 
+    /// The number of ray bounces after which paths are terminated by Russian roulette
+    pub min_bounces: u32,
+
     /// Number of random paths traced per pixel
     pub num_samples: u32,
+
+    /// Strategy for generating camera ray samples
+    pub sampler: Sampler,
+
+    /// Reconstruction filter that places and weights the samples around each pixel
+    pub pixel_filter: PixelFilter,
+
+    /// Optional diffraction glare post-process
+    pub glare: Option<Glare>,
+
+    /// Optional bloom post-process
+    pub bloom: Option<Bloom>,
+
+    /// Tone-mapping operator applied when producing 8-bit images
+    pub tone_map: ToneMap,
+
+    /// Fraction of the frame during which the shutter is open, in [0, 1]
+    pub shutter_time: f64,
+
+    /// Optional readout time of a rolling shutter, as a fraction of the frame
+    ///
+    /// Rows of the image are exposed one after another from the top, each starting
+    /// `row / height * readout_time` into the frame, which skews fast-moving objects
+    /// like a CMOS sensor does. Without it, every row is exposed at once.
+    pub rolling_shutter: Option<f64>,
+
+    /// Maximum value of each color channel of indirect lighting at a path vertex
+    pub firefly_clamp: f64,
+
+    /// Whether to filter textures over the footprint of each pixel, using ray
+    /// differentials through the first bounce
+    pub texture_filtering: bool,
+
+    /// Optional random seed, making renders reproducible
+    pub seed: Option<u64>,
+
+    /// Strategy for choosing lights to sample at each path vertex
+    pub light_sampling: LightSampling,
+
+    /// Number of threads to render with, or `None` to use the global rayon pool
+    pub threads: Option<usize>,
+
+    /// Number of photons shot to guide scattering toward caustics, if enabled
+    pub caustic_photons: Option<usize>,
+
+    /// Optional window of `(x, y, width, height)` pixels to render, leaving out the rest
+    pub crop: Option<(u32, u32, u32, u32)>,
+
+    /// Whether cropped renders return the full image, black outside the crop window,
+    /// instead of just the window
+    pub crop_full_frame: bool,
+
+    /// Cumulative selection weights of the scene's lights, computed on first use
+    light_cdf: OnceLock<Vec<f64>>,
+
+    /// Light tree over the scene's lights for `LightSampling::Tree`, built on first use
+    light_tree: OnceLock<LightTree>,
+
+    /// Grid of the directions that caustic photons arrive from, built on first use
+    caustic_grid: OnceLock<CausticGrid>,
+
+    /// Whether any object of the scene has a texture, checked on first use
+    has_textures: OnceLock<bool>,
+
+    /// Tabulated pixel filter for importance sampling, built on first use
+    filter_table: OnceLock<FilterTable>,
+
+    /// Dedicated pool of `threads` threads, built on first use
+    thread_pool: OnceLock<Option<rayon::ThreadPool>>,
+
+    /// Optional callback invoked as each tile of a sampling pass finishes
+    progress: Option<ProgressCallback>,
 }
 
 impl<'a> Renderer<'a> {
     /// Construct a new renderer for a scene
+    ///
+    /// Panics if the camera is invalid, see `try_new`.
     pub fn new(scene: &'a Scene, camera: Arc<dyn Camera>) -> Self {
-        Self {
+        Self::try_new(scene, camera).unwrap_or_else(|err| panic!("Invalid camera: {}", err))
+    }
+
+    /// Construct a new renderer for a scene, or return an error if the camera is invalid
+    ///
+    /// Invalid cameras would otherwise render a black image, or one full of NaNs.
+    pub fn try_new(scene: &'a Scene, camera: Arc<dyn Camera>) -> Result<Self, CameraError> {
+        camera.validate()?;
+        Ok(Self {
             scene,
-            camera,
+            camera: camera.prepare().unwrap_or(camera),
             width: 800,
             height: 600,
             exposure_value: 0.0,
             filter: Filter::default(),
             max_bounces: 0,
+            min_bounces: 3,
             num_samples: 1,
-        }
+            sampler: Sampler::default(),
+            pixel_filter: PixelFilter::default(),
+            glare: None,
+            bloom: None,
+            tone_map: ToneMap::default(),
+            shutter_time: 0.0,
+            rolling_shutter: None,
+            firefly_clamp: 100.0,
+            texture_filtering: true,
+            seed: None,
+            light_sampling: LightSampling::default(),
+            threads: None,
+            caustic_photons: None,
+            crop: None,
+            crop_full_frame: false,
+            light_cdf: OnceLock::new(),
+            light_tree: OnceLock::new(),
+            caustic_grid: OnceLock::new(),
+            has_textures: OnceLock::new(),
+            filter_table: OnceLock::new(),
+            thread_pool: OnceLock::new(),
+            progress: None,
+        })
     }
 
     /// Set the width of the rendered scene
@@ -75,6 +209,11 @@ impl<'a> Renderer<'a> {
         self
     }
 
+    /// Set the exposure value from photographic shutter, ISO, and f-number settings
+    pub fn exposure(self, exposure: Exposure) -> Self {
+        self.exposure_value(exposure.stops())
+    }
+
     /// Set the noise reduction filter
     pub fn filter(mut self, filter: Filter) -> Self {
         self.filter = filter;
@@ -87,85 +226,904 @@ impl<'a> Renderer<'a> {
         self
     }
 
+    /// Set the number of ray bounces after which Russian roulette termination kicks in
+    ///
+    /// Past this depth, each path survives with a probability based on its throughput,
+    /// which is unbiased. The `max_bounces` limit still applies as a hard cap.
+    pub fn min_bounces(mut self, min_bounces: u32) -> Self {
+        self.min_bounces = min_bounces;
+        self
+    }
+
     /// Set the number of random paths traced per pixel
     pub fn num_samples(mut self, num_samples: u32) -> Self {
         self.num_samples = num_samples;
         self
     }
 
+    /// Set the bloom post-process
+    pub fn bloom(mut self, bloom: Bloom) -> Self {
+        self.bloom = Some(bloom);
+        self
+    }
+
+    /// Set the diffraction glare post-process
+    pub fn glare(mut self, glare: Glare) -> Self {
+        self.glare = Some(glare);
+        self
+    }
+
+    /// Set the tone-mapping operator applied when producing 8-bit images
+    pub fn tone_map(mut self, tone_map: ToneMap) -> Self {
+        self.tone_map = tone_map;
+        self
+    }
+
+    /// Set the fraction of the frame during which the shutter is open, for motion blur
+    pub fn shutter_time(mut self, shutter_time: f64) -> Self {
+        self.shutter_time = shutter_time;
+        self
+    }
+
+    /// Expose the rows of the image one after another, over a readout time given as a
+    /// fraction of the frame
+    ///
+    /// The sum of the readout time and the shutter time should be at most 1, so that
+    /// every row is exposed within the frame.
+    pub fn rolling_shutter(mut self, readout_time: f64) -> Self {
+        self.rolling_shutter = Some(readout_time);
+        self
+    }
+
+    /// Set the strategy for generating camera ray samples
+    pub fn sampler(mut self, sampler: Sampler) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Set the reconstruction filter for pixels
+    ///
+    /// Smoother filters than the default box give cleaner edges, at the cost of a
+    /// little sharpness.
+    pub fn pixel_filter(mut self, pixel_filter: PixelFilter) -> Self {
+        self.pixel_filter = pixel_filter;
+        self.filter_table = OnceLock::new();
+        self
+    }
+
+    /// Set the maximum value of each color channel of indirect lighting at a path vertex
+    ///
+    /// Clamping suppresses fireflies from rare paths that find a small, bright light, but
+    /// it biases the render by darkening such lighting. Use `f64::INFINITY` to disable it.
+    pub fn firefly_clamp(mut self, firefly_clamp: f64) -> Self {
+        self.firefly_clamp = firefly_clamp;
+        self
+    }
+
+    /// Set whether textures are filtered over the footprint of each pixel
+    ///
+    /// Filtering avoids aliasing in minified textures, such as a textured floor
+    /// receding into the distance. Without it, textures are sampled at a point.
+    pub fn texture_filtering(mut self, texture_filtering: bool) -> Self {
+        self.texture_filtering = texture_filtering;
+        self
+    }
+
+    /// Set a fixed random seed, so that repeated renders produce identical images
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Limit rendering to a dedicated pool of `threads` threads (builder pattern)
+    ///
+    /// By default, renders use the global rayon pool, which has one thread per core.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self.thread_pool = OnceLock::new();
+        self
+    }
+
+    /// Render only a window of the image, with its top-left pixel at `(x, y)`
+    ///
+    /// Pixels in the window are identical to those of a full render with the same seed,
+    /// except near its edges when filters or post-processes spread light across pixels.
+    /// The rendered image is the size of the window, unless `crop_full_frame` is set.
+    pub fn crop(mut self, x: u32, y: u32, width: u32, height: u32) -> Self {
+        self.crop = Some((x, y, width, height));
+        self
+    }
+
+    /// Set whether cropped renders return the full image, black outside the window
+    pub fn crop_full_frame(mut self, crop_full_frame: bool) -> Self {
+        self.crop_full_frame = crop_full_frame;
+        self
+    }
+
+    /// Set the strategy for choosing lights to sample at each path vertex
+    ///
+    /// Sampling a single light per vertex makes each sample much cheaper in scenes with
+    /// many lights, at the cost of more noise per sample.
+    pub fn light_sampling(mut self, light_sampling: LightSampling) -> Self {
+        self.light_sampling = light_sampling;
+        self.light_cdf = OnceLock::new();
+        self.light_tree = OnceLock::new();
+        self
+    }
+
+    /// Guide path tracing toward caustics, by shooting a number of photons from the
+    /// emissive objects of the scene through its specular surfaces
+    ///
+    /// The photons are binned into a coarse grid before rendering starts. Wherever
+    /// enough of them land, diffuse scattering is mixed with sampling the directions
+    /// they arrived from, which finds light focused by glass and mirrors much more
+    /// often. The image converges to the same result either way. A few hundred thousand
+    /// photons are usually enough, as only the directions are kept.
+    pub fn enable_caustic_guiding(mut self, photons: usize) -> Self {
+        self.caustic_photons = Some(photons);
+        self.caustic_grid = OnceLock::new();
+        self
+    }
+
+    /// Set a callback that is invoked with `(completed_tiles, total_tiles)` as each
+    /// tile of the image finishes rendering
+    ///
+    /// Tiles finish on worker threads, so the callback may be called from any thread,
+    /// though never concurrently. Each pass of `iterative_render` counts its tiles anew.
+    pub fn progress<F>(mut self, callback: F) -> Self
+    where
+        F: FnMut(usize, usize) + Send + 'static,
+    {
+        self.progress = Some(Mutex::new(Box::new(callback)));
+        self
+    }
+
     /// Render the scene by path tracing
     pub fn render(&self) -> RgbImage {
-        let mut buffer = Buffer::new(self.width, self.height, self.filter);
-        self.sample(self.num_samples, &mut buffer);
-        buffer.image()
+        let mut buffer = self.new_buffer();
+        self.render_into(&mut buffer, self.num_samples);
+        self.crop_image(buffer.image())
+    }
+
+    /// Path trace a number of samples per pixel into a caller-owned buffer, adding to
+    /// the samples already in it
+    ///
+    /// This accumulates renders from several renderers into one image, such as frames
+    /// with different seeds or cameras to average, or parts of a render distributed
+    /// across machines. Samples are numbered after those already in the buffer, so with
+    /// a seed set, splitting a render into several calls gives the same result as an
+    /// `iterative_render` that stops at the same sample counts. The buffer keeps its own
+    /// filter and post-processing settings.
+    ///
+    /// Panics if the buffer is not the size of the rendered image.
+    pub fn render_into(&self, buffer: &mut Buffer, samples: u32) {
+        assert!(
+            buffer.dimensions() == (self.width, self.height),
+            "Buffer has incorrect size"
+        );
+        let start = buffer.samples_taken();
+        self.sample(start, samples, Integrator::PathTracing, buffer);
+    }
+
+    /// Render the scene by bidirectional path tracing
+    ///
+    /// Each sample traces a path from the camera and a path from a light, and connects
+    /// every pair of their vertices, weighting the resulting estimates by multiple
+    /// importance sampling. This is slower per sample than `render`, but converges much
+    /// faster for caustics and for scenes lit through small openings.
+    ///
+    /// Light paths start from point and spot lights. Other lights are sampled from each
+    /// vertex of the camera path, as in `render`, and participating media are ignored.
+    pub fn render_bdpt(&self) -> RgbImage {
+        let mut buffer = self.new_buffer();
+        self.sample(0, self.num_samples, Integrator::Bidirectional, &mut buffer);
+        self.crop_image(buffer.image())
+    }
+
+    /// Render the scene by path tracing with a wavefront integrator
+    ///
+    /// Rather than following one path at a time to its end, this traces one sample of
+    /// every pixel at once, a bounce at a time. All the rays of a bounce are intersected
+    /// with the scene together, and the surviving paths are sorted by the object they
+    /// hit before shading, which keeps memory accesses coherent in large scenes. The
+    /// image converges to the same result as `render`, though it uses different random
+    /// numbers.
+    ///
+    /// Participating media, ray differentials, and the firefly clamp are not supported,
+    /// and memory use grows with the number of pixels.
+    pub fn render_wavefront(&self) -> RgbImage {
+        let mut buffer = self.new_buffer();
+        install(self.thread_pool(), || self.sample_wavefront(&mut buffer));
+        self.crop_image(buffer.image())
+    }
+
+    /// Render the scene by path tracing, returning linear radiance values
+    ///
+    /// Unlike `render`, this does not clamp or gamma-encode the colors, so the result
+    /// preserves highlight detail. Pixels are in row-major order, and can be written to
+    /// an OpenEXR file with `save_exr`. A crop window is cut out as with `render`.
+    pub fn render_hdr(&self) -> Vec<[f32; 3]> {
+        let mut buffer = self.new_buffer();
+        self.sample(0, self.num_samples, Integrator::PathTracing, &mut buffer);
+        let (x0, y0, x1, y1) = self.crop_bounds();
+        let full_frame = self.crop.is_none() || self.crop_full_frame;
+        buffer
+            .colors()
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| {
+                let (x, y) = (index as u32 % self.width, index as u32 / self.width);
+                full_frame || (x0..x1).contains(&x) && (y0..y1).contains(&y)
+            })
+            .map(|(_, c)| [c.x as f32, c.y as f32, c.z as f32])
+            .collect()
+    }
+
+    /// Render the scene by path tracing, along with the radius of the circle of confusion
+    /// of each pixel, in pixels
+    ///
+    /// The radius is found from the depth of the surface seen through the center of each
+    /// pixel and the center of the lens, with the focal distance and aperture of the
+    /// camera (see `Camera::circle_of_confusion`), so that a compositor can apply depth
+    /// of field as a cheap gather-based post-process. The image itself is rendered as by
+    /// `render`, so to blur it only in post, render it with a copy of the camera without
+    /// an aperture. Radii are in row-major order, with a crop window cut out as with
+    /// `render`.
+    pub fn render_with_coc(&self) -> (RgbImage, Vec<f32>) {
+        let (x0, y0, x1, y1) = if self.crop_full_frame {
+            (0, 0, self.width, self.height)
+        } else {
+            self.crop_bounds()
+        };
+        let pixels_per_unit = f64::from(self.width.max(self.height)) / 2.0;
+        let coc = install(self.thread_pool(), || {
+            (y0..y1)
+                .into_par_iter()
+                .flat_map(|y| {
+                    (x0..x1)
+                        .map(|x| {
+                            let (xn, yn) = normalize_pixel(x, y, self.width, self.height);
+                            let ray = self.camera.chief_ray(xn, yn);
+                            let distance = self
+                                .get_closest_hit(ray, 0.0)
+                                .map_or(f64::INFINITY, |(h, _)| h.time);
+                            let radius = self.camera.circle_of_confusion(&ray, distance);
+                            (radius * pixels_per_unit) as f32
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        });
+        (self.render(), coc)
+    }
+
+    /// Render the scene by path tracing, yielding each tile of the image as it completes
+    ///
+    /// Tiles are rendered lazily, a batch of one per thread at a time, so a consumer can
+    /// show a preview while the render is still in progress. With a seed set, the tiles
+    /// assemble into exactly the image returned by `render`. Noise-reduction filters,
+    /// bloom, and glare need the whole image, so they are not applied.
+    pub fn render_stream(&self) -> impl Iterator<Item = RenderedTile> + Send + '_ {
+        let tiles = self.render_tiles();
+        let total = tiles.len();
+        let completed = AtomicUsize::new(0);
+        let pool = self.thread_pool();
+        let mut pending = tiles.into_iter().peekable();
+        let mut ready = std::collections::VecDeque::new();
+        std::iter::from_fn(move || {
+            if ready.is_empty() && pending.peek().is_some() {
+                let num_threads = pool.map_or_else(rayon::current_num_threads, |pool| {
+                    pool.current_num_threads()
+                });
+                let batch: Vec<_> = pending.by_ref().take(num_threads).collect();
+                ready.extend(install(pool, || {
+                    batch
+                        .into_par_iter()
+                        .map(|tile| {
+                            let (colors, _) = self.sample_tile(
+                                tile,
+                                0,
+                                self.num_samples,
+                                Integrator::PathTracing,
+                            );
+                            self.report_progress(&completed, total);
+                            RenderedTile {
+                                x: tile.x0,
+                                y: tile.y0,
+                                width: tile.x1 - tile.x0,
+                                height: tile.y1 - tile.y0,
+                                colors,
+                                tone_map: self.tone_map,
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                }));
+            }
+            ready.pop_front()
+        })
     }
 
     /// Render the scene iteratively, calling a callback after every k samples
+    ///
+    /// The callback can check convergence with `Buffer::pixel_variances`, or display
+    /// where noise remains with `Buffer::variance_image`.
     pub fn iterative_render<F>(&self, callback_interval: u32, mut callback: F)
     where
         F: FnMut(u32, &Buffer),
     {
-        let mut buffer = Buffer::new(self.width, self.height, self.filter);
+        let mut buffer = self.new_buffer();
         let mut iteration = 0;
         while iteration < self.num_samples {
             let steps = std::cmp::min(self.num_samples - iteration, callback_interval);
-            self.sample(steps, &mut buffer);
+            self.sample(iteration, steps, Integrator::PathTracing, &mut buffer);
             iteration += steps;
             callback(iteration, &buffer);
         }
     }
 
-    fn sample(&self, iterations: u32, buffer: &mut Buffer) {
-        let colors: Vec<_> = (0..self.height)
-            .into_par_iter()
-            .flat_map(|y| {
-                let mut rng = StdRng::from_entropy();
-                (0..self.width)
-                    .map(|x| self.get_color(x, y, iterations, &mut rng))
-                    .collect::<Vec<_>>()
+    /// Continue path tracing into a buffer, such as one loaded from a checkpoint
+    ///
+    /// Samples are numbered after those already in the buffer, so with a seed set,
+    /// resuming gives the same result as an uninterrupted `iterative_render` that stops
+    /// at the same sample counts. The returned buffer uses the filter and
+    /// post-processing settings of this renderer.
+    pub fn resume(&self, buffer: Buffer, additional_samples: u32) -> Buffer {
+        let start = buffer.samples_taken();
+        let mut resumed = self.new_buffer();
+        resumed.replace_samples(buffer);
+        self.sample(
+            start,
+            additional_samples,
+            Integrator::PathTracing,
+            &mut resumed,
+        );
+        resumed
+    }
+
+    /// Render auxiliary depth, normal, and albedo buffers from the primary rays
+    ///
+    /// Each pixel casts a single ray through its center, without bouncing. Cameras that
+    /// sample their aperture use a fixed seed, so the result is deterministic.
+    pub fn render_aovs(&self) -> Aovs {
+        let hits: Vec<_> = install(self.thread_pool(), || {
+            (0..self.height)
+                .into_par_iter()
+                .flat_map(|y| {
+                    let mut rng = StdRng::seed_from_u64(u64::from(y));
+                    (0..self.width)
+                        .map(|x| {
+                            let (ray, _, _) =
+                                self.camera
+                                    .ray_for_pixel(x, y, self.width, self.height, &mut rng);
+                            match self.get_closest_hit(ray, 0.0) {
+                                Some((h, object)) => {
+                                    let n = h.normal;
+                                    let c = object.material.at(&h.uv).color;
+                                    (
+                                        h.time as f32,
+                                        [n.x as f32, n.y as f32, n.z as f32],
+                                        [c.x as f32, c.y as f32, c.z as f32],
+                                    )
+                                }
+                                None => (f32::INFINITY, [0.0; 3], [0.0; 3]),
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        });
+        Aovs {
+            width: self.width,
+            height: self.height,
+            depth: hits.iter().map(|hit| hit.0).collect(),
+            normal: hits.iter().map(|hit| hit.1).collect(),
+            albedo: hits.iter().map(|hit| hit.2).collect(),
+        }
+    }
+
+    /// Render a false-color visualization of the first hit of each primary ray
+    ///
+    /// This bypasses shading entirely, which helps to diagnose problems with geometry
+    /// and intersection. Pixels whose ray misses the scene are black.
+    pub fn render_debug(&self, mode: DebugMode) -> RgbImage {
+        let hits: Vec<_> = install(self.thread_pool(), || {
+            (0..self.height)
+                .into_par_iter()
+                .flat_map(|y| {
+                    let mut rng = StdRng::seed_from_u64(u64::from(y));
+                    (0..self.width)
+                        .map(|x| {
+                            let (ray, _, _) =
+                                self.camera
+                                    .ray_for_pixel(x, y, self.width, self.height, &mut rng);
+                            self.scene
+                                .intersect_index(ray, 0.0)
+                                .map(|(h, index)| (ray.at(h.time), h, index))
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect()
+        });
+
+        // Positions are normalized to the bounding box of everything in view
+        let (lo, hi) = hits.iter().flatten().fold(
+            (
+                glm::DVec3::repeat(f64::INFINITY),
+                -glm::DVec3::repeat(f64::INFINITY),
+            ),
+            |(lo, hi), (pos, _, _)| (glm::min2(&lo, pos), glm::max2(&hi, pos)),
+        );
+        let mut buf = Vec::with_capacity(3 * hits.len());
+        for hit in &hits {
+            let color = match hit {
+                None => glm::vec3(0.0, 0.0, 0.0),
+                Some((pos, h, index)) => match mode {
+                    DebugMode::Position => {
+                        (pos - lo).component_div(&(hi - lo).map(|d| d.max(1e-9)))
+                    }
+                    DebugMode::Barycentric => h.barycentric.unwrap_or_else(glm::DVec3::zeros),
+                    DebugMode::ObjectIndex => index_color(*index),
+                    DebugMode::Normal => h.normal.normalize().add_scalar(1.0) / 2.0,
+                },
+            };
+            buf.extend(
+                color
+                    .iter()
+                    .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8),
+            );
+        }
+        RgbImage::from_raw(self.width, self.height, buf).expect("Image buffer has incorrect size")
+    }
+
+    fn new_buffer(&self) -> Buffer {
+        let mut buffer = Buffer::new(self.width, self.height, self.filter).tone_map(self.tone_map);
+        if matches!(
+            self.filter,
+            Filter::Bilateral { .. } | Filter::NonLocalMeans { .. }
+        ) {
+            // Edge-preserving filters are guided by the geometry of the first hits
+            buffer = buffer.guide(self.render_aovs());
+        }
+        if let Some(bloom) = self.bloom {
+            buffer = buffer.bloom(bloom);
+        }
+        match self.glare {
+            Some(ref glare) => buffer.glare(glare.clone()),
+            None => buffer,
+        }
+    }
+
+    /// Pixel bounds `(x0, y0, x1, y1)` of the crop window, or of the whole image
+    fn crop_bounds(&self) -> (u32, u32, u32, u32) {
+        match self.crop {
+            Some((x, y, width, height)) => (
+                x.min(self.width),
+                y.min(self.height),
+                x.saturating_add(width).min(self.width),
+                y.saturating_add(height).min(self.height),
+            ),
+            None => (0, 0, self.width, self.height),
+        }
+    }
+
+    /// Tiles covering the pixels to render, clipped to the crop window
+    fn render_tiles(&self) -> Vec<Tile> {
+        let (x0, y0, x1, y1) = self.crop_bounds();
+        tiles(self.width, self.height)
+            .into_iter()
+            .map(|tile| Tile {
+                x0: tile.x0.max(x0),
+                y0: tile.y0.max(y0),
+                x1: tile.x1.min(x1),
+                y1: tile.y1.min(y1),
             })
+            .filter(|tile| tile.x0 < tile.x1 && tile.y0 < tile.y1)
+            .collect()
+    }
+
+    /// Cut the crop window out of a rendered image, unless the full frame is wanted
+    fn crop_image(&self, image: RgbImage) -> RgbImage {
+        if self.crop.is_none() || self.crop_full_frame {
+            return image;
+        }
+        let (x0, y0, x1, y1) = self.crop_bounds();
+        image::imageops::crop_imm(&image, x0, y0, x1 - x0, y1 - y0).to_image()
+    }
+
+    /// Dedicated pool of `threads` threads, or `None` to use the global pool
+    ///
+    /// The pool is built once and shared by every render of this renderer.
+    fn thread_pool(&self) -> Option<&rayon::ThreadPool> {
+        self.thread_pool
+            .get_or_init(|| {
+                self.threads.map(|threads| {
+                    rayon::ThreadPoolBuilder::new()
+                        .num_threads(threads)
+                        .build()
+                        .expect("Failed to create the render thread pool")
+                })
+            })
+            .as_ref()
+    }
+
+    /// Trace `iterations` samples per pixel, after `start` samples have already been taken
+    fn sample(&self, start: u32, iterations: u32, integrator: Integrator, buffer: &mut Buffer) {
+        install(self.thread_pool(), || {
+            if let Filter::OutlierReject { .. } = self.filter {
+                // Separate batches let outlier rejection single out rare bright samples
+                let batch = iterations.div_ceil(OUTLIER_BATCHES).max(1);
+                let mut done = 0;
+                while done < iterations {
+                    let steps = batch.min(iterations - done);
+                    self.sample_in_pool(start + done, steps, integrator, buffer);
+                    done += steps;
+                }
+            } else {
+                self.sample_in_pool(start, iterations, integrator, buffer)
+            }
+        })
+    }
+
+    /// Body of `sample`, which runs in the thread pool of the renderer
+    fn sample_in_pool(
+        &self,
+        start: u32,
+        iterations: u32,
+        integrator: Integrator,
+        buffer: &mut Buffer,
+    ) {
+        let tiles = self.render_tiles();
+        if tiles.len() < MIN_PARALLEL_TILES && iterations >= MIN_BATCHES * SAMPLE_BATCH_SIZE {
+            // Too few tiles to keep every thread busy, as with small, high-quality renders
+            return self.sample_batches_in_pool(&tiles, start, iterations, integrator, buffer);
+        }
+        let completed = AtomicUsize::new(0);
+        let mut colors = vec![glm::vec3(0.0, 0.0, 0.0); (self.width * self.height) as usize];
+
+        // Light paths splat onto arbitrary pixels, so tiles are rendered in batches that
+        // bound the memory for their splats, which are then added in tile order
+        let batch_size = match integrator {
+            Integrator::PathTracing => tiles.len(),
+            Integrator::Bidirectional => 4 * rayon::current_num_threads(),
+        };
+        for batch in tiles.chunks(batch_size) {
+            let results: Vec<_> = batch
+                .par_iter()
+                .map(|&tile| {
+                    let result = self.sample_tile(tile, start, iterations, integrator);
+                    self.report_progress(&completed, tiles.len());
+                    result
+                })
+                .collect();
+
+            for (tile, (tile_colors, splats)) in batch.iter().zip(results) {
+                for ((x, y), color) in tile.pixels().zip(tile_colors) {
+                    colors[(y * self.width + x) as usize] += color;
+                }
+                for (index, splat) in splats {
+                    colors[index] += splat;
+                }
+            }
+        }
+        buffer.add_estimates(&colors, iterations);
+    }
+
+    /// Body of `sample` for renders with few tiles, which traces batches of samples for
+    /// the whole image in parallel
+    ///
+    /// The batches are summed in order, so seeded renders do not depend on the number of
+    /// threads, though they differ from renders that are parallelized over tiles.
+    fn sample_batches_in_pool(
+        &self,
+        tiles: &[Tile],
+        start: u32,
+        iterations: u32,
+        integrator: Integrator,
+        buffer: &mut Buffer,
+    ) {
+        let batches: Vec<(u32, u32)> = (0..iterations)
+            .step_by(SAMPLE_BATCH_SIZE as usize)
+            .map(|offset| (start + offset, SAMPLE_BATCH_SIZE.min(iterations - offset)))
             .collect();
-        buffer.add_samples(&colors);
+        let total = batches.len() * tiles.len();
+        let completed = AtomicUsize::new(0);
+        let num_pixels = (self.width * self.height) as usize;
+        let mut colors = vec![glm::vec3(0.0, 0.0, 0.0); num_pixels];
+
+        // Bound the memory for the images of the batches in flight
+        for group in batches.chunks(4 * rayon::current_num_threads()) {
+            let results: Vec<_> = group
+                .par_iter()
+                .map(|&(batch_start, count)| {
+                    let mut batch_colors = vec![glm::vec3(0.0, 0.0, 0.0); num_pixels];
+                    for &tile in tiles {
+                        let (tile_colors, splats) =
+                            self.sample_tile(tile, batch_start, count, integrator);
+                        self.report_progress(&completed, total);
+                        for ((x, y), color) in tile.pixels().zip(tile_colors) {
+                            batch_colors[(y * self.width + x) as usize] += color;
+                        }
+                        for (index, splat) in splats {
+                            batch_colors[index] += splat;
+                        }
+                    }
+                    (count, batch_colors)
+                })
+                .collect();
+            for (count, batch_colors) in results {
+                // Each batch is an average over its own samples
+                let weight = f64::from(count) / f64::from(iterations);
+                for (color, batch_color) in colors.iter_mut().zip(batch_colors) {
+                    *color += batch_color * weight;
+                }
+            }
+        }
+        buffer.add_estimates(&colors, iterations);
+    }
+
+    /// Trace `iterations` samples for each pixel of a tile, returning its colors in
+    /// row-major order along with any splats onto other pixels
+    fn sample_tile(
+        &self,
+        tile: Tile,
+        start: u32,
+        iterations: u32,
+        integrator: Integrator,
+    ) -> (Vec<Color>, Vec<(usize, Color)>) {
+        // Seeded renders give each pixel its own generator, so that the result does not
+        // depend on the tiling or the number of threads
+        let mut splats = Vec::new();
+        let colors = match self.seed {
+            Some(seed) => tile
+                .pixels()
+                .map(|(x, y)| {
+                    let values = [u64::from(x), u64::from(y), u64::from(start)];
+                    let mut rng = StdRng::seed_from_u64(mix_seed(seed, &values));
+                    self.get_color(x, y, start, iterations, integrator, &mut splats, &mut rng)
+                })
+                .collect(),
+            None => with_thread_rng(|rng| {
+                tile.pixels()
+                    .map(|(x, y)| {
+                        self.get_color(x, y, start, iterations, integrator, &mut splats, rng)
+                    })
+                    .collect()
+            }),
+        };
+        (colors, splats)
+    }
+
+    fn report_progress(&self, completed: &AtomicUsize, total: usize) {
+        if let Some(progress) = &self.progress {
+            let mut callback = progress.lock().unwrap();
+            let done = completed.fetch_add(1, Ordering::SeqCst) + 1;
+            callback(done, total);
+        }
+    }
+
+    /// Whether any object of the scene has a texture to filter
+    fn has_textures(&self) -> bool {
+        *self.has_textures.get_or_init(|| {
+            self.scene
+                .objects
+                .iter()
+                .any(|object| object.material.has_filtered_textures())
+        })
+    }
+
+    /// Tabulated pixel filter, shared by every sample of every render
+    fn filter_table(&self) -> &FilterTable {
+        self.filter_table.get_or_init(|| self.pixel_filter.table())
+    }
+
+    /// Time within the frame at which the exposure of row `y` starts
+    fn row_time(&self, y: u32) -> f64 {
+        self.rolling_shutter.map_or(0.0, |readout| {
+            readout * f64::from(y) / f64::from(self.height)
+        })
     }
 
-    fn get_color(&self, x: u32, y: u32, iterations: u32, rng: &mut StdRng) -> Color {
+    /// Estimate the color of a pixel, averaged over `iterations` samples
+    ///
+    /// Bidirectional samples may also contribute to other pixels, which are pushed onto
+    /// `splats` as pairs of the pixel index and color.
+    #[allow(clippy::too_many_arguments)]
+    fn get_color(
+        &self,
+        x: u32,
+        y: u32,
+        start: u32,
+        iterations: u32,
+        integrator: Integrator,
+        splats: &mut Vec<(usize, Color)>,
+        rng: &mut StdRng,
+    ) -> Color {
+        let first_splat = splats.len();
         let dim = std::cmp::max(self.width, self.height) as f64;
-        let xn = ((2 * x + 1) as f64 - self.width as f64) / dim;
-        let yn = ((2 * (self.height - y) - 1) as f64 - self.height as f64) / dim;
+        let (xn, yn) = normalize_pixel(x, y, self.width, self.height);
+        let shift: [f64; SAMPLER_DIMENSIONS] = rng.gen();
+        let filter = self.filter_table();
+        let differentials =
+            self.texture_filtering && integrator == Integrator::PathTracing && self.has_textures();
         let mut color = glm::vec3(0.0, 0.0, 0.0);
-        for _ in 0..iterations {
-            let dx = rng.gen_range((-1.0 / dim)..(1.0 / dim));
-            let dy = rng.gen_range((-1.0 / dim)..(1.0 / dim));
-            let (ray, ray_color, pdf) = self.camera.cast_ray(xn + dx, yn + dy, rng);
-            color += ray_color.component_mul(&self.trace_ray(ray, 0, rng)) / pdf;
+        // Samples are stratified over this call, rather than all `num_samples`, so that
+        // partial passes such as those of `iterative_render` still cover the whole pixel
+        let count = u64::from(iterations);
+        for i in 0..iterations {
+            let sample = SampleCursor::new(self.sampler, u64::from(start + i), count, shift);
+            let (offset_x, weight_x) = filter.sample(sample.get(DIM_PIXEL_X, rng));
+            let (offset_y, weight_y) = filter.sample(sample.get(DIM_PIXEL_Y, rng));
+            let time = self.row_time(y)
+                + if self.shutter_time > 0.0 {
+                    sample.get(DIM_TIME, rng) * self.shutter_time
+                } else {
+                    0.0
+                };
+            let (xs, ys) = (xn + 2.0 * offset_x / dim, yn + 2.0 * offset_y / dim);
+            // Rays offset by one pixel reuse the random numbers of the main ray, so
+            // that they pass through the same point of any aperture
+            let offset_rng = differentials.then(|| rng.clone());
+            let rays = self.camera.cast_rays(xs, ys, time, &sample, rng);
+            let differential = offset_rng.map(|offset_rng| {
+                let pixel = 2.0 / dim;
+                let cast = |x, y| {
+                    let mut rng = offset_rng.clone();
+                    self.camera.cast_ray(x, y, time, &sample, &mut rng).0
+                };
+                RayDifferential {
+                    dx: cast(xs + pixel, ys),
+                    dy: cast(xs, ys - pixel),
+                }
+            });
+            for (ray, ray_color, pdf) in rays {
+                let throughput = ray_color * (weight_x * weight_y / pdf);
+                color += match integrator {
+                    Integrator::PathTracing => throughput.component_mul(&self.trace_ray(
+                        ray,
+                        0,
+                        &throughput,
+                        None,
+                        differential.as_ref(),
+                        time,
+                        &sample,
+                        rng,
+                    )),
+                    Integrator::Bidirectional => {
+                        self.trace_bidirectional(ray, &throughput, time, splats, rng)
+                    }
+                };
+            }
         }
-        color / f64::from(iterations) * 2.0_f64.powf(self.exposure_value)
+        let scale = 2.0_f64.powf(self.exposure_value) / f64::from(iterations);
+        for (_, splat) in &mut splats[first_splat..] {
+            *splat *= scale;
+        }
+        color * scale
     }
 
     /// Trace a ray, obtaining a Monte Carlo estimate of the luminance
-    fn trace_ray(&self, ray: Ray, num_bounces: u32, rng: &mut StdRng) -> Color {
-        match self.get_closest_hit(ray) {
-            None => self.scene.environment.get_color(&ray.dir),
-            Some((h, object)) => {
+    ///
+    /// The `throughput` is the weight that the path so far applies to this estimate,
+    /// which is used for Russian roulette termination. If the ray was sampled from a
+    /// non-delta BSDF, `bsdf_pdf` is its density, which is used to weight light from
+    /// the environment against explicit environment sampling. The `differential`
+    /// holds rays offset by one pixel, which are used to filter textures. The BSDF sample
+    /// and light choice at each bounce come from `sample`.
+    #[allow(clippy::too_many_arguments)]
+    fn trace_ray(
+        &self,
+        ray: Ray,
+        num_bounces: u32,
+        throughput: &Color,
+        bsdf_pdf: Option<f64>,
+        differential: Option<&RayDifferential>,
+        time: f64,
+        sample: &SampleCursor,
+        rng: &mut StdRng,
+    ) -> Color {
+        let hit = self.get_closest_hit(ray, time);
+        if let Some(medium) = &self.scene.medium {
+            // Sample a free-flight distance, and scatter if it falls before the hit
+            if medium.sigma_t() > 0.0 {
+                let t = medium.sample_distance(rng) / ray.dir.magnitude();
+                if hit.as_ref().is_none_or(|(h, _)| t < h.time) {
+                    let wo = -glm::normalize(&ray.dir);
+                    let pos = ray.at(t);
+                    return self.scatter_in_medium(
+                        medium,
+                        &pos,
+                        &wo,
+                        num_bounces,
+                        throughput,
+                        time,
+                        sample,
+                        rng,
+                    );
+                }
+            }
+        }
+        match hit {
+            None => {
+                let color = self.scene.environment.get_color(&ray.dir);
+                match bsdf_pdf {
+                    Some(pdf) => color * power_heuristic(pdf, self.scene.environment.pdf(&ray.dir)),
+                    None => color,
+                }
+            }
+            Some((h, object)) if object.material.model == ShadingModel::ShadowCatcher => {
+                // Continue to whatever is behind the matte, darkened by its shadows
+                let pos = ray.at(h.time);
+                let behind = Ray {
+                    origin: pos,
+                    dir: ray.dir,
+                };
+                let shadow = self.shadow_factor(&pos, &h.normal, time, rng);
+                let background = self.trace_ray(
+                    behind,
+                    num_bounces,
+                    throughput,
+                    bsdf_pdf,
+                    None,
+                    time,
+                    sample,
+                    rng,
+                );
+                background * shadow
+            }
+            Some((mut h, object)) => {
                 let world_pos = ray.at(h.time);
-                let material = object.material;
                 let wo = -glm::normalize(&ray.dir);
+                h.normal = object.material.shading_normal(&h, &wo);
+                let footprint = differential.map(|d| d.footprint(&h, &world_pos));
+                let material = object
+                    .material
+                    .filtered_at(&h.uv, footprint.map_or(0.0, |(_, _, width)| width));
+                let frame = Frame::new(h.normal, h.tangent);
 
                 let mut color = material.emittance * material.color;
-                color += self.sample_lights(&material, &world_pos, &h.normal, &wo, rng);
+                let scattering = Scattering::Surface(&material, frame);
+                let last = num_bounces >= self.max_bounces;
+                let u = sample.light(num_bounces, rng);
+                color += self.sample_lights(&scattering, &world_pos, &wo, last, time, u, rng);
                 if num_bounces < self.max_bounces {
-                    if let Some((wi, pdf)) = material.sample_f(&h.normal, &wo, rng) {
-                        let f = material.bsdf(&h.normal, &wo, &wi);
-                        let ray = Ray {
-                            origin: world_pos,
-                            dir: wi,
-                        };
-                        let indirect = 1.0 / pdf
-                            * f.component_mul(&self.trace_ray(ray, num_bounces + 1, rng))
-                            * wi.dot(&h.normal).abs();
-                        color.x += indirect.x.min(FIREFLY_CLAMP);
-                        color.y += indirect.y.min(FIREFLY_CLAMP);
-                        color.z += indirect.z.min(FIREFLY_CLAMP);
+                    let u = sample.bsdf(num_bounces, rng);
+                    if let Some((wi, pdf)) =
+                        self.sample_scattering(&material, &frame, &wo, &world_pos, u, rng)
+                    {
+                        let f = material.bsdf(&frame, &wo, &wi);
+                        let weight = f * wi.dot(&h.normal).abs() / pdf;
+                        let throughput = throughput.component_mul(&weight);
+                        let survival = self.survival(num_bounces, &throughput);
+                        if survival >= 1.0 || rng.gen::<f64>() < survival {
+                            let ray = Ray {
+                                origin: world_pos,
+                                dir: wi,
+                            };
+                            let bsdf_pdf = if material.is_delta() { None } else { Some(pdf) };
+                            // Differentials are only carried through the first bounce
+                            let differential =
+                                match footprint {
+                                    Some((dpdx, dpdy, _)) if num_bounces == 0 => {
+                                        let mirror = material.is_delta()
+                                            && wi.dot(&h.normal) * wo.dot(&h.normal) > 0.0;
+                                        Some(differential.unwrap().bounce(
+                                            &world_pos, &dpdx, &dpdy, &wi, &h.normal, mirror,
+                                        ))
+                                    }
+                                    _ => None,
+                                };
+                            let indirect = weight.component_mul(&self.trace_ray(
+                                ray,
+                                num_bounces + 1,
+                                &throughput,
+                                bsdf_pdf,
+                                differential.as_ref(),
+                                time,
+                                sample,
+                                rng,
+                            )) / survival;
+                            color += indirect.map(|c| c.min(self.firefly_clamp));
+                        }
                     }
                 }
 
@@ -174,49 +1132,1685 @@ impl<'a> Renderer<'a> {
         }
     }
 
+    /// Scatter a path at a point within the scene's participating medium, with the
+    /// same light sampling and path continuation as at a surface
+    #[allow(clippy::too_many_arguments)]
+    fn scatter_in_medium(
+        &self,
+        medium: &Medium,
+        pos: &glm::DVec3,
+        wo: &glm::DVec3,
+        num_bounces: u32,
+        throughput: &Color,
+        time: f64,
+        sample: &SampleCursor,
+        rng: &mut StdRng,
+    ) -> Color {
+        let scattering = Scattering::Medium(medium);
+        let last = num_bounces >= self.max_bounces;
+        let u = sample.light(num_bounces, rng);
+        let mut color = self.sample_lights(&scattering, pos, wo, last, time, u, rng);
+        if !last {
+            let (wi, pdf) = medium.sample_phase(wo, sample.bsdf(num_bounces, rng));
+            let weight = scattering.f(wo, &wi) / pdf;
+            let throughput = throughput.component_mul(&weight);
+            let survival = self.survival(num_bounces, &throughput);
+            if survival >= 1.0 || rng.gen::<f64>() < survival {
+                let ray = Ray {
+                    origin: *pos,
+                    dir: wi,
+                };
+                let indirect = weight.component_mul(&self.trace_ray(
+                    ray,
+                    num_bounces + 1,
+                    &throughput,
+                    Some(pdf),
+                    None,
+                    time,
+                    sample,
+                    rng,
+                )) / survival;
+                color += indirect.map(|c| c.min(self.firefly_clamp));
+            }
+        }
+        color
+    }
+
+    /// Probability of continuing a path for Russian roulette, which terminates
+    /// low-throughput paths randomly
+    fn survival(&self, num_bounces: u32, throughput: &Color) -> f64 {
+        if num_bounces >= self.min_bounces {
+            throughput.max().min(1.0)
+        } else {
+            1.0
+        }
+    }
+
     /// Explicitly sample from all the lights in the scene
+    ///
+    /// If `last` is set, the path ends at this vertex, so the environment may be
+    /// approximated by its cached irradiance. Strategies that sample a single light
+    /// choose it with the number `u` in [0, 1).
+    #[allow(clippy::too_many_arguments)]
     fn sample_lights(
         &self,
-        material: &Material,
+        scattering: &Scattering,
         pos: &glm::DVec3,
-        n: &glm::DVec3,
         wo: &glm::DVec3,
+        last: bool,
+        time: f64,
+        u: f64,
         rng: &mut StdRng,
     ) -> Color {
         let mut color = glm::vec3(0.0, 0.0, 0.0);
         for light in &self.scene.lights {
             if let Light::Ambient(ambient_color) = light {
-                color += ambient_color.component_mul(&material.color);
-            } else {
-                let (intensity, wi, dist_to_light) = light.illuminate(pos, rng);
-                let closest_hit = self
-                    .get_closest_hit(Ray {
-                        origin: *pos,
-                        dir: wi,
-                    })
-                    .map(|(r, _)| r.time);
-                if closest_hit.is_none() || closest_hit.unwrap() > dist_to_light {
-                    let f = material.bsdf(n, wo, &wi);
-                    color += f.component_mul(&intensity) * wi.dot(n);
+                color += ambient_color.component_mul(&scattering.albedo());
+            } else if self.light_sampling == LightSampling::All {
+                color += self.sample_light(light, scattering, pos, wo, time, rng);
+            }
+        }
+        if self.light_sampling != LightSampling::All {
+            if let Some((light, prob)) = self.choose_light(pos, u) {
+                color += self.sample_light(light, scattering, pos, wo, time, rng) / prob;
+            }
+        }
+        color + self.sample_environment(scattering, pos, wo, last, time, rng)
+    }
+
+    /// Sample light from the environment, weighted against finding it by sampling the
+    /// BSDF, which happens when a path escapes the scene
+    ///
+    /// At the last vertex of a path, no BSDF sample follows, so the light sample takes
+    /// the full weight, and rough diffuse surfaces are lit by the cached irradiance of
+    /// the environment instead, if it has one, without a shadow ray.
+    fn sample_environment(
+        &self,
+        scattering: &Scattering,
+        pos: &glm::DVec3,
+        wo: &glm::DVec3,
+        last: bool,
+        time: f64,
+        rng: &mut StdRng,
+    ) -> Color {
+        if let (true, Scattering::Surface(material, frame), Some(cache)) =
+            (last, scattering, self.scene.environment.sh_cache())
+        {
+            if material.is_rough_diffuse() {
+                let irradiance =
+                    cache.irradiance(&frame.normal) * self.transmittance(f64::INFINITY);
+                return material.color.component_mul(&irradiance) / std::f64::consts::PI;
+            }
+        }
+        if !scattering.is_delta() {
+            if let Some((wi, radiance, pdf)) = self.scene.environment.sample(rng) {
+                let ray = Ray {
+                    origin: *pos,
+                    dir: wi,
+                };
+                if self.get_closest_hit(ray, time).is_none() {
+                    let f = scattering.f(wo, &wi) * self.transmittance(f64::INFINITY);
+                    // Without a BSDF sample after the last vertex, this is the only
+                    // strategy that finds the environment
+                    let mis = if last {
+                        1.0
+                    } else {
+                        power_heuristic(pdf, scattering.pdf(wo, &wi))
+                    };
+                    return f.component_mul(&radiance) * (mis / pdf);
                 }
             }
         }
-        color
+        glm::vec3(0.0, 0.0, 0.0)
     }
 
-    /// Loop through all objects in the scene to find the closest hit.
+    /// Estimate the direct lighting from a single light, with a shadow ray
+    fn sample_light(
+        &self,
+        light: &Light,
+        scattering: &Scattering,
+        pos: &glm::DVec3,
+        wo: &glm::DVec3,
+        time: f64,
+        rng: &mut StdRng,
+    ) -> Color {
+        let (intensity, wi, dist_to_light) = light.illuminate(pos, rng);
+        let closest_hit = self
+            .get_closest_hit(
+                Ray {
+                    origin: *pos,
+                    dir: wi,
+                },
+                time,
+            )
+            .map(|(r, _)| r.time);
+        if closest_hit.is_none() || closest_hit.unwrap() > dist_to_light {
+            let f = scattering.f(wo, &wi) * self.transmittance(dist_to_light);
+            f.component_mul(&intensity)
+        } else {
+            glm::vec3(0.0, 0.0, 0.0)
+        }
+    }
+
+    /// Fraction of the light from the scene's lights that reaches a point on a surface,
+    /// used to darken shadow catchers
+    ///
+    /// Lights are weighted by the irradiance they would give the surface unoccluded, and
+    /// the point is fully lit when no light reaches it at all.
+    fn shadow_factor(&self, pos: &glm::DVec3, n: &glm::DVec3, time: f64, rng: &mut StdRng) -> f64 {
+        let (mut visible, mut total) = (0.0, 0.0);
+        for light in &self.scene.lights {
+            if let Light::Ambient(_) = light {
+                continue;
+            }
+            let (intensity, wi, dist_to_light) = light.illuminate(pos, rng);
+            let irradiance = luminance(&intensity) * n.dot(&wi).abs();
+            total += irradiance;
+            let ray = Ray {
+                origin: *pos,
+                dir: wi,
+            };
+            if self
+                .get_closest_hit(ray, time)
+                .is_none_or(|(h, _)| h.time > dist_to_light)
+            {
+                visible += irradiance;
+            }
+        }
+        if total > 0.0 {
+            visible / total
+        } else {
+            1.0
+        }
+    }
+
+    /// Choose one non-ambient light to sample at a position according to
+    /// `light_sampling`, with a number `u` in [0, 1), returning it with the probability
+    /// that it was chosen
     ///
-    /// Note that we intentionally do not use a `KdTree` to accelerate this computation.
-    /// The reason is that some objects, like planes, have infinite extent, so it would
-    /// not be appropriate to put them indiscriminately into a kd-tree.
-    fn get_closest_hit(&self, ray: Ray) -> Option<(HitRecord, &'_ Object)> {
-        let mut h = HitRecord::new();
-        let mut hit = None;
-        for object in &self.scene.objects {
-            if object.shape.intersect(&ray, EPSILON, &mut h) {
-                hit = Some(object);
+    /// Lights in the scene are never hit by rays, so their samples need no multiple
+    /// importance sampling weight. Dividing by the probability keeps them unbiased.
+    fn choose_light(&self, pos: &glm::DVec3, u: f64) -> Option<(&Light, f64)> {
+        if self.light_sampling == LightSampling::Tree {
+            let tree = self
+                .light_tree
+                .get_or_init(|| LightTree::new(&self.scene.lights));
+            let (index, prob) = tree.sample(pos, u)?;
+            return Some((&self.scene.lights[index], prob));
+        }
+        let cdf = self.light_cdf.get_or_init(|| {
+            let weights = self.scene.lights.iter().map(|light| match light {
+                Light::Ambient(_) => 0.0,
+                _ if self.light_sampling == LightSampling::Power => light.power(),
+                _ => 1.0,
+            });
+            weights
+                .scan(0.0, |total, weight| {
+                    *total += weight;
+                    Some(*total)
+                })
+                .collect()
+        });
+        let total = *cdf.last()?;
+        if total <= 0.0 {
+            return None;
+        }
+        let u = u * total;
+        let index = cdf.partition_point(|&c| c <= u).min(cdf.len() - 1);
+        let prev = if index == 0 { 0.0 } else { cdf[index - 1] };
+        Some((&self.scene.lights[index], (cdf[index] - prev) / total))
+    }
+
+    /// Fraction of light that passes unscattered through the scene's medium over a
+    /// distance
+    fn transmittance(&self, distance: f64) -> f64 {
+        self.scene
+            .medium
+            .map_or(1.0, |medium| medium.transmittance(distance))
+    }
+
+    /// Find the closest hit in the scene, see `Scene::intersect`
+    fn get_closest_hit(&self, ray: Ray, time: f64) -> Option<(HitRecord, &'_ Object)> {
+        self.scene.intersect(ray, time)
+    }
+}
+
+/// Run a parallel operation in a thread pool, or in the global pool if there is none
+fn install<R: Send>(pool: Option<&rayon::ThreadPool>, op: impl FnOnce() -> R + Send) -> R {
+    match pool {
+        Some(pool) => pool.install(op),
+        None => op(),
+    }
+}
+
+/// Power heuristic for multiple importance sampling, with an exponent of 2
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+/// Light transport algorithm used to estimate the color of each sample
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Integrator {
+    /// Unidirectional path tracing from the camera
+    PathTracing,
+
+    /// Bidirectional path tracing, see `Renderer::render_bdpt`
+    Bidirectional,
+}
+
+/// How light scatters at a path vertex, either from a surface or within a medium
+enum Scattering<'a> {
+    /// Scattering from a surface, given its material and shading frame
+    Surface(&'a Material, Frame),
+
+    /// Scattering within a participating medium
+    Medium(&'a Medium),
+}
+
+impl Scattering<'_> {
+    /// Fraction of light arriving from `wi` that scatters toward `wo`, including the
+    /// cosine factor at surfaces
+    fn f(&self, wo: &glm::DVec3, wi: &glm::DVec3) -> Color {
+        match self {
+            Scattering::Surface(material, frame) => {
+                material.bsdf(frame, wo, wi) * wi.dot(&frame.normal).abs()
+            }
+            Scattering::Medium(medium) => {
+                glm::vec3(1.0, 1.0, 1.0) * (medium.albedo() * medium.phase(wo, wi))
+            }
+        }
+    }
+
+    /// Density of sampling `wi` given `wo`
+    fn pdf(&self, wo: &glm::DVec3, wi: &glm::DVec3) -> f64 {
+        match self {
+            Scattering::Surface(material, frame) => material.pdf(frame, wo, wi),
+            Scattering::Medium(medium) => medium.phase(wo, wi),
+        }
+    }
+
+    /// Whether scattering is in a discrete set of directions
+    fn is_delta(&self) -> bool {
+        match self {
+            Scattering::Surface(material, _) => material.is_delta(),
+            Scattering::Medium(_) => false,
+        }
+    }
+
+    /// Fraction of uniform ambient light that is scattered
+    fn albedo(&self) -> Color {
+        match self {
+            Scattering::Surface(material, _) => material.color,
+            Scattering::Medium(medium) => glm::vec3(1.0, 1.0, 1.0) * medium.albedo(),
+        }
+    }
+}
+
+/// Rays offset from a camera ray by one pixel horizontally and vertically, which
+/// track the footprint of the pixel on surfaces that the ray hits
+struct RayDifferential {
+    dx: Ray,
+    dy: Ray,
+}
+
+impl RayDifferential {
+    /// Find the offsets of the footprint on the tangent plane at a hit, and the
+    /// corresponding width in texture coordinates
+    fn footprint(&self, h: &HitRecord, pos: &glm::DVec3) -> (glm::DVec3, glm::DVec3, f64) {
+        let offset = |ray: &Ray| {
+            let t = h.normal.dot(&(pos - ray.origin)) / h.normal.dot(&ray.dir);
+            if t.is_finite() {
+                ray.at(t) - pos
+            } else {
+                glm::vec3(0.0, 0.0, 0.0)
+            }
+        };
+        let (dpdx, dpdy) = (offset(&self.dx), offset(&self.dy));
+
+        // Least-squares solve for the UV offsets, given the tangent dp/du and bitangent
+        // dp/dv of the surface
+        let (t, b) = (h.tangent, h.bitangent);
+        let (tt, tb, bb) = (t.dot(&t), t.dot(&b), b.dot(&b));
+        let det = tt * bb - tb * tb;
+        if det.abs() < 1e-12 {
+            return (dpdx, dpdy, 0.0);
+        }
+        let duv = |dp: &glm::DVec3| {
+            let (tp, bp) = (t.dot(dp), b.dot(dp));
+            glm::vec2(bb * tp - tb * bp, tt * bp - tb * tp) / det
+        };
+        let width = duv(&dpdx).norm().max(duv(&dpdy).norm());
+        (dpdx, dpdy, width)
+    }
+
+    /// Differentials of a ray leaving a hit, with the offset origins of the footprint
+    ///
+    /// Mirror reflections reflect the offset directions, so the footprint spreads as it
+    /// would for the reflected image. Other scattering keeps the offset rays parallel.
+    fn bounce(
+        &self,
+        pos: &glm::DVec3,
+        dpdx: &glm::DVec3,
+        dpdy: &glm::DVec3,
+        wi: &glm::DVec3,
+        n: &glm::DVec3,
+        mirror: bool,
+    ) -> RayDifferential {
+        let offset = |ray: &Ray, dp: &glm::DVec3| Ray {
+            origin: pos + dp,
+            dir: if mirror {
+                ray.dir - 2.0 * ray.dir.dot(n) * n
+            } else {
+                *wi
+            },
+        };
+        RayDifferential {
+            dx: offset(&self.dx, dpdx),
+            dy: offset(&self.dy, dpdy),
+        }
+    }
+}
+
+/// A rectangular block of pixels, rendered together by one thread
+#[derive(Copy, Clone, Debug)]
+struct Tile {
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+}
+
+impl Tile {
+    /// Iterate over the pixels of the tile in row-major order
+    fn pixels(self) -> impl Iterator<Item = (u32, u32)> {
+        (self.y0..self.y1).flat_map(move |y| (self.x0..self.x1).map(move |x| (x, y)))
+    }
+}
+
+/// Quantity visualized by `Renderer::render_debug`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DebugMode {
+    /// World-space position of the hit, scaled to the bounds of the visible hits
+    Position,
+
+    /// Barycentric coordinates within the triangle that was hit, or black for other shapes
+    Barycentric,
+
+    /// A distinct color for the index of each object in the scene
+    ObjectIndex,
+
+    /// Shading normal of the hit, mapped from [-1, 1] to [0, 1]
+    Normal,
+}
+
+/// A false color for an index, spreading consecutive indices around the color wheel
+fn index_color(index: usize) -> Color {
+    // Successive multiples of the golden ratio are well spread out modulo 1
+    let hue = (index as f64 * 0.618_033_988_749_895 + 0.1).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    glm::vec3(r, g, b)
+}
+
+/// A rectangle of rendered pixels, yielded by `Renderer::render_stream`
+#[derive(Clone, Debug)]
+pub struct RenderedTile {
+    /// Column of the top-left pixel of the tile in the image
+    pub x: u32,
+
+    /// Row of the top-left pixel of the tile in the image
+    pub y: u32,
+
+    /// Width of the tile, in pixels
+    pub width: u32,
+
+    /// Height of the tile, in pixels
+    pub height: u32,
+
+    /// Linear radiance of each pixel in the tile, in row-major order
+    pub colors: Vec<Color>,
+
+    /// Tone-mapping operator of the renderer, used to convert the tile to an image
+    pub tone_map: ToneMap,
+}
+
+impl RenderedTile {
+    /// Convert the tile to an image, which can be copied into the full image at (x, y)
+    pub fn image(&self) -> RgbImage {
+        let buf = self
+            .colors
+            .iter()
+            .flat_map(|color| color_bytes(&self.tone_map.apply(color)))
+            .collect();
+        RgbImage::from_raw(self.width, self.height, buf).expect("Tile has incorrect size")
+    }
+}
+
+/// Divide an image into tiles of at most `TILE_SIZE` by `TILE_SIZE` pixels
+fn tiles(width: u32, height: u32) -> Vec<Tile> {
+    let mut tiles = Vec::new();
+    for y0 in (0..height).step_by(TILE_SIZE as usize) {
+        for x0 in (0..width).step_by(TILE_SIZE as usize) {
+            tiles.push(Tile {
+                x0,
+                y0,
+                x1: (x0 + TILE_SIZE).min(width),
+                y1: (y0 + TILE_SIZE).min(height),
+            });
+        }
+    }
+    tiles
+}
+
+thread_local! {
+    /// Generator reused by every unseeded tile rendered on a thread
+    static THREAD_RNG: std::cell::Cell<Option<StdRng>> = const { std::cell::Cell::new(None) };
+}
+
+/// Run a function with this thread's random number generator, which is seeded from
+/// entropy the first time it is used
+///
+/// Unseeded renders share one generator per thread across all of their tiles and
+/// batches, rather than drawing fresh entropy for each one.
+pub(crate) fn with_thread_rng<T>(f: impl FnOnce(&mut StdRng) -> T) -> T {
+    // Take the generator out while it is in use, so a nested call gets its own
+    let mut rng = THREAD_RNG
+        .with(|cell| cell.take())
+        .unwrap_or_else(StdRng::from_entropy);
+    let result = f(&mut rng);
+    THREAD_RNG.with(|cell| cell.set(Some(rng)));
+    result
+}
+
+/// Combine a seed with a list of values into a new seed, using the SplitMix64 finalizer
+fn mix_seed(seed: u64, values: &[u64]) -> u64 {
+    values.iter().fold(seed, |hash, &value| {
+        let mut z = (hash ^ value).wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hex_color, sphere, Environment, Falloff, PinholeCamera, SceneAdd, Transformable};
+
+    fn test_scene() -> Scene {
+        let mut scene = Scene::new();
+        scene.add(Object::new(sphere()).material(Material::diffuse(hex_color(0xAAAAAA))));
+        scene.add(Light::Object(
+            Object::new(
+                sphere()
+                    .scale(&glm::vec3(0.5, 0.5, 0.5))
+                    .translate(&glm::vec3(0.0, 3.0, 0.0)),
+            )
+            .material(Material::light(hex_color(0xFFFFFF), 20.0)),
+        ));
+        scene
+    }
+
+    #[test]
+    fn seeded_renders_are_reproducible() {
+        let scene = test_scene();
+        let render = |seed| {
+            Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+                .width(40)
+                .height(36)
+                .max_bounces(2)
+                .num_samples(4)
+                .seed(seed)
+                .render()
+        };
+        assert_eq!(render(7), render(7));
+        assert_ne!(render(7), render(8));
+
+        // The thread count must not change the output either
+        let pool = |threads| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap()
+        };
+        assert_eq!(pool(1).install(|| render(7)), pool(3).install(|| render(7)));
+    }
+
+    #[test]
+    fn thread_count_does_not_change_render() {
+        let scene = test_scene();
+        let renderer = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+            .width(40)
+            .height(36)
+            .max_bounces(2)
+            .num_samples(4)
+            .seed(3);
+        let image = renderer.render();
+        let single = renderer.threads(1);
+        let render = single.render();
+        assert_eq!(render.dimensions(), (40, 36));
+        assert_eq!(render, image);
+
+        let double = single.threads(2);
+        let pool = double.thread_pool().unwrap();
+        assert_eq!(pool.current_num_threads(), 2);
+        // The pool is reused across renders
+        assert!(std::ptr::eq(pool, double.thread_pool().unwrap()));
+    }
+
+    #[test]
+    fn render_into_accumulates_across_renderers() {
+        let scene = test_scene();
+        let renderer = |seed| {
+            Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+                .width(20)
+                .height(18)
+                .max_bounces(2)
+                .num_samples(64)
+                .seed(seed)
+        };
+        let mut expected = None;
+        renderer(4).iterative_render(32, |_, buffer| expected = Some(buffer.image()));
+
+        // Two halves of a seeded render continue each other's sample sequence
+        let mut buffer = Buffer::new(20, 18, Filter::default());
+        renderer(4).render_into(&mut buffer, 32);
+        renderer(4).render_into(&mut buffer, 32);
+        assert_eq!(buffer.samples_taken(), 64);
+        assert_eq!(Some(buffer.image()), expected);
+
+        // Renders with different seeds average to the same image, within noise
+        let mut mixed = Buffer::new(20, 18, Filter::default());
+        renderer(5).render_into(&mut mixed, 32);
+        renderer(6).render_into(&mut mixed, 32);
+        let mut single = Buffer::new(20, 18, Filter::default());
+        renderer(4).render_into(&mut single, 64);
+        let mean = |buffer: &Buffer| buffer.colors().iter().sum::<Color>().mean();
+        assert!((mean(&mixed) - mean(&single)).abs() < 0.03 * mean(&single));
+        assert_eq!(single.image(), renderer(4).render());
+    }
+
+    #[test]
+    fn partial_passes_are_stratified_over_the_pixel() {
+        // An emissive wall covering the top half of the view, seen by a single pixel
+        let mut scene = Scene::new();
+        scene.add(
+            Object::new(
+                crate::cube()
+                    .scale(&glm::vec3(100.0, 100.0, 0.1))
+                    .translate(&glm::vec3(0.0, 50.0, 0.0)),
+            )
+            .material(Material::light(hex_color(0xFFFFFF), 1.0)),
+        );
+        let renderer = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+            .width(1)
+            .height(1)
+            .num_samples(16)
+            .seed(2);
+        let mut buffer = renderer.new_buffer();
+        renderer.render_into(&mut buffer, 4);
+        let color = buffer.colors()[0];
+        assert!((color.x - 0.5).abs() < 1e-9, "{:?}", color);
+    }
+
+    #[test]
+    #[should_panic(expected = "incorrect size")]
+    fn render_into_rejects_mismatched_buffer() {
+        let scene = test_scene();
+        let mut buffer = Buffer::new(10, 10, Filter::default());
+        Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+            .width(20)
+            .height(18)
+            .render_into(&mut buffer, 1);
+    }
+
+    #[test]
+    fn resuming_from_checkpoint_matches_uninterrupted_render() {
+        let scene = test_scene();
+        let renderer = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+            .width(20)
+            .height(18)
+            .max_bounces(2)
+            .num_samples(8)
+            .seed(9);
+        let mut expected = None;
+        renderer.iterative_render(4, |_, buffer| expected = Some(buffer.image()));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("render.ckpt");
+        let mut buffer = renderer.new_buffer();
+        renderer.sample(0, 4, Integrator::PathTracing, &mut buffer);
+        buffer.save_checkpoint(&path).unwrap();
+        let loaded = Buffer::load_checkpoint(&path).unwrap();
+        assert_eq!(loaded.samples_taken(), 4);
+        let resumed = renderer.resume(loaded, 4);
+        assert_eq!(resumed.samples_taken(), 8);
+        assert_eq!(Some(resumed.image()), expected);
+
+        // Passes of different lengths are weighted by their sample counts
+        let mut uneven = renderer.new_buffer();
+        renderer.sample(0, 24, Integrator::PathTracing, &mut uneven);
+        let uneven = renderer.resume(uneven, 8);
+        let mean = |buffer: &Buffer| buffer.colors().iter().sum::<Color>().mean();
+        let single = renderer.resume(renderer.new_buffer(), 32);
+        assert!((mean(&uneven) - mean(&single)).abs() < 0.05 * mean(&single));
+    }
+
+    #[test]
+    fn debug_object_index_distinguishes_objects() {
+        let mut scene = Scene::new();
+        for x in [-1.5, 1.5] {
+            scene.add(Object::new(sphere().translate(&glm::vec3(x, 0.0, 0.0))));
+        }
+        let renderer = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+            .width(40)
+            .height(20);
+        let image = renderer.render_debug(DebugMode::ObjectIndex);
+        let (left, right) = (image.get_pixel(12, 10), image.get_pixel(27, 10));
+        assert_ne!(left, right);
+        assert_ne!(left.0, [0, 0, 0]);
+        assert_ne!(right.0, [0, 0, 0]);
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0]);
+
+        // Spheres are not triangles, so they have no barycentric coordinates
+        let image = renderer.render_debug(DebugMode::Barycentric);
+        assert_eq!(image.get_pixel(12, 10).0, [0, 0, 0]);
+    }
+
+    #[test]
+    fn russian_roulette_preserves_brightness() {
+        let mut scene = test_scene();
+        scene.environment = crate::Environment::Color(glm::vec3(0.5, 0.5, 0.5));
+        let mean = |min_bounces| {
+            let renderer = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+                .width(16)
+                .height(16)
+                .max_bounces(12)
+                .min_bounces(min_bounces)
+                .num_samples(64)
+                .seed(1);
+            let mut buffer = renderer.new_buffer();
+            renderer.sample(
+                0,
+                renderer.num_samples,
+                Integrator::PathTracing,
+                &mut buffer,
+            );
+            let colors = buffer.colors();
+            colors.iter().sum::<Color>().mean() / colors.len() as f64
+        };
+        let reference = mean(u32::MAX);
+        let roulette = mean(0);
+        assert!((reference - roulette).abs() < 0.03 * reference);
+    }
+
+    #[test]
+    fn glass_sphere_furnace() {
+        // A lossless dielectric in a uniform environment is invisible
+        let mut scene = Scene::new();
+        scene.add(Object::new(sphere()).material(Material::dielectric(1.5, 0.0)));
+        scene.environment = crate::Environment::Color(glm::vec3(1.0, 1.0, 1.0));
+        let pixels = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+            .width(16)
+            .height(16)
+            .max_bounces(32)
+            .min_bounces(32)
+            .num_samples(16)
+            .seed(5)
+            .render_hdr();
+        let mean = pixels.iter().map(|p| p[0] as f64).sum::<f64>() / pixels.len() as f64;
+        assert!((mean - 1.0).abs() < 0.02, "{}", mean);
+    }
+
+    #[test]
+    fn flat_normal_map_is_a_no_op() {
+        let render = |material: Material| {
+            let mut scene = test_scene();
+            scene.add(Object::new(crate::plane(glm::vec3(0.0, 1.0, 0.0), -1.0)).material(material));
+            Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+                .width(16)
+                .height(16)
+                .max_bounces(2)
+                .num_samples(4)
+                .seed(11)
+                .render()
+        };
+        let floor = Material::diffuse(hex_color(0x808080));
+        let flat = crate::Texture::Solid(glm::vec3(0.5, 0.5, 1.0));
+        assert_eq!(render(floor.clone()), render(floor.normal_map(flat)));
+    }
+
+    #[test]
+    fn halton_sampler_converges_faster() {
+        // An unlit emissive sphere, so that pixel values only depend on coverage
+        let mut scene = Scene::new();
+        scene.add(Object::new(sphere()).material(Material::light(hex_color(0xFFFFFF), 1.0)));
+        let render = |sampler, num_samples, seed| {
+            let renderer = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+                .width(16)
+                .height(16)
+                .num_samples(num_samples)
+                .sampler(sampler)
+                .seed(seed);
+            let mut buffer = renderer.new_buffer();
+            renderer.sample(0, num_samples, Integrator::PathTracing, &mut buffer);
+            buffer.colors()
+        };
+        // The reference uses independent samples, which none of the renders share
+        let reference = render(Sampler::Random, 4096, 4);
+        let rmse = |colors: Vec<Color>| {
+            let sum: f64 = colors
+                .iter()
+                .zip(&reference)
+                .map(|(c, r)| (c - r).magnitude_squared())
+                .sum();
+            (sum / colors.len() as f64).sqrt()
+        };
+        let random = rmse(render(Sampler::Random, 64, 3));
+        let halton = rmse(render(Sampler::Halton, 64, 3));
+        let sobol = rmse(render(Sampler::Sobol, 64, 3));
+        assert!(halton < random);
+        assert!(sobol < random);
+    }
+
+    #[test]
+    fn tiles_cover_every_pixel_once() {
+        let (width, height) = (70, 45);
+        let mut counts = vec![0; (width * height) as usize];
+        for tile in tiles(width, height) {
+            for (x, y) in tile.pixels() {
+                counts[(y * width + x) as usize] += 1;
+            }
+        }
+        assert!(counts.iter().all(|&count| count == 1));
+
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let scene = test_scene();
+        let log = Arc::clone(&calls);
+        Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+            .width(width)
+            .height(height)
+            .progress(move |done, total| log.lock().unwrap().push((done, total)))
+            .render();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            (1..=6).map(|done| (done, 6)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn streamed_tiles_assemble_into_render() {
+        let scene = test_scene();
+        let renderer = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+            .width(70)
+            .height(45)
+            .num_samples(4)
+            .seed(7);
+
+        // Drain the tiles on another thread, as a preview window would
+        let tiles: Vec<_> = std::thread::scope(|s| {
+            let stream = renderer.render_stream();
+            s.spawn(move || stream.collect()).join().unwrap()
+        });
+        assert_eq!(tiles.len(), 6);
+        let mut image = RgbImage::new(70, 45);
+        for tile in &tiles {
+            image::imageops::replace(&mut image, &tile.image(), tile.x, tile.y);
+        }
+        assert_eq!(image, renderer.render());
+    }
+
+    #[test]
+    fn one_light_sampling_matches_all_lights() {
+        let mut scene = Scene::new();
+        scene.add(Object::new(sphere()).material(Material::diffuse(hex_color(0xAAAAAA))));
+        for i in 0..12 {
+            let angle = i as f64 * std::f64::consts::PI / 6.0;
+            let position = glm::vec3(3.0 * angle.cos(), 2.0, 3.0 * angle.sin());
+            scene.add(Light::Point(
+                glm::vec3(2.0, 2.0, 2.0),
+                position,
+                Falloff::Quadratic,
+            ));
+        }
+        scene.add(Light::Point(
+            glm::vec3(20.0, 10.0, 5.0),
+            glm::vec3(0.0, 4.0, 2.0),
+            Falloff::Quadratic,
+        ));
+        let mean = |light_sampling| {
+            let pixels = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+                .width(16)
+                .height(16)
+                .num_samples(512)
+                .light_sampling(light_sampling)
+                .seed(2)
+                .render_hdr();
+            pixels.iter().map(|p| p[0] as f64).sum::<f64>() / pixels.len() as f64
+        };
+        let all = mean(LightSampling::All);
+        for strategy in [
+            LightSampling::Uniform,
+            LightSampling::Power,
+            LightSampling::Tree,
+        ] {
+            let one = mean(strategy);
+            assert!(
+                (one / all - 1.0).abs() < 0.02,
+                "{:?}: {} vs {}",
+                strategy,
+                one,
+                all
+            );
+        }
+    }
+
+    #[test]
+    fn light_tree_sampling_scales_to_many_lights() {
+        // A floor lit by a grid of small lights, each of which only lights its surroundings
+        let mut scene = Scene::new();
+        scene.add(
+            Object::new(crate::plane(glm::vec3(0.0, 1.0, 0.0), 0.0))
+                .material(Material::diffuse(hex_color(0xFFFFFF))),
+        );
+        for i in 0..12 {
+            for j in 0..12 {
+                let position = glm::vec3(i as f64 - 5.5, 0.3, j as f64 - 5.5);
+                let brightness = 0.2 * (1 + (i * 7 + j * 3) % 5) as f64;
+                scene.add(Light::Point(
+                    glm::vec3(1.0, 1.0, 1.0) * brightness,
+                    position,
+                    Falloff::Quadratic,
+                ));
+            }
+        }
+        let camera = PinholeCamera::look_at(
+            glm::vec3(0.0, 10.0, 0.0),
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(0.0, 0.0, -1.0),
+            2.0 * 0.6_f64.atan(),
+        );
+        let render = |light_sampling, num_samples| {
+            let pixels = Renderer::new(&scene, Arc::new(camera.clone()))
+                .width(16)
+                .height(16)
+                .num_samples(num_samples)
+                .light_sampling(light_sampling)
+                .seed(3)
+                .render_hdr();
+            pixels.iter().map(|p| p[0] as f64).collect::<Vec<_>>()
+        };
+        // Relative RMS error of each pixel against the reference
+        let error = |pixels: &[f64], reference: &[f64]| {
+            let sum: f64 = pixels
+                .iter()
+                .zip(reference)
+                .map(|(p, r)| ((p - r) / r).powi(2))
+                .sum();
+            (sum / pixels.len() as f64).sqrt()
+        };
+
+        // Every light is sampled at every pixel, for 144 shadow rays per sample
+        let reference = render(LightSampling::All, 4);
+        // One shadow ray per sample, for less than a tenth of the cost
+        let tree = render(LightSampling::Tree, 16);
+        let uniform = render(LightSampling::Uniform, 16);
+        let mean = |pixels: &[f64]| pixels.iter().sum::<f64>() / pixels.len() as f64;
+        assert!((mean(&tree) / mean(&reference) - 1.0).abs() < 0.05);
+        assert!(
+            error(&tree, &reference) < 0.35,
+            "{}",
+            error(&tree, &reference)
+        );
+        assert!(
+            error(&tree, &reference) < 0.3 * error(&uniform, &reference),
+            "{} vs {}",
+            error(&tree, &reference),
+            error(&uniform, &reference)
+        );
+    }
+
+    #[test]
+    fn firefly_clamp_darkens_bright_caustics() {
+        let mut scene = Scene::new();
+        scene.add(
+            Object::new(crate::plane(glm::vec3(0.0, 1.0, 0.0), -1.0))
+                .material(Material::diffuse(hex_color(0xFFFFFF))),
+        );
+        scene.add(
+            Object::new(
+                sphere()
+                    .scale(&glm::vec3(0.1, 0.1, 0.1))
+                    .translate(&glm::vec3(0.0, -0.8, 6.0)),
+            )
+            .material(Material::light(hex_color(0xFFFFFF), 1e4)),
+        );
+        let mean = |clamp| {
+            let pixels = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+                .width(16)
+                .height(16)
+                .max_bounces(1)
+                .num_samples(64)
+                .firefly_clamp(clamp)
+                .seed(4)
+                .render_hdr();
+            pixels.iter().map(|p| p[0] as f64).sum::<f64>() / pixels.len() as f64
+        };
+        let unclamped = mean(f64::INFINITY);
+        let clamped = mean(100.0);
+        assert!(clamped < 0.9 * unclamped, "{} vs {}", clamped, unclamped);
+    }
+
+    #[test]
+    fn texture_filtering_removes_aliasing() {
+        // A finely checkered floor receding into the distance
+        let checker = |dark: u8| {
+            let mut checker = RgbImage::from_pixel(2, 2, image::Rgb([dark, dark, dark]));
+            checker.put_pixel(0, 0, image::Rgb([255, 255, 255]));
+            checker.put_pixel(1, 1, image::Rgb([255, 255, 255]));
+            checker
+        };
+        let (v1, v2, v3, v4) = (
+            glm::vec3(-50.0, -1.0, 10.0),
+            glm::vec3(50.0, -1.0, 10.0),
+            glm::vec3(50.0, -1.0, -200.0),
+            glm::vec3(-50.0, -1.0, -200.0),
+        );
+        let (uv1, uv2, uv3, uv4) = (
+            glm::vec2(0.0, 0.0),
+            glm::vec2(100.0, 0.0),
+            glm::vec2(100.0, 200.0),
+            glm::vec2(0.0, 200.0),
+        );
+        let floor = |material: Material| {
+            let mut scene = Scene::new();
+            scene.add(
+                Object::new(crate::Mesh::new(vec![
+                    crate::Triangle::from_vertices(v1, v2, v3).uvs(uv1, uv2, uv3),
+                    crate::Triangle::from_vertices(v1, v3, v4).uvs(uv1, uv3, uv4),
+                ]))
+                .material(material),
+            );
+            scene
+        };
+
+        let variance = |scene: &Scene, texture_filtering, rows: std::ops::Range<usize>| {
+            let pixels = Renderer::new(scene, Arc::new(PinholeCamera::default()))
+                .width(64)
+                .height(64)
+                .max_bounces(0)
+                .num_samples(1)
+                .texture_filtering(texture_filtering)
+                .seed(6)
+                .render_hdr();
+            let values: Vec<f64> = pixels[rows.start * 64..rows.end * 64]
+                .iter()
+                .map(|p| p[0] as f64)
+                .collect();
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64
+        };
+        // Rows just below the horizon, where many checks fall in each pixel, are
+        // blurred, while the nearby checks stay sharp
+        let mut scene = floor(Material::diffuse(hex_color(0xFFFFFF)).texture(checker(0)));
+        scene.add(Light::Ambient(glm::vec3(1.0, 1.0, 1.0)));
+        let (point, filtered) = (
+            variance(&scene, false, 33..40),
+            variance(&scene, true, 33..40),
+        );
+        assert!(filtered < 0.1 * point, "{} vs {}", filtered, point);
+        let (point, filtered) = (
+            variance(&scene, false, 56..64),
+            variance(&scene, true, 56..64),
+        );
+        assert!(filtered > 0.5 * point, "{} vs {}", filtered, point);
+
+        // A roughness map is filtered even when the material has no other texture
+        let mut scene =
+            floor(Material::metallic(hex_color(0xFFFFFF), 0.5).roughness_map(checker(50)));
+        scene.add(Light::Point(
+            glm::vec3(1.0, 1.0, 1.0) * 1000.0,
+            glm::vec3(0.0, 10.0, -100.0),
+            Falloff::Quadratic,
+        ));
+        // The highlight varies across the rows, so compare renders with different pixel
+        // positions rather than the spread within one
+        let flicker = |texture_filtering| {
+            let render = |seed| {
+                Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+                    .width(64)
+                    .height(64)
+                    .max_bounces(0)
+                    .num_samples(1)
+                    .texture_filtering(texture_filtering)
+                    .seed(seed)
+                    .render_hdr()
+            };
+            let (a, b) = (render(6), render(7));
+            (33 * 64..40 * 64)
+                .map(|i| (a[i][0] as f64 - b[i][0] as f64).powi(2))
+                .sum::<f64>()
+                / (7.0 * 64.0)
+        };
+        let (point, filtered) = (flicker(false), flicker(true));
+        assert!(filtered < 0.1 * point, "{} vs {}", filtered, point);
+    }
+
+    #[test]
+    fn bidirectional_matches_path_tracing() {
+        let mut scene = Scene::new();
+        scene.add(
+            Object::new(crate::plane(glm::vec3(0.0, 1.0, 0.0), -1.0))
+                .material(Material::diffuse(hex_color(0xCCCCCC))),
+        );
+        scene.add(
+            Object::new(sphere().translate(&glm::vec3(-1.0, 0.0, 0.0)))
+                .material(Material::diffuse(hex_color(0xAA6644))),
+        );
+        scene.add(
+            Object::new(sphere().translate(&glm::vec3(1.2, 0.0, -0.5)))
+                .material(Material::specular(hex_color(0x4466AA), 0.3)),
+        );
+        scene.add(Light::Point(
+            glm::vec3(20.0, 20.0, 20.0),
+            glm::vec3(0.0, 3.0, 2.0),
+            Falloff::Quadratic,
+        ));
+        scene.add(Light::Spot {
+            position: glm::vec3(3.0, 3.0, 3.0),
+            direction: glm::vec3(-1.0, -1.0, -1.0),
+            color: glm::vec3(10.0, 8.0, 6.0),
+            inner_angle: 0.3,
+            outer_angle: 0.5,
+        });
+        let renderer = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+            .width(16)
+            .height(16)
+            .max_bounces(2)
+            .num_samples(256)
+            .firefly_clamp(f64::INFINITY)
+            .seed(9);
+        let mean = |integrator| {
+            let mut buffer = renderer.new_buffer();
+            renderer.sample(0, renderer.num_samples, integrator, &mut buffer);
+            let colors = buffer.colors();
+            colors.iter().sum::<Color>() / colors.len() as f64
+        };
+        let path = mean(Integrator::PathTracing);
+        let bidirectional = mean(Integrator::Bidirectional);
+        for c in 0..3 {
+            assert!(
+                (bidirectional[c] / path[c] - 1.0).abs() < 0.02,
+                "{} vs {}",
+                bidirectional,
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn caustic_guiding_reduces_variance() {
+        let mut scene = Scene::new();
+        scene.add(Object::new(sphere()).material(Material::dielectric(1.5, 0.0)));
+        scene.add(
+            Object::new(crate::plane(glm::vec3(0.0, 1.0, 0.0), -2.0))
+                .material(Material::diffuse(hex_color(0xFFFFFF))),
+        );
+        scene.add(
+            Object::new(
+                sphere()
+                    .scale(&glm::vec3(0.5, 0.5, 0.5))
+                    .translate(&glm::vec3(0.0, 10.0, 0.0)),
+            )
+            .material(Material::light(hex_color(0xFFFFFF), 50.0)),
+        );
+
+        // Estimate the light focused onto the floor below the sphere, from the side
+        let ray = Ray {
+            origin: glm::vec3(4.0, -1.0, 0.0),
+            dir: glm::vec3(-4.0, -1.0, 0.0),
+        };
+        let stats = |renderer: &Renderer| {
+            let mut rng = StdRng::seed_from_u64(0);
+            let n = 20000;
+            let samples: Vec<f64> = (0..n)
+                .map(|_| {
+                    let ones = glm::vec3(1.0, 1.0, 1.0);
+                    let color = renderer.trace_ray(
+                        ray,
+                        0,
+                        &ones,
+                        None,
+                        None,
+                        0.0,
+                        &SampleCursor::random(),
+                        &mut rng,
+                    );
+                    crate::color::luminance(&color)
+                })
+                .collect();
+            let mean = samples.iter().sum::<f64>() / n as f64;
+            let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n as f64;
+            (mean, variance)
+        };
+        let renderer = || {
+            Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+                .max_bounces(4)
+                .firefly_clamp(f64::INFINITY)
+                .seed(0)
+        };
+        let (plain_mean, plain_variance) = stats(&renderer());
+        let (guided_mean, guided_variance) = stats(&renderer().enable_caustic_guiding(100_000));
+        // Guiding only changes how directions are sampled, so the estimates agree
+        assert!((guided_mean - plain_mean).abs() < 0.15 * plain_mean);
+        assert!(guided_variance < 0.5 * plain_variance);
+    }
+
+    #[test]
+    fn crop_matches_full_render() {
+        let scene = test_scene();
+        let renderer = || {
+            Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+                .width(40)
+                .height(30)
+                .max_bounces(1)
+                .num_samples(2)
+                .seed(7)
+        };
+        let full = renderer().render();
+        let cropped = renderer().crop(25, 10, 20, 8).render();
+        // The window is clipped to the image
+        assert_eq!(cropped.dimensions(), (15, 8));
+        assert!(cropped.pixels().any(|pixel| pixel.0 != [0, 0, 0]));
+        for (x, y, pixel) in cropped.enumerate_pixels() {
+            assert_eq!(pixel, full.get_pixel(x + 25, y + 10));
+        }
+
+        let framed = renderer()
+            .crop(25, 10, 20, 8)
+            .crop_full_frame(true)
+            .render();
+        assert_eq!(framed.dimensions(), (40, 30));
+        for (x, y, pixel) in framed.enumerate_pixels() {
+            if x >= 25 && (10..18).contains(&y) {
+                assert_eq!(pixel, full.get_pixel(x, y));
+            } else {
+                assert_eq!(pixel.0, [0, 0, 0]);
+            }
+        }
+    }
+
+    #[test]
+    fn wavefront_matches_recursive_render() {
+        let mut scene = test_scene();
+        scene.add(
+            Object::new(crate::plane(glm::vec3(0.0, 1.0, 0.0), -1.0))
+                .material(Material::diffuse(hex_color(0xAAAAAA))),
+        );
+        let renderer = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+            .width(24)
+            .height(18)
+            .max_bounces(3)
+            .num_samples(256)
+            .firefly_clamp(f64::INFINITY)
+            .seed(3);
+        let mut recursive = renderer.new_buffer();
+        renderer.sample(
+            0,
+            renderer.num_samples,
+            Integrator::PathTracing,
+            &mut recursive,
+        );
+        let mut wavefront = renderer.new_buffer();
+        renderer.sample_wavefront(&mut wavefront);
+
+        // Compare the brightness over blocks of the image, which averages out the noise
+        let (recursive, wavefront) = (recursive.colors(), wavefront.colors());
+        for (block_x, block_y) in [(0, 0), (12, 0), (0, 9), (12, 9)] {
+            let mean = |colors: &[Color]| {
+                let mut sum = glm::vec3(0.0, 0.0, 0.0);
+                for y in block_y..block_y + 9 {
+                    for x in block_x..block_x + 12 {
+                        sum += colors[y * 24 + x];
+                    }
+                }
+                sum / 108.0
+            };
+            let (a, b) = (mean(&recursive), mean(&wavefront));
+            assert!(
+                (a - b).abs().max() <= 0.02 + 0.03 * a.max(),
+                "{} vs {}",
+                a,
+                b
+            );
+        }
+    }
+
+    #[test]
+    fn last_vertex_takes_full_environment_weight() {
+        use crate::environment::Hdri;
+
+        // A bright band above the horizon, over a dim sky
+        let (width, height) = (16, 8);
+        let buf = (0..width * height)
+            .map(|i| glm::vec3(1.0, 1.0, 1.0) * if i / width == 2 { 20.0 } else { 0.5 })
+            .collect();
+        let mut scene = Scene::new();
+        scene.environment = Environment::Hdri(Hdri::new(width, height, buf));
+        scene.add(Object::new(sphere()).material(Material::diffuse(hex_color(0xFFFFFF))));
+
+        // A convex object only receives direct light, so one more bounce adds nothing
+        let render = |max_bounces, wavefront: bool| {
+            let renderer = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+                .width(8)
+                .height(8)
+                .max_bounces(max_bounces)
+                .num_samples(1024)
+                .firefly_clamp(f64::INFINITY)
+                .seed(0);
+            let mut buffer = renderer.new_buffer();
+            if wavefront {
+                renderer.sample_wavefront(&mut buffer);
+            } else {
+                renderer.sample(0, 1024, Integrator::PathTracing, &mut buffer);
+            }
+            let colors = buffer.colors();
+            let mut sum = glm::vec3(0.0, 0.0, 0.0);
+            for y in 2..6 {
+                for x in 2..6 {
+                    sum += colors[y * 8 + x];
+                }
+            }
+            sum / 16.0
+        };
+        for wavefront in [false, true] {
+            let (direct, bounced) = (render(0, wavefront), render(1, wavefront));
+            assert!(
+                (direct - bounced).abs().max() < 0.05 * bounced.max(),
+                "{} vs {}",
+                direct,
+                bounced
+            );
+        }
+    }
+
+    #[test]
+    fn shadow_catcher_only_shows_shadows() {
+        let mut scene = Scene::new();
+        scene.add(Object::new(sphere()).material(Material::diffuse(hex_color(0xAAAAAA))));
+        scene.add(
+            Object::new(crate::plane(glm::vec3(0.0, 1.0, 0.0), -1.0))
+                .material(Material::shadow_catcher()),
+        );
+        scene.set_environment(crate::Environment::Color(glm::vec3(0.5, 0.6, 0.7)));
+        scene.add(Light::Point(
+            glm::vec3(50.0, 50.0, 50.0),
+            glm::vec3(0.0, 5.0, 0.0),
+            Falloff::Quadratic,
+        ));
+        let renderer = Renderer::new(&scene, Arc::new(PinholeCamera::default())).max_bounces(2);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut radiance = |target: glm::DVec3| {
+            let origin = glm::vec3(6.0, 0.5, 0.0);
+            let ray = Ray {
+                origin,
+                dir: target - origin,
+            };
+            let ones = glm::vec3(1.0, 1.0, 1.0);
+            let color = renderer.trace_ray(
+                ray,
+                0,
+                &ones,
+                None,
+                None,
+                0.0,
+                &SampleCursor::random(),
+                &mut rng,
+            );
+            (color, scene.environment.get_color(&ray.dir))
+        };
+
+        // Away from the sphere, the matte shows the background unchanged
+        let (color, background) = radiance(glm::vec3(3.0, -1.0, 2.0));
+        assert!((color - background).abs().max() < 1e-9);
+        assert!(background.max() > 0.0);
+
+        // The sphere shadows the point right below it
+        let (color, background) = radiance(glm::vec3(0.0, -1.0, 0.0));
+        assert!(color.max() < 0.1 * background.max());
+    }
+
+    #[test]
+    fn gaussian_filter_spreads_subpixel_feature() {
+        // A tiny light in the middle of the center pixel, much smaller than a pixel
+        let mut scene = Scene::new();
+        scene.add(
+            Object::new(sphere().scale(&glm::vec3(0.12, 0.12, 0.12)))
+                .material(Material::light(hex_color(0xFFFFFF), 100.0)),
+        );
+        let render = |filter| {
+            let colors = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+                .width(9)
+                .height(9)
+                .num_samples(1024)
+                .pixel_filter(filter)
+                .seed(0)
+                .render_hdr();
+            move |x: usize, y: usize| colors[y * 9 + x][1]
+        };
+
+        let boxed = render(PixelFilter::Box);
+        assert!(boxed(4, 4) > 0.0);
+        assert_eq!(boxed(5, 4), 0.0);
+        assert_eq!(boxed(5, 5), 0.0);
+
+        let gaussian = render(PixelFilter::Gaussian(0.5));
+        let (center, side, corner) = (gaussian(4, 4), gaussian(5, 4), gaussian(5, 5));
+        assert!(center > side && side > corner && corner > 0.0);
+        assert!(gaussian(3, 4) > 0.0 && gaussian(4, 3) > 0.0);
+        // The filter reaches less than two pixels away
+        assert_eq!(gaussian(6, 4), 0.0);
+        // Spreading the feature out conserves its total brightness
+        let total: f32 = (0..81).map(|i| gaussian(i % 9, i / 9)).sum();
+        assert!(
+            (total / boxed(4, 4) - 1.0).abs() < 0.1,
+            "{}",
+            total / boxed(4, 4)
+        );
+    }
+
+    #[test]
+    fn sample_batches_match_tiles() {
+        let scene = test_scene();
+        let renderer = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+            .width(16)
+            .height(12)
+            .max_bounces(1)
+            .num_samples(2048)
+            .seed(5);
+        // A single tile is sampled in batches over the samples
+        let mut batched = renderer.new_buffer();
+        renderer.sample(0, 2048, Integrator::PathTracing, &mut batched);
+        let tile = renderer.render_tiles()[0];
+        let (tiled, _) = renderer.sample_tile(tile, 0, 2048, Integrator::PathTracing);
+
+        let mean = |colors: &[Color]| colors.iter().sum::<Color>() / colors.len() as f64;
+        let batched = batched.colors();
+        let (a, b) = (mean(&batched), mean(&tiled));
+        assert!(a.max() > 0.0);
+        assert!((a - b).abs().max() < 0.02 * a.max(), "{} vs {}", a, b);
+        // The batches do not reuse the random numbers of one another
+        assert_ne!(batched, tiled);
+    }
+
+    #[test]
+    fn thread_rng_matches_seeded_statistics() {
+        let scene = test_scene();
+        let new_renderer = || {
+            Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+                .width(16)
+                .height(12)
+                .max_bounces(1)
+                .num_samples(2048)
+        };
+        // Small batches exercise reuse of the generator across calls to `sample`
+        let (renderer, mut unseeded) = (new_renderer(), Vec::new());
+        renderer.iterative_render(64, |_, buffer| unseeded = buffer.colors());
+        let mut seeded = Vec::new();
+        new_renderer()
+            .seed(9)
+            .iterative_render(64, |_, buffer| seeded = buffer.colors());
+
+        let mean = |colors: &[Color]| colors.iter().sum::<Color>() / colors.len() as f64;
+        let (a, b) = (mean(&unseeded), mean(&seeded));
+        assert!(a.max() > 0.0);
+        // The light is small and bright, so the means converge slowly
+        assert!((a - b).abs().max() < 0.05 * a.max(), "{} vs {}", a, b);
+        // The shared generator keeps advancing, so tiles never repeat random numbers
+        let tile = renderer.render_tiles()[0];
+        let (first, _) = renderer.sample_tile(tile, 0, 4, Integrator::PathTracing);
+        let (second, _) = renderer.sample_tile(tile, 0, 4, Integrator::PathTracing);
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn exposure_scales_brightness_by_stops() {
+        let scene = test_scene();
+        let render = |exposure| {
+            let renderer = Renderer::new(&scene, Arc::new(PinholeCamera::default()))
+                .width(8)
+                .height(6)
+                .max_bounces(1)
+                .num_samples(4)
+                .seed(2)
+                .exposure(exposure);
+            let pixels = renderer.render_hdr();
+            pixels.iter().map(|p| f64::from(p[1])).sum::<f64>()
+        };
+        let base = Exposure {
+            shutter_s: 1.0 / 60.0,
+            iso: 100.0,
+            f_number: 2.8,
+        };
+        let brightness = render(base);
+        assert!(brightness > 0.0);
+        let doubled_iso = render(Exposure { iso: 200.0, ..base });
+        assert!((doubled_iso / brightness - 2.0).abs() < 1e-5);
+        // Opening up by one stop divides the f-number by √2
+        let one_stop = render(Exposure {
+            f_number: 2.8 / 2.0_f64.sqrt(),
+            ..base
+        });
+        assert!((one_stop / brightness - 2.0).abs() < 1e-5);
+        assert!((base.ev100() - (2.8_f64.powi(2) * 60.0).log2()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn rolling_shutter_skews_moving_objects() {
+        // Render a black sphere against a white sky, returning the mean column of the
+        // dark pixels in the top and bottom halves of the image, and how many rows they
+        // cover
+        let render = |start: glm::DVec3, end: glm::DVec3, rolling_shutter: bool| {
+            let mut scene = Scene::new();
+            scene.environment = Environment::Color(glm::vec3(1.0, 1.0, 1.0));
+            scene.add(
+                Object::new(sphere().scale(&glm::vec3(0.8, 0.8, 0.8)))
+                    .material(Material::diffuse(glm::vec3(0.0, 0.0, 0.0)))
+                    .motion(start, end),
+            );
+            let camera = PinholeCamera::look_at(
+                glm::vec3(0.0, 0.0, 5.0),
+                glm::vec3(0.0, 0.0, 0.0),
+                glm::vec3(0.0, 1.0, 0.0),
+                1.1,
+            );
+            let mut renderer = Renderer::new(&scene, Arc::new(camera))
+                .width(48)
+                .height(48)
+                .num_samples(4)
+                .seed(0);
+            if rolling_shutter {
+                renderer = renderer.rolling_shutter(1.0);
+            }
+            let image = renderer.render();
+            let dark: Vec<_> = image
+                .enumerate_pixels()
+                .filter(|(.., pixel)| pixel.0[0] < 128)
+                .map(|(x, y, _)| (f64::from(x), y))
+                .collect();
+            let rows = |top: bool| dark.iter().filter(move |(_, y)| (*y < 24) == top);
+            let column =
+                |top: bool| rows(top).map(|(x, _)| x).sum::<f64>() / rows(top).count() as f64;
+            let height = |top: bool| {
+                let mut ys: Vec<_> = rows(top).map(|(_, y)| *y).collect();
+                ys.dedup();
+                ys.len()
+            };
+            (column(true), column(false), height(true) + height(false))
+        };
+
+        // Horizontal motion shears the sphere, with the bottom read out later
+        let (left, right) = (glm::vec3(-3.0, 0.0, 0.0), glm::vec3(3.0, 0.0, 0.0));
+        let (top, bottom, _) = render(left, right, false);
+        assert!((top - bottom).abs() < 0.5, "{} {}", top, bottom);
+        let (top, bottom, _) = render(left, right, true);
+        assert!(bottom - top > 3.0, "{} {}", top, bottom);
+
+        // An object moving up against the readout is squashed
+        let (down, up) = (glm::vec3(0.0, -2.0, 0.0), glm::vec3(0.0, 2.0, 0.0));
+        let (.., global) = render(down, up, false);
+        let (.., rolling) = render(down, up, true);
+        assert!(rolling + 3 < global, "{} {}", rolling, global);
+    }
+
+    #[test]
+    fn circle_of_confusion_grows_with_defocus() {
+        use crate::ThinLens;
+
+        // Spheres whose fronts are in focus, behind the focal plane, and in front of it
+        let mut scene = Scene::new();
+        for (x, z) in [(-2.0, -0.5), (0.0, -6.5), (1.0, 4.5)] {
+            scene.add(Object::new(
+                sphere()
+                    .scale(&glm::vec3(0.5, 0.5, 0.5))
+                    .translate(&glm::vec3(x, 0.0, z)),
+            ));
+        }
+        let lens = ThinLens {
+            f_number: 2.0,
+            mm_per_unit: 10.0,
+            ..Default::default()
+        };
+        let pinhole = PinholeCamera::look_at(
+            glm::vec3(0.0, 0.0, 10.0),
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0),
+            lens.fov(),
+        );
+        let camera = pinhole.clone().photographic(lens, glm::vec3(0.0, 0.0, 0.0));
+        let size = 64;
+        let (image, coc) = Renderer::new(&scene, Arc::new(camera))
+            .width(size)
+            .height(size)
+            .render_with_coc();
+        assert_eq!(image.dimensions(), (size, size));
+        assert_eq!(coc.len(), (size * size) as usize);
+
+        // Radius of the circle of confusion at the pixel where a point appears, and the
+        // expected radius for its depth, in pixels
+        let d = (lens.fov() / 2.0).tan().recip();
+        let expected = |depth: f64| {
+            let defocus = (depth - 10.0).abs() / depth;
+            d * lens.aperture_radius() * defocus / 10.0 * f64::from(size) / 2.0
+        };
+        let at = |point: glm::DVec3| {
+            let (p, ..) = pinhole.project(&point).unwrap();
+            let x = ((p.x + 1.0) / 2.0 * f64::from(size)) as usize;
+            let y = ((1.0 - p.y) / 2.0 * f64::from(size)) as usize;
+            f64::from(coc[y * size as usize + x])
+        };
+        let focused = at(glm::vec3(-2.0, 0.0, 0.0));
+        let far = at(glm::vec3(0.0, 0.0, -6.0));
+        let near = at(glm::vec3(1.0, 0.0, 5.0));
+        let background = f64::from(coc[0]);
+        assert!(focused < 0.1, "{}", focused);
+        assert!((far / expected(16.0) - 1.0).abs() < 0.05, "{}", far);
+        assert!((near / expected(5.0) - 1.0).abs() < 0.05, "{}", near);
+        assert!(focused < far && far < near, "{} {} {}", focused, far, near);
+        // The background is infinitely far, where the blur is the whole aperture
+        let limit = d * lens.aperture_radius() / 10.0 * f64::from(size) / 2.0;
+        assert!((background / limit - 1.0).abs() < 1e-6, "{}", background);
+
+        // Cameras without an aperture have everything in focus
+        let (_, sharp) = Renderer::new(&scene, Arc::new(pinhole))
+            .width(size)
+            .height(size)
+            .render_with_coc();
+        assert!(sharp.iter().all(|&r| r == 0.0));
+    }
+
+    #[test]
+    fn aovs_record_first_hit() {
+        use crate::ThinLens;
+
+        let mut scene = Scene::new();
+        let color = glm::vec3(0.2, 0.4, 0.6);
+        scene.add(Object::new(sphere()).material(Material::diffuse(color)));
+        let camera = PinholeCamera::look_at(
+            glm::vec3(0.0, 0.0, 5.0),
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0),
+            1.0,
+        );
+        let aovs = Renderer::new(&scene, Arc::new(camera.clone()))
+            .width(9)
+            .height(9)
+            .render_aovs();
+        assert_eq!((aovs.width, aovs.height), (9, 9));
+        assert_eq!(aovs.depth.len(), 81);
+
+        // The center pixel sees the front of the sphere, and the corners miss it
+        let center = 4 * 9 + 4;
+        assert!(
+            (aovs.depth[center] - 4.0).abs() < 1e-5,
+            "{}",
+            aovs.depth[center]
+        );
+        assert_eq!(aovs.normal[center], [0.0, 0.0, 1.0]);
+        assert_eq!(aovs.albedo[center], [0.2, 0.4, 0.6]);
+        assert_eq!(aovs.depth[0], f32::INFINITY);
+        assert_eq!((aovs.normal[0], aovs.albedo[0]), ([0.0; 3], [0.0; 3]));
+
+        // Cameras with an aperture give the same buffers every time
+        let lens = ThinLens {
+            mm_per_unit: 10.0,
+            ..Default::default()
+        };
+        let renderer = Renderer::new(
+            &scene,
+            Arc::new(camera.photographic(lens, glm::vec3(0.0, 0.0, 1.0))),
+        )
+        .width(9)
+        .height(9);
+        let (a, b) = (renderer.render_aovs(), renderer.render_aovs());
+        assert_eq!((a.depth, a.normal, a.albedo), (b.depth, b.normal, b.albedo));
+    }
+
+    #[test]
+    fn mid_gray_renders_back_to_its_hex_value() {
+        // A Lambertian sphere under a uniform white sky reflects exactly its albedo
+        let mut scene = Scene::new();
+        scene.environment = Environment::Color(glm::vec3(1.0, 1.0, 1.0));
+        scene.add(Object::new(sphere()).material(Material::oren_nayar(hex_color(0x808080), 0.0)));
+        let camera = PinholeCamera::look_at(
+            glm::vec3(0.0, 0.0, 5.0),
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0),
+            0.1,
+        );
+        let image = Renderer::new(&scene, Arc::new(camera))
+            .width(8)
+            .height(8)
+            .max_bounces(1)
+            .num_samples(256)
+            .seed(0)
+            .render();
+        for pixel in image.pixels() {
+            for channel in pixel.0 {
+                assert!((i32::from(channel) - 0x80).abs() <= 3, "{:?}", pixel);
             }
         }
-        Some((h, hit?))
     }
 }
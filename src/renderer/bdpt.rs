@@ -0,0 +1,549 @@
+use rand::{rngs::StdRng, Rng};
+use std::f64::consts::PI;
+
+use super::{power_heuristic, Renderer, Scattering};
+use crate::color::Color;
+use crate::light::Light;
+use crate::material::{local_to_world, Frame, Material};
+use crate::shape::Ray;
+
+/// A scattering vertex on a camera or light subpath
+struct Vertex {
+    /// Position of the vertex
+    pos: glm::DVec3,
+
+    /// Shading frame at the vertex
+    frame: Frame,
+
+    /// Direction toward the previous vertex of the subpath
+    wo: glm::DVec3,
+
+    /// Material of the surface, evaluated at the hit
+    material: Material,
+
+    /// Weight of the subpath ending at this vertex
+    beta: Color,
+
+    /// Density of sampling this vertex from the previous vertex of its subpath, with
+    /// respect to area, or zero if the previous vertex scattered with a delta BSDF
+    pdf_fwd: f64,
+
+    /// Density of sampling this vertex from the next vertex, as if the path had been
+    /// traced in the opposite direction
+    pdf_rev: f64,
+}
+
+impl Vertex {
+    /// Whether the vertex scatters with a delta BSDF, so it cannot be connected to
+    fn is_delta(&self) -> bool {
+        self.material.is_delta()
+    }
+}
+
+impl Renderer<'_> {
+    /// Estimate the light arriving along a camera ray by bidirectional path tracing,
+    /// already multiplied by the camera ray's `throughput`
+    ///
+    /// Paths of `k` edges can be sampled by up to `k` strategies, one for each edge that
+    /// connects a camera subpath to a light subpath, so all these estimates are combined
+    /// with the power heuristic. Connecting a light subpath to the camera itself is only
+    /// possible for cameras that implement `Camera::project`, and lands on an arbitrary
+    /// pixel, so those estimates are pushed onto `splats`.
+    ///
+    /// Lights that do not start light subpaths are only reached from the camera
+    /// subpath, and their light is not reweighted.
+    pub(super) fn trace_bidirectional(
+        &self,
+        ray: Ray,
+        throughput: &Color,
+        time: f64,
+        splats: &mut Vec<(usize, Color)>,
+        rng: &mut StdRng,
+    ) -> Color {
+        let (camera, mut color) = self.camera_subpath(ray, throughput, time, rng);
+        let (light, prob) = match self.choose_subpath_light(rng) {
+            Some(choice) => choice,
+            None => return color,
+        };
+        let light_path = self.light_subpath(light, prob, time, rng);
+
+        // Both subpaths can be as long as a path traced with `max_bounces`
+        let max_vertices = self.max_bounces as usize + 3;
+        for s in 2..=light_path.len() + 1 {
+            if s < max_vertices {
+                splats.extend(self.connect_to_camera(&light_path, &camera, s, time));
+            }
+        }
+        for t in 2..=camera.len() + 1 {
+            for s in 1..=light_path.len() + 1 {
+                if s + t <= max_vertices {
+                    color += self.connect(light, prob, &light_path, &camera, s, t, time, rng);
+                }
+            }
+        }
+        color
+    }
+
+    /// Trace a subpath from the camera, returning its vertices along with the light
+    /// that it finds without connecting to a light subpath
+    fn camera_subpath(
+        &self,
+        mut ray: Ray,
+        throughput: &Color,
+        time: f64,
+        rng: &mut StdRng,
+    ) -> (Vec<Vertex>, Color) {
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let mut color = glm::vec3(0.0, 0.0, 0.0);
+        let mut beta = *throughput;
+        let mut bsdf_pdf = None;
+        loop {
+            let (mut h, object) = match self.get_closest_hit(ray, time) {
+                Some(hit) => hit,
+                None => {
+                    let radiance = self.scene.environment.get_color(&ray.dir);
+                    let mis = bsdf_pdf.map_or(1.0, |pdf| {
+                        power_heuristic(pdf, self.scene.environment.pdf(&ray.dir))
+                    });
+                    color += beta.component_mul(&radiance) * mis;
+                    break;
+                }
+            };
+            let pos = ray.at(h.time);
+            let wo = -glm::normalize(&ray.dir);
+            h.normal = object.material.shading_normal(&h, &wo);
+            let material = object.material.at(&h.uv);
+            let frame = Frame::new(h.normal, h.tangent);
+            let pdf_fwd = if vertices.is_empty() {
+                // The density of the camera ray, if light paths can also be connected to it
+                let density = self.camera.project(&pos).map_or(0.0, |(_, _, d)| d);
+                self.image_density(density) * area_density(&ray.origin, &pos, &h.normal)
+            } else {
+                bsdf_pdf.unwrap_or(0.0) * area_density(&ray.origin, &pos, &h.normal)
+            };
+
+            color += beta.component_mul(&(material.emittance * material.color));
+            let scattering = Scattering::Surface(&material, frame);
+            color += beta.component_mul(&self.sample_unconnected_lights(
+                &scattering,
+                &pos,
+                &wo,
+                time,
+                rng,
+            ));
+
+            vertices.push(Vertex {
+                pos,
+                frame,
+                wo,
+                material,
+                beta,
+                pdf_fwd,
+                pdf_rev: 0.0,
+            });
+            match self.extend(&mut vertices, &mut beta, false, rng) {
+                Some((wi, pdf)) => {
+                    bsdf_pdf = pdf;
+                    ray = Ray {
+                        origin: pos,
+                        dir: wi,
+                    };
+                }
+                None => break,
+            }
+        }
+        (vertices, color)
+    }
+
+    /// Trace a subpath from a point or spot light, which was chosen with probability
+    /// `prob`, returning its vertices after the light itself
+    fn light_subpath(&self, light: &Light, prob: f64, time: f64, rng: &mut StdRng) -> Vec<Vertex> {
+        let mut vertices: Vec<Vertex> = Vec::new();
+        let (origin, dir, pdf_dir) = sample_emission(light, rng);
+        if pdf_dir == 0.0 {
+            return vertices;
+        }
+        let mut beta = light.intensity(&dir) / (prob * pdf_dir);
+        let mut ray = Ray { origin, dir };
+        let mut pdf_dir = Some(pdf_dir);
+        while let Some((mut h, object)) = self.get_closest_hit(ray, time) {
+            let pos = ray.at(h.time);
+            let wo = -glm::normalize(&ray.dir);
+            h.normal = object.material.shading_normal(&h, &wo);
+            vertices.push(Vertex {
+                pos,
+                frame: Frame::new(h.normal, h.tangent),
+                wo,
+                material: object.material.at(&h.uv),
+                beta,
+                pdf_fwd: pdf_dir.unwrap_or(0.0) * area_density(&ray.origin, &pos, &h.normal),
+                pdf_rev: 0.0,
+            });
+            match self.extend(&mut vertices, &mut beta, true, rng) {
+                Some((wi, pdf)) => {
+                    pdf_dir = pdf;
+                    ray = Ray {
+                        origin: pos,
+                        dir: wi,
+                    };
+                }
+                None => break,
+            }
+        }
+        vertices
+    }
+
+    /// Sample a direction to continue a subpath from its last vertex, updating the
+    /// weight `beta` and the reverse density of the previous vertex
+    ///
+    /// Returns the direction with its density, which is `None` for delta BSDFs. Light
+    /// flows forward along light subpaths, so they evaluate the BSDF with its directions
+    /// swapped, and a delta BSDF is then relative to the direction toward the light.
+    fn extend(
+        &self,
+        vertices: &mut [Vertex],
+        beta: &mut Color,
+        from_light: bool,
+        rng: &mut StdRng,
+    ) -> Option<(glm::DVec3, Option<f64>)> {
+        if vertices.len() > self.max_bounces as usize {
+            return None;
+        }
+        let (previous, vertex) = match vertices {
+            [.., previous, vertex] => (Some(previous), vertex),
+            [vertex] => (None, vertex),
+            [] => return None,
+        };
+        let (frame, wo, material) = (&vertex.frame, &vertex.wo, &vertex.material);
+        let n = &frame.normal;
+        let (wi, pdf) = material.sample_f(frame, wo, rng.gen(), rng)?;
+        let (f, cosine) = if from_light {
+            let cosine = if material.is_delta() { wo } else { &wi }.dot(n).abs();
+            (material.bsdf(frame, &wi, wo), cosine)
+        } else {
+            (material.bsdf(frame, wo, &wi), wi.dot(n).abs())
+        };
+        *beta = beta.component_mul(&f) * (cosine / pdf);
+        if beta.max() <= 0.0 || beta.iter().any(|c| !c.is_finite()) {
+            return None;
+        }
+        if material.is_delta() {
+            if let Some(previous) = previous {
+                previous.pdf_rev = 0.0;
+            }
+            return Some((wi, None));
+        }
+        if let Some(previous) = previous {
+            let pdf_rev = material.pdf(frame, &wi, wo);
+            previous.pdf_rev =
+                pdf_rev * area_density(&vertex.pos, &previous.pos, &previous.frame.normal);
+        }
+        Some((wi, Some(pdf)))
+    }
+
+    /// Estimate the light from the strategy that connects the first `t` vertices of the
+    /// camera subpath (counting the camera) to the first `s` vertices of the light
+    /// subpath (counting the light), weighted by multiple importance sampling
+    #[allow(clippy::too_many_arguments)]
+    fn connect(
+        &self,
+        light: &Light,
+        prob: f64,
+        light_path: &[Vertex],
+        camera: &[Vertex],
+        s: usize,
+        t: usize,
+        time: f64,
+        rng: &mut StdRng,
+    ) -> Color {
+        let black = glm::vec3(0.0, 0.0, 0.0);
+        let z = &camera[t - 2];
+        if z.is_delta() {
+            return black;
+        }
+
+        // The connection gives the densities of its endpoints, and of their neighbors,
+        // being sampled in the opposite direction from their subpaths
+        let (color, camera_rev, light_rev) = if s == 1 {
+            let (radiance, wi, dist) = light.illuminate(&z.pos, rng);
+            if !self.is_visible(&z.pos, &wi, dist, time) {
+                return black;
+            }
+            let f = z.material.bsdf(&z.frame, &z.wo, &wi) * wi.dot(&z.frame.normal).abs();
+            let camera_rev = (
+                emission_pdf(light, &-wi) * z.frame.normal.dot(&wi).abs() / (dist * dist),
+                z.material.pdf(&z.frame, &wi, &z.wo),
+            );
+            let color = z.beta.component_mul(&f).component_mul(&radiance) / prob;
+            (color, camera_rev, (0.0, 0.0))
+        } else {
+            let y = &light_path[s - 2];
+            if y.is_delta() {
+                return black;
+            }
+            let disp = y.pos - z.pos;
+            let dist = disp.magnitude();
+            let w = disp / dist;
+            let g = (w.dot(&z.frame.normal) * w.dot(&y.frame.normal)).abs() / (dist * dist);
+            if g == 0.0 || !self.is_visible(&z.pos, &w, dist * (1.0 - 1e-6), time) {
+                return black;
+            }
+            let fz = z.material.bsdf(&z.frame, &z.wo, &w);
+            let fy = y.material.bsdf(&y.frame, &-w, &y.wo);
+            let camera_rev = (
+                y.material.pdf(&y.frame, &y.wo, &-w) * z.frame.normal.dot(&w).abs() / (dist * dist),
+                z.material.pdf(&z.frame, &w, &z.wo),
+            );
+            let light_rev = (
+                z.material.pdf(&z.frame, &z.wo, &w) * y.frame.normal.dot(&w).abs() / (dist * dist),
+                y.material.pdf(&y.frame, &-w, &y.wo),
+            );
+            let color = z
+                .beta
+                .component_mul(&fz)
+                .component_mul(&fy)
+                .component_mul(&y.beta)
+                * g;
+            (color, camera_rev, light_rev)
+        };
+        if color.max() <= 0.0 {
+            return black;
+        }
+        color * mis_weight(light_path, camera, s, t, camera_rev, light_rev)
+    }
+
+    /// Connect the first `s` vertices of the light subpath directly to the camera,
+    /// returning the pixel that the connection lands on and its weighted estimate
+    fn connect_to_camera(
+        &self,
+        light_path: &[Vertex],
+        camera: &[Vertex],
+        s: usize,
+        time: f64,
+    ) -> Option<(usize, Color)> {
+        let y = &light_path[s - 2];
+        if y.is_delta() {
+            return None;
+        }
+        let (coords, eye, density) = self.camera.project(&y.pos)?;
+        let index = self.pixel_index(&coords)?;
+        let disp = eye - y.pos;
+        let dist = disp.magnitude();
+        let w = disp / dist;
+        if !self.is_visible(&y.pos, &w, dist, time) {
+            return None;
+        }
+        let importance = self.image_density(density);
+        let fy = y.material.bsdf(&y.frame, &w, &y.wo);
+        let color =
+            fy.component_mul(&y.beta) * (importance * w.dot(&y.frame.normal).abs() / (dist * dist));
+        if color.max() <= 0.0 {
+            return None;
+        }
+        let light_rev = (
+            importance * y.frame.normal.dot(&w).abs() / (dist * dist),
+            y.material.pdf(&y.frame, &w, &y.wo),
+        );
+        let weight = mis_weight(light_path, camera, s, 1, (0.0, 0.0), light_rev);
+        Some((index, color * weight))
+    }
+
+    /// Density of sampling a camera ray, per unit solid angle, given the camera's density
+    /// per unit area of normalized image coordinates
+    fn image_density(&self, density: f64) -> f64 {
+        let dim = std::cmp::max(self.width, self.height) as f64;
+        let area = 4.0 * f64::from(self.width) * f64::from(self.height) / (dim * dim);
+        density / area
+    }
+
+    /// Find the row-major index of the pixel containing normalized image coordinates
+    fn pixel_index(&self, coords: &glm::DVec2) -> Option<usize> {
+        // Inverse of `normalize_pixel`, extended to the whole area of each pixel
+        let dim = std::cmp::max(self.width, self.height) as f64;
+        let x = (coords.x * dim + f64::from(self.width)) / 2.0;
+        let y = (f64::from(self.height) - coords.y * dim) / 2.0;
+        if x < 0.0 || y < 0.0 || x >= f64::from(self.width) || y >= f64::from(self.height) {
+            return None;
+        }
+        Some(y as usize * self.width as usize + x as usize)
+    }
+
+    /// Sample the lights that do not start light subpaths, which are only reached by
+    /// sampling them directly from the camera subpath
+    fn sample_unconnected_lights(
+        &self,
+        scattering: &Scattering,
+        pos: &glm::DVec3,
+        wo: &glm::DVec3,
+        time: f64,
+        rng: &mut StdRng,
+    ) -> Color {
+        let mut color = glm::vec3(0.0, 0.0, 0.0);
+        for light in &self.scene.lights {
+            match light {
+                Light::Ambient(ambient_color) => {
+                    color += ambient_color.component_mul(&scattering.albedo());
+                }
+                Light::Point(..) | Light::Spot { .. } => {}
+                _ => color += self.sample_light(light, scattering, pos, wo, time, rng),
+            }
+        }
+        color + self.sample_environment(scattering, pos, wo, false, time, rng)
+    }
+
+    /// Choose a point or spot light to start a light subpath, with probability
+    /// proportional to its power
+    fn choose_subpath_light(&self, rng: &mut StdRng) -> Option<(&Light, f64)> {
+        let lights = || {
+            self.scene
+                .lights
+                .iter()
+                .filter(|light| matches!(light, Light::Point(..) | Light::Spot { .. }))
+        };
+        let total: f64 = lights().map(Light::power).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut u = rng.gen::<f64>() * total;
+        let mut chosen = None;
+        for light in lights() {
+            let power = light.power();
+            if power > 0.0 {
+                chosen = Some((light, power / total));
+                if u < power {
+                    break;
+                }
+                u -= power;
+            }
+        }
+        chosen
+    }
+
+    /// Check whether a point can see along a direction up to some distance
+    fn is_visible(&self, pos: &glm::DVec3, dir: &glm::DVec3, dist: f64, time: f64) -> bool {
+        let ray = Ray {
+            origin: *pos,
+            dir: *dir,
+        };
+        self.get_closest_hit(ray, time)
+            .is_none_or(|(h, _)| h.time > dist)
+    }
+}
+
+/// Weight the strategy that connects `s` light subpath vertices to `t` camera subpath
+/// vertices with the power heuristic
+///
+/// The other strategies for the same path move the connection toward the light or the
+/// camera, and the ratios of their densities to this one are accumulated one vertex at a
+/// time. The connection sets the reverse densities of its endpoints, and the solid angle
+/// densities of sampling their neighbors, which are given in `camera_rev` and
+/// `light_rev`. Delta vertices have zero densities, which are skipped over, and cannot
+/// be connected to.
+fn mis_weight(
+    light_path: &[Vertex],
+    camera: &[Vertex],
+    s: usize,
+    t: usize,
+    camera_rev: (f64, f64),
+    light_rev: (f64, f64),
+) -> f64 {
+    let remap = |pdf: f64| if pdf == 0.0 { 1.0 } else { pdf };
+    let mut sum = 0.0;
+
+    // Strategies with fewer camera vertices, down to connecting to the camera itself
+    let mut ratio = 1.0;
+    for i in (0..t.saturating_sub(1)).rev() {
+        let pdf_rev = match t - 2 - i {
+            0 => camera_rev.0,
+            1 => {
+                camera_rev.1
+                    * area_density(&camera[t - 2].pos, &camera[i].pos, &camera[i].frame.normal)
+            }
+            _ => camera[i].pdf_rev,
+        };
+        if i == 0 && camera[0].pdf_fwd == 0.0 {
+            // Light paths cannot be connected to this camera
+            break;
+        }
+        ratio *= remap(pdf_rev) / remap(camera[i].pdf_fwd);
+        if !camera[i].is_delta() && (i == 0 || !camera[i - 1].is_delta()) {
+            sum += ratio * ratio;
+        }
+    }
+
+    // Strategies with fewer light vertices, down to sampling the light directly
+    let mut ratio = 1.0;
+    for i in (0..s - 1).rev() {
+        let pdf_rev = match s - 2 - i {
+            0 => light_rev.0,
+            1 => {
+                let y = &light_path[s - 2];
+                light_rev.1 * area_density(&y.pos, &light_path[i].pos, &light_path[i].frame.normal)
+            }
+            _ => light_path[i].pdf_rev,
+        };
+        ratio *= remap(pdf_rev) / remap(light_path[i].pdf_fwd);
+        if !light_path[i].is_delta() && (i == 0 || !light_path[i - 1].is_delta()) {
+            sum += ratio * ratio;
+        }
+    }
+    1.0 / (1.0 + sum)
+}
+
+/// Convert a solid angle density at `from` to an area density at `to`, on a surface
+/// with the given normal
+fn area_density(from: &glm::DVec3, to: &glm::DVec3, normal: &glm::DVec3) -> f64 {
+    let disp = to - from;
+    let dist2 = disp.magnitude_squared();
+    normal.dot(&disp).abs() / (dist2 * dist2.sqrt())
+}
+
+/// Sample a direction of emission from a point or spot light, returning the position
+/// of the light with the direction and its density
+fn sample_emission(light: &Light, rng: &mut StdRng) -> (glm::DVec3, glm::DVec3, f64) {
+    let (u, v): (f64, f64) = rng.gen();
+    let phi = 2.0 * PI * v;
+    match light {
+        Light::Point(_, position, _) => {
+            let z = 1.0 - 2.0 * u;
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let dir = glm::vec3(r * phi.cos(), r * phi.sin(), z);
+            (*position, dir, 0.25 / PI)
+        }
+        Light::Spot {
+            position,
+            direction,
+            outer_angle,
+            ..
+        } => {
+            // Uniformly sample the cone of directions that the light reaches
+            let cos_outer = outer_angle.cos();
+            let z = 1.0 - u * (1.0 - cos_outer);
+            let r = (1.0 - z * z).max(0.0).sqrt();
+            let local = glm::vec3(r * phi.cos(), r * phi.sin(), z);
+            let dir = local_to_world(&direction.normalize()) * local;
+            (*position, dir, emission_pdf(light, &dir))
+        }
+        _ => (glm::vec3(0.0, 0.0, 0.0), glm::vec3(0.0, 0.0, 1.0), 0.0),
+    }
+}
+
+/// Density of `sample_emission` generating a direction
+fn emission_pdf(light: &Light, dir: &glm::DVec3) -> f64 {
+    match light {
+        Light::Point(..) => 0.25 / PI,
+        Light::Spot {
+            direction,
+            outer_angle,
+            ..
+        } => {
+            let cos_outer = outer_angle.cos();
+            if dir.dot(&direction.normalize()) >= cos_outer {
+                1.0 / (2.0 * PI * (1.0 - cos_outer))
+            } else {
+                0.0
+            }
+        }
+        _ => 0.0,
+    }
+}
@@ -0,0 +1,245 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::f64::consts::PI;
+
+use super::Renderer;
+use crate::color::{luminance, Color};
+use crate::kdtree::BoundingBox;
+use crate::material::{local_to_world, Frame, Material};
+use crate::sampler::sample_cosine_hemisphere;
+use crate::shape::Ray;
+
+/// Number of cells along each axis of the caustic photon grid
+const GRID_RESOLUTION: usize = 16;
+
+/// Minimum number of photons landing in a cell for it to guide scattering
+const MIN_CELL_PHOTONS: usize = 4;
+
+/// Upper limit on the concentration of a guiding lobe
+const MAX_CONCENTRATION: f64 = 1000.0;
+
+/// Maximum number of specular bounces followed for each photon
+const MAX_PHOTON_BOUNCES: u32 = 8;
+
+/// Probability of sampling the guiding lobe instead of the BSDF, where there is one
+const GUIDE_FRACTION: f64 = 0.5;
+
+/// Coarse grid over the places where caustic photons land, storing the distribution of
+/// directions that they arrive from in each cell
+///
+/// Photons are shot from the emissive objects of the scene, and only land on the first
+/// non-specular surface they hit after passing through at least one specular surface.
+/// Point and spot lights are skipped, since paths from the camera can never reach them.
+pub(super) struct CausticGrid {
+    bounds: BoundingBox,
+    cells: Vec<Option<Lobe>>,
+}
+
+/// Von Mises-Fisher distribution of directions, which is peaked around a mean
+#[derive(Copy, Clone, Debug)]
+struct Lobe {
+    mean: glm::DVec3,
+    concentration: f64,
+}
+
+impl CausticGrid {
+    /// Bin photons, given as (position, direction toward their source, weight)
+    fn new(photons: &[(glm::DVec3, glm::DVec3, f64)]) -> Self {
+        let bounds = photons.iter().fold(BoundingBox::default(), |b, (p, _, _)| {
+            b.merge(&BoundingBox {
+                p_min: *p,
+                p_max: *p,
+            })
+        });
+        let mut sums = vec![(glm::vec3(0.0, 0.0, 0.0), 0.0, 0); GRID_RESOLUTION.pow(3)];
+        let mut grid = Self {
+            bounds,
+            cells: Vec::new(),
+        };
+        for (pos, dir, weight) in photons {
+            if let Some(cell) = grid.cell(pos) {
+                let sum = &mut sums[cell];
+                sum.0 += dir * *weight;
+                sum.1 += weight;
+                sum.2 += 1;
+            }
+        }
+        grid.cells = sums
+            .into_iter()
+            .map(|(sum, weight, count)| {
+                if count < MIN_CELL_PHOTONS {
+                    None
+                } else {
+                    Lobe::fit(&sum, weight)
+                }
+            })
+            .collect();
+        grid
+    }
+
+    /// Index of the cell containing a position, if it is within the grid
+    fn cell(&self, pos: &glm::DVec3) -> Option<usize> {
+        let mut index = 0;
+        for axis in 0..3 {
+            let (lo, hi) = (self.bounds.p_min[axis], self.bounds.p_max[axis]);
+            // Allow some slack around the grid, as photons often all lie on a plane
+            let slack = 1e-3 * (hi - lo).max(1.0);
+            if !(lo - slack..=hi + slack).contains(&pos[axis]) {
+                return None;
+            }
+            let offset = if hi > lo {
+                (pos[axis] - lo) / (hi - lo)
+            } else {
+                0.0
+            };
+            let i = ((offset * GRID_RESOLUTION as f64) as usize).min(GRID_RESOLUTION - 1);
+            index = index * GRID_RESOLUTION + i;
+        }
+        Some(index)
+    }
+
+    /// Guiding lobe at a position, if enough caustic photons landed near it
+    fn lobe(&self, pos: &glm::DVec3) -> Option<&Lobe> {
+        self.cells.get(self.cell(pos)?)?.as_ref()
+    }
+}
+
+impl Lobe {
+    /// Fit a lobe to the weighted sum of a set of unit directions, by matching the
+    /// length of their mean
+    fn fit(sum: &glm::DVec3, weight: f64) -> Option<Self> {
+        let length = sum.magnitude();
+        if weight <= 0.0 || length <= 0.0 {
+            return None;
+        }
+        let r = (length / weight).min(1.0 - 1e-9);
+        Some(Self {
+            mean: sum / length,
+            concentration: (r * (3.0 - r * r) / (1.0 - r * r)).min(MAX_CONCENTRATION),
+        })
+    }
+
+    /// Sample a direction from the lobe, given a point of the unit square
+    fn sample(&self, [u, v]: [f64; 2]) -> glm::DVec3 {
+        let k = self.concentration;
+        let z = 1.0 + (u + (1.0 - u) * (-2.0 * k).exp()).ln() / k;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * v;
+        local_to_world(&self.mean) * glm::vec3(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    /// Probability density of sampling a direction, with respect to solid angle
+    fn pdf(&self, dir: &glm::DVec3) -> f64 {
+        let k = self.concentration;
+        k / (2.0 * PI * (1.0 - (-2.0 * k).exp())) * (k * (self.mean.dot(dir) - 1.0)).exp()
+    }
+}
+
+impl Renderer<'_> {
+    /// Sample a direction to continue a path from a surface, given a point `u` of the
+    /// unit square, returning it with its density
+    ///
+    /// With caustic guiding enabled, directions are drawn from a mixture of the BSDF and
+    /// the guiding lobe where caustics land, and the density is that of the mixture.
+    pub(super) fn sample_scattering(
+        &self,
+        material: &Material,
+        frame: &Frame,
+        wo: &glm::DVec3,
+        pos: &glm::DVec3,
+        u: [f64; 2],
+        rng: &mut StdRng,
+    ) -> Option<(glm::DVec3, f64)> {
+        let lobe = match self.caustic_lobe(pos) {
+            Some(lobe) if !material.is_delta() => lobe,
+            _ => return material.sample_f(frame, wo, u, rng),
+        };
+        let wi = if rng.gen::<f64>() < GUIDE_FRACTION {
+            lobe.sample(u)
+        } else {
+            material.sample_f(frame, wo, u, rng)?.0
+        };
+        let pdf =
+            GUIDE_FRACTION * lobe.pdf(&wi) + (1.0 - GUIDE_FRACTION) * material.pdf(frame, wo, &wi);
+        Some((wi, pdf)).filter(|_| pdf > 0.0)
+    }
+
+    /// Guiding lobe toward caustics at a position, building the grid on first use
+    fn caustic_lobe(&self, pos: &glm::DVec3) -> Option<&Lobe> {
+        let photons = self.caustic_photons?;
+        self.caustic_grid
+            .get_or_init(|| self.build_caustic_grid(photons))
+            .lobe(pos)
+    }
+
+    /// Shoot photons from the emissive objects of the scene through specular surfaces,
+    /// and bin the ones that land
+    fn build_caustic_grid(&self, photons: usize) -> CausticGrid {
+        let mut rng = StdRng::seed_from_u64(self.seed.unwrap_or(0));
+        let emitters: Vec<_> = self
+            .scene
+            .objects
+            .iter()
+            .map(|object| (object, object.material.color * object.material.emittance))
+            .filter(|(_, radiance)| luminance(radiance) > 0.0)
+            .collect();
+        let total: f64 = emitters
+            .iter()
+            .map(|(_, radiance)| luminance(radiance))
+            .sum();
+        let mut landed = Vec::new();
+        if emitters.is_empty() {
+            return CausticGrid::new(&landed);
+        }
+        for _ in 0..photons {
+            // Choose an emitter in proportion to its radiance, and a point on it
+            let mut u = rng.gen::<f64>() * total;
+            let (object, radiance) = emitters
+                .iter()
+                .find(|(_, radiance)| {
+                    u -= luminance(radiance);
+                    u < 0.0
+                })
+                .unwrap_or(&emitters[emitters.len() - 1]);
+            let target = glm::normalize(&glm::vec3(
+                rng.gen::<f64>() - 0.5,
+                rng.gen::<f64>() - 0.5,
+                rng.gen::<f64>() - 0.5,
+            )) * 1e6;
+            let (origin, normal, _) = object.shape.sample(&target, &mut rng);
+            let (dir, _) = sample_cosine_hemisphere(&normal, rng.gen());
+            let mut beta: Color = *radiance;
+            let mut ray = Ray { origin, dir };
+            let mut specular = false;
+            for _ in 0..MAX_PHOTON_BOUNCES {
+                let (mut h, object) = match self.get_closest_hit(ray, 0.0) {
+                    Some(hit) => hit,
+                    None => break,
+                };
+                let pos = ray.at(h.time);
+                let wo = -glm::normalize(&ray.dir);
+                h.normal = object.material.shading_normal(&h, &wo);
+                let material = object.material.at(&h.uv);
+                let frame = Frame::new(h.normal, h.tangent);
+                if !material.is_delta() {
+                    if specular {
+                        landed.push((pos, wo, luminance(&beta)));
+                    }
+                    break;
+                }
+                // Light flows forward along the photon, so the BSDF directions are swapped
+                let (wi, pdf) = match material.sample_f(&frame, &wo, rng.gen(), &mut rng) {
+                    Some(sample) => sample,
+                    None => break,
+                };
+                let f = material.bsdf(&frame, &wi, &wo);
+                beta = beta.component_mul(&f) * (wo.dot(&h.normal).abs() / pdf);
+                specular = true;
+                ray = Ray {
+                    origin: pos,
+                    dir: wi,
+                };
+            }
+        }
+        CausticGrid::new(&landed)
+    }
+}
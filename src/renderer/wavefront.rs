@@ -0,0 +1,286 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rayon::prelude::*;
+
+use super::{mix_seed, power_heuristic, Renderer, Scattering};
+use crate::buffer::Buffer;
+use crate::camera::normalize_pixel;
+use crate::color::Color;
+use crate::material::Frame;
+use crate::sampler::{
+    FilterTable, SampleCursor, DIM_PIXEL_X, DIM_PIXEL_Y, DIM_TIME, SAMPLER_DIMENSIONS,
+};
+use crate::shape::{HitRecord, Ray};
+
+/// Value mixed into the seed of each pixel, so that wavefront renders do not reuse the
+/// random numbers of the recursive path tracer
+const WAVEFRONT_SEED: u64 = 0x5741_5645;
+
+/// State of a pixel that persists between the sampling passes of a wavefront render
+struct PixelState {
+    /// Position of the pixel in the image
+    x: u32,
+    y: u32,
+    /// Generator for the camera samples of the pixel
+    rng: StdRng,
+    /// Random shift of the sample sequence, as in `Renderer::get_color`
+    shift: [f64; SAMPLER_DIMENSIONS],
+}
+
+/// Paths in flight during a wavefront render, stored as a structure of arrays
+///
+/// Every array has one entry per active path. Between bounces, terminated paths are
+/// removed and the rest are sorted by the object that they hit, so that paths shading
+/// the same material are processed together.
+#[derive(Default)]
+struct PathBatch {
+    /// Index of the pixel that each path contributes to
+    pixels: Vec<usize>,
+    /// Ray currently being traced along each path
+    rays: Vec<Ray>,
+    /// Product of the camera weight and BSDF weights along each path so far
+    throughputs: Vec<Color>,
+    /// Density of the BSDF sample that generated each ray, or `None` for camera rays and
+    /// delta BSDFs
+    bsdf_pdfs: Vec<Option<f64>>,
+    /// Time in the frame at which each path is traced
+    times: Vec<f64>,
+    /// Position of each path in the sample sequence of its pixel
+    samples: Vec<SampleCursor>,
+    /// Generator for the random decisions along each path
+    rngs: Vec<StdRng>,
+}
+
+impl PathBatch {
+    fn len(&self) -> usize {
+        self.pixels.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pixels.is_empty()
+    }
+
+    fn push(
+        &mut self,
+        pixel: usize,
+        ray: Ray,
+        throughput: Color,
+        time: f64,
+        sample: SampleCursor,
+        rng: StdRng,
+    ) {
+        self.pixels.push(pixel);
+        self.rays.push(ray);
+        self.throughputs.push(throughput);
+        self.bsdf_pdfs.push(None);
+        self.times.push(time);
+        self.samples.push(sample);
+        self.rngs.push(rng);
+    }
+}
+
+impl Renderer<'_> {
+    /// Trace `num_samples` samples per pixel with the wavefront integrator
+    ///
+    /// Each pass traces one sample for every pixel, advancing all of the paths together
+    /// a bounce at a time.
+    pub(super) fn sample_wavefront(&self, buffer: &mut Buffer) {
+        let (x0, y0, x1, y1) = self.crop_bounds();
+        let base_seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        let mut pixels: Vec<PixelState> = (y0..y1)
+            .flat_map(|y| (x0..x1).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let values = [u64::from(x), u64::from(y), WAVEFRONT_SEED];
+                let mut rng = StdRng::seed_from_u64(mix_seed(base_seed, &values));
+                let shift = rng.gen();
+                PixelState { x, y, rng, shift }
+            })
+            .collect();
+        let filter = self.filter_table();
+        let mut colors = vec![glm::vec3(0.0, 0.0, 0.0); (self.width * self.height) as usize];
+        for index in 0..self.num_samples {
+            let mut batch = self.primary_paths(&mut pixels, filter, index);
+            let mut num_bounces = 0;
+            while !batch.is_empty() {
+                let (contributions, next) = self.advance_paths(batch, num_bounces);
+                for (pixel, color) in contributions {
+                    colors[pixel] += color;
+                }
+                batch = next;
+                num_bounces += 1;
+            }
+        }
+        let scale = 2.0_f64.powf(self.exposure_value) / f64::from(self.num_samples.max(1));
+        for color in &mut colors {
+            *color *= scale;
+        }
+        buffer.add_estimates(&colors, self.num_samples);
+    }
+
+    /// Generate the camera rays for one sample of every pixel
+    fn primary_paths(
+        &self,
+        pixels: &mut [PixelState],
+        filter: &FilterTable,
+        index: u32,
+    ) -> PathBatch {
+        let dim = std::cmp::max(self.width, self.height) as f64;
+        let rays: Vec<_> = pixels
+            .par_iter_mut()
+            .map(|pixel| {
+                let (xn, yn) = normalize_pixel(pixel.x, pixel.y, self.width, self.height);
+                let (index, count) = (u64::from(index), u64::from(self.num_samples));
+                let sample = SampleCursor::new(self.sampler, index, count, pixel.shift);
+                let rng = &mut pixel.rng;
+                let (offset_x, weight_x) = filter.sample(sample.get(DIM_PIXEL_X, rng));
+                let (offset_y, weight_y) = filter.sample(sample.get(DIM_PIXEL_Y, rng));
+                let (dx, dy) = (2.0 * offset_x / dim, 2.0 * offset_y / dim);
+                let time = self.row_time(pixel.y)
+                    + if self.shutter_time > 0.0 {
+                        sample.get(DIM_TIME, rng) * self.shutter_time
+                    } else {
+                        0.0
+                    };
+                let rays = self.camera.cast_rays(xn + dx, yn + dy, time, &sample, rng);
+                let rays: Vec<_> = rays
+                    .into_iter()
+                    .map(|ray| (ray, StdRng::seed_from_u64(pixel.rng.gen())))
+                    .collect();
+                let pixel_index = (pixel.y * self.width + pixel.x) as usize;
+                (pixel_index, weight_x * weight_y, time, sample, rays)
+            })
+            .collect();
+        let mut batch = PathBatch::default();
+        for (pixel, weight, time, sample, rays) in rays {
+            for ((ray, color, pdf), rng) in rays {
+                batch.push(pixel, ray, color * (weight / pdf), time, sample, rng);
+            }
+        }
+        batch
+    }
+
+    /// Intersect every path of a batch with the scene in bulk, then shade the hits
+    ///
+    /// Returns the light gathered by each path at this bounce, along with the batch of
+    /// paths that continue to the next bounce.
+    fn advance_paths(
+        &self,
+        batch: PathBatch,
+        num_bounces: u32,
+    ) -> (Vec<(usize, Color)>, PathBatch) {
+        let mut hits: Vec<Option<(HitRecord, usize)>> = batch
+            .rays
+            .par_iter()
+            .zip(batch.times.par_iter())
+            .map(|(ray, &time)| self.scene.intersect_index(*ray, time))
+            .collect();
+
+        // Sort the paths by the object they hit, for coherent shading
+        let mut order: Vec<usize> = (0..batch.len()).collect();
+        order.sort_by_key(|&i| hits[i].as_ref().map(|(_, object)| *object));
+
+        let PathBatch {
+            pixels,
+            rays,
+            throughputs,
+            bsdf_pdfs,
+            times,
+            samples,
+            rngs,
+        } = batch;
+        let mut rngs: Vec<Option<StdRng>> = rngs.into_iter().map(Some).collect();
+        let paths: Vec<_> = order
+            .into_iter()
+            .map(|i| (i, hits[i].take(), rngs[i].take().unwrap()))
+            .collect();
+        let results: Vec<_> = paths
+            .into_par_iter()
+            .map(|(i, hit, mut rng)| {
+                let (color, next) = self.shade_path(
+                    rays[i],
+                    hit,
+                    &throughputs[i],
+                    bsdf_pdfs[i],
+                    times[i],
+                    num_bounces,
+                    &samples[i],
+                    &mut rng,
+                );
+                (i, color, next, rng)
+            })
+            .collect();
+
+        let mut contributions = Vec::with_capacity(results.len());
+        let mut next_batch = PathBatch::default();
+        for (i, color, next, rng) in results {
+            contributions.push((pixels[i], throughputs[i].component_mul(&color)));
+            if let Some((ray, throughput, bsdf_pdf)) = next {
+                next_batch.push(pixels[i], ray, throughput, times[i], samples[i], rng);
+                // Continuations remember the density of their BSDF sample, for MIS
+                *next_batch.bsdf_pdfs.last_mut().unwrap() = bsdf_pdf;
+            }
+        }
+        (contributions, next_batch)
+    }
+
+    /// Shade one vertex of a path, like a single level of `trace_ray`
+    ///
+    /// Returns the light reaching the previous vertex from this one, before weighting
+    /// by the path throughput, and the ray, throughput, and BSDF density of the path's
+    /// continuation if it survives.
+    #[allow(clippy::too_many_arguments, clippy::type_complexity)]
+    fn shade_path(
+        &self,
+        ray: Ray,
+        hit: Option<(HitRecord, usize)>,
+        throughput: &Color,
+        bsdf_pdf: Option<f64>,
+        time: f64,
+        num_bounces: u32,
+        sample: &SampleCursor,
+        rng: &mut StdRng,
+    ) -> (Color, Option<(Ray, Color, Option<f64>)>) {
+        let (mut h, object) = match hit {
+            None => {
+                let color = self.scene.environment.get_color(&ray.dir);
+                let color = match bsdf_pdf {
+                    Some(pdf) => color * power_heuristic(pdf, self.scene.environment.pdf(&ray.dir)),
+                    None => color,
+                };
+                return (color, None);
+            }
+            Some((h, index)) => (h, &self.scene.objects[index]),
+        };
+        let world_pos = ray.at(h.time);
+        let wo = -glm::normalize(&ray.dir);
+        h.normal = object.material.shading_normal(&h, &wo);
+        let material = object.material.at(&h.uv);
+        let frame = Frame::new(h.normal, h.tangent);
+
+        let mut color = material.emittance * material.color;
+        let scattering = Scattering::Surface(&material, frame);
+        let last = num_bounces >= self.max_bounces;
+        let u = sample.light(num_bounces, rng);
+        color += self.sample_lights(&scattering, &world_pos, &wo, last, time, u, rng);
+        if last {
+            return (color, None);
+        }
+        let u = sample.bsdf(num_bounces, rng);
+        let (wi, pdf) = match self.sample_scattering(&material, &frame, &wo, &world_pos, u, rng) {
+            Some(sample) => sample,
+            None => return (color, None),
+        };
+        let f = material.bsdf(&frame, &wo, &wi);
+        let weight = f * wi.dot(&h.normal).abs() / pdf;
+        let throughput = throughput.component_mul(&weight);
+        let survival = self.survival(num_bounces, &throughput);
+        if survival < 1.0 && rng.gen::<f64>() >= survival {
+            return (color, None);
+        }
+        let ray = Ray {
+            origin: world_pos,
+            dir: wi,
+        };
+        let bsdf_pdf = if material.is_delta() { None } else { Some(pdf) };
+        (color, Some((ray, throughput / survival, bsdf_pdf)))
+    }
+}
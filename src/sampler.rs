@@ -0,0 +1,514 @@
+use rand::{rngs::StdRng, Rng};
+use std::f64::consts::{FRAC_1_PI, FRAC_PI_2, FRAC_PI_4};
+
+use crate::material::local_to_world;
+
+/// Dimension used for the horizontal pixel offset of a camera ray
+pub(crate) const DIM_PIXEL_X: usize = 0;
+
+/// Dimension used for the vertical pixel offset of a camera ray
+pub(crate) const DIM_PIXEL_Y: usize = 1;
+
+/// Dimension used for the shutter time of a camera ray
+pub(crate) const DIM_TIME: usize = 2;
+
+/// First of the two dimensions used for the point on the lens of a camera ray
+const DIM_LENS: usize = 3;
+
+/// First dimension used by the bounces of a path
+const DIM_BOUNCES: usize = 5;
+
+/// Dimensions used by each bounce, two for sampling the BSDF and one for choosing a light
+const DIMS_PER_BOUNCE: usize = 3;
+
+/// Number of bounces at the start of each path whose samples come from the sampler
+const SAMPLED_BOUNCES: usize = 2;
+
+/// Number of sample dimensions supported by the low-discrepancy samplers
+pub(crate) const SAMPLER_DIMENSIONS: usize = DIM_BOUNCES + SAMPLED_BOUNCES * DIMS_PER_BOUNCE;
+
+const PRIMES: [u64; SAMPLER_DIMENSIONS] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31];
+
+/// Sobol primitive polynomials and initial direction numbers (s, a, m), for dimensions
+/// after the first, from Joe & Kuo's `new-joe-kuo-6.21201` table
+const SOBOL_PARAMS: [(u32, u32, [u32; 5]); SAMPLER_DIMENSIONS - 1] = [
+    (1, 0, [1, 0, 0, 0, 0]),
+    (2, 1, [1, 3, 0, 0, 0]),
+    (3, 1, [1, 3, 1, 0, 0]),
+    (3, 2, [1, 1, 1, 0, 0]),
+    (4, 1, [1, 1, 3, 3, 0]),
+    (4, 4, [1, 3, 5, 13, 0]),
+    (5, 2, [1, 1, 5, 5, 17]),
+    (5, 4, [1, 1, 5, 5, 5]),
+    (5, 7, [1, 1, 7, 11, 19]),
+    (5, 11, [1, 1, 5, 1, 1]),
+];
+
+/// Strategy for generating the numbers that drive each pixel sample
+///
+/// The sampler determines the pixel offset, shutter time, and lens position of each camera
+/// ray, along with the BSDF sample and light choice at the first few bounces of its path
+/// (see `SampleCursor`). Samples are indexed by their number within the pixel, so that
+/// low-discrepancy sequences stay stratified across calls to `iterative_render`. Each pixel
+/// applies its own random (Cranley-Patterson) rotation to the sequence to avoid correlation
+/// between pixels. Later bounces, and other decisions along a path, are drawn from the
+/// pixel's random number generator.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Sampler {
+    /// Independent uniform random numbers
+    Random,
+
+    /// Stratified pixel offsets, with one jittered sample in each cell of a
+    /// `sqrt(n)` by `sqrt(n)` grid over the pixel, for `n` samples per pixel
+    ///
+    /// The grid covers the samples of each pass, so with `iterative_render`, `n` is the
+    /// number of samples between callbacks. Other dimensions, and pixel offsets when `n`
+    /// is not a perfect square, are independent uniform random numbers.
+    #[default]
+    Stratified,
+
+    /// The Halton sequence, using consecutive prime bases for each dimension
+    Halton,
+
+    /// The Sobol sequence
+    Sobol,
+}
+
+impl Sampler {
+    /// Returns dimension `dim` of sample `index` out of `count`, rotated by `shift`, in [0, 1)
+    pub(crate) fn get(
+        &self,
+        index: u64,
+        count: u64,
+        dim: usize,
+        shift: f64,
+        rng: &mut StdRng,
+    ) -> f64 {
+        let value = match self {
+            Self::Random => return rng.gen(),
+            Self::Stratified => return stratified(index, count, dim, rng),
+            Self::Halton => radical_inverse(PRIMES[dim], index),
+            Self::Sobol => sobol(index, dim),
+        };
+        (value + shift).fract()
+    }
+}
+
+/// Position of one camera path in the sample sequence of its pixel, which hands out the
+/// numbers for each decision along the path from a fixed dimension of the sequence
+///
+/// Fixed dimensions keep every decision stratified across the samples of a pixel, no
+/// matter how many random numbers earlier decisions used. Decisions past the dimensions
+/// of the sampler are drawn from the random number generator instead.
+#[derive(Copy, Clone, Debug)]
+pub struct SampleCursor {
+    sampler: Sampler,
+    index: u64,
+    count: u64,
+    shift: [f64; SAMPLER_DIMENSIONS],
+}
+
+impl SampleCursor {
+    /// Cursor for sample `index` out of `count` of a pixel, whose sequence is rotated by
+    /// `shift`
+    pub(crate) fn new(
+        sampler: Sampler,
+        index: u64,
+        count: u64,
+        shift: [f64; SAMPLER_DIMENSIONS],
+    ) -> Self {
+        Self {
+            sampler,
+            index,
+            count,
+            shift,
+        }
+    }
+
+    /// Cursor that draws every number from the random number generator, for rays that
+    /// are cast outside of a render
+    pub fn random() -> Self {
+        Self::new(Sampler::Random, 0, 1, [0.0; SAMPLER_DIMENSIONS])
+    }
+
+    /// Returns dimension `dim` of the sample, in [0, 1)
+    pub(crate) fn get(&self, dim: usize, rng: &mut StdRng) -> f64 {
+        if dim >= SAMPLER_DIMENSIONS {
+            return rng.gen();
+        }
+        self.sampler
+            .get(self.index, self.count, dim, self.shift[dim], rng)
+    }
+
+    fn get_2d(&self, dim: usize, rng: &mut StdRng) -> [f64; 2] {
+        [self.get(dim, rng), self.get(dim + 1, rng)]
+    }
+
+    /// Point in the unit square for sampling the lens or aperture of a camera
+    pub fn lens(&self, rng: &mut StdRng) -> [f64; 2] {
+        self.get_2d(DIM_LENS, rng)
+    }
+
+    /// Point in the unit square for sampling the BSDF at a bounce of the path
+    pub(crate) fn bsdf(&self, num_bounces: u32, rng: &mut StdRng) -> [f64; 2] {
+        self.get_2d(bounce_dim(num_bounces), rng)
+    }
+
+    /// Number in [0, 1) for choosing a light to sample at a bounce of the path
+    pub(crate) fn light(&self, num_bounces: u32, rng: &mut StdRng) -> f64 {
+        self.get(bounce_dim(num_bounces) + 2, rng)
+    }
+}
+
+/// First dimension used by a bounce of a path
+fn bounce_dim(num_bounces: u32) -> usize {
+    DIM_BOUNCES + (num_bounces as usize).saturating_mul(DIMS_PER_BOUNCE)
+}
+
+/// Number of bins in the tabulated distribution used to sample pixel filters
+const FILTER_TABLE_SIZE: usize = 64;
+
+/// Reconstruction filter that weights the camera samples around each pixel
+///
+/// Filters are separable, and given in units of pixels. Sample offsets from the pixel
+/// center are drawn in proportion to the magnitude of the filter, so samples reach into
+/// neighboring pixels and each pixel blends in a little of its surroundings, with weights
+/// that fall off with distance. Filters with negative lobes weight those samples
+/// negatively, which sharpens the image.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum PixelFilter {
+    /// Uniform weight within the pixel, which is the least smooth
+    #[default]
+    Box,
+
+    /// Weight falling off linearly to zero at a distance of one pixel
+    Tent,
+
+    /// Gaussian with a standard deviation in pixels, truncated at three deviations
+    Gaussian(f64),
+
+    /// Mitchell-Netravali cubic with B = C = 1/3, over a radius of two pixels
+    MitchellNetravali,
+}
+
+impl PixelFilter {
+    /// Distance from the pixel center, in pixels, beyond which the filter is zero
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Self::Box => 0.5,
+            Self::Tent => 1.0,
+            Self::Gaussian(sigma) => 3.0 * sigma,
+            Self::MitchellNetravali => 2.0,
+        }
+    }
+
+    /// Weight of the filter at an offset along one axis, in pixels
+    pub fn weight(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x > self.radius() {
+            return 0.0;
+        }
+        match *self {
+            Self::Box => 1.0,
+            Self::Tent => 1.0 - x,
+            Self::Gaussian(sigma) => (-x * x / (2.0 * sigma * sigma)).exp(),
+            Self::MitchellNetravali => {
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+                let w = if x < 1.0 {
+                    (12.0 - 9.0 * b - 6.0 * c) * x.powi(3)
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b)
+                } else {
+                    (-b - 6.0 * c) * x.powi(3)
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c)
+                };
+                w / 6.0
+            }
+        }
+    }
+
+    /// Tabulate the filter for sampling offsets
+    pub(crate) fn table(&self) -> FilterTable {
+        let radius = self.radius();
+        let width = 2.0 * radius / FILTER_TABLE_SIZE as f64;
+        let values: Vec<f64> = (0..FILTER_TABLE_SIZE)
+            .map(|i| self.weight(-radius + (i as f64 + 0.5) * width))
+            .collect();
+        let mut cdf = Vec::with_capacity(FILTER_TABLE_SIZE);
+        let mut total = 0.0;
+        for value in &values {
+            total += value.abs();
+            cdf.push(total);
+        }
+        let integral = values.iter().sum::<f64>() * width;
+        FilterTable {
+            filter: *self,
+            radius,
+            cdf,
+            // Density of the offset in a bin is |f| / norm
+            norm: total * width,
+            integral,
+            values,
+        }
+    }
+}
+
+/// Distribution for sampling offsets from a `PixelFilter`, with piecewise-constant
+/// density in proportion to the magnitude of the filter
+#[derive(Clone, Debug)]
+pub(crate) struct FilterTable {
+    filter: PixelFilter,
+    radius: f64,
+    values: Vec<f64>,
+    cdf: Vec<f64>,
+    norm: f64,
+    integral: f64,
+}
+
+impl FilterTable {
+    /// Map a uniform sample in [0, 1) to an offset from the pixel center, in pixels,
+    /// returning it with its sample weight
+    ///
+    /// The weight is the filter divided by the sampling density, normalized so that its
+    /// expected value is one. The box filter is sampled exactly, with a weight of one.
+    pub(crate) fn sample(&self, u: f64) -> (f64, f64) {
+        if self.filter == PixelFilter::Box {
+            return (u - 0.5, 1.0);
+        }
+        let target = u * self.cdf[self.cdf.len() - 1];
+        let bin = self
+            .cdf
+            .partition_point(|&c| c <= target)
+            .min(self.cdf.len() - 1);
+        let start = if bin == 0 { 0.0 } else { self.cdf[bin - 1] };
+        let fraction = (target - start) / (self.cdf[bin] - start);
+        let width = 2.0 * self.radius / self.cdf.len() as f64;
+        let x = -self.radius + (bin as f64 + fraction) * width;
+        let pdf = self.values[bin].abs() / self.norm;
+        (x, self.filter.weight(x) / (pdf * self.integral))
+    }
+}
+
+/// Map a point of the unit square onto the unit disc, preserving area
+///
+/// This is Shirley and Chiu's concentric mapping, which keeps nearby points close, so
+/// stratified points of the square stay stratified on the disc.
+pub(crate) fn sample_disc([u, v]: [f64; 2]) -> [f64; 2] {
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return [0.0, 0.0];
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, FRAC_PI_2 - FRAC_PI_4 * (a / b))
+    };
+    [r * theta.cos(), r * theta.sin()]
+}
+
+/// Sample a direction from the cosine-weighted hemisphere about a unit normal, given a
+/// point `u` in the unit square, returning it with its density `cos θ / π` with respect
+/// to solid angle
+///
+/// Directions are generated with Malley's method, by projecting a uniform point on the
+/// unit disc up onto the hemisphere. For a Lambertian BSDF, the weight `f cos θ / pdf`
+/// of a sample is then exactly the albedo.
+pub fn sample_cosine_hemisphere(n: &glm::DVec3, u: [f64; 2]) -> (glm::DVec3, f64) {
+    let [x, y] = sample_disc(u);
+    let z = (1.0_f64 - x * x - y * y).max(0.0).sqrt();
+    (local_to_world(n) * glm::vec3(x, y, z), z * FRAC_1_PI)
+}
+
+/// Dimension `dim` of a sample jittered within stratum `index` of a square grid of
+/// `count` strata, or a uniform random number if there is no such grid
+fn stratified(index: u64, count: u64, dim: usize, rng: &mut StdRng) -> f64 {
+    let n = (count as f64).sqrt().round() as u64;
+    let jitter: f64 = rng.gen();
+    if n * n != count || n < 2 || (dim != DIM_PIXEL_X && dim != DIM_PIXEL_Y) {
+        return jitter;
+    }
+    let stratum = index % count;
+    let cell = if dim == DIM_PIXEL_X {
+        stratum % n
+    } else {
+        stratum / n
+    };
+    ((cell as f64 + jitter) / n as f64).min(1.0 - f64::EPSILON)
+}
+
+/// Radical inverse of an integer in a given base
+fn radical_inverse(base: u64, mut index: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_n = 1.0;
+    let mut reversed = 0;
+    while index > 0 {
+        let next = index / base;
+        reversed = reversed * base + (index - next * base);
+        inv_base_n *= inv_base;
+        index = next;
+    }
+    (reversed as f64 * inv_base_n).min(1.0 - f64::EPSILON)
+}
+
+/// Direction numbers of the Sobol sequence in each dimension
+const SOBOL_DIRECTIONS: [[u32; 32]; SAMPLER_DIMENSIONS] = sobol_directions();
+
+/// Derive the direction numbers of each dimension from the parameters of its primitive
+/// polynomial, at compile time
+const fn sobol_directions() -> [[u32; 32]; SAMPLER_DIMENSIONS] {
+    let mut directions = [[0u32; 32]; SAMPLER_DIMENSIONS];
+    let mut k = 0;
+    while k < 32 {
+        directions[0][k] = 1 << (31 - k);
+        k += 1;
+    }
+    let mut dim = 1;
+    while dim < SAMPLER_DIMENSIONS {
+        let (s, a, m) = SOBOL_PARAMS[dim - 1];
+        let s = s as usize;
+        let mut k = 0;
+        while k < 32 {
+            directions[dim][k] = if k < s {
+                m[k] << (31 - k)
+            } else {
+                let mut v = directions[dim][k - s] ^ (directions[dim][k - s] >> s);
+                let mut j = 1;
+                while j < s {
+                    if (a >> (s - 1 - j)) & 1 == 1 {
+                        v ^= directions[dim][k - j];
+                    }
+                    j += 1;
+                }
+                v
+            };
+            k += 1;
+        }
+        dim += 1;
+    }
+    directions
+}
+
+/// Dimension `dim` of the `index`-th point of the Sobol sequence
+fn sobol(index: u64, dim: usize) -> f64 {
+    let directions = &SOBOL_DIRECTIONS[dim];
+    let mut result = 0u32;
+    let mut index = index as u32;
+    let mut k = 0;
+    while index > 0 {
+        if index & 1 == 1 {
+            result ^= directions[k];
+        }
+        index >>= 1;
+        k += 1;
+    }
+    result as f64 / (1u64 << 32) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequences_are_stratified() {
+        assert_eq!(radical_inverse(2, 1), 0.5);
+        assert_eq!(radical_inverse(2, 3), 0.75);
+        assert!((radical_inverse(3, 4) - 4.0 / 9.0).abs() < 1e-12);
+        for dim in 0..SAMPLER_DIMENSIONS {
+            // The first 2^k Sobol points fall in distinct intervals of width 2^-k
+            let mut seen = [false; 16];
+            for index in 0..16 {
+                let value = sobol(index, dim);
+                assert!((0.0..1.0).contains(&value));
+                seen[(value * 16.0) as usize] = true;
+            }
+            assert!(seen.iter().all(|&s| s));
+        }
+    }
+
+    #[test]
+    fn stratified_offsets_cover_every_stratum() {
+        use rand::SeedableRng;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut seen = [[false; 4]; 4];
+        for index in 0..16 {
+            let x = Sampler::Stratified.get(index, 16, DIM_PIXEL_X, 0.0, &mut rng);
+            let y = Sampler::Stratified.get(index, 16, DIM_PIXEL_Y, 0.0, &mut rng);
+            assert!((0.0..1.0).contains(&x) && (0.0..1.0).contains(&y));
+            seen[(y * 4.0) as usize][(x * 4.0) as usize] = true;
+        }
+        assert!(seen.iter().flatten().all(|&s| s));
+
+        // Counts that are not perfect squares fall back to independent samples
+        let mut fallback = StdRng::seed_from_u64(1);
+        let value = Sampler::Stratified.get(3, 10, DIM_PIXEL_X, 0.0, &mut fallback);
+        assert_eq!(value, StdRng::seed_from_u64(1).gen::<f64>());
+    }
+
+    #[test]
+    fn cursor_stratifies_each_decision() {
+        use rand::SeedableRng;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let shift = [0.0; SAMPLER_DIMENSIONS];
+        for bounce in 0..SAMPLED_BOUNCES as u32 {
+            let mut seen = [[false; 4]; 4];
+            let mut lights = [false; 16];
+            for index in 0..16 {
+                let sample = SampleCursor::new(Sampler::Sobol, index, 16, shift);
+                // Numbers drawn for earlier decisions do not shift later ones
+                let _ = sample.lens(&mut rng);
+                let [u, v] = sample.bsdf(bounce, &mut rng);
+                seen[(v * 4.0) as usize][(u * 4.0) as usize] = true;
+                lights[(sample.light(bounce, &mut rng) * 16.0) as usize] = true;
+            }
+            assert!(seen.iter().flatten().all(|&s| s));
+            assert!(lights.iter().all(|&s| s));
+        }
+
+        // Bounces past the dimensions of the sampler draw from the generator
+        let sample = SampleCursor::new(Sampler::Sobol, 5, 16, shift);
+        let value = sample.light(SAMPLED_BOUNCES as u32, &mut StdRng::seed_from_u64(1));
+        assert_eq!(value, StdRng::seed_from_u64(1).gen::<f64>());
+    }
+
+    #[test]
+    fn concentric_disc_mapping_covers_the_disc() {
+        assert_eq!(sample_disc([0.5, 0.5]), [0.0, 0.0]);
+        let [x, y] = sample_disc([1.0, 0.5]);
+        assert!((x - 1.0).abs() < 1e-12 && y.abs() < 1e-12);
+        // Equal areas of the square map to equal areas of the disc
+        let n = 64;
+        let inner = (0..n * n)
+            .map(|i| sample_disc([(i % n) as f64 / n as f64, (i / n) as f64 / n as f64]))
+            .filter(|[x, y]| x * x + y * y < 0.25)
+            .count();
+        assert!((inner as f64 / (n * n) as f64 - 0.25).abs() < 0.02);
+    }
+
+    #[test]
+    fn cosine_hemisphere_matches_pdf() {
+        use rand::SeedableRng;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let n = glm::normalize(&glm::vec3(1.0, -2.0, 0.5));
+        let (samples, bins) = (100_000, 10);
+        let mut counts = vec![0; bins];
+        for _ in 0..samples {
+            let (wi, pdf) = sample_cosine_hemisphere(&n, rng.gen());
+            let cos = wi.dot(&n);
+            assert!((wi.magnitude() - 1.0).abs() < 1e-9);
+            assert!((pdf - cos * FRAC_1_PI).abs() < 1e-9);
+            counts[((cos * bins as f64) as usize).min(bins - 1)] += 1;
+        }
+        // Integrating the pdf over a band of the hemisphere gives the change in cos²θ
+        for (i, &count) in counts.iter().enumerate() {
+            let (lo, hi) = (i as f64 / bins as f64, (i + 1) as f64 / bins as f64);
+            let expected = (hi * hi - lo * lo) * samples as f64;
+            let fraction = count as f64 / expected;
+            assert!((fraction - 1.0).abs() < 0.05, "bin {}: {}", i, fraction);
+        }
+    }
+}
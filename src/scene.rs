@@ -1,11 +1,24 @@
+use std::sync::OnceLock;
+
+use crate::bvh::Bvh;
 use crate::environment::Environment;
+use crate::kdtree::BoundingBox;
 use crate::light::Light;
+use crate::medium::Medium;
 use crate::object::Object;
+use crate::shape::{HitRecord, Ray};
+
+/// Minimum ray parameter for intersections, to avoid self-intersection
+const EPSILON: f64 = 1e-12;
 
 /// Object representing a scene that can be rendered
 #[derive(Default)]
 pub struct Scene {
     /// Collection of objects in the scene
+    ///
+    /// Objects added with `add` or `add_many` are picked up automatically, but after
+    /// modifying this directly, call `build_accel` before rendering. Objects pushed here
+    /// directly are also not displaced by their materials (see `Object::displaced`).
     pub objects: Vec<Object>,
 
     /// Collection of lights in the scene
@@ -13,6 +26,27 @@ pub struct Scene {
 
     /// Environment map used for scene lighting
     pub environment: Environment,
+
+    /// Participating medium that fills the space between objects, such as fog
+    ///
+    /// The medium extends to infinity, so no light reaches the scene from the
+    /// environment or from directional lights while it is set.
+    pub medium: Option<Medium>,
+
+    /// Partition of the objects for intersection, built on first use
+    accel: OnceLock<SceneAccel>,
+}
+
+/// Objects of a scene, partitioned for intersection
+struct SceneAccel {
+    /// Hierarchy over the objects with finite bounds
+    bvh: Bvh,
+
+    /// Objects that have infinite extent, which are intersected one by one
+    unbounded: Vec<usize>,
+
+    /// Number of objects in the scene when this was built
+    num_objects: usize,
 }
 
 impl Scene {
@@ -20,6 +54,124 @@ impl Scene {
     pub fn new() -> Self {
         Default::default()
     }
+
+    /// Set the environment map used for scene lighting
+    pub fn set_environment(&mut self, environment: Environment) {
+        self.environment = environment;
+    }
+
+    /// Add every object from an iterator to the scene
+    pub fn add_many(&mut self, objects: impl IntoIterator<Item = Object>) {
+        self.accel.take();
+        self.objects
+            .extend(objects.into_iter().map(Object::displaced));
+    }
+
+    /// Build an acceleration structure over the objects of the scene
+    ///
+    /// Objects whose shapes have finite bounds (see `Shape::bounds`) are put into a
+    /// `Bvh`, while unbounded shapes like planes are still checked one at a time.
+    ///
+    /// This happens automatically on the first intersection after objects are added with
+    /// `add` or `add_many`. After modifying `objects` directly, such as replacing, moving,
+    /// or removing an object, it must be called again, as the old structure would miss
+    /// or misplace hits.
+    pub fn build_accel(&mut self) {
+        self.accel = OnceLock::from(self.partition());
+    }
+
+    /// Partition the objects into a hierarchy of bounded ones and a list of the rest
+    fn partition(&self) -> SceneAccel {
+        let mut bounded = Vec::new();
+        let mut unbounded = Vec::new();
+        for (index, object) in self.objects.iter().enumerate() {
+            match object_bounds(object) {
+                Some(bbox) => bounded.push((index, bbox)),
+                None => unbounded.push(index),
+            }
+        }
+        SceneAccel {
+            bvh: Bvh::new(bounded),
+            unbounded,
+            num_objects: self.objects.len(),
+        }
+    }
+
+    /// Find the closest hit of a ray with the objects in the scene.
+    ///
+    /// Bounded objects are found through the acceleration structure, and unbounded
+    /// objects are tested linearly after it. The structure is only rebuilt automatically
+    /// for objects added with `add` or `add_many` (see `build_accel`).
+    ///
+    /// Panics if the number of objects changed directly since the structure was built,
+    /// which is the one kind of stale structure that can be detected.
+    ///
+    /// Moving objects are intersected at their position at the given time in the frame.
+    pub fn intersect(&self, ray: Ray, time: f64) -> Option<(HitRecord, &'_ Object)> {
+        let (h, index) = self.intersect_index(ray, time)?;
+        Some((h, &self.objects[index]))
+    }
+
+    /// Find the closest hit of a ray, like `intersect`, along with the index of the
+    /// object that was hit in `objects`
+    pub fn intersect_index(&self, ray: Ray, time: f64) -> Option<(HitRecord, usize)> {
+        let mut h = HitRecord::new();
+        let intersect_object = |index: usize, h: &mut HitRecord| {
+            let object = &self.objects[index];
+            let local_ray = match object.motion {
+                Some(motion) => Ray {
+                    origin: ray.origin - motion.at(time),
+                    dir: ray.dir,
+                },
+                None => ray,
+            };
+            // Only triangles set barycentric coordinates, so clear them for other shapes
+            let previous = *h;
+            h.barycentric = None;
+            let mut t_min = EPSILON;
+            loop {
+                if !object.shape.intersect(&local_ray, t_min, h) {
+                    *h = previous;
+                    return false;
+                }
+                if !object.material.is_cut_out(&h.uv) {
+                    return true;
+                }
+                // Keep searching the same object past the hole in its opacity mask
+                t_min = h.time.next_up();
+                *h = previous;
+                h.barycentric = None;
+            }
+        };
+        let accel = self.accel.get_or_init(|| self.partition());
+        assert_eq!(
+            accel.num_objects,
+            self.objects.len(),
+            "Scene objects were modified directly without calling `build_accel`"
+        );
+        let mut hit = accel.bvh.intersect(&ray, EPSILON, &mut h, intersect_object);
+        for &index in &accel.unbounded {
+            if intersect_object(index, &mut h) {
+                hit = Some(index);
+            }
+        }
+        Some((h, hit?))
+    }
+}
+
+/// Bounding box of an object, covering its whole path if it is moving
+fn object_bounds(object: &Object) -> Option<BoundingBox> {
+    let bbox = object.shape.bounds()?;
+    Some(match object.motion {
+        Some(motion) => {
+            let at = |offset: glm::DVec3| BoundingBox {
+                p_min: bbox.p_min + offset,
+                p_max: bbox.p_max + offset,
+            };
+            at(motion.start).merge(&at(motion.end))
+        }
+        None => bbox,
+    })
 }
 
 /// Trait that allows adding an object or light to a scene
@@ -30,7 +182,8 @@ pub trait SceneAdd<T> {
 
 impl SceneAdd<Object> for Scene {
     fn add(&mut self, object: Object) {
-        self.objects.push(object);
+        self.accel.take();
+        self.objects.push(object.displaced());
     }
 }
 
@@ -39,3 +192,244 @@ impl SceneAdd<Light> for Scene {
         self.lights.push(light);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::shape::{plane, sphere, Transformable};
+
+    /// Scene of random spheres, with an optional plane cutting through them
+    fn random_scene(rng: &mut StdRng, with_plane: bool) -> Scene {
+        let mut scene = Scene::new();
+        if with_plane {
+            scene.add(Object::new(plane(glm::vec3(0.0, 0.0, 1.0), 5.0)));
+        }
+        for _ in 0..500 {
+            let radius = rng.gen_range(0.05..0.45);
+            let center = glm::vec3(rng.gen(), rng.gen(), rng.gen()) * 10.0;
+            scene.add(Object::new(
+                sphere()
+                    .scale(&glm::vec3(radius, radius, radius))
+                    .translate(&center),
+            ));
+        }
+        scene
+    }
+
+    /// Compare intersections of the scene against testing every object in order
+    fn check_against_brute_force(scene: &Scene, rng: &mut StdRng) -> usize {
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = glm::vec3(rng.gen(), rng.gen(), rng.gen()) * 14.0;
+            let dir = glm::vec3(rng.gen(), rng.gen(), rng.gen()) * 2.0;
+            let ray = Ray {
+                origin: origin - glm::vec3(2.0, 2.0, 2.0),
+                dir: (dir - glm::vec3(1.0, 1.0, 1.0)).normalize(),
+            };
+            let mut expected = HitRecord::new();
+            let mut expected_object = None;
+            for object in &scene.objects {
+                if object.shape.intersect(&ray, EPSILON, &mut expected) {
+                    expected_object = Some(object as *const Object);
+                }
+            }
+            let actual = scene.intersect(ray, 0.0);
+            assert_eq!(
+                actual.as_ref().map(|(_, o)| *o as *const _),
+                expected_object
+            );
+            if let Some((h, _)) = actual {
+                assert_eq!(h.time, expected.time);
+                assert_eq!(h.normal, expected.normal);
+                hits += 1;
+            }
+        }
+        hits
+    }
+
+    #[test]
+    fn bvh_matches_brute_force() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut scene = random_scene(&mut rng, false);
+        scene.build_accel();
+        assert_eq!(scene.accel.get().unwrap().bvh.len(), 500);
+        assert!(check_against_brute_force(&scene, &mut rng) > 100);
+    }
+
+    #[test]
+    fn rebuilding_after_direct_edits_finds_new_objects() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut scene = random_scene(&mut rng, false);
+        assert!(check_against_brute_force(&scene, &mut rng) > 100);
+
+        // Replacing objects without changing their count needs an explicit rebuild
+        for object in scene.objects.iter_mut().step_by(2) {
+            let center = glm::vec3(rng.gen(), rng.gen(), rng.gen()) * 10.0;
+            *object = Object::new(sphere().scale(&glm::vec3(0.3, 0.3, 0.3)).translate(&center));
+        }
+        scene.build_accel();
+        assert!(check_against_brute_force(&scene, &mut rng) > 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "without calling `build_accel`")]
+    fn stale_accel_after_removing_objects_panics() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut scene = random_scene(&mut rng, false);
+        check_against_brute_force(&scene, &mut rng);
+        scene.objects.truncate(100);
+        check_against_brute_force(&scene, &mut rng);
+    }
+
+    #[test]
+    fn unbounded_objects_are_tested_separately() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let scene = random_scene(&mut rng, true);
+        assert!(check_against_brute_force(&scene, &mut rng) > 1000);
+        let accel = scene.accel.get().unwrap();
+        assert_eq!(accel.bvh.len(), 500);
+        assert_eq!(accel.unbounded, vec![0]);
+    }
+
+    #[test]
+    fn opacity_cutout_passes_rays_through() {
+        use crate::material::Material;
+        use crate::shape::{Mesh, Triangle};
+        use crate::texture::Texture;
+
+        // Unit quad in the z = 0 plane, with UVs matching x and y
+        let quad = |z: f64, uv: [glm::DVec2; 4]| {
+            let p = |x: f64, y: f64| glm::vec3(x, y, z);
+            vec![
+                Triangle::from_vertices(p(0.0, 0.0), p(1.0, 0.0), p(1.0, 1.0))
+                    .uvs(uv[0], uv[1], uv[2]),
+                Triangle::from_vertices(p(0.0, 0.0), p(1.0, 1.0), p(0.0, 1.0))
+                    .uvs(uv[0], uv[2], uv[3]),
+            ]
+        };
+        let front = quad(
+            0.0,
+            [
+                glm::vec2(0.0, 0.0),
+                glm::vec2(1.0, 0.0),
+                glm::vec2(1.0, 1.0),
+                glm::vec2(0.0, 1.0),
+            ],
+        );
+        // A second quad behind the first, in the same mesh, that is opaque everywhere
+        let back = quad(-1.0, [glm::vec2(0.75, 0.25); 4]);
+        let checker = Texture::Checker(glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 1.0, 1.0), 2.0);
+
+        let mut scene = Scene::new();
+        scene.add(
+            Object::new(Mesh::new(front.into_iter().chain(back).collect()))
+                .material(Material::diffuse(glm::vec3(1.0, 1.0, 1.0)).opacity(checker)),
+        );
+        let mut passed = 0;
+        for i in 0..4 {
+            for j in 0..4 {
+                let (x, y) = ((i as f64 + 0.5) / 4.0, (j as f64 + 0.5) / 4.0);
+                let ray = Ray {
+                    origin: glm::vec3(x, y, 1.0),
+                    dir: glm::vec3(0.0, 0.0, -1.0),
+                };
+                let (hit, _) = scene.intersect(ray, 0.0).unwrap();
+                let transparent = ((2.0 * x).floor() + (2.0 * y).floor()) % 2.0 == 0.0;
+                if transparent {
+                    assert_eq!(hit.time, 2.0);
+                    assert_eq!(hit.uv, glm::vec2(0.75, 0.25));
+                    passed += 1;
+                } else {
+                    assert_eq!(hit.time, 1.0);
+                    assert_eq!(hit.uv, glm::vec2(x, y));
+                }
+            }
+        }
+        assert_eq!(passed, 8);
+    }
+
+    #[test]
+    fn add_many_places_transformed_batches() {
+        use crate::object::transform_objects;
+
+        let mut scene = Scene::new();
+        scene.add(Object::new(sphere()));
+        // Three unit spheres along the x axis, moved up as a group
+        let batch = (0..3).map(|i| {
+            Object::new(sphere().translate(&glm::vec3(3.0 * i as f64, 0.0, 0.0)))
+                .motion(glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 0.0, 0.0))
+        });
+        let offset = glm::translate(&glm::identity(), &glm::vec3(0.0, 10.0, 0.0));
+        let scale = glm::scale(&glm::identity(), &glm::vec3(2.0, 2.0, 2.0));
+        scene.add_many(transform_objects(batch, offset * scale));
+        assert_eq!(scene.objects.len(), 4);
+
+        for i in 0..3 {
+            let ray = Ray {
+                origin: glm::vec3(6.0 * i as f64, 20.0, 0.0),
+                dir: glm::vec3(0.0, -1.0, 0.0),
+            };
+            let (hit, index) = scene.intersect_index(ray, 0.0).unwrap();
+            assert_eq!(index, i + 1);
+            assert!((hit.time - 8.0).abs() < 1e-9);
+            // The motion is scaled along with the objects
+            let motion = scene.objects[index].motion.unwrap();
+            assert_eq!(motion.end, glm::vec3(2.0, 0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn displacement_applies_to_added_meshes_and_monomial_surfaces() {
+        use crate::shape::{monomial_surface, Mesh, Triangle};
+        use crate::Material;
+
+        let down = |x: f64, z: f64| Ray {
+            origin: glm::vec3(x, 10.0, z),
+            dir: glm::vec3(0.0, -1.0, 0.0),
+        };
+        let height = |scene: &Scene, x: f64, z: f64| {
+            let ray = down(x, z);
+            ray.at(scene.intersect(ray, 0.0).unwrap().0.time).y
+        };
+
+        // A white height map raises a unit square by the scale in world units, even
+        // though the mesh is scaled up
+        let (a, b, c, d) = (
+            glm::vec3(0.0, 0.0, 0.0),
+            glm::vec3(0.0, 0.0, 1.0),
+            glm::vec3(1.0, 0.0, 1.0),
+            glm::vec3(1.0, 0.0, 0.0),
+        );
+        let square = Mesh::new(vec![
+            Triangle::from_vertices(a, b, c).uvs(a.xz(), b.xz(), c.xz()),
+            Triangle::from_vertices(a, c, d).uvs(a.xz(), c.xz(), d.xz()),
+        ]);
+        let white = Material::diffuse(glm::vec3(0.5, 0.5, 0.5))
+            .displacement(glm::vec3(1.0, 1.0, 1.0), 0.25);
+        let mut scene = Scene::new();
+        scene.add(Object::new(square.scale(&glm::vec3(2.0, 2.0, 2.0))).material(white.clone()));
+        assert!((height(&scene, 1.3, 0.6) - 0.25).abs() < 1e-9);
+
+        // Shapes without triangles are not displaced
+        scene.add(Object::new(sphere().translate(&glm::vec3(5.0, 0.0, 0.0))).material(white));
+        assert!((height(&scene, 5.0, 0.0) - 1.0).abs() < 1e-9);
+
+        // A dome, with a height map that is black for x < 0 and white for x > 0
+        let image = image::RgbImage::from_fn(8, 8, |x, _| {
+            if x < 4 {
+                image::Rgb([0, 0, 0])
+            } else {
+                image::Rgb([255, 255, 255])
+            }
+        });
+        let material = Material::diffuse(glm::vec3(0.5, 0.5, 0.5)).displacement(image, 0.1);
+        let dome = monomial_surface(0.5, 2.0).rotate_x(std::f64::consts::PI);
+        let mut scene = Scene::new();
+        scene.add(Object::new(dome).material(material));
+        let (dark, bright) = (height(&scene, -0.3, 0.2), height(&scene, 0.3, 0.2));
+        assert!((dark + 0.5 * 0.13).abs() < 1e-3, "{}", dark);
+        assert!(bright + 0.5 * 0.13 > 0.09, "{}", bright);
+    }
+}
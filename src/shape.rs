@@ -2,13 +2,19 @@ use rand::rngs::StdRng;
 use std::sync::Arc;
 
 use crate::kdtree::{Bounded, BoundingBox};
+pub use cone::Cone;
 pub use cube::Cube;
+pub use cylinder::Cylinder;
+pub use disk::Disk;
 pub use mesh::{Mesh, Triangle};
 pub use monomial_surface::MonomialSurface;
 pub use plane::Plane;
 pub use sphere::Sphere;
 
+mod cone;
 mod cube;
+mod cylinder;
+mod disk;
 mod mesh;
 mod monomial_surface;
 mod plane;
@@ -18,10 +24,29 @@ mod sphere;
 pub trait Shape: Send + Sync {
     /// Intersect the shape with a ray, for `t >= t_min`, returning true and mutating
     /// `h` if an intersection was found before the current closest one
+    ///
+    /// The time of the current closest hit, `record.time`, bounds the search from above,
+    /// so shapes should skip any part of themselves that lies entirely beyond it.
     fn intersect(&self, ray: &Ray, t_min: f64, record: &mut HitRecord) -> bool;
 
     /// Sample the shape for a random point on its surface, also returning the normal and PDF
     fn sample(&self, target: &glm::DVec3, rng: &mut StdRng) -> (glm::DVec3, glm::DVec3, f64);
+
+    /// Returns the shape's bounding box, or `None` if it has infinite extent
+    ///
+    /// Bounded shapes should override this, so that scenes can accelerate them.
+    fn bounds(&self) -> Option<BoundingBox> {
+        None
+    }
+
+    /// Returns the triangles that make up the shape, or that approximate it for curved
+    /// shapes that can be tessellated, or `None` if it has no triangle form
+    ///
+    /// Scenes use this to displace the surfaces of objects by the height maps of their
+    /// materials (see `Material::displacement`).
+    fn triangles(&self) -> Option<Vec<Triangle>> {
+        None
+    }
 }
 
 impl<T: Shape + ?Sized> Shape for Box<T> {
@@ -32,6 +57,14 @@ impl<T: Shape + ?Sized> Shape for Box<T> {
     fn sample(&self, target: &glm::DVec3, rng: &mut StdRng) -> (glm::DVec3, glm::DVec3, f64) {
         self.as_ref().sample(target, rng)
     }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        self.as_ref().bounds()
+    }
+
+    fn triangles(&self) -> Option<Vec<Triangle>> {
+        self.as_ref().triangles()
+    }
 }
 
 impl<T: Shape + ?Sized> Shape for Arc<T> {
@@ -42,6 +75,14 @@ impl<T: Shape + ?Sized> Shape for Arc<T> {
     fn sample(&self, target: &glm::DVec3, rng: &mut StdRng) -> (glm::DVec3, glm::DVec3, f64) {
         self.as_ref().sample(target, rng)
     }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        self.as_ref().bounds()
+    }
+
+    fn triangles(&self) -> Option<Vec<Triangle>> {
+        self.as_ref().triangles()
+    }
 }
 
 /// An infinite ray in one direction
@@ -72,12 +113,25 @@ impl Ray {
 }
 
 /// Record of when a hit occurs, and the corresponding normal
+#[derive(Copy, Clone)]
 pub struct HitRecord {
     /// The time at which the hit occurs (see `Ray`)
     pub time: f64,
 
     /// The normal of the hit in some coordinate system
     pub normal: glm::DVec3,
+
+    /// Surface texture coordinates of the hit
+    pub uv: glm::DVec2,
+
+    /// Direction of increasing u along the surface, or zero if unknown
+    pub tangent: glm::DVec3,
+
+    /// Direction of increasing v along the surface, or zero if unknown
+    pub bitangent: glm::DVec3,
+
+    /// Barycentric coordinates of the hit within a triangle, or `None` for other shapes
+    pub barycentric: Option<glm::DVec3>,
 }
 
 impl Default for HitRecord {
@@ -85,6 +139,10 @@ impl Default for HitRecord {
         Self {
             time: f64::INFINITY,
             normal: glm::vec3(0.0, 0.0, 0.0),
+            uv: glm::vec2(0.0, 0.0),
+            tangent: glm::vec3(0.0, 0.0, 0.0),
+            bitangent: glm::vec3(0.0, 0.0, 0.0),
+            barycentric: None,
         }
     }
 }
@@ -130,6 +188,9 @@ impl<T: Shape> Shape for Transformed<T> {
         if self.shape.intersect(&local_ray, t_min, record) {
             // Fix normal vectors by multiplying by M^-T
             record.normal = (self.normal_transform * record.normal).normalize();
+            // Tangent vectors lie in the surface, so they transform directly
+            record.tangent = self.linear * record.tangent;
+            record.bitangent = self.linear * record.bitangent;
             true
         } else {
             false
@@ -148,12 +209,43 @@ impl<T: Shape> Shape for Transformed<T> {
             p / parallelepiped_base, // divide PDF by the area scale factor
         )
     }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        self.shape.bounds().map(|bbox| self.transform_bounds(&bbox))
+    }
+
+    fn triangles(&self) -> Option<Vec<Triangle>> {
+        let point = |v: glm::DVec3| (self.transform * glm::vec4(v.x, v.y, v.z, 1.0)).xyz();
+        let normal = |n: glm::DVec3| (self.normal_transform * n).normalize();
+        let triangles = self.shape.triangles()?;
+        Some(
+            triangles
+                .into_iter()
+                .map(|tri| Triangle {
+                    v1: point(tri.v1),
+                    v2: point(tri.v2),
+                    v3: point(tri.v3),
+                    n1: normal(tri.n1),
+                    n2: normal(tri.n2),
+                    n3: normal(tri.n3),
+                    ..tri
+                })
+                .collect(),
+        )
+    }
 }
 
 impl<T: Bounded> Bounded for Transformed<T> {
     fn bounding_box(&self) -> BoundingBox {
+        self.transform_bounds(&self.shape.bounding_box())
+    }
+}
+
+impl<T> Transformed<T> {
+    /// Bounding box of the transformed image of a local bounding box
+    fn transform_bounds(&self, bbox: &BoundingBox) -> BoundingBox {
         // This is not necessarily the best bounding box, but it is correct
-        let BoundingBox { p_min, p_max } = self.shape.bounding_box();
+        let BoundingBox { p_min, p_max } = *bbox;
         let v1 = (self.transform * glm::vec4(p_min.x, p_min.y, p_min.z, 1.0)).xyz();
         let v2 = (self.transform * glm::vec4(p_min.x, p_min.y, p_max.z, 1.0)).xyz();
         let v3 = (self.transform * glm::vec4(p_min.x, p_max.y, p_min.z, 1.0)).xyz();
@@ -283,6 +375,17 @@ impl<T: Shape> Transformed<T> {
     }
 }
 
+/// A transformed reference to shared geometry
+///
+/// Each instance stores only its transform, so many instances of a mesh can share a
+/// single kd-tree, and instances of different shapes can be collected together.
+pub type Instance = Transformed<Arc<dyn Bounded>>;
+
+/// Helper function to construct a transformed instance of shared geometry
+pub fn instance(shape: &Arc<dyn Bounded>, transform: glm::DMat4) -> Instance {
+    Transformed::new(Arc::clone(shape), transform)
+}
+
 /// Helper function to construct a sphere
 pub fn sphere() -> Sphere {
     Sphere
@@ -290,7 +393,7 @@ pub fn sphere() -> Sphere {
 
 /// Helper function to construct a glass-like monomial surface
 pub fn monomial_surface(height: f64, exp: f64) -> MonomialSurface {
-    MonomialSurface { height, exp }
+    MonomialSurface::new(height, exp)
 }
 
 /// Helper function to construct a plane
@@ -303,6 +406,21 @@ pub fn cube() -> Cube {
     Cube
 }
 
+/// Helper function to construct a closed cylinder
+pub fn cylinder() -> Cylinder {
+    Cylinder::default()
+}
+
+/// Helper function to construct a closed cone
+pub fn cone() -> Cone {
+    Cone::default()
+}
+
+/// Helper function to construct a disk
+pub fn disk() -> Disk {
+    Disk
+}
+
 /// Helper function to construct a simple polygon made from triangles
 pub fn polygon(verts: &[glm::DVec3]) -> Mesh {
     let mut tris = Vec::new();
@@ -311,3 +429,34 @@ pub fn polygon(verts: &[glm::DVec3]) -> Mesh {
     }
     Mesh::new(tris)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instance_matches_transformed_shape() {
+        let offset = glm::vec3(1.0, -2.0, 0.5);
+        let shared: Arc<dyn Bounded> = Arc::new(sphere());
+        let instanced = instance(&shared, glm::translate(&glm::identity(), &offset));
+        let translated = sphere().translate(&offset);
+        assert_eq!(
+            instanced.bounding_box().p_min,
+            translated.bounding_box().p_min
+        );
+
+        for i in 0..100 {
+            let angle = i as f64 * 0.1;
+            let ray = Ray {
+                origin: glm::vec3(angle.cos() * 5.0, angle.sin() * 5.0, 0.3),
+                dir: (offset - glm::vec3(angle.cos() * 5.0, angle.sin() * 5.0, 0.0)
+                    + glm::vec3(0.0, 0.0, (i % 7) as f64 * 0.2 - 0.6))
+                .normalize(),
+            };
+            let (mut a, mut b) = (HitRecord::new(), HitRecord::new());
+            let hit = instanced.intersect(&ray, 0.0, &mut a);
+            assert_eq!(hit, translated.intersect(&ray, 0.0, &mut b));
+            assert_eq!((a.time, a.normal, a.uv), (b.time, b.normal, b.uv));
+        }
+    }
+}
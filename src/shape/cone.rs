@@ -0,0 +1,131 @@
+use rand::{rngs::StdRng, Rng};
+use rand_distr::UnitDisc;
+
+use super::cylinder::{cap_hit, side_hit};
+use super::{HitRecord, Ray, Shape};
+use crate::kdtree::{Bounded, BoundingBox};
+
+/// A cone around the y-axis with a base of radius 1 at y = 0 and its apex at y = 1
+#[derive(Copy, Clone)]
+pub struct Cone {
+    /// Whether the base is closed by a disk
+    pub capped: bool,
+}
+
+impl Default for Cone {
+    fn default() -> Self {
+        Self { capped: true }
+    }
+}
+
+impl Shape for Cone {
+    fn intersect(&self, ray: &Ray, t_min: f64, record: &mut HitRecord) -> bool {
+        let mut hit = false;
+
+        // Side: x^2 + z^2 = (1 - y)^2, solved as a t^2 + 2b t + c = 0
+        let (o, d) = (ray.origin, ray.dir);
+        let k = 1.0 - o.y;
+        let a = d.x * d.x + d.z * d.z - d.y * d.y;
+        let b = o.x * d.x + o.z * d.z + k * d.y;
+        let c = o.x * o.x + o.z * o.z - k * k;
+        let roots = if a.abs() < 1e-12 {
+            if b == 0.0 {
+                vec![]
+            } else {
+                vec![-c / (2.0 * b)]
+            }
+        } else {
+            let disc = b * b - a * c;
+            if disc < 0.0 {
+                vec![]
+            } else {
+                let disc = disc.sqrt();
+                let (t1, t2) = ((-b - disc) / a, (-b + disc) / a);
+                vec![t1.min(t2), t1.max(t2)]
+            }
+        };
+        for t in roots {
+            let p = ray.at(t);
+            if t >= t_min && t < record.time && (0.0..=1.0).contains(&p.y) {
+                side_hit(record, t, glm::vec3(p.x, 1.0 - p.y, p.z), p.y);
+                hit = true;
+                break;
+            }
+        }
+
+        if self.capped {
+            hit |= cap_hit(ray, t_min, record, 0.0, -1.0);
+        }
+        hit
+    }
+
+    fn sample(&self, _target: &glm::DVec3, rng: &mut StdRng) -> (glm::DVec3, glm::DVec3, f64) {
+        // The side has area π√2 and the base has area π
+        let side = std::f64::consts::SQRT_2;
+        let area = if self.capped { side + 1.0 } else { side } * std::f64::consts::PI;
+        let [x, z]: [f64; 2] = rng.sample(UnitDisc);
+        if rng.gen::<f64>() * area / std::f64::consts::PI < side {
+            // The side projects uniformly onto the base disk
+            let r = (x * x + z * z).sqrt();
+            let normal = glm::vec3(x, r, z).normalize();
+            (glm::vec3(x, 1.0 - r, z), normal, area.recip())
+        } else {
+            (
+                glm::vec3(x, 0.0, z),
+                glm::vec3(0.0, -1.0, 0.0),
+                area.recip(),
+            )
+        }
+    }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        Some(self.bounding_box())
+    }
+}
+
+impl Bounded for Cone {
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox {
+            p_min: glm::vec3(-1.0, 0.0, -1.0),
+            p_max: glm::vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(shape: &impl Shape, origin: glm::DVec3, dir: glm::DVec3) -> Option<HitRecord> {
+        let mut record = HitRecord::new();
+        let ray = Ray { origin, dir };
+        if shape.intersect(&ray, 1e-9, &mut record) {
+            Some(record)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn cone_intersections() {
+        let cone = Cone::default();
+
+        // Side, halfway up where the radius is 0.5
+        let h = hit(&cone, glm::vec3(-3.0, 0.5, 0.0), glm::vec3(1.0, 0.0, 0.0)).unwrap();
+        assert!((h.time - 2.5).abs() < 1e-12);
+        let expected = glm::vec3(-1.0, 1.0, 0.0).normalize();
+        assert!((h.normal - expected).norm() < 1e-12);
+
+        // Base cap from below, and straight through to the side when open
+        let h = hit(&cone, glm::vec3(0.5, -2.0, 0.0), glm::vec3(0.0, 1.0, 0.0)).unwrap();
+        assert!((h.time - 2.0).abs() < 1e-12);
+        assert_eq!(h.normal, glm::vec3(0.0, -1.0, 0.0));
+        let open = Cone { capped: false };
+        let h = hit(&open, glm::vec3(0.5, -2.0, 0.0), glm::vec3(0.0, 1.0, 0.0)).unwrap();
+        assert!((h.time - 2.5).abs() < 1e-12);
+
+        // Misses: above the apex, and beside the base
+        assert!(hit(&cone, glm::vec3(-3.0, 1.5, 0.0), glm::vec3(1.0, 0.0, 0.0)).is_none());
+        assert!(hit(&cone, glm::vec3(-3.0, 0.5, 0.6), glm::vec3(1.0, 0.0, 0.0)).is_none());
+    }
+}
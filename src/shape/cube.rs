@@ -63,8 +63,32 @@ impl Shape for Cube {
             (start, start_normal)
         };
         if time < record.time {
+            // Project onto the two axes spanning the face
+            let p = ray.at(time);
+            let (uv, tangent, bitangent) = if normal.x != 0.0 {
+                (
+                    glm::vec2(p.z, p.y),
+                    glm::vec3(0.0, 0.0, 1.0),
+                    glm::vec3(0.0, 1.0, 0.0),
+                )
+            } else if normal.y != 0.0 {
+                (
+                    glm::vec2(p.x, p.z),
+                    glm::vec3(1.0, 0.0, 0.0),
+                    glm::vec3(0.0, 0.0, 1.0),
+                )
+            } else {
+                (
+                    glm::vec2(p.x, p.y),
+                    glm::vec3(1.0, 0.0, 0.0),
+                    glm::vec3(0.0, 1.0, 0.0),
+                )
+            };
             record.time = time;
             record.normal = normal;
+            record.uv = uv.add_scalar(0.5);
+            record.tangent = tangent;
+            record.bitangent = bitangent;
             true
         } else {
             false
@@ -85,4 +109,8 @@ impl Shape for Cube {
         };
         (v, n, 1.0 / 6.0)
     }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        Some(self.bounding_box())
+    }
 }
@@ -0,0 +1,177 @@
+use rand::{rngs::StdRng, Rng};
+use rand_distr::UnitDisc;
+
+use super::{HitRecord, Ray, Shape};
+use crate::kdtree::{Bounded, BoundingBox};
+
+/// A cylinder of radius 1 around the y-axis, extending from y = 0 to y = 1
+#[derive(Copy, Clone)]
+pub struct Cylinder {
+    /// Whether the top and bottom are closed by disks
+    pub capped: bool,
+}
+
+impl Default for Cylinder {
+    fn default() -> Self {
+        Self { capped: true }
+    }
+}
+
+impl Shape for Cylinder {
+    fn intersect(&self, ray: &Ray, t_min: f64, record: &mut HitRecord) -> bool {
+        let mut hit = false;
+
+        // Side: x^2 + z^2 = 1
+        let a = ray.dir.x * ray.dir.x + ray.dir.z * ray.dir.z;
+        let b = ray.origin.x * ray.dir.x + ray.origin.z * ray.dir.z;
+        let c = ray.origin.x * ray.origin.x + ray.origin.z * ray.origin.z - 1.0;
+        let d = b * b - a * c;
+        if a > 0.0 && d >= 0.0 {
+            let d = d.sqrt();
+            for &t in &[(-b - d) / a, (-b + d) / a] {
+                let p = ray.at(t);
+                if t >= t_min && t < record.time && (0.0..=1.0).contains(&p.y) {
+                    let normal = glm::vec3(p.x, 0.0, p.z);
+                    side_hit(record, t, normal, p.y);
+                    hit = true;
+                    break;
+                }
+            }
+        }
+
+        if self.capped {
+            for &(y, sign) in &[(0.0, -1.0), (1.0, 1.0)] {
+                hit |= cap_hit(ray, t_min, record, y, sign);
+            }
+        }
+        hit
+    }
+
+    fn sample(&self, _target: &glm::DVec3, rng: &mut StdRng) -> (glm::DVec3, glm::DVec3, f64) {
+        // The side has area 2π and each cap has area π
+        let area = if self.capped { 4.0 } else { 2.0 } * std::f64::consts::PI;
+        let choice = rng.gen::<f64>() * area / std::f64::consts::PI;
+        if choice < 2.0 {
+            let theta = rng.gen::<f64>() * std::f64::consts::TAU;
+            let (z, x) = theta.sin_cos();
+            (
+                glm::vec3(x, rng.gen(), z),
+                glm::vec3(x, 0.0, z),
+                area.recip(),
+            )
+        } else {
+            let [x, z]: [f64; 2] = rng.sample(UnitDisc);
+            if choice < 3.0 {
+                (
+                    glm::vec3(x, 0.0, z),
+                    glm::vec3(0.0, -1.0, 0.0),
+                    area.recip(),
+                )
+            } else {
+                (glm::vec3(x, 1.0, z), glm::vec3(0.0, 1.0, 0.0), area.recip())
+            }
+        }
+    }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        Some(self.bounding_box())
+    }
+}
+
+impl Bounded for Cylinder {
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox {
+            p_min: glm::vec3(-1.0, 0.0, -1.0),
+            p_max: glm::vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+/// Record a hit on the curved side of a surface of revolution around the y-axis
+pub(super) fn side_hit(record: &mut HitRecord, t: f64, normal: glm::DVec3, v: f64) {
+    let normal = normal.normalize();
+    let u = 0.5 + normal.z.atan2(normal.x) / std::f64::consts::TAU;
+    record.time = t;
+    record.normal = normal;
+    record.uv = glm::vec2(u, v);
+    record.tangent = glm::vec3(-normal.z, 0.0, normal.x) * std::f64::consts::TAU;
+    record.bitangent = glm::vec3(0.0, 1.0, 0.0);
+}
+
+/// Intersect a ray with a unit disk at height `y` whose normal points along `sign` in y
+pub(super) fn cap_hit(ray: &Ray, t_min: f64, record: &mut HitRecord, y: f64, sign: f64) -> bool {
+    if ray.dir.y == 0.0 {
+        return false;
+    }
+    let t = (y - ray.origin.y) / ray.dir.y;
+    let p = ray.at(t);
+    if t >= t_min && t < record.time && p.x * p.x + p.z * p.z <= 1.0 {
+        record.time = t;
+        record.normal = glm::vec3(0.0, sign, 0.0);
+        record.uv = glm::vec2(0.5 * (p.x + 1.0), 0.5 * (p.z + 1.0));
+        record.tangent = glm::vec3(2.0, 0.0, 0.0);
+        record.bitangent = glm::vec3(0.0, 0.0, 2.0);
+        true
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(shape: &impl Shape, origin: glm::DVec3, dir: glm::DVec3) -> Option<HitRecord> {
+        let mut record = HitRecord::new();
+        let ray = Ray { origin, dir };
+        if shape.intersect(&ray, 1e-9, &mut record) {
+            Some(record)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn cylinder_intersections() {
+        let cylinder = Cylinder::default();
+
+        // Side
+        let h = hit(
+            &cylinder,
+            glm::vec3(-3.0, 0.5, 0.0),
+            glm::vec3(1.0, 0.0, 0.0),
+        )
+        .unwrap();
+        assert!((h.time - 2.0).abs() < 1e-12);
+        assert!((h.normal - glm::vec3(-1.0, 0.0, 0.0)).norm() < 1e-12);
+
+        // Top cap, and through an open top to the inside of the far wall
+        let h = hit(
+            &cylinder,
+            glm::vec3(0.5, 3.0, 0.0),
+            glm::vec3(0.0, -1.0, 0.0),
+        )
+        .unwrap();
+        assert!((h.time - 2.0).abs() < 1e-12);
+        assert_eq!(h.normal, glm::vec3(0.0, 1.0, 0.0));
+        let open = Cylinder { capped: false };
+        let dir = glm::vec3(1.0, -1.0, 0.0).normalize();
+        let h = hit(&open, glm::vec3(-1.5, 2.5, 0.0), dir).unwrap();
+        assert!((h.normal - glm::vec3(1.0, 0.0, 0.0)).norm() < 1e-12);
+
+        // Misses
+        assert!(hit(
+            &cylinder,
+            glm::vec3(-3.0, 1.5, 0.0),
+            glm::vec3(1.0, 0.0, 0.0)
+        )
+        .is_none());
+        assert!(hit(
+            &cylinder,
+            glm::vec3(-3.0, 0.5, 1.5),
+            glm::vec3(1.0, 0.0, 0.0)
+        )
+        .is_none());
+        assert!(hit(&open, glm::vec3(0.5, 3.0, 0.0), glm::vec3(0.0, -1.0, 0.0)).is_none());
+    }
+}
@@ -0,0 +1,68 @@
+use rand::{rngs::StdRng, Rng};
+use rand_distr::UnitDisc;
+
+use super::cylinder::cap_hit;
+use super::{HitRecord, Ray, Shape};
+use crate::kdtree::{Bounded, BoundingBox};
+
+/// A disk of radius 1 in the xz-plane, centered at the origin and facing +y
+#[derive(Copy, Clone)]
+pub struct Disk;
+
+impl Shape for Disk {
+    fn intersect(&self, ray: &Ray, t_min: f64, record: &mut HitRecord) -> bool {
+        cap_hit(ray, t_min, record, 0.0, 1.0)
+    }
+
+    fn sample(&self, _target: &glm::DVec3, rng: &mut StdRng) -> (glm::DVec3, glm::DVec3, f64) {
+        let [x, z]: [f64; 2] = rng.sample(UnitDisc);
+        (
+            glm::vec3(x, 0.0, z),
+            glm::vec3(0.0, 1.0, 0.0),
+            std::f64::consts::FRAC_1_PI,
+        )
+    }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        Some(self.bounding_box())
+    }
+}
+
+impl Bounded for Disk {
+    fn bounding_box(&self) -> BoundingBox {
+        BoundingBox {
+            p_min: glm::vec3(-1.0, 0.0, -1.0),
+            p_max: glm::vec3(1.0, 0.0, 1.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disk_intersections() {
+        let mut h = HitRecord::new();
+        let down = glm::vec3(0.0, -1.0, 0.0);
+        let ray = Ray {
+            origin: glm::vec3(0.6, 2.0, -0.6),
+            dir: down,
+        };
+        assert!(Disk.intersect(&ray, 1e-9, &mut h));
+        assert!((h.time - 2.0).abs() < 1e-12);
+        assert_eq!(h.normal, glm::vec3(0.0, 1.0, 0.0));
+
+        let mut h = HitRecord::new();
+        let ray = Ray {
+            origin: glm::vec3(0.8, 2.0, -0.8),
+            dir: down,
+        };
+        assert!(!Disk.intersect(&ray, 1e-9, &mut h));
+        let ray = Ray {
+            origin: glm::vec3(0.0, 2.0, 0.0),
+            dir: glm::vec3(1.0, 0.0, 0.0),
+        };
+        assert!(!Disk.intersect(&ray, 1e-9, &mut h));
+    }
+}
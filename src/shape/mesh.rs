@@ -3,7 +3,7 @@ use rand::{rngs::StdRng, Rng};
 use super::{HitRecord, Ray, Shape};
 use crate::kdtree::{Bounded, BoundingBox, KdTree};
 
-/// A triangle with three vertices and three normals
+/// A triangle with three vertices, three normals, and three texture coordinates
 #[derive(Copy, Clone)]
 pub struct Triangle {
     /// The first vertex
@@ -19,10 +19,18 @@ pub struct Triangle {
     pub n2: glm::DVec3,
     /// The third normal vector
     pub n3: glm::DVec3,
+
+    /// The first texture coordinate
+    pub uv1: glm::DVec2,
+    /// The second texture coordinate
+    pub uv2: glm::DVec2,
+    /// The third texture coordinate
+    pub uv3: glm::DVec2,
 }
 
 impl Triangle {
-    /// Construct a triangle from three vertices, inferring the normals
+    /// Construct a triangle from three vertices, inferring the normals, with zero
+    /// texture coordinates
     pub fn from_vertices(v1: glm::DVec3, v2: glm::DVec3, v3: glm::DVec3) -> Self {
         let n = (v2 - v1).cross(&(v3 - v1)).normalize();
         Self {
@@ -32,6 +40,29 @@ impl Triangle {
             n1: n,
             n2: n,
             n3: n,
+            uv1: glm::vec2(0.0, 0.0),
+            uv2: glm::vec2(0.0, 0.0),
+            uv3: glm::vec2(0.0, 0.0),
+        }
+    }
+
+    /// Set the texture coordinates of the three vertices
+    pub fn uvs(mut self, uv1: glm::DVec2, uv2: glm::DVec2, uv3: glm::DVec2) -> Self {
+        self.uv1 = uv1;
+        self.uv2 = uv2;
+        self.uv3 = uv3;
+        self
+    }
+
+    /// Normal interpolated from the vertex normals at barycentric coordinates (u, v, w),
+    /// or `None` if they cancel out, as for vertex normals that point in opposite ways
+    fn shading_normal(&self, u: f64, v: f64, w: f64) -> Option<glm::DVec3> {
+        let n = u * self.n1 + v * self.n2 + w * self.n3;
+        let length = n.magnitude();
+        if length > 1e-12 && length.is_finite() {
+            Some(n / length)
+        } else {
+            None
         }
     }
 }
@@ -74,7 +105,20 @@ impl Shape for Triangle {
 
         if u >= 0.0 && v >= 0.0 && w >= 0.0 {
             record.time = time;
-            record.normal = (u * self.n1 + v * self.n2 + w * self.n3).normalize();
+            record.normal = self.shading_normal(u, v, w).unwrap_or(plane_normal);
+            record.uv = u * self.uv1 + v * self.uv2 + w * self.uv3;
+            record.barycentric = Some(glm::vec3(u, v, w));
+
+            // Solve for the surface derivatives dp/du and dp/dv from UV gradients
+            let (duv0, duv1) = (self.uv2 - self.uv1, self.uv3 - self.uv1);
+            let det = duv0.x * duv1.y - duv0.y * duv1.x;
+            if det.abs() > 1e-12 {
+                record.tangent = (d0 * duv1.y - d1 * duv0.y) / det;
+                record.bitangent = (d1 * duv0.x - d0 * duv1.x) / det;
+            } else {
+                record.tangent = glm::vec3(0.0, 0.0, 0.0);
+                record.bitangent = glm::vec3(0.0, 0.0, 0.0);
+            }
             true
         } else {
             false
@@ -89,14 +133,133 @@ impl Shape for Triangle {
             v = rng.gen();
         }
         let w = 1.0 - u - v;
-        let area: f64 = 0.5 * (self.v2 - self.v1).cross(&(self.v3 - self.v1)).magnitude();
+        let cross = (self.v2 - self.v1).cross(&(self.v3 - self.v1));
+        let area: f64 = 0.5 * cross.magnitude();
         (
             u * self.v1 + v * self.v2 + w * self.v3,
-            (u * self.n1 + v * self.n2 + w * self.n3).normalize(),
+            self.shading_normal(u, v, w)
+                .unwrap_or_else(|| cross.normalize()),
             area.recip(),
         )
     }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        Some(self.bounding_box())
+    }
+
+    fn triangles(&self) -> Option<Vec<Triangle>> {
+        Some(vec![*self])
+    }
 }
 
 /// A triangle mesh, stored using a kd-tree
 pub type Mesh = KdTree<Triangle>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Octahedron subdivided `levels` times and projected onto the unit sphere, with the
+    /// vertex normals of a smooth sphere
+    fn sphere_mesh(levels: usize) -> Vec<Triangle> {
+        let (x, y, z) = (glm::DVec3::x(), glm::DVec3::y(), glm::DVec3::z());
+        let mut faces = vec![
+            [x, y, z],
+            [y, -x, z],
+            [-x, -y, z],
+            [-y, x, z],
+            [y, x, -z],
+            [-x, y, -z],
+            [-y, -x, -z],
+            [x, -y, -z],
+        ];
+        for _ in 0..levels {
+            faces = faces
+                .iter()
+                .flat_map(|&[a, b, c]| {
+                    let (ab, bc, ca) = (
+                        (a + b).normalize(),
+                        (b + c).normalize(),
+                        (c + a).normalize(),
+                    );
+                    vec![[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]
+                })
+                .collect();
+        }
+        faces
+            .into_iter()
+            .map(|[a, b, c]| {
+                let mut triangle = Triangle::from_vertices(a, b, c);
+                triangle.n1 = a;
+                triangle.n2 = b;
+                triangle.n3 = c;
+                triangle
+            })
+            .collect()
+    }
+
+    /// Normal of the first hit along a ray from outside the unit sphere toward the origin
+    fn normal_toward(triangles: &[Triangle], dir: &glm::DVec3) -> glm::DVec3 {
+        let ray = Ray {
+            origin: dir * 3.0,
+            dir: -dir,
+        };
+        let mut record = HitRecord::new();
+        for triangle in triangles {
+            triangle.intersect(&ray, 0.0, &mut record);
+        }
+        assert!(record.time.is_finite());
+        record.normal
+    }
+
+    #[test]
+    fn vertex_normals_interpolate_smoothly() {
+        let triangles = sphere_mesh(2);
+        let face = triangles[0];
+        let face_normal = (face.v2 - face.v1).cross(&(face.v3 - face.v1)).normalize();
+
+        // Sweep across several faces, in a direction that avoids passing through vertices
+        let (start, end) = (glm::vec3(0.9, 0.05, 0.1), glm::vec3(0.1, 0.3, 0.8));
+        let steps = 200;
+        let mut previous: Option<glm::DVec3> = None;
+        for i in 0..=steps {
+            let dir = glm::mix(&start, &end, i as f64 / steps as f64).normalize();
+            let normal = normal_toward(&triangles, &dir);
+            assert!((normal.magnitude() - 1.0).abs() < 1e-12);
+            assert!((normal - dir).magnitude() < 0.1);
+            if let Some(previous) = previous {
+                assert!((normal - previous).magnitude() < 0.02);
+            }
+            previous = Some(normal);
+        }
+
+        // Away from the face normal, the shading normal follows the sphere
+        let centroid = (face.v1 + face.v2 + face.v3) / 3.0;
+        let near_vertex = (face.v1 * 0.9 + face.v2 * 0.05 + face.v3 * 0.05).normalize();
+        let normal = normal_toward(&triangles, &near_vertex);
+        assert!((normal - near_vertex).magnitude() < 0.05);
+        assert!((normal - face_normal).magnitude() > 0.1);
+        let normal = normal_toward(&triangles, &centroid.normalize());
+        let mean = (face.n1 + face.n2 + face.n3).normalize();
+        assert!((normal - mean).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn canceling_vertex_normals_fall_back_to_face_normal() {
+        let mut triangle = Triangle::from_vertices(
+            glm::vec3(-1.0, -1.0, 0.0),
+            glm::vec3(1.0, -1.0, 0.0),
+            glm::vec3(0.0, 1.0, 0.0),
+        );
+        triangle.n1 = glm::vec3(0.0, 0.0, 1.0);
+        triangle.n2 = glm::vec3(0.0, 0.0, -1.0);
+        triangle.n3 = glm::vec3(0.0, 0.0, 0.0);
+        let ray = Ray {
+            origin: glm::vec3(0.0, -1.0, 1.0),
+            dir: glm::vec3(0.0, 0.0, -1.0),
+        };
+        let mut record = HitRecord::new();
+        assert!(triangle.intersect(&ray, 0.0, &mut record));
+        assert_eq!(record.normal, glm::vec3(0.0, 0.0, 1.0));
+    }
+}
@@ -1,20 +1,28 @@
 use rand::{rngs::StdRng, Rng};
-use rand_distr::UnitCircle;
+use rand_distr::UnitDisc;
 
-use super::{HitRecord, Ray, Shape};
+use super::{HitRecord, Ray, Shape, Triangle};
 use crate::kdtree::{Bounded, BoundingBox};
 
+/// Number of rings in the tessellation used to displace the surface
+const TESSELLATION_RINGS: usize = 128;
+
+/// Number of segments around each ring in the tessellation used to displace the surface
+const TESSELLATION_SEGMENTS: usize = 256;
+
 /// Represents a glass-shaped surface with height and exp parameters
 ///
 /// Points satisfy the relation y = height * sqrt(x^2 + z^2)^exp, x^2 + z^2 <= 1.
 ///
-/// Normals and other things probably can't be generalized, so they work only for exp=4 for now
+/// Construct this with `MonomialSurface::new`, which precomputes the surface area.
 #[derive(Copy, Clone)]
 pub struct MonomialSurface {
     /// The height of the surface
     pub height: f64,
-    /// The surface exponent, must be equal to 4 currently
+    /// The surface exponent
     pub exp: f64,
+    /// Surface area of one side, for light sampling
+    area: f64,
 }
 
 impl Shape for MonomialSurface {
@@ -27,23 +35,25 @@ impl Shape for MonomialSurface {
             let x = ray.origin.x + t * ray.dir.x;
             let y = ray.origin.y + t * ray.dir.y;
             let z = ray.origin.z + t * ray.dir.z;
-            y - self.height * (x * x + z * z).powi(2)
+            y - self.height * (x * x + z * z).powf(self.exp / 2.)
         };
+        // Squared radius along the ray, q(t) = coef0 + coef1 t + coef2 t^2
         let coef0 = ray.origin.x.powi(2) + ray.origin.z.powi(2);
         let coef1 = 2. * (ray.origin.x * ray.dir.x + ray.origin.z * ray.dir.z);
         let coef2 = ray.dir.x.powi(2) + ray.dir.z.powi(2);
+        let q = |t: f64| (coef0 + coef1 * t + coef2 * t * t).max(1e-300);
+        let k = self.exp / 2.;
         let deriv = |t: f64| {
-            let dy = 2. * coef0 * coef1
-                + 2. * t * (coef1 * coef1 + 2. * coef0 * coef2)
-                + 3. * t.powi(2) * 2. * coef1 * coef2
-                + 4. * t.powi(3) * coef2 * coef2;
-            ray.dir.y - self.height * dy
+            // d/dt q^k = k q^(k-1) q'
+            let dq = coef1 + 2. * coef2 * t;
+            ray.dir.y - self.height * k * q(t).powf(k - 1.) * dq
         };
         let deriv2 = |t: f64| {
-            let dy = 2. * (coef1 * coef1 + 2. * coef0 * coef2)
-                + 3. * 2. * t * 2. * coef1 * coef2
-                + 4. * 3. * t.powi(2) * coef2 * coef2;
-            -self.height * dy
+            // d²/dt² q^k = k (k-1) q^(k-2) q'^2 + k q^(k-1) q''
+            let dq = coef1 + 2. * coef2 * t;
+            let qt = q(t);
+            -self.height
+                * (k * (k - 1.) * qt.powf(k - 2.) * dq * dq + k * qt.powf(k - 1.) * 2. * coef2)
         };
         let t_max;
         let maximize: bool = dist(t_min) < 0.0;
@@ -90,12 +100,11 @@ impl Shape for MonomialSurface {
             return false;
         }
         record.time = r;
+        record.uv = glm::vec2(0.5 * (pos.x + 1.0), 0.5 * (pos.z + 1.0));
+        record.tangent = glm::vec3(0.0, 0.0, 0.0);
+        record.bitangent = glm::vec3(0.0, 0.0, 0.0);
 
-        record.normal = glm::normalize(&glm::vec3(
-            self.height * 4.0 * pos.x * (pos.x * pos.x + pos.z * pos.z),
-            -1.0,
-            self.height * 4.0 * pos.z * (pos.x * pos.x + pos.z * pos.z),
-        ));
+        record.normal = self.normal(pos.x, pos.z);
 
         // The surface is two-sided, so we choose the appropriate normal
         if glm::dot(&record.normal, &ray.dir) > 0.0 {
@@ -106,23 +115,127 @@ impl Shape for MonomialSurface {
     }
 
     fn sample(&self, _target: &glm::DVec3, rng: &mut StdRng) -> (glm::DVec3, glm::DVec3, f64) {
-        let [x, z]: [f64; 2] = rng.sample(UnitCircle);
+        // Rejection sampling of the projected disc by the area element, so that points are
+        // uniformly distributed over the surface area
+        let max_slope = self.slope(1.).max(self.slope(0.));
+        let (x, z) = loop {
+            let [x, z]: [f64; 2] = rng.sample(UnitDisc);
+            let r = x.hypot(z);
+            if rng.gen::<f64>() * max_slope <= self.slope(r) {
+                break (x, z);
+            }
+        };
         let pos = glm::vec3(x, self.height * (x * x + z * z).powf(self.exp / 2.), z);
-        let mut normal = glm::normalize(&glm::vec3(
-            self.height * 4. * pos.x * (pos.x * pos.x + pos.z * pos.z),
-            -1.,
-            self.height * 4. * pos.z * (pos.x * pos.x + pos.z * pos.z),
-        ));
-        // Again, only valid for exp = 4
-        const AREA: f64 = 6.3406654362; // thanks WolframAlpha, hope I have set up the integrals correctly
+        let mut normal = self.normal(x, z);
         if rng.gen::<bool>() {
             normal = -normal;
         }
-        (pos, normal, 1. / (2. * AREA)) // 2 * AREA because there are two sides
+        (pos, normal, 1. / (2. * self.area)) // 2 * area because there are two sides
+    }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        Some(self.bounding_box())
+    }
+
+    fn triangles(&self) -> Option<Vec<Triangle>> {
+        Some(self.tessellate(TESSELLATION_RINGS, TESSELLATION_SEGMENTS))
     }
 }
 
 impl MonomialSurface {
+    /// Construct a new surface, numerically integrating its surface area
+    pub fn new(height: f64, exp: f64) -> Self {
+        let mut surface = Self {
+            height,
+            exp,
+            area: 0.,
+        };
+        // Midpoint rule for A = ∫ 2πr √(1 + (dy/dr)^2) dr over [0, 1]
+        let steps = 1000;
+        surface.area = (0..steps)
+            .map(|i| {
+                let r = (i as f64 + 0.5) / steps as f64;
+                std::f64::consts::TAU * r * surface.slope(r)
+            })
+            .sum::<f64>()
+            / steps as f64;
+        surface
+    }
+
+    /// Surface area of one side
+    pub fn area(&self) -> f64 {
+        self.area
+    }
+
+    /// Approximate the surface by triangles on a polar grid, with `rings` rings out to
+    /// the rim and `segments` segments around each
+    ///
+    /// The vertices carry the exact normals and texture coordinates of the surface, with
+    /// UVs mapping the unit disc in the xz-plane onto the unit square as in `intersect`.
+    pub fn tessellate(&self, rings: usize, segments: usize) -> Vec<Triangle> {
+        let vertex = |ring: usize, segment: usize| {
+            let r = ring as f64 / rings as f64;
+            let phi = std::f64::consts::TAU * segment as f64 / segments as f64;
+            let (x, z) = (r * phi.cos(), r * phi.sin());
+            (
+                glm::vec3(x, self.profile(r), z),
+                self.normal(x, z),
+                glm::vec2(0.5 * (x + 1.0), 0.5 * (z + 1.0)),
+            )
+        };
+        let triangle = |[a, b, c]: [(glm::DVec3, glm::DVec3, glm::DVec2); 3]| Triangle {
+            v1: a.0,
+            v2: b.0,
+            v3: c.0,
+            n1: a.1,
+            n2: b.1,
+            n3: c.1,
+            uv1: a.2,
+            uv2: b.2,
+            uv3: c.2,
+        };
+        let mut triangles = Vec::with_capacity(segments * (2 * rings - 1));
+        for segment in 0..segments {
+            let next = (segment + 1) % segments;
+            // The innermost ring is a fan around the apex
+            triangles.push(triangle([
+                vertex(0, 0),
+                vertex(1, segment),
+                vertex(1, next),
+            ]));
+            for ring in 1..rings {
+                let (a, b) = (vertex(ring, segment), vertex(ring, next));
+                let (c, d) = (vertex(ring + 1, next), vertex(ring + 1, segment));
+                triangles.push(triangle([a, d, c]));
+                triangles.push(triangle([a, c, b]));
+            }
+        }
+        triangles
+    }
+
+    /// Area element √(1 + (dy/dr)^2) at radius r
+    fn slope(&self, r: f64) -> f64 {
+        let dy = self.height * self.exp * r.powf(self.exp - 1.);
+        (1. + dy * dy).sqrt()
+    }
+
+    /// Unit normal at a point given by its (x, z) coordinates, from the gradient of
+    /// y - height * (x^2 + z^2)^(exp/2)
+    fn normal(&self, x: f64, z: f64) -> glm::DVec3 {
+        let r2 = x * x + z * z;
+        let scale = if r2 > 0. {
+            self.height * self.exp * r2.powf(self.exp / 2. - 1.)
+        } else {
+            0.
+        };
+        glm::normalize(&glm::vec3(scale * x, -1.0, scale * z))
+    }
+
+    /// Height of the surface at a signed radius
+    fn profile(&self, r: f64) -> f64 {
+        self.height * r.abs().powf(self.exp)
+    }
+
     /// Estimates the closest point on the surface to a given point
     pub fn closest_point(&self, point: &glm::DVec3) -> glm::DVec3 {
         if glm::length(point) < 1e-12 {
@@ -136,17 +249,13 @@ impl MonomialSurface {
         let mut res = (1e18, -1.);
         for x in -100..101 {
             let xf = x as f64 / 100.;
-            let dist2 = glm::distance2(&pt, &glm::vec2(xf, self.height * xf.powi(4)));
+            let dist2 = glm::distance2(&pt, &glm::vec2(xf, self.profile(xf)));
             if dist2 < res.0 {
                 res = (dist2, xf);
             }
         }
         let xz = res.1 * glm::normalize(&glm::vec2(point.x, point.z));
-        glm::vec3(
-            xz.x,
-            self.height * (xz.x.powi(2) + xz.y.powi(2)).powi(2),
-            xz.y,
-        )
+        glm::vec3(xz.x, self.profile(res.1), xz.y)
     }
 
     /// More precise and slower version of the closest_point function
@@ -162,17 +271,13 @@ impl MonomialSurface {
         let mut res = (1e18, -1.);
         for x in -10000..10001 {
             let xf = x as f64 / 10000.;
-            let dist2 = glm::distance2(&pt, &glm::vec2(xf, self.height * xf.powi(4)));
+            let dist2 = glm::distance2(&pt, &glm::vec2(xf, self.profile(xf)));
             if dist2 < res.0 {
                 res = (dist2, xf);
             }
         }
         let xz = res.1 * glm::normalize(&glm::vec2(point.x, point.z));
-        glm::vec3(
-            xz.x,
-            self.height * (xz.x.powi(2) + xz.y.powi(2)).powi(2),
-            xz.y,
-        )
+        glm::vec3(xz.x, self.profile(res.1), xz.y)
     }
 }
 
@@ -191,10 +296,7 @@ mod tests {
 
     #[test]
     fn monomial_closest_point_works() {
-        let surf = MonomialSurface {
-            height: 1.,
-            exp: 4.,
-        };
+        let surf = MonomialSurface::new(1., 4.);
         let test_xz = |x: f64, z: f64| {
             // Test the closest point to (x, y(x, z), z)
             let pt = glm::vec3(x, (x.powi(2) + z.powi(2)).powi(2), z);
@@ -238,4 +340,34 @@ mod tests {
         test_xy(-1., 2.);
         test_xy(-1., 0.5);
     }
+
+    #[test]
+    fn monomial_normals_are_perpendicular() {
+        for &exp in &[2., 4., 6.] {
+            let surf = MonomialSurface::new(1.5, exp);
+            let point = |x: f64, z: f64| glm::vec3(x, surf.profile(x.hypot(z)), z);
+            for &(x, z) in &[(0.3, 0.4), (-0.5, 0.1), (0.7, -0.6)] {
+                let h = 1e-6;
+                let tx = point(x + h, z) - point(x - h, z);
+                let tz = point(x, z + h) - point(x, z - h);
+                let n = surf.normal(x, z);
+                assert!(n.dot(&tx.normalize()).abs() < 1e-6);
+                assert!(n.dot(&tz.normalize()).abs() < 1e-6);
+
+                // Rays straight down hit the surface at the same point
+                let mut record = HitRecord::new();
+                let ray = Ray {
+                    origin: glm::vec3(x, 10., z),
+                    dir: glm::vec3(0., -1., 0.),
+                };
+                assert!(surf.intersect(&ray, 0., &mut record));
+                assert!(glm::distance(&ray.at(record.time), &point(x, z)) < 1e-6);
+                assert!((record.normal - n).norm() < 1e-9 || (record.normal + n).norm() < 1e-9);
+            }
+        }
+
+        // A paraboloid y = r^2 has area π/6 (5√5 - 1)
+        let area = MonomialSurface::new(1., 2.).area();
+        assert!((area - std::f64::consts::PI / 6. * (5. * 5f64.sqrt() - 1.)).abs() < 1e-5);
+    }
 }
@@ -3,6 +3,10 @@ use rand::rngs::StdRng;
 use super::{HitRecord, Ray, Shape};
 
 /// A plane represented by the linear equation x • normal = value
+///
+/// Planes are two-sided, so the recorded normal always faces the incoming ray. Texture
+/// coordinates are the position along two axes in the plane (see `Plane::basis`), so
+/// textures repeat once per unit of distance.
 #[derive(Copy, Clone)]
 pub struct Plane {
     /// The normal vector
@@ -12,6 +16,25 @@ pub struct Plane {
     pub value: f64,
 }
 
+impl Plane {
+    /// Orthonormal axes `(u, v)` within the plane, along which texture coordinates
+    /// increase
+    ///
+    /// The u axis is the projection of the x axis onto the plane (or the y axis, when
+    /// the plane is nearly perpendicular to x), and v completes a right-handed frame
+    /// with the normal, so a floor facing +y has u along +x and v along -z.
+    pub fn basis(&self) -> (glm::DVec3, glm::DVec3) {
+        let n = self.normal.normalize();
+        let axis = if n.x.abs() < 0.9 {
+            glm::vec3(1.0, 0.0, 0.0)
+        } else {
+            glm::vec3(0.0, 1.0, 0.0)
+        };
+        let u = (axis - n * n.dot(&axis)).normalize();
+        (u, n.cross(&u))
+    }
+}
+
 impl Shape for Plane {
     /// Ray-plane intersection
     fn intersect(&self, ray: &Ray, t_min: f64, record: &mut HitRecord) -> bool {
@@ -24,7 +47,13 @@ impl Shape for Plane {
         let time = (self.value - self.normal.dot(&ray.origin)) / cosine;
         if time >= t_min && time < record.time {
             record.time = time;
+            // Face the normal toward the ray, so both sides are shaded alike
             record.normal = -self.normal.normalize() * cosine.signum();
+            let (u, v) = self.basis();
+            let pos = ray.at(time);
+            record.uv = glm::vec2(pos.dot(&u), pos.dot(&v));
+            record.tangent = u;
+            record.bitangent = v;
             true
         } else {
             false
@@ -35,3 +64,30 @@ impl Shape for Plane {
         unimplemented!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plane_is_two_sided_with_uvs() {
+        let plane = Plane {
+            normal: glm::vec3(0.0, 2.0, 0.0),
+            value: -2.0,
+        };
+        let mut record = HitRecord::new();
+        let ray = Ray {
+            origin: glm::vec3(0.25, -3.0, 0.5),
+            dir: glm::vec3(0.0, 1.0, 0.0),
+        };
+        assert!(plane.intersect(&ray, 0.0, &mut record));
+        assert!((record.time - 2.0).abs() < 1e-12);
+        // Hit from below, the normal faces back down toward the ray
+        assert_eq!(record.normal, glm::vec3(0.0, -1.0, 0.0));
+        assert!(record.normal.dot(&ray.dir) < 0.0);
+        // The hit at (0.25, -1, 0.5) maps to u along +x and v along -z
+        assert!((record.uv - glm::vec2(0.25, -0.5)).magnitude() < 1e-12);
+        assert_eq!(record.tangent, glm::vec3(1.0, 0.0, 0.0));
+        assert_eq!(record.bitangent, glm::vec3(0.0, 0.0, -1.0));
+    }
+}
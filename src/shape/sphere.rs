@@ -1,8 +1,9 @@
 use rand::{rngs::StdRng, Rng};
-use rand_distr::UnitDisc;
+use rand_distr::UnitSphere;
 
 use super::{HitRecord, Ray, Shape};
 use crate::kdtree::{Bounded, BoundingBox};
+use crate::material::local_to_world;
 
 /// A unit sphere centered at the origin
 #[derive(Copy, Clone)]
@@ -37,30 +38,65 @@ impl Shape for Sphere {
 
         if t < record.time {
             record.time = t;
-            record.normal = ray.at(t).normalize();
+            let n = ray.at(t).normalize();
+            record.normal = n;
+            record.uv = glm::vec2(
+                0.5 + n.z.atan2(n.x) / (2.0 * glm::pi::<f64>()),
+                0.5 + n.y.asin() / glm::pi::<f64>(),
+            );
+            record.tangent = glm::vec3(-n.z, 0.0, n.x) * (2.0 * glm::pi::<f64>());
+            record.bitangent = if n.x == 0.0 && n.z == 0.0 {
+                glm::vec3(0.0, 0.0, 0.0)
+            } else {
+                record.tangent.cross(&n).normalize() * glm::pi::<f64>()
+            };
             true
         } else {
             false
         }
     }
 
-    /// Sample a spherical light source, somewhat respecting the solid angle from a target point
+    /// Sample a spherical light source uniformly over the solid angle it subtends from a
+    /// target point
     ///
-    /// Currently, this implementation just generates a random point in the hemisphere facing
-    /// the target point, weighted by the cosine. This isn't the most sophisticated technique,
-    /// since you can sample the solid angle exactly, but it's pretty good.
+    /// Only the cap of the sphere that is visible from the target is sampled, and the
+    /// returned PDF is converted from solid angle to area. Targets inside the sphere
+    /// sample its whole surface uniformly instead.
     fn sample(&self, target: &glm::DVec3, rng: &mut StdRng) -> (glm::DVec3, glm::DVec3, f64) {
-        let [x, y]: [f64; 2] = rng.sample(UnitDisc);
-        let z = (1.0 - x * x - y * y).sqrt();
-        let n = target.normalize();
-        let n1 = if n.x.is_normal() {
-            glm::vec3(n.y, -n.x, 0.0).normalize()
-        } else {
-            glm::vec3(0.0, -n.z, n.y).normalize()
-        };
-        let n2 = n1.cross(&n);
-        let p = x * n1 + y * n2 + z * n;
-        (p, p, z * std::f64::consts::FRAC_1_PI)
+        let d2 = target.magnitude_squared();
+        if d2 <= 1.0 {
+            let p = rng.sample(UnitSphere);
+            return (p.into(), p.into(), 0.25 * std::f64::consts::FRAC_1_PI);
+        }
+        let d = d2.sqrt();
+        let w = target / d;
+        // Half-angle of the cone subtended by the sphere, with 1 - cos θ computed from
+        // sin² θ to avoid cancellation for distant targets
+        let sin2_max = 1.0 / d2;
+        let cos_max = (1.0 - sin2_max).sqrt();
+        let one_minus_cos_max = sin2_max / (1.0 + cos_max);
+
+        // Sample a direction in the cone, then find where it meets the sphere
+        let (u, v): (f64, f64) = rng.gen();
+        let one_minus_cos = u * one_minus_cos_max;
+        let cos_t = 1.0 - one_minus_cos;
+        let sin2_t = one_minus_cos * (2.0 - one_minus_cos);
+        let dist = d * cos_t - (1.0 - d2 * sin2_t).max(0.0).sqrt();
+        // Angle at the center of the sphere between the target and the sampled point
+        let cos_a = ((d2 + 1.0 - dist * dist) / (2.0 * d)).min(1.0);
+        let sin_a = (1.0 - cos_a * cos_a).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * v;
+        let p = local_to_world(&w) * glm::vec3(sin_a * phi.cos(), sin_a * phi.sin(), cos_a);
+
+        let disp = target - p;
+        let cosine = p.dot(&disp).max(0.0) / disp.magnitude();
+        let pdf =
+            cosine / (disp.magnitude_squared() * 2.0 * std::f64::consts::PI * one_minus_cos_max);
+        (p, p, pdf)
+    }
+
+    fn bounds(&self) -> Option<BoundingBox> {
+        Some(self.bounding_box())
     }
 }
 
@@ -72,3 +108,63 @@ impl Bounded for Sphere {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn solid_angle_sampling_reduces_variance() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let target = glm::vec3(0.0, 0.0, 3.0);
+        let normal = glm::vec3(0.0, 0.0, -1.0);
+        // Irradiance from a sphere of unit radiance, as an estimate from one sample
+        let irradiance = |p: &glm::DVec3, pdf: f64| {
+            let disp = p - target;
+            let dist2 = disp.magnitude_squared();
+            let cos_target = disp.dot(&normal).max(0.0) / dist2.sqrt();
+            let cos_light = (-disp).dot(p).max(0.0) / dist2.sqrt();
+            cos_target * cos_light / (dist2 * pdf)
+        };
+        let samples = 100_000;
+        let stats = |estimates: Vec<f64>| {
+            let mean = estimates.iter().sum::<f64>() / samples as f64;
+            let variance =
+                estimates.iter().map(|e| (e - mean).powi(2)).sum::<f64>() / samples as f64;
+            (mean, variance)
+        };
+        let (cone_mean, cone_variance) = stats(
+            (0..samples)
+                .map(|_| {
+                    let (p, n, pdf) = Sphere.sample(&target, &mut rng);
+                    assert_eq!(p, n);
+                    assert!((p.magnitude() - 1.0).abs() < 1e-9);
+                    irradiance(&p, pdf)
+                })
+                .collect(),
+        );
+        let (area_mean, area_variance) = stats(
+            (0..samples)
+                .map(|_| {
+                    let p: glm::DVec3 = rng.sample(UnitSphere).into();
+                    irradiance(&p, 0.25 * std::f64::consts::FRAC_1_PI)
+                })
+                .collect(),
+        );
+
+        // A sphere subtending a cone of half-angle θ gives an irradiance of π sin² θ
+        let expected = std::f64::consts::PI / 9.0;
+        assert!(
+            (cone_mean - expected).abs() < 0.01 * expected,
+            "{}",
+            cone_mean
+        );
+        assert!(
+            (area_mean - expected).abs() < 0.02 * expected,
+            "{}",
+            area_mean
+        );
+        assert!(cone_variance < 0.2 * area_variance);
+    }
+}
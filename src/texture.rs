@@ -0,0 +1,213 @@
+use std::sync::Arc;
+
+use image::RgbImage;
+
+use crate::color::{srgb_to_linear, Color};
+
+/// A spatially varying color, evaluated at surface texture coordinates
+#[derive(Clone)]
+pub enum Texture {
+    /// A constant color everywhere
+    Solid(Color),
+
+    /// An sRGB image, repeated across UV space with (0, 0) at the bottom-left
+    Image(Arc<MipMap>),
+
+    /// A checkerboard of two colors, with the given number of squares per unit
+    /// of UV space
+    Checker(Color, Color, f64),
+}
+
+impl Texture {
+    /// Evaluate the texture at UV coordinates, returning a linear color
+    pub fn color(&self, uv: &glm::DVec2) -> Color {
+        self.filtered_color(uv, 0.0)
+    }
+
+    /// Evaluate the texture without decoding sRGB gamma, for
+    /// textures that store non-color data such as normal maps
+    pub fn value(&self, uv: &glm::DVec2) -> glm::DVec3 {
+        self.filtered_value(uv, 0.0)
+    }
+
+    /// Evaluate the average linear color of the texture over a square footprint with
+    /// side length `width` in UV space, centered at the given coordinates
+    pub fn filtered_color(&self, uv: &glm::DVec2, width: f64) -> Color {
+        match self {
+            Texture::Image(_) => self.filtered_value(uv, width).map(srgb_to_linear),
+            _ => self.filtered_value(uv, width),
+        }
+    }
+
+    /// Evaluate the average raw value of the texture over a square footprint with side
+    /// length `width` in UV space, centered at the given coordinates
+    ///
+    /// Images are sampled from the mip level matching the footprint, interpolating
+    /// between levels, and checkerboards are filtered exactly. A width of zero gives
+    /// the unfiltered value at a point.
+    pub fn filtered_value(&self, uv: &glm::DVec2, width: f64) -> glm::DVec3 {
+        match self {
+            Texture::Solid(color) => *color,
+            Texture::Image(mipmap) => mipmap.lookup(uv, width),
+            Texture::Checker(a, b, scale) => {
+                let fraction = if width > 0.0 {
+                    // Integrate the alternating squares along each axis, as in PBRT
+                    let ds = width * scale;
+                    let integral = |x: f64| {
+                        (x / 2.0).floor() + 2.0 * ((x / 2.0) - (x / 2.0).floor() - 0.5).max(0.0)
+                    };
+                    let average = |x: f64| {
+                        let (x0, x1) = (x - ds / 2.0, x + ds / 2.0);
+                        (integral(x1) - integral(x0)) / ds
+                    };
+                    let (s, t) = (average(uv.x * scale), average(uv.y * scale));
+                    s + t - 2.0 * s * t
+                } else {
+                    let parity = (uv.x * scale).floor() + (uv.y * scale).floor();
+                    if parity.rem_euclid(2.0) < 1.0 {
+                        0.0
+                    } else {
+                        1.0
+                    }
+                };
+                a * (1.0 - fraction) + b * fraction
+            }
+        }
+    }
+}
+
+/// An image stored with a pyramid of successively halved copies, for filtered lookups
+#[derive(Clone, Debug)]
+pub struct MipMap {
+    /// Levels from full resolution down to a single texel, each holding its width,
+    /// height, and raw values in [0, 1] in row-major order from the top row
+    levels: Vec<(u32, u32, Vec<glm::DVec3>)>,
+}
+
+impl MipMap {
+    /// Build the pyramid for an image by repeatedly averaging 2x2 blocks of texels
+    pub fn new(image: &RgbImage) -> Self {
+        let (width, height) = image.dimensions();
+        let base: Vec<glm::DVec3> = image
+            .pixels()
+            .map(|p| glm::vec3(p[0] as f64, p[1] as f64, p[2] as f64) / 255.0)
+            .collect();
+        let mut levels = vec![(width, height, base)];
+        loop {
+            let (w, h, texels) = levels.last().unwrap();
+            if *w == 1 && *h == 1 {
+                break;
+            }
+            let (nw, nh) = ((w / 2).max(1), (h / 2).max(1));
+            let mut next = Vec::with_capacity((nw * nh) as usize);
+            for y in 0..nh {
+                for x in 0..nw {
+                    // Odd dimensions fold their last row or column into the previous one
+                    let xs = (2 * x)..(2 * x + 2 + u32::from(x == nw - 1 && w % 2 == 1));
+                    let ys = (2 * y)..(2 * y + 2 + u32::from(y == nh - 1 && h % 2 == 1));
+                    let mut sum = glm::vec3(0.0, 0.0, 0.0);
+                    let mut count = 0.0;
+                    for j in ys.clone().filter(|&j| j < *h) {
+                        for i in xs.clone().filter(|&i| i < *w) {
+                            sum += texels[(j * w + i) as usize];
+                            count += 1.0;
+                        }
+                    }
+                    next.push(sum / count);
+                }
+            }
+            levels.push((nw, nh, next));
+        }
+        Self { levels }
+    }
+
+    /// Width and height of the full-resolution image
+    pub fn dimensions(&self) -> (u32, u32) {
+        let (width, height, _) = self.levels[0];
+        (width, height)
+    }
+
+    /// Number of levels in the pyramid
+    pub fn num_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Look up the average value over a footprint of side length `width` in UV space
+    pub fn lookup(&self, uv: &glm::DVec2, width: f64) -> glm::DVec3 {
+        let (w, h) = self.dimensions();
+        let texels = width * w.max(h) as f64;
+        if texels <= 1.0 {
+            return self.nearest(0, uv);
+        }
+        let lod = texels.log2().min((self.levels.len() - 1) as f64);
+        let level = lod.floor() as usize;
+        let t = lod - level as f64;
+        if level + 1 < self.levels.len() {
+            self.bilinear(level, uv) * (1.0 - t) + self.bilinear(level + 1, uv) * t
+        } else {
+            self.bilinear(level, uv)
+        }
+    }
+
+    /// The texel containing a UV coordinate at a given level
+    fn nearest(&self, level: usize, uv: &glm::DVec2) -> glm::DVec3 {
+        let (width, height, ref texels) = self.levels[level];
+        let u = uv.x - uv.x.floor();
+        let v = 1.0 - (uv.y - uv.y.floor());
+        let x = ((u * width as f64) as u32).min(width - 1);
+        let y = ((v * height as f64) as u32).min(height - 1);
+        texels[(y * width + x) as usize]
+    }
+
+    /// Bilinear interpolation between texel centers at a given level, wrapping around
+    fn bilinear(&self, level: usize, uv: &glm::DVec2) -> glm::DVec3 {
+        let (width, height, ref texels) = self.levels[level];
+        let x = uv.x * width as f64 - 0.5;
+        let y = (1.0 - uv.y) * height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let texel = |i: f64, j: f64| {
+            let i = (i as i64).rem_euclid(width as i64);
+            let j = (j as i64).rem_euclid(height as i64);
+            texels[(j * width as i64 + i) as usize]
+        };
+        let top = texel(x0, y0) * (1.0 - fx) + texel(x0 + 1.0, y0) * fx;
+        let bottom = texel(x0, y0 + 1.0) * (1.0 - fx) + texel(x0 + 1.0, y0 + 1.0) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+impl From<Color> for Texture {
+    fn from(color: Color) -> Self {
+        Texture::Solid(color)
+    }
+}
+
+impl From<RgbImage> for Texture {
+    fn from(image: RgbImage) -> Self {
+        Texture::Image(Arc::new(MipMap::new(&image)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn textures_are_sampled_in_uv_space() {
+        let black = glm::vec3(0.0, 0.0, 0.0);
+        let white = glm::vec3(1.0, 1.0, 1.0);
+        let checker = Texture::Checker(black, white, 2.0);
+        assert_eq!(checker.color(&glm::vec2(0.25, 0.25)), black);
+        assert_eq!(checker.color(&glm::vec2(0.75, 0.25)), white);
+        assert_eq!(checker.color(&glm::vec2(-0.25, 0.25)), white);
+
+        // Image rows go from the top down, while v goes from the bottom up
+        let mut image = RgbImage::new(1, 2);
+        image.put_pixel(0, 0, image::Rgb([255, 255, 255]));
+        let image = Texture::from(image);
+        assert_eq!(image.color(&glm::vec2(0.5, 0.75)), white);
+        assert_eq!(image.color(&glm::vec2(0.5, 0.25)), black);
+        assert_eq!(image.color(&glm::vec2(1.5, 1.25)), black);
+    }
+}